@@ -0,0 +1,64 @@
+/*
+ * C API of metalgbm for scoring trained models, see src/capi.rs. Names follow
+ * LightGBM's C API: functions return 0 on success and -1 on failure, when
+ * MGBM_GetLastError() describes what went wrong on the calling thread.
+ *
+ * Link against the cdylib (libmetalgbm.so, libmetalgbm.dylib or metalgbm.dll)
+ * built by `cargo build --release`.
+ */
+
+#ifndef METALGBM_H
+#define METALGBM_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* predict_type: the objective's transform, e.g. probabilities, or raw scores */
+#define C_API_PREDICT_NORMAL 0
+#define C_API_PREDICT_RAW_SCORE 1
+
+/* A booster, safe to predict with from several threads at once. */
+typedef void *BoosterHandle;
+
+/* Valid until the next failing call on the same thread. */
+const char *MGBM_GetLastError(void);
+
+/* Reads a model in metalgbm's binary format from len bytes at data. */
+int MGBM_BoosterLoadModelFromBytes(const void *data, size_t len, BoosterHandle *out);
+
+/* Reads a model in LightGBM's text format from a NUL-terminated string. */
+int MGBM_BoosterLoadModelFromString(const char *model_str, BoosterHandle *out);
+
+int MGBM_BoosterFree(BoosterHandle handle);
+
+/* The scores per row: the number of classes, 1 for single-output models. */
+int MGBM_BoosterGetNumClasses(BoosterHandle handle, int *out_len);
+
+/* The features per row, -1 for models that don't record them. */
+int MGBM_BoosterGetNumFeature(BoosterHandle handle, int *out_len);
+
+/*
+ * Scores one row of ncol features, NaN for missing values. out_result must
+ * hold MGBM_BoosterGetNumClasses floats; out_len receives their number.
+ */
+int MGBM_BoosterPredictForMatSingleRow(BoosterHandle handle, const float *data, int32_t ncol,
+                                       int predict_type, int64_t *out_len, float *out_result);
+
+/*
+ * Scores the nrow rows of the row-major nrow x ncol matrix at data, in
+ * parallel. out_result must hold nrow * MGBM_BoosterGetNumClasses floats,
+ * written row by row; out_len receives their number.
+ */
+int MGBM_BoosterPredictForMat(BoosterHandle handle, const float *data, int32_t nrow,
+                              int32_t ncol, int predict_type, int64_t *out_len,
+                              float *out_result);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* METALGBM_H */
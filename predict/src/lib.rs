@@ -0,0 +1,46 @@
+// The prediction core of metalgbm, for running trained models where the standard library
+// isn't available: it builds with `no_std` and `alloc` only, and does no file IO and starts
+// no threads. metalgbm trains models and writes them in the format of `lite`, whose
+// `LiteModel` reads them from bytes and predicts; metalgbm shares `FeatureRow` and
+// `in_categories` with it so both evaluate splits the same way.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod lite;
+
+// A row of features that trees can be evaluated on.
+pub trait FeatureRow {
+    fn value(&self, feature: usize) -> f32;
+}
+
+impl FeatureRow for [f32] {
+    fn value(&self, feature: usize) -> f32 {
+        self[feature]
+    }
+}
+
+// whether `value` is one of the ascending `categories`; NaN and values that aren't
+// non-negative integers never are
+pub fn in_categories(categories: &[u32], value: f32) -> bool {
+    value >= 0.0
+        && value - libm::truncf(value) == 0.0
+        && categories.binary_search(&(value as u32)).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_categories() {
+        let categories = [1, 4, 9];
+        assert!(in_categories(&categories, 4.0));
+        assert!(!in_categories(&categories, 4.5));
+        assert!(!in_categories(&categories, 2.0));
+        assert!(!in_categories(&categories, -1.0));
+        assert!(!in_categories(&categories, f32::NAN));
+        assert!(!in_categories(&[u32::MAX], f32::INFINITY));
+    }
+}
@@ -0,0 +1,545 @@
+// The "lite" model format for embedded targets, which metalgbm's `Model::to_lite_bytes`
+// writes: only what prediction needs, with the numbers of every node squeezed into 16 bits.
+//
+// Thresholds are stored like the threshold tables of metalgbm's binary format, as a u16
+// index into the ascending thresholds of their feature, which is exact: rounding a
+// threshold would move rows across it, an error no bound could be given for. Leaf values
+// carry the shrinkage (and the averaging of averaging models) and are rounded to a
+// `LitePrecision`, so each raw score is off by at most the sum of its trees' largest
+// rounding errors, which the file records.
+//
+// A file starts with the magic bytes `MGBL` and a format version, all little endian:
+// - the precision (u8) and the fixed-point scale (f32), outputs (u32), objective (u8 tag
+//   and u32 parameter, as in the binary format), one f32 base score per output, the
+//   number of features (u32) and the raw error bound (f32);
+// - each feature's thresholds, a u32 count and f32s;
+// - the category sets of the categorical splits (u32 count), each a u32 count and u32s;
+// - the trees (u32 count), each its number of nodes (u32) and the nodes in preorder, left
+//   child first: a leaf is its tag (u8) and value (u16), a split its tag, feature (u16)
+//   and threshold index or category set (u16).
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{FeatureRow, in_categories};
+
+pub const MAGIC: [u8; 4] = *b"MGBL";
+pub const FORMAT_VERSION: u32 = 1;
+
+// node tags
+pub const LEAF: u8 = 0;
+pub const SPLIT: u8 = 1;
+pub const SPLIT_DEFAULT_LEFT: u8 = 2;
+pub const CATEGORICAL_SPLIT: u8 = 3;
+
+// how leaf values are rounded to 16 bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LitePrecision {
+    // IEEE half precision: about three significant digits at any magnitude up to 65504
+    #[default]
+    F16,
+    // multiples of a scale, the largest leaf value / 32767, which is more precise than f16
+    // for leaves of similar magnitudes
+    Fixed,
+}
+
+impl LitePrecision {
+    // the tag of the precision in the file
+    pub fn code(self) -> u8 {
+        match self {
+            LitePrecision::F16 => 0,
+            LitePrecision::Fixed => 1,
+        }
+    }
+
+    // the 16 bits `value` is stored as; `scale` only matters for `Fixed`
+    pub fn encode(self, value: f32, scale: f32) -> u16 {
+        match self {
+            LitePrecision::F16 => f16_bits(value),
+            LitePrecision::Fixed => libm::roundf(value / scale) as i16 as u16,
+        }
+    }
+
+    pub fn decode(self, bits: u16, scale: f32) -> f32 {
+        match self {
+            LitePrecision::F16 => f16_value(bits),
+            LitePrecision::Fixed => f32::from(bits as i16) * scale,
+        }
+    }
+}
+
+// why bytes aren't a lite model
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatError {
+    pub reason: String,
+}
+
+impl FormatError {
+    fn new(reason: impl Into<String>) -> Self {
+        FormatError {
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "malformed lite model: {}", self.reason)
+    }
+}
+
+impl core::error::Error for FormatError {}
+
+// what turns raw scores into predictions, by the objective of the model
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Transform {
+    Identity,
+    Sigmoid,
+    Softmax,
+    Exp,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiteModel {
+    precision: LitePrecision,
+    // what one step of a fixed-point leaf value is worth
+    scale: f32,
+    num_outputs: usize,
+    transform: Transform,
+    base_scores: Vec<f32>,
+    num_features: usize,
+    raw_error_bound: f32,
+    thresholds: Vec<f32>,
+    // feature f's thresholds start at thresholds[threshold_offsets[f]]
+    threshold_offsets: Vec<u32>,
+    category_sets: Vec<Vec<u32>>,
+    // the nodes of all trees, each tree in preorder from `roots[tree]`, so a split's left
+    // child is the node after it
+    nodes: Vec<LiteNode>,
+    roots: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct LiteNode {
+    // the right child of a split
+    right: u32,
+    feature: u16,
+    // a leaf's value, a split's threshold index within its feature, or a categorical
+    // split's category set
+    arg: u16,
+    tag: u8,
+}
+
+impl LiteModel {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FormatError> {
+        let mut input = Reader { bytes };
+        if input.take(MAGIC.len())? != MAGIC {
+            return Err(FormatError::new("not a metalgbm lite model"));
+        }
+        let version = input.u32()?;
+        if version != FORMAT_VERSION {
+            return Err(FormatError::new(format!(
+                "format version {version} isn't the one this version of metalgbm reads, \
+                 {FORMAT_VERSION}"
+            )));
+        }
+        let precision = match input.u8()? {
+            0 => LitePrecision::F16,
+            1 => LitePrecision::Fixed,
+            code => return Err(FormatError::new(format!("unknown precision {code}"))),
+        };
+        let scale = input.f32()?;
+        let num_outputs = input.u32()? as usize;
+        if num_outputs == 0 {
+            return Err(FormatError::new("the model has no outputs"));
+        }
+        let (tag, _parameter) = (input.u8()?, input.u32()?);
+        let transform = match tag {
+            // none (a user's objective), squared error, LambdaRank, quantile, Huber, fair
+            0 | 1 | 4..=7 => Transform::Identity,
+            2 => Transform::Sigmoid,
+            3 => Transform::Softmax,
+            // Poisson, gamma and Tweedie, whose raw scores are log means
+            8..=10 => Transform::Exp,
+            _ => return Err(FormatError::new(format!("unknown objective {tag}"))),
+        };
+        let base_scores = (0..num_outputs)
+            .map(|_| input.f32())
+            .collect::<Result<Vec<_>, _>>()?;
+        let num_features = input.u32()? as usize;
+        let raw_error_bound = input.f32()?;
+
+        let mut thresholds = Vec::new();
+        let mut threshold_offsets = Vec::with_capacity(num_features.min(input.bytes.len()));
+        for _ in 0..num_features {
+            threshold_offsets.push(thresholds.len() as u32);
+            let len = input.u32()?;
+            for _ in 0..len {
+                thresholds.push(input.f32()?);
+            }
+        }
+        let num_sets = input.u32()? as usize;
+        let mut category_sets = Vec::with_capacity(num_sets.min(input.bytes.len()));
+        for _ in 0..num_sets {
+            let len = input.u32()? as usize;
+            category_sets.push((0..len).map(|_| input.u32()).collect::<Result<_, _>>()?);
+        }
+
+        let mut model = LiteModel {
+            precision,
+            scale,
+            num_outputs,
+            transform,
+            base_scores,
+            num_features,
+            raw_error_bound,
+            thresholds,
+            threshold_offsets,
+            category_sets,
+            nodes: Vec::new(),
+            roots: Vec::new(),
+        };
+        let num_trees = input.u32()?;
+        for tree in 0..num_trees {
+            model.roots.push(model.nodes.len() as u32);
+            model
+                .read_tree(&mut input)
+                .map_err(|err| FormatError::new(format!("tree {tree}: {}", err.reason)))?;
+        }
+        if !input.bytes.is_empty() {
+            return Err(FormatError::new("there are bytes after the trees"));
+        }
+        Ok(model)
+    }
+
+    // The raw scores of `row`, one per output, each within `raw_error_bound` of the full
+    // model's.
+    pub fn predict_raw<R: FeatureRow + ?Sized>(&self, row: &R) -> Vec<f32> {
+        let mut scores = self.base_scores.clone();
+        for (tree, &root) in self.roots.iter().enumerate() {
+            scores[tree % self.num_outputs] += self.leaf_value(root as usize, row);
+        }
+        scores
+    }
+
+    // `predict_raw` with the objective's transform, e.g. probabilities
+    pub fn predict<R: FeatureRow + ?Sized>(&self, row: &R) -> Vec<f32> {
+        let mut scores = self.predict_raw(row);
+        match self.transform {
+            Transform::Identity => {}
+            Transform::Sigmoid => {
+                for score in &mut scores {
+                    *score = 1.0 / (1.0 + libm::expf(-*score));
+                }
+            }
+            Transform::Exp => {
+                for score in &mut scores {
+                    *score = libm::expf(*score);
+                }
+            }
+            Transform::Softmax => {
+                let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                for score in &mut scores {
+                    *score = libm::expf(*score - max);
+                }
+                let sum: f32 = scores.iter().sum();
+                for score in &mut scores {
+                    *score /= sum;
+                }
+            }
+        }
+        scores
+    }
+
+    // how far a raw score can be from the full model's because of rounded leaf values
+    pub fn raw_error_bound(&self) -> f32 {
+        self.raw_error_bound
+    }
+
+    pub fn precision(&self) -> LitePrecision {
+        self.precision
+    }
+
+    pub fn num_outputs(&self) -> usize {
+        self.num_outputs
+    }
+
+    pub fn num_features(&self) -> usize {
+        self.num_features
+    }
+
+    pub fn num_trees(&self) -> usize {
+        self.roots.len()
+    }
+
+    fn leaf_value<R: FeatureRow + ?Sized>(&self, mut index: usize, row: &R) -> f32 {
+        loop {
+            let node = self.nodes[index];
+            let feature = usize::from(node.feature);
+            let left = match node.tag {
+                LEAF => return self.precision.decode(node.arg, self.scale),
+                CATEGORICAL_SPLIT => in_categories(
+                    &self.category_sets[usize::from(node.arg)],
+                    row.value(feature),
+                ),
+                tag => {
+                    let value = row.value(feature);
+                    if value.is_nan() {
+                        tag == SPLIT_DEFAULT_LEFT
+                    } else {
+                        value < self.threshold_table(feature)[usize::from(node.arg)]
+                    }
+                }
+            };
+            index = if left { index + 1 } else { node.right as usize };
+        }
+    }
+
+    fn threshold_table(&self, feature: usize) -> &[f32] {
+        let start = self.threshold_offsets[feature] as usize;
+        let end = self
+            .threshold_offsets
+            .get(feature + 1)
+            .map_or(self.thresholds.len(), |&end| end as usize);
+        &self.thresholds[start..end]
+    }
+
+    // Reads the nodes of a tree, linking every split to its right child; fails unless they
+    // form one whole tree whose features, thresholds and category sets exist.
+    fn read_tree(&mut self, input: &mut Reader) -> Result<(), FormatError> {
+        let root = self.nodes.len();
+        let num_nodes = input.u32()? as usize;
+        // splits above the node being read, with whether it is in their right subtree
+        let mut open_splits: Vec<(usize, bool)> = Vec::new();
+        for i in 0..num_nodes {
+            if i > 0 && open_splits.is_empty() {
+                return Err(FormatError::new("nodes after the last leaf"));
+            }
+            let tag = input.u8()?;
+            let feature = if tag == LEAF { 0 } else { input.u16()? };
+            let arg = input.u16()?;
+            let in_range = match tag {
+                LEAF => true,
+                SPLIT | SPLIT_DEFAULT_LEFT => {
+                    usize::from(feature) < self.num_features
+                        && usize::from(arg) < self.threshold_table(usize::from(feature)).len()
+                }
+                CATEGORICAL_SPLIT => {
+                    usize::from(feature) < self.num_features
+                        && usize::from(arg) < self.category_sets.len()
+                }
+                _ => return Err(FormatError::new(format!("unknown node tag {tag}"))),
+            };
+            if !in_range {
+                return Err(FormatError::new(format!(
+                    "node {i} refers to a feature, threshold or category set the model \
+                     doesn't have"
+                )));
+            }
+            self.nodes.push(LiteNode {
+                right: 0,
+                feature,
+                arg,
+                tag,
+            });
+            if tag == LEAF {
+                // the leaf ends the right subtrees it is the last node of, and then the
+                // left subtree of the split whose right child comes next
+                while let Some((split, in_right)) = open_splits.pop() {
+                    if !in_right {
+                        self.nodes[split].right = (root + i + 1) as u32;
+                        open_splits.push((split, true));
+                        break;
+                    }
+                }
+            } else {
+                open_splits.push((root + i, false));
+            }
+        }
+        if num_nodes == 0 || !open_splits.is_empty() {
+            return Err(FormatError::new("the tree ends before its last leaf"));
+        }
+        Ok(())
+    }
+}
+
+// reads from the front of `bytes`, failing on files that end early
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], FormatError> {
+        if self.bytes.len() < len {
+            return Err(FormatError::new("the file ends early"));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], FormatError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, FormatError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, FormatError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, FormatError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, FormatError> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+}
+
+// `value` as IEEE half precision bits, rounded to nearest with ties to even
+fn f16_bits(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    // the exponent rebiased for f16, whose normal numbers have 1 to 30
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    let (half, remainder, halfway) = if exponent > 0 {
+        (
+            ((exponent as u32) << 10) | (mantissa >> 13),
+            mantissa & 0x1fff,
+            0x1000,
+        )
+    } else if exponent >= -10 {
+        // subnormal: the implicit leading one becomes explicit
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        (
+            mantissa >> shift,
+            mantissa & ((1 << shift) - 1),
+            1 << (shift - 1),
+        )
+    } else {
+        return sign;
+    };
+    let round_up = remainder > halfway || (remainder == halfway && half & 1 == 1);
+    // a carry out of the mantissa correctly moves to the next exponent, or to infinity
+    sign | (half + u32::from(round_up)) as u16
+}
+
+fn f16_value(bits: u16) -> f32 {
+    let sign = u32::from(bits & 0x8000) << 16;
+    let exponent = u32::from(bits >> 10) & 0x1f;
+    let mantissa = u32::from(bits & 0x3ff);
+    match exponent {
+        0 => {
+            // subnormal: mantissa * 2^-24
+            let magnitude = mantissa as f32 * f32::from_bits(0x3380_0000);
+            if sign == 0 { magnitude } else { -magnitude }
+        }
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 127 - 15) << 23) | (mantissa << 13)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_f16_round_trip() {
+        for value in [0.0, -0.0, 1.0, -2.5, 0.1, 65504.0, 6.0e-8, 1.0e-5, -3.3] {
+            let rounded = f16_value(f16_bits(value));
+            assert!(
+                libm::fabsf(rounded - value) <= libm::fabsf(value) / 1024.0 + 6.0e-8,
+                "{value} {rounded}"
+            );
+        }
+        assert_eq!(f16_bits(1.0), 0x3c00);
+        assert_eq!(f16_bits(-2.0), 0xc000);
+        assert_eq!(f16_bits(65504.0), 0x7bff);
+        assert_eq!(f16_bits(1.0e6), 0x7c00);
+        assert!(f16_value(f16_bits(f32::NAN)).is_nan());
+        // 1 + 2^-11 lies halfway between 1 and the next f16 and rounds to the even 1
+        assert_eq!(f16_bits(1.0 + f32::EPSILON * 4096.0), 0x3c00);
+        assert_eq!(f16_value(1), f32::from_bits(0x3380_0000));
+    }
+
+    // a stump on feature 1 < 2.5, missing values left, with leaves -1 and 3 in fixed point
+    // of scale 1/8, and a sigmoid
+    fn stump() -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.push(LitePrecision::Fixed.code());
+        bytes.extend_from_slice(&0.125f32.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.push(2);
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&0.5f32.to_le_bytes());
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&0.0625f32.to_le_bytes());
+        // feature 0 has no thresholds, feature 1 one
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&2.5f32.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        // one tree of three nodes
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&3u32.to_le_bytes());
+        bytes.push(SPLIT_DEFAULT_LEFT);
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        for value in [-1.0, 3.0] {
+            bytes.push(LEAF);
+            bytes.extend_from_slice(&LitePrecision::Fixed.encode(value, 0.125).to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_reads_and_predicts() {
+        let model = LiteModel::from_bytes(&stump()).unwrap();
+        assert_eq!(model.num_trees(), 1);
+        assert_eq!(model.raw_error_bound(), 0.0625);
+        assert_eq!(model.predict_raw(&[0.0, 1.0][..]), [-0.5]);
+        assert_eq!(model.predict_raw(&[0.0, 3.0][..]), [3.5]);
+        assert_eq!(model.predict_raw(&[0.0, f32::NAN][..]), [-0.5]);
+        let probability = model.predict(&[0.0, 3.0][..])[0];
+        assert!(libm::fabsf(probability - 1.0 / (1.0 + libm::expf(-3.5))) < 1e-6);
+    }
+
+    #[test]
+    fn test_rejects_malformed_trees() {
+        let bytes = stump();
+        assert!(LiteModel::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert!(LiteModel::from_bytes(&extra).is_err());
+
+        // the split's threshold index past its feature's table
+        let mut bad_threshold = bytes.clone();
+        let split = bytes.len() - 2 * 3 - 2;
+        bad_threshold[split..split + 2].copy_from_slice(&1u16.to_le_bytes());
+        assert!(LiteModel::from_bytes(&bad_threshold).is_err());
+
+        // a tree of two nodes: a split without its right child
+        let mut short = bytes[..bytes.len() - 3].to_vec();
+        let count = short.len() - 2 * 3 - 4;
+        short[count..count + 4].copy_from_slice(&2u32.to_le_bytes());
+        assert!(LiteModel::from_bytes(&short).is_err());
+    }
+}
@@ -0,0 +1,488 @@
+// The `metalgbm` command line, for training and predicting from shell pipelines:
+//
+//     metalgbm train --config params.json --data train.csv --valid valid.csv \
+//         --metric auc --early-stopping-rounds 10 --output model.json
+//     metalgbm predict --model model.json --data test.csv --output predictions.csv
+//
+// The config file holds parameters as JSON or in LightGBM's `key = value` format, see
+// `metalgbm::config`, with defaults for those it leaves out; the flags override it. Data is
+// CSV, or Parquet with the `parquet` feature, and models are JSON (`.json`), LightGBM text
+// (`.txt`) or binary files (anything else).
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+
+use metalgbm::booster::Booster;
+use metalgbm::callback::LogEvaluation;
+use metalgbm::config;
+use metalgbm::dataset::Dataset;
+use metalgbm::io::{self, CsvOptions, LabelColumn};
+use metalgbm::metrics::MetricType;
+use metalgbm::model::Model;
+use metalgbm::params::Params;
+
+const USAGE: &str = "\
+usage: metalgbm train --data <file> --output <model> [options]
+       metalgbm predict --model <model> --data <file> --output <file> [options]
+
+train options:
+  --config <file>                 parameters as JSON (.json) or a LightGBM config
+  --valid <file>                  a validation set, may be repeated
+  --metric <name>                 rmse, mae, r2, auc, binary_logloss, ndcg@k or map@k,
+                                  may be repeated
+  --num-iterations <n>
+  --learning-rate <rate>
+  --early-stopping-rounds <n>     stop when the first metric on the first validation
+                                  set hasn't improved for n iterations
+  --log-period <n>                print the metrics every n iterations (default 1)
+
+predict options:
+  --raw                           raw scores instead of transformed predictions
+  --metric <name>                 evaluate the predictions, needs --label
+
+data options:
+  --label <column>                the label column, by name or 0-based index (default 0
+                                  for training, none for predicting)
+  --no-header                     CSV files have no header line
+  --delimiter <char>              the CSV delimiter (default ,)
+";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(reason) => {
+            eprintln!("metalgbm: {reason}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    match args.first().map(String::as_str) {
+        Some("train") => train(&Flags::parse(&args[1..], &["no-header"])?),
+        Some("predict") => predict(&Flags::parse(&args[1..], &["no-header", "raw"])?),
+        Some("help" | "--help" | "-h") => {
+            print!("{USAGE}");
+            Ok(())
+        }
+        Some(command) => Err(format!("unknown command `{command}`\n\n{USAGE}")),
+        None => Err(USAGE.to_string()),
+    }
+}
+
+fn train(flags: &Flags) -> Result<(), String> {
+    let mut params = match flags.last("config") {
+        Some(path) => {
+            let config = config::read_config(Path::new(path)).map_err(|err| err.to_string())?;
+            for warning in config.warnings {
+                eprintln!("metalgbm: {path}: {warning}");
+            }
+            config.params
+        }
+        None => Params::default(),
+    };
+    if let Some(num_iterations) = flags.parsed("num-iterations")? {
+        params.num_iterations = num_iterations;
+    }
+    if let Some(learning_rate) = flags.parsed("learning-rate")? {
+        params.learning_rate = learning_rate;
+    }
+    if let Some(rounds) = flags.parsed("early-stopping-rounds")? {
+        params.early_stopping_rounds = Some(rounds);
+    }
+    for metric in flags.all("metric") {
+        params.metrics.push(parse_metric(metric)?);
+    }
+    let output = flags.required("output")?;
+
+    let label = match flags.last("label") {
+        Some(label) => parse_label(label),
+        None => LabelColumn::Index(0),
+    };
+    // the codes of the training data's string columns, which the validation sets share
+    let mut categories = BTreeMap::new();
+    let (dataset, categorical_features) = read_data(
+        Path::new(flags.required("data")?),
+        flags,
+        &label,
+        &mut categories,
+    )?;
+    for feature in categorical_features {
+        if !params.categorical_features.contains(&feature) {
+            params.categorical_features.push(feature);
+        }
+    }
+    let valid_sets = flags
+        .all("valid")
+        .map(|path| {
+            let (valid, _) = read_data(Path::new(path), flags, &label, &mut categories)?;
+            Ok((path, valid))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let valid_sets: Vec<(&str, &Dataset)> = valid_sets
+        .iter()
+        .map(|(path, dataset)| (*path, dataset))
+        .collect();
+
+    let mut booster = Booster::new();
+    if !params.metrics.is_empty() {
+        let period = flags.parsed("log-period")?.unwrap_or(1);
+        booster.add_callback(Box::new(LogEvaluation::new(period)));
+    }
+    booster
+        .train_with_validation(&dataset, &params, &valid_sets)
+        .map_err(|err| err.to_string())?;
+    if let Some(best_iteration) = booster.best_iteration() {
+        println!("best iteration: {best_iteration}");
+    }
+    if !categories.is_empty() {
+        io::save_categories(&categories, booster.metadata_mut());
+    }
+    save_model(&booster.to_model(), Path::new(output))
+}
+
+fn predict(flags: &Flags) -> Result<(), String> {
+    let model = load_model(Path::new(flags.required("model")?))?;
+    let output = flags.required("output")?;
+    let label = flags
+        .last("label")
+        .map_or(LabelColumn::Unlabeled, parse_label);
+    let metrics = flags
+        .all("metric")
+        .map(parse_metric)
+        .collect::<Result<Vec<_>, _>>()?;
+    if !metrics.is_empty() && label == LabelColumn::Unlabeled {
+        return Err("--metric needs the --label of the data".to_string());
+    }
+    let mut categories = io::load_categories(model.metadata()).map_err(|err| err.to_string())?;
+    let (dataset, _) = read_data(
+        Path::new(flags.required("data")?),
+        flags,
+        &label,
+        &mut categories,
+    )?;
+
+    let booster = Booster::from_model(model);
+    booster
+        .check_features(dataset.num_features(), dataset.feature_names())
+        .map_err(|err| err.to_string())?;
+    let rows: Vec<Vec<f32>> = if flags.has("raw") {
+        (0..dataset.num_rows())
+            .map(|i| booster.predict_raw_row(&dataset.row(i)))
+            .collect()
+    } else {
+        booster
+            .predict_dataset(&dataset)
+            .map_err(|err| err.to_string())?
+    };
+
+    let mut text = String::new();
+    for row in &rows {
+        let fields: Vec<String> = row.iter().map(f32::to_string).collect();
+        writeln!(text, "{}", fields.join(",")).unwrap();
+    }
+    fs::write(output, text).map_err(|err| format!("can't write {output}: {err}"))?;
+
+    // metrics take the predictions output by output
+    let num_outputs = booster.num_outputs();
+    let preds: Vec<f32> = (0..num_outputs)
+        .flat_map(|k| rows.iter().map(move |row| row[k]))
+        .collect();
+    for metric in metrics {
+        let metric = metric.create();
+        println!("{}: {}", metric.name(), metric.evaluate(&preds, &dataset));
+    }
+    Ok(())
+}
+
+// the flags of a command, `--name value`, `--name=value` or `--switch`, in order
+#[derive(Debug, PartialEq)]
+struct Flags {
+    values: Vec<(String, Option<String>)>,
+}
+
+impl Flags {
+    // `switches` are the flags that take no value
+    fn parse(args: &[String], switches: &[&str]) -> Result<Self, String> {
+        let mut values = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let Some(flag) = arg.strip_prefix("--") else {
+                return Err(format!("unexpected argument `{arg}`\n\n{USAGE}"));
+            };
+            let (name, value) = match flag.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None if switches.contains(&flag) => (flag, None),
+                None => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("--{flag} needs a value"))?;
+                    (flag, Some(value.clone()))
+                }
+            };
+            if switches.contains(&name) && value.is_some() {
+                return Err(format!("--{name} takes no value"));
+            }
+            values.push((name.to_string(), value));
+        }
+        Ok(Flags { values })
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.values.iter().any(|(flag, _)| flag == name)
+    }
+
+    fn all(&self, name: &'static str) -> impl Iterator<Item = &str> {
+        self.values
+            .iter()
+            .filter(move |(flag, _)| flag == name)
+            .filter_map(|(_, value)| value.as_deref())
+    }
+
+    // the value of a flag given more than once is the last one
+    fn last(&self, name: &'static str) -> Option<&str> {
+        self.all(name).last()
+    }
+
+    fn required(&self, name: &'static str) -> Result<&str, String> {
+        self.last(name)
+            .ok_or_else(|| format!("--{name} is required"))
+    }
+
+    fn parsed<T: std::str::FromStr>(&self, name: &'static str) -> Result<Option<T>, String> {
+        self.last(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| format!("--{name}: `{value}` isn't a valid value"))
+            })
+            .transpose()
+    }
+}
+
+fn parse_label(label: &str) -> LabelColumn {
+    match label.parse() {
+        Ok(index) => LabelColumn::Index(index),
+        Err(_) => LabelColumn::Name(label.to_string()),
+    }
+}
+
+fn parse_metric(name: &str) -> Result<MetricType, String> {
+    let at_k = |prefix: &str| {
+        let k = name.strip_prefix(prefix)?.strip_prefix('@')?;
+        k.parse().ok()
+    };
+    Ok(match name {
+        "rmse" => MetricType::Rmse,
+        "mae" => MetricType::Mae,
+        "r2" => MetricType::R2,
+        "auc" => MetricType::Auc,
+        "binary_logloss" => MetricType::BinaryLogloss,
+        _ => match (at_k("ndcg"), at_k("map")) {
+            (Some(k), _) => MetricType::Ndcg { k },
+            (_, Some(k)) => MetricType::Map { k },
+            _ => return Err(format!("unknown metric `{name}`")),
+        },
+    })
+}
+
+// Parquet string columns are coded with `categories`, which gains the codes of the ones it
+// has none for, and come back as the indices of categorical features.
+fn read_data(
+    path: &Path,
+    flags: &Flags,
+    label: &LabelColumn,
+    categories: &mut BTreeMap<String, Vec<String>>,
+) -> Result<(Dataset, Vec<usize>), String> {
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "parquet")
+    {
+        return read_parquet(path, label, categories);
+    }
+    let delimiter = match flags.last("delimiter") {
+        Some("\\t" | "tab") => '\t',
+        Some(delimiter) => {
+            let mut chars = delimiter.chars();
+            match (chars.next(), chars.next()) {
+                (Some(delimiter), None) => delimiter,
+                _ => return Err(format!("--delimiter `{delimiter}` isn't one character")),
+            }
+        }
+        None => ',',
+    };
+    let options = CsvOptions {
+        has_header: !flags.has("no-header"),
+        label: label.clone(),
+        delimiter,
+        ..CsvOptions::default()
+    };
+    let dataset =
+        io::read_csv(path, &options).map_err(|err| format!("{}: {err}", path.display()))?;
+    Ok((dataset, vec![]))
+}
+
+#[cfg(feature = "parquet")]
+fn read_parquet(
+    path: &Path,
+    label: &LabelColumn,
+    categories: &mut BTreeMap<String, Vec<String>>,
+) -> Result<(Dataset, Vec<usize>), String> {
+    let label = match label {
+        LabelColumn::Name(label) => Some(label.clone()),
+        LabelColumn::Unlabeled => None,
+        LabelColumn::Index(_) => {
+            return Err("Parquet data needs the --label column by name".to_string());
+        }
+    };
+    let options = io::ParquetOptions {
+        label,
+        categories: categories.clone(),
+        ..io::ParquetOptions::default()
+    };
+    let data = io::read_parquet(path, &options).map_err(|err| err.to_string())?;
+    categories.extend(data.categories);
+    Ok((data.dataset, data.categorical_features))
+}
+
+#[cfg(not(feature = "parquet"))]
+fn read_parquet(
+    path: &Path,
+    _label: &LabelColumn,
+    _categories: &mut BTreeMap<String, Vec<String>>,
+) -> Result<(Dataset, Vec<usize>), String> {
+    Err(format!(
+        "{}: reading Parquet needs metalgbm built with the `parquet` feature",
+        path.display()
+    ))
+}
+
+enum ModelFormat {
+    Json,
+    LightGbm,
+    Binary,
+}
+
+fn model_format(path: &Path) -> ModelFormat {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => ModelFormat::Json,
+        Some("txt") => ModelFormat::LightGbm,
+        _ => ModelFormat::Binary,
+    }
+}
+
+fn save_model(model: &Model, path: &Path) -> Result<(), String> {
+    match model_format(path) {
+        ModelFormat::Json => model.save_json(path),
+        ModelFormat::LightGbm => model.save_lightgbm(path),
+        ModelFormat::Binary => model.save_binary(path),
+    }
+    .map_err(|err| err.to_string())
+}
+
+fn load_model(path: &Path) -> Result<Model, String> {
+    match model_format(path) {
+        ModelFormat::Json => Model::load_json(path),
+        ModelFormat::LightGbm => Model::load_lightgbm(path),
+        ModelFormat::Binary => Model::load_binary(path),
+    }
+    .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parses_flags() {
+        let flags = Flags::parse(
+            &args("--data a.csv --metric auc --metric=rmse --no-header --data b.csv"),
+            &["no-header"],
+        )
+        .unwrap();
+        assert_eq!(flags.last("data"), Some("b.csv"));
+        assert_eq!(flags.all("metric").collect::<Vec<_>>(), ["auc", "rmse"]);
+        assert!(flags.has("no-header"));
+        assert_eq!(flags.parsed::<usize>("log-period"), Ok(None));
+        assert!(flags.required("output").is_err());
+
+        assert!(Flags::parse(&args("--data"), &[]).is_err());
+        assert!(Flags::parse(&args("data.csv"), &[]).is_err());
+        assert!(Flags::parse(&args("--raw=yes"), &["raw"]).is_err());
+        let flags = Flags::parse(&args("--num-iterations ten"), &[]).unwrap();
+        assert!(flags.parsed::<usize>("num-iterations").is_err());
+
+        assert_eq!(parse_metric("ndcg@5"), Ok(MetricType::Ndcg { k: 5 }));
+        assert_eq!(
+            parse_metric("binary_logloss"),
+            Ok(MetricType::BinaryLogloss)
+        );
+        assert!(parse_metric("ndcg").is_err());
+        assert_eq!(parse_label("2"), LabelColumn::Index(2));
+        assert_eq!(parse_label("y"), LabelColumn::Name("y".to_string()));
+    }
+
+    #[test]
+    fn test_trains_and_predicts_files() {
+        let dir = env::temp_dir().join(format!("metalgbm-cli-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str| dir.join(name).to_str().unwrap().to_string();
+
+        let mut train_csv = String::from("x,y,label\n");
+        let mut test_csv = String::from("x,y\n");
+        for i in 0..200 {
+            let (x, y) = (i % 20, (i * 7) % 13);
+            let label = u8::from(x + y > 15);
+            writeln!(train_csv, "{x},{y},{label}").unwrap();
+            if i < 5 {
+                writeln!(test_csv, "{x},{y}").unwrap();
+            }
+        }
+        fs::write(file("train.csv"), train_csv).unwrap();
+        fs::write(file("test.csv"), test_csv).unwrap();
+        fs::write(
+            file("params.json"),
+            r#"{"objective": "BinaryLogistic", "num_iterations": 200}"#,
+        )
+        .unwrap();
+
+        let train_args = format!(
+            "train --config {} --data {} --valid {} --label label --metric auc \
+             --early-stopping-rounds 5 --log-period 50 --output {}",
+            file("params.json"),
+            file("train.csv"),
+            file("train.csv"),
+            file("model.json"),
+        );
+        run(&args(&train_args)).unwrap();
+        let booster = Booster::from_model(Model::load_json(file("model.json")).unwrap());
+        assert!(booster.best_iteration().is_some_and(|best| best < 200));
+
+        let predict_args = format!(
+            "predict --model {} --data {} --output {}",
+            file("model.json"),
+            file("test.csv"),
+            file("predictions.csv"),
+        );
+        run(&args(&predict_args)).unwrap();
+        let predictions = fs::read_to_string(file("predictions.csv")).unwrap();
+        let lines: Vec<&str> = predictions.lines().collect();
+        assert_eq!(lines.len(), 5);
+        let first: f32 = lines[0].parse().unwrap();
+        assert_eq!(first, booster.predict(&[0.0, 0.0]));
+
+        // the training file has a column the model doesn't know
+        let predict_args = format!("{predict_args} --data {}", file("train.csv"));
+        assert!(run(&args(&predict_args)).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,2357 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::callback::{Callback, CallbackAction, CallbackEnv};
+use crate::checkpoint::Checkpoint;
+use crate::codegen;
+use crate::dataset::{BinnedDataset, Dataset};
+use crate::error::{Error, Result};
+use crate::gpu::{
+    Backend, Device, DeviceTimings, FlatForest, GpuContext, GpuDataset, GpuPredictor,
+};
+use crate::metrics::{EvalHistory, EvalMetric};
+use crate::model::{self, Model};
+use crate::objective::{Objective, ObjectiveType, SquaredError};
+use crate::params::{BoostingType, Params};
+use crate::random::Rng;
+use crate::sampling::{self, SampleStrategy};
+use crate::sparse::FeatureRow;
+use crate::tree::{FlatTree, Tree};
+use crate::tree_builder::TreeBuilder;
+
+pub struct Booster {
+    // every tree carries the learning rate it was trained with
+    trees: Vec<Tree>,
+    objective: Box<dyn Objective>,
+    // what `objective` was created from, None for a user supplied one
+    objective_type: Option<ObjectiveType>,
+    // the parameters of the last training
+    params: Params,
+    // one tree per output and boosting round: tree k of round t is trees[t * num_outputs + k]
+    num_outputs: usize,
+    // constant raw score per output the trees are added to
+    base_scores: Vec<f32>,
+    eval_history: EvalHistory,
+    // set by early stopping: number of iterations that gave the best validation score
+    best_iteration: Option<usize>,
+    // random forests average their trees instead of summing them
+    average_output: bool,
+    // taken from the training data, if it had any
+    feature_names: Option<Vec<String>>,
+    // columns of the training data, None for models that don't record them
+    num_features: Option<usize>,
+    // the crate version that last trained the model
+    crate_version: Option<String>,
+    // whatever the user wants kept with the model, e.g. where its training data came from
+    metadata: BTreeMap<String, String>,
+    // whether the last training ran out of `max_train_seconds` before all its iterations
+    timed_out: bool,
+    // where the last training built its node histograms and how long that took
+    histogram_timings: DeviceTimings,
+    callbacks: Vec<Box<dyn Callback>>,
+    // built by the first training for `Params::num_threads` and kept for later ones
+    thread_pool: Option<Arc<ThreadPool>>,
+}
+
+impl Default for Booster {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Booster {
+    pub fn new() -> Self {
+        Self {
+            trees: vec![],
+            objective: Box::new(SquaredError),
+            objective_type: Some(ObjectiveType::SquaredError),
+            params: Params::default(),
+            num_outputs: 1,
+            base_scores: vec![0.0],
+            eval_history: EvalHistory::new(),
+            best_iteration: None,
+            average_output: false,
+            feature_names: None,
+            num_features: None,
+            crate_version: None,
+            metadata: BTreeMap::new(),
+            timed_out: false,
+            histogram_timings: DeviceTimings::default(),
+            callbacks: vec![],
+            thread_pool: None,
+        }
+    }
+
+    // callbacks are invoked in registration order around every iteration of later trainings
+    pub fn add_callback(&mut self, callback: Box<dyn Callback>) {
+        self.callbacks.push(callback);
+    }
+
+    pub fn fit(&mut self, features: &[Vec<f32>], labels: &[f32], params: &Params) -> Result<()> {
+        self.train(&Dataset::new(features.to_vec(), labels.to_vec()), params)
+    }
+
+    pub fn train(&mut self, dataset: &Dataset, params: &Params) -> Result<()> {
+        self.train_with_validation(dataset, params, &[])
+    }
+
+    // `valid_sets` are (name, dataset) pairs on which `params.metrics` are evaluated after
+    // every iteration alongside the training data, which is recorded as "training".
+    pub fn train_with_validation(
+        &mut self,
+        dataset: &Dataset,
+        params: &Params,
+        valid_sets: &[(&str, &Dataset)],
+    ) -> Result<()> {
+        self.train_with_objective(dataset, params, valid_sets, params.objective.create())?;
+        self.objective_type = Some(params.objective);
+        Ok(())
+    }
+
+    // Trains with a user supplied objective instead of `params.objective`, e.g. a
+    // `CustomObjective` wrapping a closure.
+    pub fn train_with_objective(
+        &mut self,
+        dataset: &Dataset,
+        params: &Params,
+        valid_sets: &[(&str, &Dataset)],
+        objective: Box<dyn Objective>,
+    ) -> Result<()> {
+        params.validate()?;
+
+        self.trees.clear();
+        self.best_iteration = None;
+        self.feature_names = dataset.feature_names().map(<[String]>::to_vec);
+        self.objective = objective;
+        self.objective_type = None;
+        self.objective.init(dataset);
+        self.num_outputs = self.objective.num_outputs();
+        // init scores already say where each row starts
+        self.base_scores = if params.boost_from_average && dataset.init_scores().is_none() {
+            let weights = training_weights(dataset, params);
+            self.objective
+                .base_scores(dataset.labels(), weights.as_deref())
+        } else {
+            vec![0.0; self.num_outputs]
+        };
+
+        self.boost(dataset, params, valid_sets, None)
+    }
+
+    pub fn continue_training(&mut self, dataset: &Dataset, params: &Params) -> Result<()> {
+        self.continue_training_with_validation(dataset, params, &[])
+    }
+
+    // Adds `params.num_iterations` rounds to the trees already in the model, which keeps its
+    // base scores. A model that stopped early continues from its best iteration. The
+    // learning rate schedule, callbacks and `eval_history` start over with the new rounds.
+    pub fn continue_training_with_validation(
+        &mut self,
+        dataset: &Dataset,
+        params: &Params,
+        valid_sets: &[(&str, &Dataset)],
+    ) -> Result<()> {
+        params.validate()?;
+        self.check_features(dataset.num_features(), dataset.feature_names())?;
+        if self.average_output || params.boosting_type == BoostingType::RandomForest {
+            return Err(Error::invalid_parameter(
+                "boosting_type",
+                "random forests can't be trained further",
+            ));
+        }
+        let objective = params.objective.create();
+        if objective.num_outputs() != self.num_outputs {
+            return Err(Error::invalid_parameter(
+                "objective",
+                format!(
+                    "{:?} has {} outputs, the model {}",
+                    params.objective,
+                    objective.num_outputs(),
+                    self.num_outputs
+                ),
+            ));
+        }
+
+        if let Some(best_iteration) = self.best_iteration.take() {
+            self.trees.truncate(best_iteration * self.num_outputs);
+        }
+        self.objective = objective;
+        self.objective_type = Some(params.objective);
+        self.objective.init(dataset);
+
+        self.boost(dataset, params, valid_sets, None)
+    }
+
+    // Picks a run that wrote checkpoints (see `Params::checkpoint_path`) back up after its
+    // last checkpoint and trains it to completion with the checkpoint's parameters. The
+    // dataset and validation sets have to be the ones the run started with; the result is
+    // then the same as if training had never stopped. The objective is rebuilt from
+    // `params.objective`, so runs with a custom objective can't be resumed.
+    pub fn resume_from_checkpoint(
+        &mut self,
+        path: impl AsRef<Path>,
+        dataset: &Dataset,
+        valid_sets: &[(&str, &Dataset)],
+    ) -> Result<()> {
+        let checkpoint = Checkpoint::load(path.as_ref())?;
+        let params = checkpoint.params;
+        params.validate()?;
+        // the restored bag indexes rows of the dataset, and early stopping carries on watching
+        // the first metric on the first validation set
+        if checkpoint.state.num_rows != dataset.num_rows() {
+            return Err(Error::invalid_data(format!(
+                "checkpoint was written training on {} rows, the dataset has {}",
+                checkpoint.state.num_rows,
+                dataset.num_rows()
+            )));
+        }
+        if checkpoint.state.early_stopping.is_some() {
+            let metric = params.metrics.first().map(|metric| metric.create());
+            let monitored = valid_sets
+                .first()
+                .zip(metric)
+                .is_some_and(|((name, _), metric)| {
+                    checkpoint.eval_history.get(name, metric.name()).is_some()
+                });
+            if !monitored {
+                return Err(Error::invalid_data(
+                    "checkpoint stops early on a validation set that isn't the first one given",
+                ));
+            }
+        }
+
+        self.objective = params.objective.create();
+        self.objective_type = Some(params.objective);
+        self.objective.init(dataset);
+        self.num_outputs = self.objective.num_outputs();
+        if checkpoint.num_outputs != self.num_outputs {
+            return Err(Error::invalid_data(format!(
+                "checkpoint has {} outputs, its objective {}",
+                checkpoint.num_outputs, self.num_outputs
+            )));
+        }
+        self.trees = checkpoint.trees;
+        self.base_scores = checkpoint.base_scores;
+        self.eval_history = checkpoint.eval_history;
+        self.best_iteration = None;
+        self.feature_names = dataset.feature_names().map(<[String]>::to_vec);
+
+        self.boost(dataset, &params, valid_sets, Some(checkpoint.state))
+    }
+
+    // The boosting loop, adding trees on top of the ones already in `self.trees`. `resume`
+    // is the state of a checkpointed run to carry on with instead of starting a new one.
+    fn boost(
+        &mut self,
+        dataset: &Dataset,
+        params: &Params,
+        valid_sets: &[(&str, &Dataset)],
+        resume: Option<TrainingState>,
+    ) -> Result<()> {
+        self.params = params.clone();
+        self.num_features = Some(dataset.num_features());
+        self.crate_version = Some(env!("CARGO_PKG_VERSION").to_string());
+        let labels = dataset.labels();
+        // the dataset's sample weights with class weights applied; metrics only use the former
+        let training_weights = training_weights(dataset, params);
+        let weights = training_weights.as_deref();
+
+        let metrics: Vec<_> = params
+            .metrics
+            .iter()
+            .map(|metric| metric.create())
+            .collect();
+        let mut state = match resume {
+            Some(state) => state,
+            None => {
+                self.eval_history = EvalHistory::new();
+                let mut rng = Rng::new(params.seed);
+                TrainingState {
+                    iteration: 0,
+                    previous_iterations: self.num_iterations(),
+                    builder_rng: rng.fork(),
+                    rng,
+                    num_rows: dataset.num_rows(),
+                    bag: sampling::weighted_rows(dataset.num_rows(), weights),
+                    early_stopping: match (
+                        params.early_stopping_rounds,
+                        metrics.first(),
+                        valid_sets.first(),
+                    ) {
+                        (Some(rounds), Some(metric), Some(_)) => {
+                            Some(EarlyStopping::new(rounds, metric.higher_is_better()))
+                        }
+                        _ => None,
+                    },
+                }
+            }
+        };
+        // Random forest trees are all fit to the same targets: the gradients at the initial
+        // scores, which stay in `initial_predictions`.
+        let random_forest = params.boosting_type == BoostingType::RandomForest;
+        self.average_output = random_forest;
+
+        let n_rows = dataset.num_rows();
+        if n_rows == 0 {
+            return Ok(());
+        }
+
+        // The bins only depend on the raw feature values, so every feature is binned once
+        // and every node starts from an empty copy of its histogram.
+        let mut binned =
+            BinnedDataset::new_with_params(dataset, params.max_bins, &params.dataset_params)
+                .with_categorical_features(&params.categorical_features)?;
+        if params.enable_bundle {
+            binned = binned.with_bundles(params.max_conflict_rate);
+        }
+        let binned = binned
+            .with_precision(params.histogram_precision)
+            .with_parallelism(params.histogram_parallelism);
+
+        // uploaded once, so every tree reuses the same GPU buffers
+        let gpu_dataset = match params.device {
+            Device::Gpu => GpuDataset::new(&binned, params.gpu_backend),
+            Device::Cpu => None,
+        };
+
+        let n_scores = n_rows * self.num_outputs;
+        let initial_predictions = self.initial_scores(dataset)?;
+        let mut predictions = initial_predictions.clone();
+        self.add_tree_scores(dataset, &mut predictions);
+        let mut gradients = vec![0.0; n_scores];
+        let mut hessians = vec![0.0; n_scores];
+        let mut builder = TreeBuilder::new(&binned, params)
+            .with_rng(state.builder_rng.clone())
+            .with_weights(weights)
+            .with_thread_pool(self.thread_pool(params.num_threads)?);
+        // without a usable GPU the histograms are built on the CPU
+        if let Some(gpu_dataset) = &gpu_dataset {
+            builder = builder.with_gpu(GpuContext::new(gpu_dataset));
+        }
+
+        let valid_initial_predictions: Vec<Vec<f32>> = valid_sets
+            .iter()
+            .map(|(_, valid)| self.initial_scores(valid))
+            .collect::<Result<_>>()?;
+        let mut valid_predictions = valid_initial_predictions.clone();
+        for ((_, valid), scores) in valid_sets.iter().zip(&mut valid_predictions) {
+            self.add_tree_scores(valid, scores);
+        }
+
+        let first_iteration = state.iteration;
+        let deadline = params
+            .max_train_seconds
+            .map(|seconds| Instant::now() + Duration::from_secs_f64(seconds.min(1e9)));
+        self.timed_out = false;
+
+        // taken out of self for the duration of the loop so callbacks can look at the booster
+        let mut callbacks = std::mem::take(&mut self.callbacks);
+
+        for iteration in first_iteration..params.num_iterations {
+            if self.run_callbacks(&mut callbacks, iteration, params, |callback, env| {
+                callback.before_iteration(env)
+            }) {
+                break;
+            }
+
+            let mut learning_rate = params.learning_rate_schedule.learning_rate(
+                params.learning_rate,
+                iteration,
+                params.num_iterations,
+            );
+            for callback in &mut callbacks {
+                learning_rate = callback.learning_rate(iteration, learning_rate);
+            }
+
+            if random_forest {
+                // no shrinkage either, the trees are averaged
+                learning_rate = 1.0;
+                if iteration == first_iteration {
+                    self.objective.compute_gradients(
+                        &initial_predictions,
+                        labels,
+                        &mut gradients,
+                        &mut hessians,
+                    );
+                }
+            } else {
+                self.objective.compute_gradients(
+                    &predictions,
+                    labels,
+                    &mut gradients,
+                    &mut hessians,
+                );
+            }
+
+            match params.sample_strategy {
+                SampleStrategy::Bagging => {
+                    if params.bagging_freq > 0
+                        && params.bagging_fraction < 1.0
+                        && iteration.is_multiple_of(params.bagging_freq)
+                    {
+                        state.bag = sampling::bagging(
+                            &mut state.rng,
+                            n_rows,
+                            weights,
+                            params.bagging_fraction,
+                        );
+                    }
+                }
+                // Like LightGBM, the first 1 / learning_rate iterations use every row while
+                // the gradients are still large everywhere.
+                SampleStrategy::Goss {
+                    top_rate,
+                    other_rate,
+                } => {
+                    if iteration as f32 >= 1.0 / params.learning_rate {
+                        state.bag = sampling::goss(
+                            &mut state.rng,
+                            &mut gradients,
+                            &mut hessians,
+                            weights,
+                            n_rows,
+                            top_rate,
+                            other_rate,
+                        );
+                    }
+                }
+            }
+
+            for k in 0..self.num_outputs {
+                let output = k * n_rows..(k + 1) * n_rows;
+                let output_predictions = if random_forest {
+                    &initial_predictions[output.clone()]
+                } else {
+                    &predictions[output.clone()]
+                };
+                let tree = builder
+                    .build_with_renewal(
+                        &gradients[output.clone()],
+                        &hessians[output.clone()],
+                        &state.bag,
+                        &|rows| {
+                            self.objective.renew_leaf_value(
+                                rows,
+                                output_predictions,
+                                labels,
+                                weights,
+                            )
+                        },
+                    )
+                    .with_shrinkage(learning_rate);
+
+                for (i, prediction) in predictions[output].iter_mut().enumerate() {
+                    *prediction += tree.predict_row(&dataset.row(i));
+                }
+                for ((_, valid), scores) in valid_sets.iter().zip(&mut valid_predictions) {
+                    let n_valid = valid.num_rows();
+                    let valid_output = &mut scores[k * n_valid..(k + 1) * n_valid];
+                    for (i, prediction) in valid_output.iter_mut().enumerate() {
+                        *prediction += tree.predict_row(&valid.row(i));
+                    }
+                }
+                self.trees.push(tree);
+            }
+
+            if !metrics.is_empty() {
+                self.record_metrics(
+                    &metrics,
+                    "training",
+                    &predictions,
+                    &initial_predictions,
+                    dataset,
+                );
+                for (((name, valid), scores), initial) in valid_sets
+                    .iter()
+                    .zip(&valid_predictions)
+                    .zip(&valid_initial_predictions)
+                {
+                    self.record_metrics(&metrics, name, scores, initial, valid);
+                }
+            }
+
+            if self.run_callbacks(&mut callbacks, iteration, params, |callback, env| {
+                callback.after_iteration(env)
+            }) {
+                break;
+            }
+
+            if let Some(early_stopping) = &mut state.early_stopping {
+                let score = self
+                    .eval_history
+                    .get(valid_sets[0].0, metrics[0].name())
+                    .and_then(|values| values.last().copied())
+                    .expect("monitored metric is recorded every iteration");
+                if early_stopping.should_stop(iteration, score) {
+                    break;
+                }
+            }
+
+            state.iteration = iteration + 1;
+            if let Some(path) = &params.checkpoint_path
+                && state.iteration.is_multiple_of(params.checkpoint_freq)
+            {
+                state.builder_rng = builder.rng().clone();
+                if let Err(err) = self.save_checkpoint(path, params, &state) {
+                    self.callbacks = callbacks;
+                    return Err(err);
+                }
+            }
+
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline)
+                && state.iteration < params.num_iterations
+            {
+                self.timed_out = true;
+                break;
+            }
+        }
+
+        self.callbacks = callbacks;
+        self.histogram_timings = *builder.timings();
+        if let Some(early_stopping) = &state.early_stopping {
+            self.best_iteration =
+                Some(state.previous_iterations + early_stopping.best_iteration + 1);
+        }
+        Ok(())
+    }
+
+    // A pool of `num_threads` threads, every core's when None, reused while the number of
+    // threads stays the same.
+    fn thread_pool(&mut self, num_threads: Option<usize>) -> Result<Arc<ThreadPool>> {
+        let num_threads = num_threads
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |cores| cores.get()));
+        if let Some(thread_pool) = &self.thread_pool
+            && thread_pool.current_num_threads() == num_threads
+        {
+            return Ok(thread_pool.clone());
+        }
+        let thread_pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .map_err(|err| Error::invalid_parameter("num_threads", err.to_string()))?;
+        let thread_pool = Arc::new(thread_pool);
+        self.thread_pool = Some(thread_pool.clone());
+        Ok(thread_pool)
+    }
+
+    fn save_checkpoint(&self, path: &Path, params: &Params, state: &TrainingState) -> Result<()> {
+        Checkpoint {
+            params: params.clone(),
+            trees: self.trees.clone(),
+            num_outputs: self.num_outputs,
+            base_scores: self.base_scores.clone(),
+            eval_history: self.eval_history.clone(),
+            state: state.clone(),
+        }
+        .save(path)
+    }
+
+    // true if any callback asked to stop
+    fn run_callbacks(
+        &self,
+        callbacks: &mut [Box<dyn Callback>],
+        iteration: usize,
+        params: &Params,
+        hook: impl Fn(&mut dyn Callback, &CallbackEnv) -> CallbackAction,
+    ) -> bool {
+        let env = CallbackEnv {
+            iteration,
+            num_iterations: params.num_iterations,
+            booster: self,
+        };
+        let mut stop = false;
+        for callback in callbacks.iter_mut() {
+            stop |= hook(callback.as_mut(), &env) == CallbackAction::Stop;
+        }
+        stop
+    }
+
+    // `initial` are the scores `raw` started from before the first tree
+    fn record_metrics(
+        &mut self,
+        metrics: &[Box<dyn EvalMetric>],
+        name: &str,
+        raw: &[f32],
+        initial: &[f32],
+        dataset: &Dataset,
+    ) {
+        let transformed = if self.average_output {
+            // only the trees are averaged, not the scores they were added to
+            let num_iterations = self.num_iterations() as f32;
+            let averaged: Vec<f32> = raw
+                .iter()
+                .zip(initial)
+                .map(|(score, initial)| initial + (score - initial) / num_iterations)
+                .collect();
+            self.transform_predictions(&averaged, dataset.num_rows())
+        } else {
+            self.transform_predictions(raw, dataset.num_rows())
+        };
+        for metric in metrics {
+            let value = metric.evaluate(&transformed, dataset);
+            self.eval_history.push(name, metric.name(), value);
+        }
+    }
+
+    // Applies the objective transform to raw scores of `n_rows` rows laid out output by
+    // output, keeping that layout.
+    fn transform_predictions(&self, raw: &[f32], n_rows: usize) -> Vec<f32> {
+        if self.num_outputs == 1 {
+            return raw
+                .iter()
+                .map(|&score| self.objective.transform(score))
+                .collect();
+        }
+
+        let mut transformed = vec![0.0; raw.len()];
+        let mut row_scores = vec![0.0; self.num_outputs];
+        for i in 0..n_rows {
+            for (k, score) in row_scores.iter_mut().enumerate() {
+                *score = raw[k * n_rows + i];
+            }
+            for (k, value) in self
+                .objective
+                .transform_scores(&row_scores)
+                .into_iter()
+                .enumerate()
+            {
+                transformed[k * n_rows + i] = value;
+            }
+        }
+        transformed
+    }
+
+    // The trained model without the training state, for `Model::save_json` or
+    // `Model::save_binary`.
+    pub fn to_model(&self) -> Model {
+        Model {
+            params: self.params.clone(),
+            objective: self.objective_type,
+            trees: self.trees.clone(),
+            num_outputs: self.num_outputs,
+            base_scores: self.base_scores.clone(),
+            best_iteration: self.best_iteration,
+            average_output: self.average_output,
+            feature_names: self.feature_names.clone(),
+            num_features: self.num_features,
+            crate_version: self.crate_version.clone(),
+            metadata: self.metadata.clone(),
+        }
+    }
+
+    // A booster predicting like the one `model` was taken from, which can also be trained
+    // further. Models with a user supplied objective give untransformed probabilities.
+    pub fn from_model(model: Model) -> Self {
+        Self {
+            objective: match model.objective {
+                Some(objective) => objective.create(),
+                None => Box::new(SquaredError),
+            },
+            objective_type: model.objective,
+            params: model.params,
+            trees: model.trees,
+            num_outputs: model.num_outputs,
+            base_scores: model.base_scores,
+            best_iteration: model.best_iteration,
+            average_output: model.average_output,
+            feature_names: model.feature_names,
+            num_features: model.num_features,
+            crate_version: model.crate_version,
+            metadata: model.metadata,
+            ..Self::new()
+        }
+    }
+
+    pub fn eval_history(&self) -> &EvalHistory {
+        &self.eval_history
+    }
+
+    // Prediction of a single-output model, its raw score mapped through the objective's
+    // transform, see `predict_proba`. Panics for multiclass models, use `predict_proba`
+    // there instead.
+    pub fn predict(&self, features: &[f32]) -> f32 {
+        assert_eq!(
+            self.num_outputs, 1,
+            "use predict_proba for multi-output models"
+        );
+        self.predict_proba(features)[0]
+    }
+
+    // one raw score per output
+    // Uses the trees up to the best iteration when training stopped early.
+    pub fn predict_raw(&self, features: &[f32]) -> Vec<f32> {
+        self.predict_raw_row(features)
+    }
+
+    // `predict_raw` on any row representation, e.g. a `SparseRow` or a dataset's `row`
+    pub fn predict_raw_row<R: FeatureRow + ?Sized>(&self, row: &R) -> Vec<f32> {
+        self.raw_scores(self.predicting_trees(), row)
+    }
+
+    // `predict_proba` for every row from the trees of the first `num_iterations` iterations
+    // only, whether or not training stopped early: `best_iteration` scores like the model,
+    // fewer iterations show what the later trees add.
+    pub fn predict_with_num_iterations(
+        &self,
+        rows: &[Vec<f32>],
+        num_iterations: usize,
+    ) -> Result<Vec<Vec<f32>>> {
+        if num_iterations > self.num_iterations() {
+            return Err(Error::invalid_parameter(
+                "num_iterations",
+                format!(
+                    "the model has {} iterations, not {num_iterations}",
+                    self.num_iterations()
+                ),
+            ));
+        }
+        for row in rows {
+            self.check_features(row.len(), None)?;
+        }
+        let trees = &self.trees[..num_iterations * self.num_outputs];
+        Ok(rows
+            .par_iter()
+            .map(|row| {
+                self.objective
+                    .transform_scores(&self.raw_scores(trees, row.as_slice()))
+            })
+            .collect())
+    }
+
+    // For every row, the index of the leaf it reaches in each tree prediction uses, see
+    // `Tree::leaf_index`. Rows reaching the same leaves get the same scores, so the indices
+    // can stand in for the rows, e.g. one-hot encoded as features of a linear model.
+    pub fn predict_leaf_indices(&self, rows: &[Vec<f32>]) -> Result<Vec<Vec<u32>>> {
+        for row in rows {
+            self.check_features(row.len(), None)?;
+        }
+        let trees = self.predicting_trees();
+        // leaf numbers of the nodes of every flat tree, worked out once for all rows
+        let ranks: Vec<Option<Vec<u32>>> = trees
+            .iter()
+            .map(|tree| tree.flat().map(FlatTree::leaf_ranks))
+            .collect();
+        Ok(rows
+            .par_iter()
+            .map(|row| {
+                let row = row.as_slice();
+                trees
+                    .iter()
+                    .zip(&ranks)
+                    .map(|(tree, ranks)| match (tree.flat(), ranks) {
+                        (Some(flat), Some(ranks)) => ranks[flat.leaf_node(row)],
+                        _ => tree.leaf_index(row) as u32,
+                    })
+                    .collect()
+            })
+            .collect())
+    }
+
+    // the raw scores of `row` from `trees`, which start at the first iteration
+    fn raw_scores<R: FeatureRow + ?Sized>(&self, trees: &[Tree], row: &R) -> Vec<f32> {
+        let mut scores = vec![0.0; self.num_outputs];
+        for (i, tree) in trees.iter().enumerate() {
+            scores[i % self.num_outputs] += tree.predict_row(row);
+        }
+        self.finish_scores(trees.len())(&mut scores);
+        scores
+    }
+
+    // `predict_raw` for every row of the row-major `features`, `num_features` values per
+    // row, laid out row by row. Rows are scored in parallel from a `FlatForest`, on a GPU
+    // of `backend` for `Device::Gpu` when there is one.
+    pub fn predict_raw_batch(
+        &self,
+        features: &[f32],
+        num_features: usize,
+        device: Device,
+        backend: Backend,
+    ) -> Vec<f32> {
+        assert!(
+            num_features > 0 && features.len().is_multiple_of(num_features),
+            "features must hold whole rows"
+        );
+        let num_outputs = self.num_outputs;
+        let trees = self.predicting_trees();
+        let forest = FlatForest::new(trees, num_outputs);
+        let gpu = match (&forest, device) {
+            (Some(forest), Device::Gpu) => GpuPredictor::new(forest, backend),
+            _ => None,
+        };
+        let mut scores = match gpu {
+            Some(gpu) => gpu.predict(features, num_features),
+            None => {
+                let mut scores = vec![0.0; features.len() / num_features * num_outputs];
+                match &forest {
+                    Some(forest) => scores
+                        .par_chunks_mut(ROWS_PER_TASK * num_outputs)
+                        .zip(features.par_chunks(ROWS_PER_TASK * num_features))
+                        .for_each(|(block_scores, block)| {
+                            forest.predict_block(block, num_features, block_scores)
+                        }),
+                    // linear leaves are only evaluated by walking the trees
+                    None => scores
+                        .par_chunks_mut(num_outputs)
+                        .zip(features.par_chunks(num_features))
+                        .for_each(|(row_scores, row)| {
+                            for (i, tree) in trees.iter().enumerate() {
+                                row_scores[i % num_outputs] += tree.predict_row(row);
+                            }
+                        }),
+                }
+                scores
+            }
+        };
+        let finish = self.finish_scores(trees.len());
+        scores.par_chunks_mut(num_outputs).for_each(finish);
+        scores
+    }
+
+    // `predict_proba` for every row, the rows split across a pool of `num_threads` threads,
+    // rayon's global one when None. The booster is `Send + Sync`, so threads serving
+    // predictions can share it instead of cloning it.
+    pub fn predict_batch(
+        &self,
+        rows: &[Vec<f32>],
+        num_threads: Option<usize>,
+    ) -> Result<Vec<Vec<f32>>> {
+        for row in rows {
+            self.check_features(row.len(), None)?;
+        }
+        let predict = || rows.par_iter().map(|row| self.predict_proba(row)).collect();
+        let Some(num_threads) = num_threads else {
+            return Ok(predict());
+        };
+        let thread_pool = match &self.thread_pool {
+            Some(thread_pool) if thread_pool.current_num_threads() == num_threads => {
+                thread_pool.clone()
+            }
+            _ => Arc::new(
+                ThreadPoolBuilder::new()
+                    .num_threads(num_threads)
+                    .build()
+                    .map_err(|err| Error::invalid_parameter("num_threads", err.to_string()))?,
+            ),
+        };
+        Ok(thread_pool.install(predict))
+    }
+
+    // the trees prediction uses: those up to the best iteration when training stopped early
+    fn predicting_trees(&self) -> &[Tree] {
+        match self.best_iteration {
+            Some(best_iteration) => &self.trees[..best_iteration * self.num_outputs],
+            None => &self.trees,
+        }
+    }
+
+    // Turns the summed outputs of the first `num_trees` trees for one row into its raw
+    // scores.
+    fn finish_scores(&self, num_trees: usize) -> impl Fn(&mut [f32]) + Sync + '_ {
+        let num_iterations =
+            (self.average_output && num_trees > 0).then(|| (num_trees / self.num_outputs) as f32);
+        let base_scores = &self.base_scores;
+        move |scores| {
+            if let Some(num_iterations) = num_iterations {
+                for score in scores.iter_mut() {
+                    *score /= num_iterations;
+                }
+            }
+            for (score, base_score) in scores.iter_mut().zip(base_scores) {
+                *score += base_score;
+            }
+        }
+    }
+
+    // Rust source of a `predict` function giving the same raw scores as `predict_raw`, see
+    // `codegen::rust_source`.
+    pub fn to_rust_source(&self) -> String {
+        codegen::rust_source(
+            self.predicting_trees(),
+            self.num_outputs,
+            &self.base_scores,
+            self.average_output,
+        )
+    }
+
+    // `predict_raw` on top of the row's init scores, one per output, for models trained on a
+    // dataset with init scores.
+    pub fn predict_raw_with_init_score(&self, features: &[f32], init_scores: &[f32]) -> Vec<f32> {
+        assert_eq!(
+            init_scores.len(),
+            self.num_outputs,
+            "there must be one init score per output"
+        );
+        let mut scores = self.predict_raw(features);
+        for (score, init) in scores.iter_mut().zip(init_scores) {
+            *score += init;
+        }
+        scores
+    }
+
+    // The raw scores mapped through the objective's transform, which undoes its link: the
+    // sigmoid for binary logistic, giving P(label = 1), the softmax for multiclass, giving
+    // one probability per class, exp for the log-link Poisson, gamma and Tweedie
+    // objectives. Others predict their raw scores. Models with a user supplied objective
+    // loaded from a file have no transform.
+    pub fn predict_proba(&self, features: &[f32]) -> Vec<f32> {
+        self.objective.transform_scores(&self.predict_raw(features))
+    }
+
+    pub fn feature_names(&self) -> Option<&[String]> {
+        self.feature_names.as_deref()
+    }
+
+    pub fn num_features(&self) -> Option<usize> {
+        self.num_features
+    }
+
+    pub fn crate_version(&self) -> Option<&str> {
+        self.crate_version.as_deref()
+    }
+
+    pub fn metadata(&self) -> &BTreeMap<String, String> {
+        &self.metadata
+    }
+
+    // Entries added here are saved with the model and survive training.
+    pub fn metadata_mut(&mut self) -> &mut BTreeMap<String, String> {
+        &mut self.metadata
+    }
+
+    // Checks that rows of `num_features` columns, named `feature_names`, are the ones the
+    // model was trained on: as many columns, with the same names in the same order when
+    // both have names. Models that don't record their columns accept any rows.
+    pub fn check_features(
+        &self,
+        num_features: usize,
+        feature_names: Option<&[String]>,
+    ) -> Result<()> {
+        model::check_features(
+            (self.num_features, self.feature_names.as_deref()),
+            num_features,
+            feature_names,
+        )
+    }
+
+    // `predict_proba` for every row of `dataset`, after checking its columns with
+    // `check_features`.
+    pub fn predict_dataset(&self, dataset: &Dataset) -> Result<Vec<Vec<f32>>> {
+        self.check_features(dataset.num_features(), dataset.feature_names())?;
+        Ok((0..dataset.num_rows())
+            .into_par_iter()
+            .map(|i| {
+                self.objective
+                    .transform_scores(&self.predict_raw_row(&dataset.row(i)))
+            })
+            .collect())
+    }
+
+    // scores per row: 1, or the number of classes for multiclass models
+    pub fn num_outputs(&self) -> usize {
+        self.num_outputs
+    }
+
+    pub fn num_trees(&self) -> usize {
+        self.trees.len()
+    }
+
+    pub fn num_iterations(&self) -> usize {
+        self.trees.len() / self.num_outputs
+    }
+
+    // Whether the last training stopped at `max_train_seconds`; `num_iterations` then
+    // tells how far it got.
+    pub fn timed_out(&self) -> bool {
+        self.timed_out
+    }
+
+    pub fn histogram_timings(&self) -> &DeviceTimings {
+        &self.histogram_timings
+    }
+
+    pub fn best_iteration(&self) -> Option<usize> {
+        self.best_iteration
+    }
+
+    pub fn base_scores(&self) -> &[f32] {
+        &self.base_scores
+    }
+
+    // Adds the raw outputs of all trees to `scores`, laid out output by output.
+    fn add_tree_scores(&self, dataset: &Dataset, scores: &mut [f32]) {
+        let n_rows = dataset.num_rows();
+        for (i, tree) in self.trees.iter().enumerate() {
+            let k = i % self.num_outputs;
+            for (row, score) in scores[k * n_rows..(k + 1) * n_rows].iter_mut().enumerate() {
+                *score += tree.predict_row(&dataset.row(row));
+            }
+        }
+    }
+
+    // The base scores plus the dataset's init scores, or only the former without them.
+    fn initial_scores(&self, dataset: &Dataset) -> Result<Vec<f32>> {
+        let n_rows = dataset.num_rows();
+        let n_scores = n_rows * self.num_outputs;
+        let mut scores = match dataset.init_scores() {
+            Some(init_scores) if init_scores.len() != n_scores => {
+                return Err(Error::invalid_data(format!(
+                    "expected {n_scores} init scores ({n_rows} rows, {} outputs), got {}",
+                    self.num_outputs,
+                    init_scores.len()
+                )));
+            }
+            Some(init_scores) => init_scores.to_vec(),
+            None => vec![0.0; n_scores],
+        };
+        for (k, base_score) in self.base_scores.iter().enumerate() {
+            for score in &mut scores[k * n_rows..(k + 1) * n_rows] {
+                *score += base_score;
+            }
+        }
+        Ok(scores)
+    }
+}
+
+// rows every parallel task of `Booster::predict_raw_batch` scores, a few blocks of
+// `FlatForest::predict_block`
+const ROWS_PER_TASK: usize = 256;
+
+// Everything the boosting loop carries from one iteration to the next besides the model
+// itself, kept in checkpoints so a resumed run continues exactly where it stopped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct TrainingState {
+    // number of iterations of this run done so far
+    iteration: usize,
+    // iterations the model already had when the run started, see `continue_training`
+    previous_iterations: usize,
+    // drives bagging and GOSS
+    rng: Rng,
+    // the tree builder's generator for feature sampling and extra trees
+    builder_rng: Rng,
+    // rows of the dataset the run trains on
+    num_rows: usize,
+    // rows the trees of the current iteration are grown on, all of them unless bagging
+    bag: Vec<usize>,
+    early_stopping: Option<EarlyStopping>,
+}
+
+impl TrainingState {
+    pub(crate) fn iteration(&self) -> usize {
+        self.iteration
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EarlyStopping {
+    rounds: usize,
+    higher_is_better: bool,
+    best_score: f32,
+    // 0-based iteration of best_score
+    best_iteration: usize,
+}
+
+impl EarlyStopping {
+    fn new(rounds: usize, higher_is_better: bool) -> Self {
+        Self {
+            rounds,
+            higher_is_better,
+            best_score: if higher_is_better {
+                f32::NEG_INFINITY
+            } else {
+                f32::INFINITY
+            },
+            best_iteration: 0,
+        }
+    }
+
+    fn should_stop(&mut self, iteration: usize, score: f32) -> bool {
+        let improved = if self.higher_is_better {
+            score > self.best_score
+        } else {
+            score < self.best_score
+        };
+        if improved {
+            self.best_score = score;
+            self.best_iteration = iteration;
+        }
+
+        iteration - self.best_iteration >= self.rounds
+    }
+}
+
+// `dataset.weights()` scaled by `params.class_weights`, or by the balanced class weights of
+// `params.is_unbalance`. None when all rows weigh the same.
+fn training_weights(dataset: &Dataset, params: &Params) -> Option<Vec<f32>> {
+    let labels = dataset.labels();
+    let class_weights = if params.is_unbalance {
+        let num_classes = params.objective.num_classes()?;
+        let mut counts = vec![0usize; num_classes];
+        for &label in labels {
+            counts[params.objective.class_of(label).min(num_classes - 1)] += 1;
+        }
+        let largest = counts.iter().copied().max().unwrap_or(0);
+        counts
+            .iter()
+            .map(|&count| {
+                if count > 0 {
+                    largest as f32 / count as f32
+                } else {
+                    1.0
+                }
+            })
+            .collect()
+    } else if !params.class_weights.is_empty() {
+        params.class_weights.clone()
+    } else {
+        return dataset.weights().map(|weights| weights.to_vec());
+    };
+
+    let class_weight = |label: f32| {
+        class_weights[params
+            .objective
+            .class_of(label)
+            .min(class_weights.len() - 1)]
+    };
+    Some(match dataset.weights() {
+        Some(weights) => labels
+            .iter()
+            .zip(weights)
+            .map(|(&label, weight)| weight * class_weight(label))
+            .collect(),
+        None => labels.iter().map(|&label| class_weight(label)).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::MetricType;
+    use crate::model::FeatureType;
+    use crate::objective::{CustomObjective, ObjectiveType};
+    use crate::schedule::LearningRateSchedule;
+    use crate::sparse::CsrMatrix;
+    use crate::tree::TreeNode;
+    use crate::tree_builder::GrowthStrategy;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_fit_step_function() {
+        let features: Vec<Vec<f32>> = (0..20).map(|i| vec![i as f32]).collect();
+        let labels: Vec<f32> = (0..20).map(|i| if i < 10 { 0.0 } else { 10.0 }).collect();
+
+        let params = Params {
+            num_iterations: 50,
+            learning_rate: 0.3,
+            num_leaves: 4,
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.fit(&features, &labels, &params).unwrap();
+
+        assert_eq!(booster.num_trees(), 50);
+        assert_abs_diff_eq!(booster.predict(&[2.0]), 0.0, epsilon = 1e-3);
+        assert_abs_diff_eq!(booster.predict(&[15.0]), 10.0, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_fit_learns_where_missing_values_go() {
+        // missing values behave like the low values, so they have to go left
+        let features: Vec<Vec<f32>> = (0..30)
+            .map(|i| vec![if i % 3 == 0 { f32::NAN } else { i as f32 }])
+            .collect();
+        let labels: Vec<f32> = (0..30)
+            .map(|i| if i % 3 == 0 || i < 15 { 0.0 } else { 10.0 })
+            .collect();
+
+        let params = Params {
+            num_iterations: 50,
+            learning_rate: 0.3,
+            num_leaves: 2,
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.fit(&features, &labels, &params).unwrap();
+
+        assert_abs_diff_eq!(booster.predict(&[f32::NAN]), 0.0, epsilon = 1e-3);
+        assert_abs_diff_eq!(booster.predict(&[2.0]), 0.0, epsilon = 1e-3);
+        assert_abs_diff_eq!(booster.predict(&[20.0]), 10.0, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_fit_reduces_training_error() {
+        let features: Vec<Vec<f32>> = (0..50).map(|i| vec![i as f32, (i % 7) as f32]).collect();
+        let labels: Vec<f32> = features.iter().map(|x| 2.0 * x[0] - x[1]).collect();
+
+        let mse = |booster: &Booster| {
+            features
+                .iter()
+                .zip(&labels)
+                .map(|(x, y)| (booster.predict(x) - y).powi(2))
+                .sum::<f32>()
+                / labels.len() as f32
+        };
+
+        let mut short = Booster::new();
+        short
+            .fit(
+                &features,
+                &labels,
+                &Params {
+                    num_iterations: 5,
+                    ..Params::default()
+                },
+            )
+            .unwrap();
+        let mut long = Booster::new();
+        long.fit(
+            &features,
+            &labels,
+            &Params {
+                num_iterations: 100,
+                ..Params::default()
+            },
+        )
+        .unwrap();
+
+        assert!(mse(&long) < mse(&short));
+        assert!(mse(&long) < 1.0);
+    }
+
+    #[test]
+    fn test_bagging_is_reproducible() {
+        let features: Vec<Vec<f32>> = (0..50).map(|i| vec![i as f32, (i % 7) as f32]).collect();
+        let labels: Vec<f32> = (0..50).map(|i| (i as f32 / 5.0).sin()).collect();
+
+        let params = |seed| Params {
+            num_iterations: 10,
+            bagging_fraction: 0.5,
+            bagging_freq: 1,
+            seed,
+            ..Params::default()
+        };
+        let fit = |params: &Params| {
+            let mut booster = Booster::new();
+            booster.fit(&features, &labels, params).unwrap();
+            features
+                .iter()
+                .map(|x| booster.predict(x))
+                .collect::<Vec<f32>>()
+        };
+
+        let first = fit(&params(3));
+        assert_eq!(first, fit(&params(3)));
+        assert_ne!(first, fit(&params(4)));
+
+        let unbagged = fit(&Params {
+            num_iterations: 10,
+            ..Params::default()
+        });
+        assert_ne!(first, unbagged);
+    }
+
+    #[test]
+    fn test_goss_still_fits() {
+        let features: Vec<Vec<f32>> = (0..100).map(|i| vec![i as f32]).collect();
+        let labels: Vec<f32> = (0..100).map(|i| if i < 50 { 0.0 } else { 10.0 }).collect();
+
+        let params = Params {
+            num_iterations: 30,
+            learning_rate: 0.3,
+            sample_strategy: SampleStrategy::Goss {
+                top_rate: 0.2,
+                other_rate: 0.1,
+            },
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.fit(&features, &labels, &params).unwrap();
+
+        assert_abs_diff_eq!(booster.predict(&[10.0]), 0.0, epsilon = 0.5);
+        assert_abs_diff_eq!(booster.predict(&[90.0]), 10.0, epsilon = 0.5);
+    }
+
+    #[test]
+    fn test_thread_count_does_not_change_the_model() {
+        let features: Vec<Vec<f32>> = (0..200)
+            .map(|i| (0..6).map(|j| ((i * (j + 3)) % 17) as f32).collect())
+            .collect();
+        let labels: Vec<f32> = features.iter().map(|x| x[0] - 2.0 * x[3] + x[5]).collect();
+
+        let predictions = |num_threads| {
+            let params = Params {
+                num_iterations: 10,
+                num_threads: Some(num_threads),
+                ..Params::default()
+            };
+            let mut booster = Booster::new();
+            booster.fit(&features, &labels, &params).unwrap();
+            features
+                .iter()
+                .map(|x| booster.predict(x))
+                .collect::<Vec<f32>>()
+        };
+        assert_eq!(predictions(1), predictions(4));
+    }
+
+    #[test]
+    fn test_gpu_device_fits_like_cpu() {
+        let features: Vec<Vec<f32>> = (0..200)
+            .map(|i| (0..6).map(|j| ((i * (j + 3)) % 17) as f32).collect())
+            .collect();
+        let labels: Vec<f32> = features.iter().map(|x| x[0] - 2.0 * x[3] + x[5]).collect();
+
+        let predictions = |device, gpu_backend| {
+            let params = Params {
+                num_iterations: 10,
+                device,
+                gpu_backend,
+                gpu_min_rows: 0,
+                ..Params::default()
+            };
+            let mut booster = Booster::new();
+            booster.fit(&features, &labels, &params).unwrap();
+            features
+                .iter()
+                .map(|x| booster.predict(x))
+                .collect::<Vec<f32>>()
+        };
+        // the GPU's sums only differ in rounding, and without one the CPU builds them
+        let cpu = predictions(Device::Cpu, Backend::Auto);
+        for backend in [Backend::Auto, Backend::Metal, Backend::Wgpu] {
+            for (gpu, cpu) in predictions(Device::Gpu, backend).into_iter().zip(&cpu) {
+                assert_abs_diff_eq!(gpu, cpu, epsilon = 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn test_small_nodes_are_built_on_the_cpu() {
+        let features: Vec<Vec<f32>> = (0..400)
+            .map(|i| (0..4).map(|j| ((i * (j + 5)) % 23) as f32).collect())
+            .collect();
+        let labels: Vec<f32> = features.iter().map(|x| x[1] - x[2] + 0.5 * x[3]).collect();
+
+        let fit = |device, growth_strategy| {
+            let params = Params {
+                num_iterations: 5,
+                device,
+                growth_strategy,
+                gpu_min_rows: 100,
+                ..Params::default()
+            };
+            let mut booster = Booster::new();
+            booster.fit(&features, &labels, &params).unwrap();
+            let predictions: Vec<f32> = features.iter().map(|x| booster.predict(x)).collect();
+            (predictions, *booster.histogram_timings())
+        };
+        for growth_strategy in [GrowthStrategy::LeafWise, GrowthStrategy::Oblivious] {
+            let (cpu, timings) = fit(Device::Cpu, growth_strategy);
+            assert_eq!(timings.gpu_nodes, 0);
+            assert!(timings.cpu_nodes > 0);
+
+            // the large nodes go to the GPU when there is one, the small ones to the CPU
+            let (hybrid, timings) = fit(Device::Gpu, growth_strategy);
+            assert!(timings.cpu_nodes > 0);
+            for (hybrid, cpu) in hybrid.into_iter().zip(&cpu) {
+                assert_abs_diff_eq!(hybrid, cpu, epsilon = 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn test_quantized_gradients_still_fit() {
+        let features: Vec<Vec<f32>> = (0..100).map(|i| vec![i as f32, (i % 7) as f32]).collect();
+        let labels: Vec<f32> = features.iter().map(|x| 0.1 * x[0] - x[1]).collect();
+
+        let params = Params {
+            num_iterations: 50,
+            learning_rate: 0.3,
+            use_quantized_grad: true,
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.fit(&features, &labels, &params).unwrap();
+
+        let mse = features
+            .iter()
+            .zip(&labels)
+            .map(|(x, y)| (booster.predict(x) - y).powi(2))
+            .sum::<f32>()
+            / labels.len() as f32;
+        assert!(mse < 0.1, "{mse}");
+    }
+
+    #[test]
+    fn test_random_forest_averages_trees_fit_to_targets() {
+        let features: Vec<Vec<f32>> = (0..40).map(|i| vec![i as f32]).collect();
+        let labels: Vec<f32> = (0..40).map(|i| if i < 20 { 1.0 } else { 5.0 }).collect();
+
+        let params = Params {
+            boosting_type: BoostingType::RandomForest,
+            num_iterations: 10,
+            bagging_fraction: 0.7,
+            bagging_freq: 1,
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.fit(&features, &labels, &params).unwrap();
+
+        // every tree predicts the targets on its own on top of the label mean, so neither
+        // summing nor shrinking
+        let base_score = booster.base_scores()[0];
+        assert_abs_diff_eq!(base_score, 3.0, epsilon = 1e-5);
+        for tree in &booster.trees {
+            assert_eq!(tree.shrinkage(), 1.0);
+            assert_abs_diff_eq!(base_score + tree.predict(&[5.0]), 1.0, epsilon = 1e-5);
+            assert_abs_diff_eq!(base_score + tree.predict(&[35.0]), 5.0, epsilon = 1e-5);
+        }
+        assert_abs_diff_eq!(booster.predict(&[5.0]), 1.0, epsilon = 1e-5);
+        assert_abs_diff_eq!(booster.predict(&[35.0]), 5.0, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn test_monotone_constraint_holds_on_noisy_data() {
+        // increasing overall, with a dip the unconstrained model follows
+        let features: Vec<Vec<f32>> = (0..60).map(|i| vec![i as f32, (i % 5) as f32]).collect();
+        let labels: Vec<f32> = (0..60)
+            .map(|i| i as f32 / 10.0 - if (20..30).contains(&i) { 4.0 } else { 0.0 })
+            .collect();
+
+        let predictions = |params: &Params| {
+            let mut booster = Booster::new();
+            booster.fit(&features, &labels, params).unwrap();
+            (0..60)
+                .map(|i| booster.predict(&[i as f32, 2.0]))
+                .collect::<Vec<f32>>()
+        };
+        let increasing = |values: &[f32]| values.windows(2).all(|w| w[1] >= w[0] - 1e-6);
+
+        let params = Params {
+            num_iterations: 30,
+            learning_rate: 0.3,
+            ..Params::default()
+        };
+        assert!(!increasing(&predictions(&params)));
+
+        let constrained = predictions(&Params {
+            monotone_constraints: vec![1, 0],
+            ..params
+        });
+        assert!(increasing(&constrained));
+        assert!(constrained[59] > constrained[0]);
+    }
+
+    #[test]
+    fn test_linear_tree_fits_piecewise_linear_data() {
+        let features: Vec<Vec<f32>> = (0..40).map(|i| vec![i as f32]).collect();
+        let labels: Vec<f32> = (0..40)
+            .map(|i| {
+                if i < 20 {
+                    i as f32
+                } else {
+                    40.0 - 2.0 * i as f32
+                }
+            })
+            .collect();
+
+        let rmse = |params: &Params| {
+            let mut booster = Booster::new();
+            booster.fit(&features, &labels, params).unwrap();
+            let predictions: Vec<f32> = features.iter().map(|x| booster.predict(x)).collect();
+            crate::metrics::rmse(&predictions, &labels)
+        };
+
+        let params = Params {
+            num_iterations: 5,
+            learning_rate: 1.0,
+            num_leaves: 2,
+            ..Params::default()
+        };
+        let constant = rmse(&params);
+        let linear = rmse(&Params {
+            linear_tree: true,
+            ..params
+        });
+
+        // the splits are still chosen for constant leaves, so the kink isn't hit exactly
+        assert!(linear < constant / 4.0);
+    }
+
+    #[test]
+    fn test_boost_from_average_starts_at_label_mean() {
+        let features: Vec<Vec<f32>> = (0..10).map(|i| vec![i as f32]).collect();
+        let labels: Vec<f32> = (0..10).map(|i| 100.0 + i as f32).collect();
+        let params = Params {
+            num_iterations: 3,
+            ..Params::default()
+        };
+
+        let mut booster = Booster::new();
+        booster.fit(&features, &labels, &params).unwrap();
+        assert_abs_diff_eq!(booster.base_scores()[0], 104.5, epsilon = 1e-4);
+        let from_average = crate::metrics::rmse(
+            &features
+                .iter()
+                .map(|x| booster.predict(x))
+                .collect::<Vec<_>>(),
+            &labels,
+        );
+
+        booster
+            .fit(
+                &features,
+                &labels,
+                &Params {
+                    boost_from_average: false,
+                    ..params
+                },
+            )
+            .unwrap();
+        assert_eq!(booster.base_scores(), &[0.0]);
+        let from_zero = crate::metrics::rmse(
+            &features
+                .iter()
+                .map(|x| booster.predict(x))
+                .collect::<Vec<_>>(),
+            &labels,
+        );
+
+        // three shrunken steps from zero are still far from labels around 100
+        assert!(from_average < 3.0);
+        assert!(from_zero > 50.0);
+    }
+
+    #[test]
+    fn test_is_unbalance_balances_the_classes() {
+        // one positive in twenty and nothing to tell them apart by
+        let features = vec![vec![0.0]; 20];
+        let labels: Vec<f32> = (0..20).map(|i| if i == 0 { 1.0 } else { 0.0 }).collect();
+        let params = Params {
+            objective: ObjectiveType::BinaryLogistic,
+            num_iterations: 20,
+            ..Params::default()
+        };
+        let probability = |params: &Params| {
+            let mut booster = Booster::new();
+            booster.fit(&features, &labels, params).unwrap();
+            booster.predict_proba(&[0.0])[0]
+        };
+
+        assert_abs_diff_eq!(probability(&params), 0.05, epsilon = 1e-3);
+        let unbalanced = Params {
+            is_unbalance: true,
+            ..params.clone()
+        };
+        assert_abs_diff_eq!(probability(&unbalanced), 0.5, epsilon = 1e-3);
+        let weighted = Params {
+            class_weights: vec![1.0, 3.0],
+            ..params
+        };
+        // 3 / (3 + 19)
+        assert_abs_diff_eq!(probability(&weighted), 3.0 / 22.0, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_sparse_dataset_trains_like_dense() {
+        // one-hot features plus a mostly zero numeric one
+        let rows: Vec<Vec<f32>> = (0..60)
+            .map(|i| {
+                let one_hot = (0..3).map(|j| (i % 3 == j) as u8 as f32);
+                one_hot
+                    .chain([if i % 4 == 0 { i as f32 } else { 0.0 }])
+                    .collect()
+            })
+            .collect();
+        let labels: Vec<f32> = rows
+            .iter()
+            .map(|x| 2.0 * x[1] - x[2] + 0.1 * x[3])
+            .collect();
+        let matrix = CsrMatrix::from_dense(&rows);
+        let params = Params {
+            num_iterations: 10,
+            num_leaves: 4,
+            ..Params::default()
+        };
+
+        let mut dense = Booster::new();
+        dense
+            .train(&Dataset::new(rows.clone(), labels.clone()), &params)
+            .unwrap();
+        let mut sparse = Booster::new();
+        let sparse_data = Dataset::from_csr(matrix.clone(), labels);
+        sparse.train(&sparse_data, &params).unwrap();
+
+        for (i, x) in rows.iter().enumerate() {
+            let expected = dense.predict(x);
+            assert_abs_diff_eq!(sparse.predict(x), expected, epsilon = 1e-4);
+            assert_eq!(sparse.predict_raw_row(&matrix.row(i))[0], sparse.predict(x));
+            assert_eq!(
+                sparse.predict_raw_row(&sparse_data.row(i))[0],
+                sparse.predict(x)
+            );
+        }
+    }
+
+    #[test]
+    fn test_feature_names_carry_into_the_model() {
+        let dataset = Dataset::new(vec![vec![0.0, 1.0], vec![1.0, 0.0]], vec![0.0, 1.0]);
+        let named = Dataset::new(dataset.features().to_vec(), dataset.labels().to_vec())
+            .with_feature_names(vec!["age".to_string(), "income".to_string()]);
+
+        let mut booster = Booster::new();
+        booster.train(&named, &Params::default()).unwrap();
+        assert_eq!(
+            booster.feature_names(),
+            Some(&["age".to_string(), "income".to_string()][..])
+        );
+        booster.train(&dataset, &Params::default()).unwrap();
+        assert_eq!(booster.feature_names(), None);
+    }
+
+    #[test]
+    fn test_prediction_inputs_are_checked_against_the_training_columns() {
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        let features = vec![vec![0.0, 1.0], vec![1.0, 0.0], vec![2.0, 2.0]];
+        let dataset = Dataset::new(features.clone(), vec![0.0, 1.0, 2.0])
+            .with_feature_names(names(&["age", "income"]));
+        let mut booster = Booster::new();
+        booster.train(&dataset, &Params::default()).unwrap();
+        booster
+            .metadata_mut()
+            .insert("owner".to_string(), "risk".to_string());
+        assert_eq!(booster.num_features(), Some(2));
+        assert_eq!(booster.crate_version(), Some(env!("CARGO_PKG_VERSION")));
+
+        let predictions = booster.predict_dataset(&dataset).unwrap();
+        assert_eq!(predictions, booster.predict_batch(&features, None).unwrap());
+        assert!(matches!(
+            booster.predict_batch(&[vec![0.0, 1.0, 2.0]], None),
+            Err(Error::InvalidData { .. })
+        ));
+        let swapped = Dataset::new(features.clone(), vec![0.0, 1.0, 2.0])
+            .with_feature_names(names(&["income", "age"]));
+        assert!(matches!(
+            booster.predict_dataset(&swapped),
+            Err(Error::InvalidData { .. })
+        ));
+        assert!(matches!(
+            booster.continue_training(&swapped, &Params::default()),
+            Err(Error::InvalidData { .. })
+        ));
+        // unnamed rows are only checked for their number
+        let unnamed = Dataset::new(features, vec![0.0, 1.0, 2.0]);
+        assert_eq!(booster.predict_dataset(&unnamed).unwrap(), predictions);
+
+        let model = booster.to_model();
+        assert_eq!(
+            model.feature_types(),
+            Some(vec![FeatureType::Numeric, FeatureType::Numeric])
+        );
+        let loaded = Booster::from_model(model);
+        assert_eq!(loaded.metadata(), booster.metadata());
+        assert_eq!(loaded.num_features(), Some(2));
+    }
+
+    #[test]
+    fn test_leaf_indices_pick_out_the_leaves_rows_score_at() {
+        let features: Vec<Vec<f32>> = (0..80)
+            .map(|i| vec![i as f32, if i % 6 == 0 { f32::NAN } else { (i % 9) as f32 }])
+            .collect();
+        let labels: Vec<f32> = (0..80).map(|i| (i % 4) as f32 + (i % 9) as f32).collect();
+        let params = Params {
+            num_iterations: 5,
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.fit(&features, &labels, &params).unwrap();
+
+        let indices = booster.predict_leaf_indices(&features).unwrap();
+        assert_eq!(indices.len(), features.len());
+        for (x, row_indices) in features.iter().zip(&indices) {
+            assert_eq!(row_indices.len(), booster.num_trees());
+            for (tree, &index) in booster.trees.iter().zip(row_indices) {
+                assert_eq!(index as usize, tree.leaf_index(x.as_slice()));
+                assert!((index as usize) < tree.num_leaves());
+            }
+        }
+        // rows at the same leaves score the same
+        for i in 0..features.len() {
+            for j in 0..i {
+                if indices[i] == indices[j] {
+                    assert_eq!(booster.predict(&features[i]), booster.predict(&features[j]));
+                }
+            }
+        }
+        assert!(indices.iter().any(|row_indices| row_indices != &indices[0]));
+    }
+
+    #[test]
+    fn test_max_train_seconds_stops_training() {
+        let features: Vec<Vec<f32>> = (0..20).map(|i| vec![i as f32]).collect();
+        let labels: Vec<f32> = (0..20).map(|i| i as f32).collect();
+        let dataset = Dataset::new(features, labels);
+
+        // a budget that is gone after the first iteration
+        let mut booster = Booster::new();
+        let params = Params::builder()
+            .num_iterations(50)
+            .max_train_seconds(1e-9)
+            .build()
+            .unwrap();
+        booster.train(&dataset, &params).unwrap();
+        assert!(booster.timed_out());
+        assert_eq!(booster.num_iterations(), 1);
+
+        let params = Params::builder()
+            .num_iterations(5)
+            .max_train_seconds(3600.0)
+            .build()
+            .unwrap();
+        booster.train(&dataset, &params).unwrap();
+        assert!(!booster.timed_out());
+        assert_eq!(booster.num_iterations(), 5);
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint_matches_uninterrupted_run() {
+        let features: Vec<Vec<f32>> = (0..50).map(|i| vec![i as f32, (i % 7) as f32]).collect();
+        let labels: Vec<f32> = (0..50).map(|i| (i as f32 / 5.0).sin()).collect();
+        let dataset = Dataset::new(features.clone(), labels);
+        let path = std::env::temp_dir().join(format!("metalgbm-{}.ckpt", std::process::id()));
+        // bagging and feature sampling make the result depend on the restored generators
+        let params = Params::builder()
+            .num_iterations(10)
+            .bagging_fraction(0.5)
+            .bagging_freq(1)
+            .feature_fraction_bynode(0.5)
+            .metric(MetricType::Rmse)
+            .seed(5)
+            .checkpoint(&path, 4)
+            .build()
+            .unwrap();
+
+        let mut uninterrupted = Booster::new();
+        uninterrupted.train(&dataset, &params).unwrap();
+        // the last checkpoint is the one after 8 iterations
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        assert_eq!(checkpoint.iteration(), 8);
+
+        let mut resumed = Booster::new();
+        resumed
+            .resume_from_checkpoint(&path, &dataset, &[])
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(resumed.num_iterations(), 10);
+        for x in &features {
+            assert_eq!(resumed.predict(x), uninterrupted.predict(x));
+        }
+        assert_eq!(
+            resumed.eval_history().get("training", "rmse"),
+            uninterrupted.eval_history().get("training", "rmse")
+        );
+
+        let err = Booster::new()
+            .resume_from_checkpoint(&path, &dataset, &[])
+            .unwrap_err();
+        assert!(matches!(err, Error::Io { .. }));
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint_rejects_other_data() {
+        let features: Vec<Vec<f32>> = (0..50).map(|i| vec![i as f32, (i % 7) as f32]).collect();
+        let labels: Vec<f32> = (0..50).map(|i| (i as f32 / 5.0).sin()).collect();
+        let dataset = Dataset::new(features.clone(), labels.clone());
+        let valid = Dataset::new(features[..20].to_vec(), labels[..20].to_vec());
+        let path = std::env::temp_dir().join(format!(
+            "metalgbm-{}-early-stopping.ckpt",
+            std::process::id()
+        ));
+        let params = Params::builder()
+            .num_iterations(10)
+            .early_stopping_rounds(50)
+            .metric(MetricType::Rmse)
+            .checkpoint(&path, 4)
+            .build()
+            .unwrap();
+        Booster::new()
+            .train_with_validation(&dataset, &params, &[("valid", &valid)])
+            .unwrap();
+
+        for valid_sets in [&[][..], &[("other", &valid)][..]] {
+            let err = Booster::new()
+                .resume_from_checkpoint(&path, &dataset, valid_sets)
+                .unwrap_err();
+            assert!(matches!(err, Error::InvalidData { .. }));
+        }
+        let err = Booster::new()
+            .resume_from_checkpoint(&path, &valid, &[("valid", &valid)])
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidData { .. }));
+
+        let mut resumed = Booster::new();
+        resumed
+            .resume_from_checkpoint(&path, &dataset, &[("valid", &valid)])
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(resumed.num_iterations(), 10);
+    }
+
+    #[test]
+    fn test_continue_training_matches_training_in_one_go() {
+        let features: Vec<Vec<f32>> = (0..30).map(|i| vec![i as f32]).collect();
+        let labels: Vec<f32> = (0..30).map(|i| ((i * 7) % 11) as f32).collect();
+        let dataset = Dataset::new(features.clone(), labels);
+        let params = Params {
+            num_iterations: 10,
+            num_leaves: 4,
+            ..Params::default()
+        };
+
+        let mut continued = Booster::new();
+        continued.train(&dataset, &params).unwrap();
+        let base_score = continued.base_scores()[0];
+        // the base score stays, even though the new data has a different mean
+        let shifted = Dataset::new(features.clone(), vec![100.0; 30]);
+        let mut refreshed = Booster::new();
+        refreshed.train(&dataset, &params).unwrap();
+        refreshed.continue_training(&shifted, &params).unwrap();
+        assert_eq!(refreshed.base_scores(), &[base_score]);
+        assert_eq!(refreshed.num_iterations(), 20);
+
+        continued.continue_training(&dataset, &params).unwrap();
+        let mut at_once = Booster::new();
+        at_once
+            .train(
+                &dataset,
+                &Params {
+                    num_iterations: 20,
+                    ..params.clone()
+                },
+            )
+            .unwrap();
+
+        assert_eq!(continued.num_trees(), 20);
+        for x in &features {
+            assert_abs_diff_eq!(continued.predict(x), at_once.predict(x), epsilon = 1e-4);
+        }
+
+        let multiclass = Params {
+            objective: ObjectiveType::Multiclass { num_class: 3 },
+            ..params
+        };
+        let err = continued
+            .continue_training(&dataset, &multiclass)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidParameter {
+                name: "objective",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_init_scores_are_an_offset() {
+        let features: Vec<Vec<f32>> = (0..20).map(|i| vec![(i % 4) as f32]).collect();
+        let init_scores: Vec<f32> = (0..20).map(|i| i as f32).collect();
+        // the trees only have to learn the +-1 on top of the offset
+        let labels: Vec<f32> = (0..20)
+            .map(|i| init_scores[i] + if i % 4 < 2 { -1.0 } else { 1.0 })
+            .collect();
+        let dataset =
+            Dataset::new(features.clone(), labels.clone()).with_init_scores(init_scores.clone());
+        let params = Params {
+            num_iterations: 30,
+            learning_rate: 0.5,
+            ..Params::default()
+        };
+
+        let mut booster = Booster::new();
+        booster.train(&dataset, &params).unwrap();
+
+        assert_abs_diff_eq!(booster.predict(&[0.0]), -1.0, epsilon = 1e-3);
+        assert_abs_diff_eq!(booster.predict(&[3.0]), 1.0, epsilon = 1e-3);
+        let with_offset = booster.predict_raw_with_init_score(&features[7], &[init_scores[7]]);
+        assert_abs_diff_eq!(with_offset[0], labels[7], epsilon = 1e-3);
+
+        let wrong_length = Dataset::new(features, labels).with_init_scores(vec![0.0; 3]);
+        let err = booster.train(&wrong_length, &params).unwrap_err();
+        assert!(matches!(err, Error::InvalidData { .. }));
+    }
+
+    #[test]
+    fn test_integer_weights_match_repeated_rows() {
+        let features: Vec<Vec<f32>> = (0..12).map(|i| vec![i as f32]).collect();
+        let labels: Vec<f32> = (0..12).map(|i| ((i * 5) % 7) as f32).collect();
+        let weights: Vec<f32> = (0..12).map(|i| (i % 3) as f32).collect();
+        let params = Params {
+            num_iterations: 5,
+            num_leaves: 4,
+            ..Params::default()
+        };
+
+        let mut weighted = Booster::new();
+        let dataset = Dataset::new(features.clone(), labels.clone()).with_weights(weights.clone());
+        weighted.train(&dataset, &params).unwrap();
+
+        let (mut repeated_features, mut repeated_labels) = (vec![], vec![]);
+        for i in 0..12 {
+            for _ in 0..weights[i] as usize {
+                repeated_features.push(features[i].clone());
+                repeated_labels.push(labels[i]);
+            }
+        }
+        let mut repeated = Booster::new();
+        repeated
+            .fit(&repeated_features, &repeated_labels, &params)
+            .unwrap();
+
+        for row in &repeated_features {
+            assert_abs_diff_eq!(weighted.predict(row), repeated.predict(row), epsilon = 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_oblivious_trees_fit_interaction() {
+        let features: Vec<Vec<f32>> = (0..64)
+            .map(|i| vec![(i % 8) as f32, (i / 8) as f32])
+            .collect();
+        let labels: Vec<f32> = features.iter().map(|x| x[0] * x[1]).collect();
+        let params = Params {
+            growth_strategy: GrowthStrategy::Oblivious,
+            num_iterations: 50,
+            learning_rate: 0.3,
+            num_leaves: 16,
+            ..Params::default()
+        };
+
+        let mut booster = Booster::new();
+        booster.fit(&features, &labels, &params).unwrap();
+
+        assert!(
+            booster
+                .trees
+                .iter()
+                .all(|tree| tree.lookup_table().is_some())
+        );
+        let predictions: Vec<f32> = features.iter().map(|x| booster.predict(x)).collect();
+        assert!(crate::metrics::rmse(&predictions, &labels) < 2.0);
+    }
+
+    #[test]
+    fn test_predict_sums_shrunken_trees() {
+        let mut booster = Booster::new();
+        booster.trees = vec![
+            Tree::new(Box::new(TreeNode::Leaf { value: 2.0 })).with_shrinkage(0.5),
+            Tree::new(Box::new(TreeNode::Leaf { value: 4.0 })).with_shrinkage(0.25),
+        ];
+
+        assert_abs_diff_eq!(booster.predict(&[0.0]), 2.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_learning_rate_schedule_sets_tree_shrinkage() {
+        let features: Vec<Vec<f32>> = (0..10).map(|i| vec![i as f32]).collect();
+        let labels: Vec<f32> = (0..10).map(|i| i as f32).collect();
+
+        let params = Params {
+            num_iterations: 3,
+            learning_rate: 0.3,
+            learning_rate_schedule: LearningRateSchedule::LinearDecay { final_rate: 0.1 },
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.fit(&features, &labels, &params).unwrap();
+
+        let shrinkages: Vec<f32> = booster.trees.iter().map(|tree| tree.shrinkage()).collect();
+        assert_abs_diff_eq!(shrinkages[..], [0.3, 0.2, 0.1][..], epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_fit_constant_feature_yields_mean_leaves() {
+        let features = vec![vec![1.0]; 4];
+        let labels = vec![1.0, 2.0, 3.0, 4.0];
+
+        let params = Params {
+            num_iterations: 1,
+            learning_rate: 1.0,
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.fit(&features, &labels, &params).unwrap();
+
+        assert_abs_diff_eq!(booster.predict(&[1.0]), 2.5, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_fit_binary_logistic() {
+        let features: Vec<Vec<f32>> = (0..40).map(|i| vec![i as f32]).collect();
+        let labels: Vec<f32> = (0..40).map(|i| if i < 20 { 0.0 } else { 1.0 }).collect();
+
+        let params = Params {
+            objective: ObjectiveType::BinaryLogistic,
+            num_iterations: 50,
+            learning_rate: 0.3,
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.fit(&features, &labels, &params).unwrap();
+
+        let raw = booster.predict_raw(&[5.0])[0];
+        assert!(raw < 0.0);
+        assert_abs_diff_eq!(booster.predict(&[5.0]), 1.0 / (1.0 + (-raw).exp()));
+        assert!(booster.predict_proba(&[5.0])[0] < 0.1);
+        assert!(booster.predict_proba(&[30.0])[0] > 0.9);
+        assert_eq!(
+            booster.predict_batch(&[vec![30.0]], None).unwrap()[0],
+            booster.predict_proba(&[30.0])
+        );
+    }
+
+    #[test]
+    fn test_fit_multiclass() {
+        let features: Vec<Vec<f32>> = (0..30).map(|i| vec![i as f32]).collect();
+        let labels: Vec<f32> = (0..30).map(|i| (i / 10) as f32).collect();
+
+        let params = Params {
+            objective: ObjectiveType::Multiclass { num_class: 3 },
+            num_iterations: 30,
+            learning_rate: 0.3,
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.fit(&features, &labels, &params).unwrap();
+
+        assert_eq!(booster.num_trees(), 90);
+        for (x, class) in [(3.0, 0), (15.0, 1), (27.0, 2)] {
+            let probs = booster.predict_proba(&[x]);
+            assert_eq!(probs.len(), 3);
+            assert_abs_diff_eq!(probs.iter().sum::<f32>(), 1.0, epsilon = 1e-5);
+            assert!(probs[class] > 0.8);
+        }
+    }
+
+    #[test]
+    fn test_predict_raw_batch_matches_predict_raw() {
+        // a categorical feature, and a numeric one that is sometimes missing
+        let features: Vec<Vec<f32>> = (0..120)
+            .map(|i| {
+                let numeric = if i % 7 == 0 { f32::NAN } else { i as f32 };
+                vec![(i % 5) as f32, numeric]
+            })
+            .collect();
+        let labels: Vec<f32> = (0..120)
+            .map(|i| ((i / 40 + usize::from(i % 5 == 3)) % 3) as f32)
+            .collect();
+        let params = Params {
+            objective: ObjectiveType::Multiclass { num_class: 3 },
+            num_iterations: 10,
+            categorical_features: vec![0],
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.fit(&features, &labels, &params).unwrap();
+
+        let flat: Vec<f32> = features.concat();
+        let expected: Vec<f32> = features
+            .iter()
+            .flat_map(|x| booster.predict_raw(x))
+            .collect();
+        assert_eq!(
+            booster.predict_raw_batch(&flat, 2, Device::Cpu, Backend::Auto),
+            expected
+        );
+        // the same sums, on a GPU or on the CPU without one
+        for backend in [Backend::Auto, Backend::Metal, Backend::Wgpu] {
+            for (gpu, cpu) in booster
+                .predict_raw_batch(&flat, 2, Device::Gpu, backend)
+                .into_iter()
+                .zip(&expected)
+            {
+                assert_abs_diff_eq!(gpu, cpu, epsilon = 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_predict_batch_from_shared_booster() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Booster>();
+        assert_send_sync::<Tree>();
+
+        let features: Vec<Vec<f32>> = (0..100).map(|i| vec![i as f32, (i % 9) as f32]).collect();
+        let labels: Vec<f32> = features.iter().map(|x| x[0] - 3.0 * x[1]).collect();
+        let params = Params {
+            num_iterations: 10,
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.fit(&features, &labels, &params).unwrap();
+        let expected: Vec<Vec<f32>> = features.iter().map(|x| booster.predict_raw(x)).collect();
+
+        // several threads predict from the same booster, each on its own pool too
+        let booster = &booster;
+        std::thread::scope(|scope| {
+            for num_threads in [None, Some(1), Some(3)] {
+                let features = &features;
+                let expected = &expected;
+                scope.spawn(move || {
+                    assert_eq!(
+                        booster.predict_batch(features, num_threads).unwrap(),
+                        *expected
+                    );
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn test_train_lambdarank_improves_ndcg() {
+        // relevance grows with the feature within every query
+        let features: Vec<Vec<f32>> = (0..40).map(|i| vec![((i * 7) % 10) as f32]).collect();
+        let labels: Vec<f32> = features
+            .iter()
+            .map(|x| (x[0] / 3.0).floor().min(2.0))
+            .collect();
+        let dataset = Dataset::new(features.clone(), labels.clone()).with_groups(&[10, 10, 10, 10]);
+
+        let params = Params {
+            objective: ObjectiveType::LambdaRank,
+            num_iterations: 20,
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.train(&dataset, &params).unwrap();
+
+        let scores: Vec<f32> = features.iter().map(|x| booster.predict(x)).collect();
+        let boundaries = dataset.query_boundaries().unwrap();
+        let ndcg = crate::ranking::mean_ndcg_at(&scores, &labels, boundaries, 5);
+        let baseline = crate::ranking::mean_ndcg_at(&[0.0; 40], &labels, boundaries, 5);
+
+        assert!(ndcg > baseline);
+        assert_abs_diff_eq!(ndcg, 1.0, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_fit_quantile_brackets_median() {
+        // labels are spread uniformly between x and x + 10
+        let features: Vec<Vec<f32>> = (0..200).map(|i| vec![(i / 20) as f32]).collect();
+        let labels: Vec<f32> = (0..200)
+            .map(|i| (i / 20) as f32 + (i % 20) as f32 / 2.0)
+            .collect();
+
+        let quantile = |alpha: f32| {
+            let params = Params {
+                objective: ObjectiveType::Quantile { alpha },
+                num_iterations: 100,
+                learning_rate: 0.2,
+                ..Params::default()
+            };
+            let mut booster = Booster::new();
+            booster.fit(&features, &labels, &params).unwrap();
+            booster.predict(&[4.0])
+        };
+
+        let low = quantile(0.1);
+        let median = quantile(0.5);
+        let high = quantile(0.9);
+
+        assert!(low < median && median < high);
+        assert_abs_diff_eq!(median, 4.0 + 4.75, epsilon = 0.3);
+        assert_abs_diff_eq!(low, 4.0 + 0.95, epsilon = 0.5);
+        assert_abs_diff_eq!(high, 4.0 + 8.55, epsilon = 0.5);
+    }
+
+    #[test]
+    fn test_fit_huber_resists_outliers() {
+        let features: Vec<Vec<f32>> = (0..50).map(|i| vec![(i % 5) as f32]).collect();
+        let mut labels: Vec<f32> = vec![1.0; 50];
+        labels[0] = 1000.0;
+
+        let fit = |objective| {
+            let params = Params {
+                objective,
+                num_iterations: 50,
+                learning_rate: 0.3,
+                num_leaves: 2,
+                ..Params::default()
+            };
+            let mut booster = Booster::new();
+            booster.fit(&features, &labels, &params).unwrap();
+            booster.predict(&[0.0])
+        };
+
+        let squared = fit(ObjectiveType::SquaredError);
+        let huber = fit(ObjectiveType::Huber { delta: 1.0 });
+        let fair = fit(ObjectiveType::Fair { c: 1.0 });
+
+        assert!(squared > 50.0);
+        assert!((huber - 1.0).abs() < (squared - 1.0).abs());
+        assert!((fair - 1.0).abs() < (squared - 1.0).abs());
+    }
+
+    #[test]
+    fn test_fit_log_link_objectives_recover_means() {
+        // mean count is 1 for x < 2 and 5 for x >= 2; Tweedie also sees exact zeros
+        let features: Vec<Vec<f32>> = (0..80).map(|i| vec![(i % 4) as f32]).collect();
+        let labels: Vec<f32> = (0..80)
+            .map(|i| {
+                let noise = if (i / 4) % 2 == 0 { -1.0 } else { 1.0 };
+                if i % 4 < 2 { 1.0 + noise } else { 5.0 + noise }
+            })
+            .collect();
+
+        for objective in [
+            ObjectiveType::Poisson {
+                max_delta_step: 0.7,
+            },
+            ObjectiveType::Tweedie {
+                variance_power: 1.5,
+            },
+        ] {
+            let params = Params {
+                objective,
+                num_iterations: 200,
+                learning_rate: 0.1,
+                ..Params::default()
+            };
+            let mut booster = Booster::new();
+            booster.fit(&features, &labels, &params).unwrap();
+
+            assert_abs_diff_eq!(booster.predict(&[0.0]), 1.0, epsilon = 0.05);
+            assert_abs_diff_eq!(booster.predict(&[3.0]), 5.0, epsilon = 0.05);
+            assert_abs_diff_eq!(booster.predict_raw(&[3.0])[0], 5.0f32.ln(), epsilon = 0.01);
+        }
+    }
+
+    #[test]
+    fn test_train_with_custom_objective_matches_builtin() {
+        let features: Vec<Vec<f32>> = (0..30).map(|i| vec![i as f32]).collect();
+        let labels: Vec<f32> = (0..30).map(|i| (i % 10) as f32).collect();
+        let dataset = Dataset::new(features.clone(), labels);
+        // custom objectives have no base score to start from
+        let params = Params {
+            num_iterations: 10,
+            boost_from_average: false,
+            ..Params::default()
+        };
+
+        let mut builtin = Booster::new();
+        builtin.train(&dataset, &params).unwrap();
+
+        let squared_error = CustomObjective::new(|preds: &[f32], labels: &[f32]| {
+            let gradients = preds.iter().zip(labels).map(|(p, y)| p - y).collect();
+            (gradients, vec![1.0; preds.len()])
+        });
+        let mut custom = Booster::new();
+        custom
+            .train_with_objective(&dataset, &params, &[], Box::new(squared_error))
+            .unwrap();
+
+        for x in &features {
+            assert_abs_diff_eq!(custom.predict(x), builtin.predict(x), epsilon = 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_eval_history_records_training_and_validation() {
+        let features: Vec<Vec<f32>> = (0..30).map(|i| vec![i as f32]).collect();
+        let labels: Vec<f32> = (0..30).map(|i| (i % 10) as f32).collect();
+        let dataset = Dataset::new(features, labels);
+        let valid = Dataset::new(vec![vec![3.5], vec![12.5]], vec![3.0, 2.0]);
+
+        let params = Params {
+            num_iterations: 20,
+            metrics: vec![MetricType::Rmse, MetricType::R2],
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster
+            .train_with_validation(&dataset, &params, &[("valid", &valid)])
+            .unwrap();
+
+        let history = booster.eval_history();
+        assert_eq!(history.entries().len(), 4);
+
+        let rmse = history.get("training", "rmse").unwrap();
+        assert_eq!(rmse.len(), 20);
+        assert!(rmse.windows(2).all(|w| w[1] <= w[0]));
+        let r2 = history.get("training", "r2").unwrap();
+        assert!(r2[19] > r2[0]);
+
+        let valid_rmse = history.get("valid", "rmse").unwrap();
+        assert_eq!(valid_rmse.len(), 20);
+        let expected = crate::metrics::rmse(
+            &[booster.predict(&[3.5]), booster.predict(&[12.5])],
+            valid.labels(),
+        );
+        assert_abs_diff_eq!(valid_rmse[19], expected, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn test_early_stopping_on_validation_metric() {
+        // the training labels are pure noise relative to the validation labels, so the
+        // validation error is best after very few iterations
+        let features: Vec<Vec<f32>> = (0..40).map(|i| vec![i as f32]).collect();
+        let labels: Vec<f32> = (0..40)
+            .map(|i| if i % 2 == 0 { 10.0 } else { 0.0 })
+            .collect();
+        let dataset = Dataset::new(features, labels);
+        let valid = Dataset::new((0..40).map(|i| vec![i as f32]).collect(), vec![5.0; 40]);
+
+        let params = Params {
+            num_iterations: 200,
+            learning_rate: 0.3,
+            metrics: vec![MetricType::Rmse],
+            early_stopping_rounds: Some(5),
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster
+            .train_with_validation(&dataset, &params, &[("valid", &valid)])
+            .unwrap();
+
+        let best_iteration = booster.best_iteration().unwrap();
+        assert_eq!(booster.num_iterations(), best_iteration + 5);
+        assert!(booster.num_iterations() < 200);
+
+        // prediction stops at the best iteration
+        let valid_rmse = booster.eval_history().get("valid", "rmse").unwrap();
+        let predictions: Vec<f32> = valid
+            .features()
+            .iter()
+            .map(|x| booster.predict(x))
+            .collect();
+        assert_abs_diff_eq!(
+            crate::metrics::rmse(&predictions, valid.labels()),
+            valid_rmse[best_iteration - 1],
+            epsilon = 1e-5
+        );
+    }
+
+    #[test]
+    fn test_early_stopping_requires_validation_set() {
+        let features: Vec<Vec<f32>> = (0..10).map(|i| vec![i as f32]).collect();
+        let labels: Vec<f32> = (0..10).map(|i| i as f32).collect();
+
+        let params = Params {
+            num_iterations: 10,
+            metrics: vec![MetricType::Rmse],
+            early_stopping_rounds: Some(1),
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.fit(&features, &labels, &params).unwrap();
+
+        assert_eq!(booster.best_iteration(), None);
+        assert_eq!(booster.num_iterations(), 10);
+    }
+}
@@ -0,0 +1,153 @@
+use crate::booster::Booster;
+
+// What a callback sees of the training run. `booster` holds the trees and evaluation
+// history built so far.
+pub struct CallbackEnv<'a> {
+    // 0-based index of the current boosting iteration
+    pub iteration: usize,
+    // the number of iterations training was asked for
+    pub num_iterations: usize,
+    pub booster: &'a Booster,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallbackAction {
+    Continue,
+    Stop,
+}
+
+// Hooks into the training loop, registered with `Booster::add_callback`. Returning
+// `CallbackAction::Stop` ends training after the current step; the trees built so far are
+// kept. Like objectives they are `Send + Sync`, since the booster holds them.
+pub trait Callback: Send + Sync {
+    // Learning rate for `iteration`, given the rate from the schedule (or from earlier
+    // callbacks). The trees of the iteration keep whatever rate is returned.
+    fn learning_rate(&mut self, _iteration: usize, learning_rate: f32) -> f32 {
+        learning_rate
+    }
+
+    fn before_iteration(&mut self, _env: &CallbackEnv) -> CallbackAction {
+        CallbackAction::Continue
+    }
+
+    // called once the iteration's trees are added and its metrics are recorded
+    fn after_iteration(&mut self, _env: &CallbackEnv) -> CallbackAction {
+        CallbackAction::Continue
+    }
+}
+
+// Prints the latest value of every recorded metric each `period` iterations.
+pub struct LogEvaluation {
+    period: usize,
+}
+
+impl LogEvaluation {
+    pub fn new(period: usize) -> Self {
+        Self {
+            period: period.max(1),
+        }
+    }
+}
+
+impl Callback for LogEvaluation {
+    fn after_iteration(&mut self, env: &CallbackEnv) -> CallbackAction {
+        if (env.iteration + 1).is_multiple_of(self.period)
+            || env.iteration + 1 == env.num_iterations
+        {
+            let results: Vec<String> = env
+                .booster
+                .eval_history()
+                .entries()
+                .iter()
+                .filter_map(|entry| {
+                    let value = entry.values.last()?;
+                    Some(format!("{}'s {}: {}", entry.dataset, entry.metric, value))
+                })
+                .collect();
+            println!("[{}]\t{}", env.iteration + 1, results.join("\t"));
+        }
+        CallbackAction::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::Params;
+
+    struct StopAfter {
+        iterations: usize,
+    }
+
+    impl Callback for StopAfter {
+        fn after_iteration(&mut self, env: &CallbackEnv) -> CallbackAction {
+            assert_eq!(env.booster.num_iterations(), env.iteration + 1);
+
+            if env.iteration + 1 >= self.iterations {
+                CallbackAction::Stop
+            } else {
+                CallbackAction::Continue
+            }
+        }
+    }
+
+    #[test]
+    fn test_callback_can_stop_training() {
+        let features: Vec<Vec<f32>> = (0..10).map(|i| vec![i as f32]).collect();
+        let labels: Vec<f32> = (0..10).map(|i| i as f32).collect();
+
+        let mut booster = Booster::new();
+        booster.add_callback(Box::new(StopAfter { iterations: 3 }));
+        booster.fit(&features, &labels, &Params::default()).unwrap();
+
+        assert_eq!(booster.num_iterations(), 3);
+    }
+
+    #[test]
+    fn test_callback_overrides_learning_rate() {
+        struct HalveEachIteration;
+        impl Callback for HalveEachIteration {
+            fn learning_rate(&mut self, iteration: usize, learning_rate: f32) -> f32 {
+                learning_rate / 2f32.powi(iteration as i32)
+            }
+        }
+
+        // a single constant feature: every tree is one leaf predicting the residual mean
+        let features = vec![vec![0.0]; 4];
+        let labels = vec![8.0; 4];
+        let params = Params {
+            num_iterations: 2,
+            learning_rate: 0.5,
+            boost_from_average: false,
+            ..Params::default()
+        };
+
+        let mut booster = Booster::new();
+        booster.add_callback(Box::new(HalveEachIteration));
+        booster.fit(&features, &labels, &params).unwrap();
+
+        // 0.5 * 8 on the first iteration, then 0.25 * (8 - 4)
+        assert_eq!(booster.predict(&[0.0]), 5.0);
+    }
+
+    #[test]
+    fn test_before_iteration_stop_skips_the_iteration() {
+        struct StopImmediately;
+        impl Callback for StopImmediately {
+            fn before_iteration(&mut self, env: &CallbackEnv) -> CallbackAction {
+                assert_eq!(env.num_iterations, 100);
+                CallbackAction::Stop
+            }
+        }
+
+        let features: Vec<Vec<f32>> = (0..10).map(|i| vec![i as f32]).collect();
+        let labels: Vec<f32> = (0..10).map(|i| i as f32).collect();
+
+        let mut booster = Booster::new();
+        booster.add_callback(Box::new(StopImmediately));
+        booster.add_callback(Box::new(LogEvaluation::new(10)));
+        booster.fit(&features, &labels, &Params::default()).unwrap();
+
+        assert_eq!(booster.num_iterations(), 0);
+    }
+}
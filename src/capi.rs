@@ -0,0 +1,350 @@
+// A C API for embedding the scorer in C, C++, Go and other services, declared in
+// `include/metalgbm.h`. The names and conventions follow LightGBM's C API: functions
+// return 0 on success and -1 on failure, after which `MGBM_GetLastError` describes the
+// failure, and boosters are opaque handles freed with `MGBM_BoosterFree`. Callers pass
+// valid pointers to buffers of the sizes the header gives; a handle may be shared by
+// threads that predict at the same time. Panics are caught and reported like errors
+// instead of unwinding into C.
+
+#![allow(non_snake_case, clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char, c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::slice;
+
+use rayon::prelude::*;
+
+use crate::booster::Booster;
+use crate::error::{Error, Result};
+use crate::gpu::{Backend, Device};
+use crate::model::Model;
+
+// `predict_type`s, as in LightGBM
+pub const C_API_PREDICT_NORMAL: c_int = 0;
+pub const C_API_PREDICT_RAW_SCORE: c_int = 1;
+
+// a booster behind an opaque pointer
+pub type BoosterHandle = *mut c_void;
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+// the description of the last failure on this thread, valid until the next call fails
+#[unsafe(no_mangle)]
+pub extern "C" fn MGBM_GetLastError() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+// Reads a model saved by `Model::save_binary` (or `to_bytes`) from `len` bytes at `data`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn MGBM_BoosterLoadModelFromBytes(
+    data: *const c_void,
+    len: usize,
+    out: *mut BoosterHandle,
+) -> c_int {
+    call(|| {
+        check_not_null(data, "data")?;
+        check_not_null(out, "out")?;
+        let bytes = unsafe { slice::from_raw_parts(data.cast::<u8>(), len) };
+        let booster = Booster::from_model(Model::from_bytes(bytes)?);
+        unsafe { *out = Box::into_raw(Box::new(booster)).cast() };
+        Ok(())
+    })
+}
+
+// Reads a model in LightGBM's text format, see `Model::from_lightgbm_str`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn MGBM_BoosterLoadModelFromString(
+    model_str: *const c_char,
+    out: *mut BoosterHandle,
+) -> c_int {
+    call(|| {
+        check_not_null(model_str, "model_str")?;
+        check_not_null(out, "out")?;
+        let text = unsafe { CStr::from_ptr(model_str) }
+            .to_str()
+            .map_err(|err| Error::invalid_parameter("model_str", err.to_string()))?;
+        let booster = Booster::from_model(Model::from_lightgbm_str(text)?);
+        unsafe { *out = Box::into_raw(Box::new(booster)).cast() };
+        Ok(())
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn MGBM_BoosterFree(handle: BoosterHandle) -> c_int {
+    call(|| {
+        if !handle.is_null() {
+            drop(unsafe { Box::from_raw(handle.cast::<Booster>()) });
+        }
+        Ok(())
+    })
+}
+
+// the scores per row, the number of classes of multiclass models and 1 otherwise
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn MGBM_BoosterGetNumClasses(
+    handle: BoosterHandle,
+    out_len: *mut c_int,
+) -> c_int {
+    call(|| {
+        let booster = unsafe { booster(handle) }?;
+        check_not_null(out_len, "out_len")?;
+        unsafe { *out_len = booster.num_outputs() as c_int };
+        Ok(())
+    })
+}
+
+// the columns of the rows the model was trained on, -1 for models that don't record them
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn MGBM_BoosterGetNumFeature(
+    handle: BoosterHandle,
+    out_len: *mut c_int,
+) -> c_int {
+    call(|| {
+        let booster = unsafe { booster(handle) }?;
+        check_not_null(out_len, "out_len")?;
+        let num_features = booster.num_features().map_or(-1, |n| n as c_int);
+        unsafe { *out_len = num_features };
+        Ok(())
+    })
+}
+
+// Scores the `ncol` features at `data`, NaN for missing values, writing one score per
+// class to `out_result` and their number to `out_len`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn MGBM_BoosterPredictForMatSingleRow(
+    handle: BoosterHandle,
+    data: *const f32,
+    ncol: i32,
+    predict_type: c_int,
+    out_len: *mut i64,
+    out_result: *mut f32,
+) -> c_int {
+    unsafe { MGBM_BoosterPredictForMat(handle, data, 1, ncol, predict_type, out_len, out_result) }
+}
+
+// Scores the `nrow` rows of `ncol` features of the row-major matrix at `data`, writing
+// `nrow` * classes scores, row by row, to `out_result` and their number to `out_len`.
+// Rows are scored in parallel.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn MGBM_BoosterPredictForMat(
+    handle: BoosterHandle,
+    data: *const f32,
+    nrow: i32,
+    ncol: i32,
+    predict_type: c_int,
+    out_len: *mut i64,
+    out_result: *mut f32,
+) -> c_int {
+    call(|| {
+        let booster = unsafe { booster(handle) }?;
+        for (pointer, name) in [(data.cast(), "data"), (out_result.cast(), "out_result")] {
+            check_not_null::<c_void>(pointer, name)?;
+        }
+        check_not_null(out_len, "out_len")?;
+        let nrow = usize::try_from(nrow)
+            .map_err(|_| Error::invalid_parameter("nrow", "must not be negative"))?;
+        let ncol = usize::try_from(ncol)
+            .ok()
+            .filter(|&ncol| ncol > 0)
+            .ok_or_else(|| Error::invalid_parameter("ncol", "must be positive"))?;
+        booster.check_features(ncol, None)?;
+
+        let features = unsafe { slice::from_raw_parts(data, nrow * ncol) };
+        let num_outputs = booster.num_outputs();
+        let out = unsafe { slice::from_raw_parts_mut(out_result, nrow * num_outputs) };
+        match predict_type {
+            C_API_PREDICT_NORMAL => out
+                .par_chunks_mut(num_outputs)
+                .zip(features.par_chunks(ncol))
+                .for_each(|(scores, row)| scores.copy_from_slice(&booster.predict_proba(row))),
+            C_API_PREDICT_RAW_SCORE => out.copy_from_slice(&booster.predict_raw_batch(
+                features,
+                ncol,
+                Device::Cpu,
+                Backend::Auto,
+            )),
+            _ => {
+                return Err(Error::invalid_parameter(
+                    "predict_type",
+                    format!("unknown predict type {predict_type}"),
+                ));
+            }
+        }
+        unsafe { *out_len = out.len() as i64 };
+        Ok(())
+    })
+}
+
+// runs `f`, recording its error or panic for `MGBM_GetLastError`
+fn call(f: impl FnOnce() -> Result<()>) -> c_int {
+    let reason = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => return 0,
+        Ok(Err(err)) => err.to_string(),
+        Err(panic) => match panic.downcast_ref::<&str>() {
+            Some(message) => format!("panic: {message}"),
+            None => match panic.downcast_ref::<String>() {
+                Some(message) => format!("panic: {message}"),
+                None => "panic".to_string(),
+            },
+        },
+    };
+    let reason = CString::new(reason.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = reason);
+    -1
+}
+
+fn check_not_null<T>(pointer: *const T, name: &'static str) -> Result<()> {
+    if pointer.is_null() {
+        return Err(Error::invalid_parameter(name, "is a null pointer"));
+    }
+    Ok(())
+}
+
+unsafe fn booster<'a>(handle: BoosterHandle) -> Result<&'a Booster> {
+    check_not_null(handle, "handle")?;
+    Ok(unsafe { &*handle.cast::<Booster>() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::Dataset;
+    use crate::objective::ObjectiveType;
+    use crate::params::Params;
+    use std::ptr;
+
+    fn model_bytes() -> Vec<u8> {
+        let features: Vec<Vec<f32>> = (0..90).map(|i| vec![(i % 9) as f32, 1.0]).collect();
+        let labels: Vec<f32> = (0..90).map(|i| ((i % 9) / 3) as f32).collect();
+        let params = Params {
+            objective: ObjectiveType::Multiclass { num_class: 3 },
+            num_iterations: 5,
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster
+            .train(&Dataset::new(features, labels), &params)
+            .unwrap();
+        booster.to_model().to_bytes().unwrap()
+    }
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(MGBM_GetLastError()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_loads_predicts_and_frees() {
+        let bytes = model_bytes();
+        let mut handle = ptr::null_mut();
+        let status = unsafe {
+            MGBM_BoosterLoadModelFromBytes(bytes.as_ptr().cast(), bytes.len(), &mut handle)
+        };
+        assert_eq!(status, 0);
+        let mut num_classes = 0;
+        assert_eq!(
+            unsafe { MGBM_BoosterGetNumClasses(handle, &mut num_classes) },
+            0
+        );
+        assert_eq!(num_classes, 3);
+        let mut num_features = 0;
+        assert_eq!(
+            unsafe { MGBM_BoosterGetNumFeature(handle, &mut num_features) },
+            0
+        );
+        assert_eq!(num_features, 2);
+
+        let rows = [0.0, 1.0, 8.0, 1.0];
+        let (mut out_len, mut out) = (0, [0.0f32; 6]);
+        let status = unsafe {
+            MGBM_BoosterPredictForMat(
+                handle,
+                rows.as_ptr(),
+                2,
+                2,
+                C_API_PREDICT_NORMAL,
+                &mut out_len,
+                out.as_mut_ptr(),
+            )
+        };
+        assert_eq!(status, 0);
+        assert_eq!(out_len, 6);
+        let booster = Booster::from_model(Model::from_bytes(&bytes).unwrap());
+        assert_eq!(&out[3..], &booster.predict_proba(&rows[2..])[..]);
+
+        let mut raw = [0.0f32; 3];
+        let status = unsafe {
+            MGBM_BoosterPredictForMatSingleRow(
+                handle,
+                rows.as_ptr(),
+                2,
+                C_API_PREDICT_RAW_SCORE,
+                &mut out_len,
+                raw.as_mut_ptr(),
+            )
+        };
+        assert_eq!(status, 0);
+        assert_eq!(out_len, 3);
+        for (raw, expected) in raw.iter().zip(booster.predict_raw(&rows[..2])) {
+            assert!((raw - expected).abs() < 1e-5);
+        }
+        assert_eq!(unsafe { MGBM_BoosterFree(handle) }, 0);
+    }
+
+    #[test]
+    fn test_reports_errors() {
+        let mut handle = ptr::null_mut();
+        let bytes = b"not a model";
+        let status = unsafe {
+            MGBM_BoosterLoadModelFromBytes(bytes.as_ptr().cast(), bytes.len(), &mut handle)
+        };
+        assert_eq!(status, -1);
+        assert!(handle.is_null());
+        assert!(last_error().contains("not a model"), "{}", last_error());
+
+        let bytes = model_bytes();
+        unsafe { MGBM_BoosterLoadModelFromBytes(bytes.as_ptr().cast(), bytes.len(), &mut handle) };
+        let (mut out_len, mut out) = (0, [0.0f32; 3]);
+        let row = [1.0f32; 3];
+        let predict = |ncol, predict_type, out: &mut [f32; 3], out_len: &mut i64| unsafe {
+            MGBM_BoosterPredictForMatSingleRow(
+                handle,
+                row.as_ptr(),
+                ncol,
+                predict_type,
+                out_len,
+                out.as_mut_ptr(),
+            )
+        };
+        assert_eq!(predict(3, C_API_PREDICT_NORMAL, &mut out, &mut out_len), -1);
+        assert_eq!(predict(2, 7, &mut out, &mut out_len), -1);
+        assert!(last_error().contains("predict_type"), "{}", last_error());
+        let status = unsafe { MGBM_BoosterGetNumClasses(ptr::null_mut(), &mut 0) };
+        assert_eq!(status, -1);
+        assert!(last_error().contains("handle"), "{}", last_error());
+        unsafe { MGBM_BoosterFree(handle) };
+    }
+
+    #[test]
+    fn test_header_declares_every_function() {
+        let header = include_str!("../include/metalgbm.h");
+        let source = include_str!("capi.rs");
+        let exported = source
+            .lines()
+            .filter_map(|line| line.split("extern \"C\" fn ").nth(1))
+            .filter_map(|rest| rest.split('(').next());
+        let mut count = 0;
+        for name in exported {
+            assert!(header.contains(&format!(" {name}(")), "{name}");
+            count += 1;
+        }
+        assert_eq!(count, 8);
+        for constant in ["C_API_PREDICT_NORMAL 0", "C_API_PREDICT_RAW_SCORE 1"] {
+            assert!(header.contains(constant), "{constant}");
+        }
+    }
+}
@@ -0,0 +1,59 @@
+// Snapshots of a training run that can be written to disk and picked up again, see
+// `Params::checkpoint_path` and `Booster::resume_from_checkpoint`.
+
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::booster::TrainingState;
+use crate::error::{Error, Result};
+use crate::metrics::EvalHistory;
+use crate::params::Params;
+use crate::tree::Tree;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub(crate) params: Params,
+    pub(crate) trees: Vec<Tree>,
+    pub(crate) num_outputs: usize,
+    pub(crate) base_scores: Vec<f32>,
+    pub(crate) eval_history: EvalHistory,
+    pub(crate) state: TrainingState,
+}
+
+impl Checkpoint {
+    // Writes to a temporary file next to `path` first and moves it into place, so a crash
+    // while writing leaves the previous checkpoint intact.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_vec(self)
+            .map_err(|err| Error::format(format!("can't serialize checkpoint: {err}")))?;
+        write_atomically(path, &json)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read(path)
+            .map_err(|err| Error::io(format!("can't read {}: {err}", path.display())))?;
+        serde_json::from_slice(&json)
+            .map_err(|err| Error::format(format!("{} isn't a checkpoint: {err}", path.display())))
+    }
+
+    // number of iterations of the run done when the checkpoint was written
+    pub fn iteration(&self) -> usize {
+        self.state.iteration()
+    }
+
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+}
+
+// Writes `bytes` to a temporary file next to `path` and moves it into place, so readers
+// never see a partly written file.
+pub(crate) fn write_atomically(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, bytes)
+        .and_then(|()| fs::rename(&tmp, path))
+        .map_err(|err| Error::io(format!("can't write {}: {err}", path.display())))
+}
@@ -0,0 +1,280 @@
+use std::fmt::Write;
+
+use crate::tree::{Tree, TreeNode};
+
+// Rust source for a `predict(features: &[f32]) -> [f32; num_outputs]` function giving the
+// raw scores of the ensemble, the trees as nested `if`/`else` over the feature values. The
+// source needs nothing but `core`, so it can be compiled into services that only score.
+// Scores are added in the same order as `Booster::predict_raw`, so they come out the same.
+//
+// `trees` add to output `i % num_outputs`, their sum is divided by the number of iterations
+// when `average_output` is set, and `base_scores` come last.
+pub fn rust_source(
+    trees: &[Tree],
+    num_outputs: usize,
+    base_scores: &[f32],
+    average_output: bool,
+) -> String {
+    let mut source = String::new();
+    writeln!(
+        source,
+        "// Generated by metalgbm: {} trees, {num_outputs} outputs.",
+        trees.len()
+    )
+    .unwrap();
+    writeln!(
+        source,
+        "pub fn predict(features: &[f32]) -> [f32; {num_outputs}] {{"
+    )
+    .unwrap();
+    writeln!(source, "    let mut scores = [0.0f32; {num_outputs}];").unwrap();
+    for i in 0..trees.len() {
+        writeln!(
+            source,
+            "    scores[{}] += tree_{i}(features);",
+            i % num_outputs
+        )
+        .unwrap();
+    }
+    if average_output && !trees.is_empty() {
+        let num_iterations = (trees.len() / num_outputs) as f32;
+        writeln!(source, "    for score in scores.iter_mut() {{").unwrap();
+        writeln!(source, "        *score /= {};", literal(num_iterations)).unwrap();
+        writeln!(source, "    }}").unwrap();
+    }
+    for (k, &base_score) in base_scores.iter().enumerate() {
+        writeln!(source, "    scores[{k}] += {};", literal(base_score)).unwrap();
+    }
+    writeln!(source, "    scores").unwrap();
+    writeln!(source, "}}").unwrap();
+
+    for (i, tree) in trees.iter().enumerate() {
+        writeln!(source).unwrap();
+        write_tree(&mut source, i, tree);
+    }
+
+    if trees.iter().any(|tree| has_categorical_split(tree.root())) {
+        writeln!(source).unwrap();
+        source.push_str(IN_CATEGORIES);
+    }
+    source
+}
+
+// like `metalgbm_predict::in_categories`
+const IN_CATEGORIES: &str = "\
+fn in_categories(categories: &[u32], value: f32) -> bool {
+    value >= 0.0 && value.fract() == 0.0 && categories.binary_search(&(value as u32)).is_ok()
+}
+";
+
+// what is left to write of a tree
+enum Part<'a> {
+    Node(&'a TreeNode),
+    Text(String),
+}
+
+// Writes `tree_{index}`. Nodes are written from a stack rather than recursively, so deep
+// trees can't overflow it.
+fn write_tree(source: &mut String, index: usize, tree: &Tree) {
+    // `!(x >= t)` is what sends NaN left
+    writeln!(source, "#[allow(clippy::neg_cmp_op_on_partial_ord)]").unwrap();
+    writeln!(source, "fn tree_{index}(features: &[f32]) -> f32 {{").unwrap();
+    let shrinkage = tree.shrinkage();
+    let mut parts = vec![(Part::Node(tree.root()), 1)];
+    while let Some((part, depth)) = parts.pop() {
+        let indent = "    ".repeat(depth);
+        let node = match part {
+            Part::Text(text) => {
+                writeln!(source, "{indent}{text}").unwrap();
+                continue;
+            }
+            Part::Node(node) => node,
+        };
+        let (condition, left_child, right_child) = match node {
+            TreeNode::Leaf { value } => {
+                writeln!(source, "{indent}{}", literal(shrinkage * value)).unwrap();
+                continue;
+            }
+            TreeNode::LinearLeaf {
+                value,
+                intercept,
+                feature_indices,
+                coefficients,
+            } => {
+                write_linear_leaf(
+                    source,
+                    &indent,
+                    shrinkage,
+                    *value,
+                    *intercept,
+                    feature_indices,
+                    coefficients,
+                );
+                continue;
+            }
+            // comparisons with NaN are false, so `!(x >= t)` sends it left and `x < t` right
+            TreeNode::Split {
+                feature_index,
+                threshold,
+                default_left,
+                left_child,
+                right_child,
+            } => {
+                let threshold = literal(*threshold);
+                let condition = if *default_left {
+                    format!("!(features[{feature_index}] >= {threshold})")
+                } else {
+                    format!("features[{feature_index}] < {threshold}")
+                };
+                (condition, left_child, right_child)
+            }
+            TreeNode::CategoricalSplit {
+                feature_index,
+                categories,
+                left_child,
+                right_child,
+            } => {
+                let categories: Vec<String> = categories.iter().map(u32::to_string).collect();
+                let condition = format!(
+                    "in_categories(&[{}], features[{feature_index}])",
+                    categories.join(", ")
+                );
+                (condition, left_child, right_child)
+            }
+        };
+        writeln!(source, "{indent}if {condition} {{").unwrap();
+        parts.push((Part::Text("}".to_string()), depth));
+        parts.push((Part::Node(right_child), depth + 1));
+        parts.push((Part::Text("} else {".to_string()), depth));
+        parts.push((Part::Node(left_child), depth + 1));
+    }
+    writeln!(source, "}}").unwrap();
+}
+
+// The linear model of a leaf, summed in the order `Tree::predict_row` sums it.
+fn write_linear_leaf(
+    source: &mut String,
+    indent: &str,
+    shrinkage: f32,
+    value: f32,
+    intercept: f32,
+    feature_indices: &[usize],
+    coefficients: &[f32],
+) {
+    let missing: Vec<String> = feature_indices
+        .iter()
+        .map(|feature_index| format!("features[{feature_index}].is_nan()"))
+        .collect();
+    let mut prediction = literal(intercept);
+    for (feature_index, &coefficient) in feature_indices.iter().zip(coefficients) {
+        prediction = format!(
+            "({prediction} + {} * features[{feature_index}])",
+            literal(coefficient)
+        );
+    }
+    let prediction = format!("{} * {prediction}", literal(shrinkage));
+    if missing.is_empty() {
+        writeln!(source, "{indent}{prediction}").unwrap();
+        return;
+    }
+    writeln!(source, "{indent}if {} {{", missing.join(" || ")).unwrap();
+    writeln!(source, "{indent}    {}", literal(shrinkage * value)).unwrap();
+    writeln!(source, "{indent}}} else {{").unwrap();
+    writeln!(source, "{indent}    {prediction}").unwrap();
+    writeln!(source, "{indent}}}").unwrap();
+}
+
+fn has_categorical_split(root: &TreeNode) -> bool {
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        match node {
+            TreeNode::CategoricalSplit { .. } => return true,
+            TreeNode::Split {
+                left_child,
+                right_child,
+                ..
+            } => stack.extend([left_child.as_ref(), right_child.as_ref()]),
+            TreeNode::Leaf { .. } | TreeNode::LinearLeaf { .. } => {}
+        }
+    }
+    false
+}
+
+// an f32 literal that parses back to exactly `value`
+fn literal(value: f32) -> String {
+    if value.is_nan() {
+        "f32::NAN".to_string()
+    } else if value == f32::INFINITY {
+        "f32::INFINITY".to_string()
+    } else if value == f32::NEG_INFINITY {
+        "f32::NEG_INFINITY".to_string()
+    } else {
+        format!("{value:?}f32")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaf(value: f32) -> Box<TreeNode> {
+        Box::new(TreeNode::Leaf { value })
+    }
+
+    #[test]
+    fn test_rust_source_of_small_ensemble() {
+        let tree = Tree::new(Box::new(TreeNode::Split {
+            feature_index: 1,
+            threshold: 2.5,
+            default_left: true,
+            left_child: Box::new(TreeNode::CategoricalSplit {
+                feature_index: 0,
+                categories: vec![1, 3],
+                left_child: leaf(1.0),
+                right_child: leaf(-2.0),
+            }),
+            right_child: leaf(4.0),
+        }))
+        .with_shrinkage(0.5);
+        let source = rust_source(&[tree], 1, &[0.25], false);
+        assert_eq!(
+            source,
+            "\
+// Generated by metalgbm: 1 trees, 1 outputs.
+pub fn predict(features: &[f32]) -> [f32; 1] {
+    let mut scores = [0.0f32; 1];
+    scores[0] += tree_0(features);
+    scores[0] += 0.25f32;
+    scores
+}
+
+#[allow(clippy::neg_cmp_op_on_partial_ord)]
+fn tree_0(features: &[f32]) -> f32 {
+    if !(features[1] >= 2.5f32) {
+        if in_categories(&[1, 3], features[0]) {
+            0.5f32
+        } else {
+            -1.0f32
+        }
+    } else {
+        2.0f32
+    }
+}
+
+fn in_categories(categories: &[u32], value: f32) -> bool {
+    value >= 0.0 && value.fract() == 0.0 && categories.binary_search(&(value as u32)).is_ok()
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_literals_parse_back_exactly() {
+        for value in [0.1f32, -3.0, 1e-30, f32::MAX, f32::MIN_POSITIVE / 4.0] {
+            let literal = literal(value);
+            let parsed: f32 = literal.trim_end_matches("f32").parse().unwrap();
+            assert_eq!(parsed.to_bits(), value.to_bits());
+        }
+        assert_eq!(literal(f32::NEG_INFINITY), "f32::NEG_INFINITY");
+    }
+}
@@ -0,0 +1,577 @@
+// `Params` from config files: JSON objects and LightGBM's `key = value` config files, so
+// configs written for LightGBM can be reused. Keys may be the names of
+// `Params`' fields or LightGBM's parameter names and their aliases, e.g. `num_leaves`,
+// `min_child_samples` or `subsample`, and values may be like LightGBM's, e.g.
+// `objective = multiclass` with `num_class = 3`, `metric = auc,binary_logloss` or
+// `max_depth = -1`. Keys this crate doesn't know are skipped with a warning.
+
+use std::fs;
+use std::path::Path;
+
+use serde_json::{Map, Value};
+
+use crate::error::{Error, Result};
+use crate::metrics::MetricType;
+use crate::objective::ObjectiveType;
+use crate::params::Params;
+
+// LightGBM's names of parameters, and their aliases, for the `Params` field they set
+const ALIASES: &[(&str, &str)] = &[
+    ("boosting", "boosting_type"),
+    ("boost", "boosting_type"),
+    ("objective_type", "objective"),
+    ("app", "objective"),
+    ("application", "objective"),
+    ("loss", "objective"),
+    ("num_iteration", "num_iterations"),
+    ("n_iter", "num_iterations"),
+    ("num_tree", "num_iterations"),
+    ("num_trees", "num_iterations"),
+    ("num_round", "num_iterations"),
+    ("num_rounds", "num_iterations"),
+    ("nrounds", "num_iterations"),
+    ("num_boost_round", "num_iterations"),
+    ("n_estimators", "num_iterations"),
+    ("max_iter", "num_iterations"),
+    ("shrinkage_rate", "learning_rate"),
+    ("eta", "learning_rate"),
+    ("num_leaf", "num_leaves"),
+    ("max_leaves", "num_leaves"),
+    ("max_leaf", "num_leaves"),
+    ("max_leaf_nodes", "num_leaves"),
+    ("max_bin", "max_bins"),
+    ("is_enable_bundle", "enable_bundle"),
+    ("bundle", "enable_bundle"),
+    ("unbalance", "is_unbalance"),
+    ("unbalanced_sets", "is_unbalance"),
+    ("reg_alpha", "lambda_l1"),
+    ("l1_regularization", "lambda_l1"),
+    ("reg_lambda", "lambda_l2"),
+    ("lambda", "lambda_l2"),
+    ("l2_regularization", "lambda_l2"),
+    ("min_split_gain", "min_gain_to_split"),
+    ("min_data_per_leaf", "min_data_in_leaf"),
+    ("min_data", "min_data_in_leaf"),
+    ("min_child_samples", "min_data_in_leaf"),
+    ("min_samples_leaf", "min_data_in_leaf"),
+    ("min_sum_hessian_per_leaf", "min_sum_hessian_in_leaf"),
+    ("min_sum_hessian", "min_sum_hessian_in_leaf"),
+    ("min_hessian", "min_sum_hessian_in_leaf"),
+    ("min_child_weight", "min_sum_hessian_in_leaf"),
+    ("data_sample_strategy", "sample_strategy"),
+    ("sub_row", "bagging_fraction"),
+    ("subsample", "bagging_fraction"),
+    ("bagging", "bagging_fraction"),
+    ("subsample_freq", "bagging_freq"),
+    ("sub_feature", "feature_fraction"),
+    ("colsample_bytree", "feature_fraction"),
+    ("sub_feature_bynode", "feature_fraction_bynode"),
+    ("colsample_bynode", "feature_fraction_bynode"),
+    ("mc", "monotone_constraints"),
+    ("monotone_constraint", "monotone_constraints"),
+    ("monotonic_cst", "monotone_constraints"),
+    ("categorical_feature", "categorical_features"),
+    ("cat_feature", "categorical_features"),
+    ("categorical_column", "categorical_features"),
+    ("cat_column", "categorical_features"),
+    ("linear_trees", "linear_tree"),
+    ("extra_tree", "extra_trees"),
+    ("random_seed", "seed"),
+    ("random_state", "seed"),
+    ("num_thread", "num_threads"),
+    ("nthread", "num_threads"),
+    ("nthreads", "num_threads"),
+    ("n_jobs", "num_threads"),
+    ("device_type", "device"),
+    ("metric", "metrics"),
+    ("metric_types", "metrics"),
+    ("early_stopping_round", "early_stopping_rounds"),
+    ("early_stopping", "early_stopping_rounds"),
+    ("n_iter_no_change", "early_stopping_rounds"),
+    ("num_classes", "num_class"),
+    ("ndcg_eval_at", "eval_at"),
+    ("ndcg_at", "eval_at"),
+    ("map_eval_at", "eval_at"),
+    ("map_at", "eval_at"),
+];
+
+// LightGBM parameters that aren't fields of `Params` but go into the value of one, e.g.
+// the `num_class` of `ObjectiveType::Multiclass`
+const PARTS: &[&str] = &[
+    "num_class",
+    "alpha",
+    "fair_c",
+    "poisson_max_delta_step",
+    "tweedie_variance_power",
+    "sigmoid",
+    "eval_at",
+    "top_rate",
+    "other_rate",
+];
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub params: Params,
+    // what was read but not used, e.g. unknown keys, for the caller to show
+    pub warnings: Vec<String>,
+}
+
+// Reads a config file, as JSON when its extension is `.json` and in LightGBM's config format
+// otherwise.
+pub fn read_config(path: &Path) -> Result<Config> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| Error::io(format!("can't read {}: {err}", path.display())))?;
+    let config = if path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        parse_json_config(&text)
+    } else {
+        parse_lightgbm_config(&text)
+    };
+    config.map_err(|err| match err {
+        Error::Format { reason } => Error::format(format!("{}: {reason}", path.display())),
+        err => err,
+    })
+}
+
+// `read_config` on the contents of a JSON file, an object of parameters
+pub fn parse_json_config(text: &str) -> Result<Config> {
+    let value: Value =
+        serde_json::from_str(text).map_err(|err| Error::format(format!("not JSON: {err}")))?;
+    let Value::Object(entries) = value else {
+        return Err(Error::format("the config isn't a JSON object"));
+    };
+    from_entries(entries.into_iter().collect())
+}
+
+// `read_config` on the contents of a LightGBM config file: `key = value` lines, with `#`
+// comments. Values are numbers, booleans, strings, quoted or not, and lists in brackets or
+// separated by commas, each on one line. This isn't TOML: there are no sections, escapes in
+// strings or tables, and `dataset_params` can only be set from JSON.
+pub fn parse_lightgbm_config(text: &str) -> Result<Config> {
+    let mut entries = Vec::new();
+    for (line_index, line) in text.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let line_number = line_index + 1;
+        if line.starts_with('[') {
+            return Err(Error::format(format!(
+                "line {line_number}: sections aren't supported, parameters are top-level keys"
+            )));
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(Error::format(format!(
+                "line {line_number}: `{line}` isn't key = value"
+            )));
+        };
+        entries.push((unquote(key.trim()).to_string(), parse_value(value.trim())));
+    }
+    from_entries(entries)
+}
+
+fn from_entries(entries: Vec<(String, Value)>) -> Result<Config> {
+    let Ok(Value::Object(fields)) = serde_json::to_value(Params::default()) else {
+        unreachable!("params serialize to an object");
+    };
+    let mut params = Map::new();
+    let mut parts = Map::new();
+    let mut warnings = Vec::new();
+    for (key, value) in entries {
+        let name = ALIASES
+            .iter()
+            .find(|&&(alias, _)| alias == key)
+            .map_or(key.as_str(), |&(_, name)| name);
+        let target = if PARTS.contains(&name) {
+            &mut parts
+        } else if fields.contains_key(name) {
+            &mut params
+        } else {
+            warnings.push(format!("unknown parameter `{key}` is ignored"));
+            continue;
+        };
+        if target.insert(name.to_string(), value).is_some() {
+            warnings.push(format!("`{key}` sets {name} again, the last value counts"));
+        }
+    }
+
+    for (name, value) in params.iter_mut() {
+        *value = field_value(name, value.take(), &parts, &mut warnings)
+            .map_err(|reason| Error::format(format!("{name}: {reason}")))?;
+    }
+    if matches!(params.get("boosting_type"), Some(Value::String(boosting)) if boosting == "goss") {
+        params.insert("boosting_type".to_string(), "Gbdt".into());
+        let goss = field_value("sample_strategy", "goss".into(), &parts, &mut warnings);
+        params.insert("sample_strategy".to_string(), goss.unwrap());
+    }
+    if parts
+        .get("sigmoid")
+        .and_then(Value::as_f64)
+        .is_some_and(|sigmoid| sigmoid != 1.0)
+    {
+        warnings.push("sigmoid is ignored, binary objectives use 1".to_string());
+    }
+
+    let params = serde_json::from_value(Value::Object(params))
+        .map_err(|err| Error::format(format!("invalid parameters: {err}")))?;
+    Ok(Config { params, warnings })
+}
+
+// The value of `Params::<name>` as `Params` deserialize it. LightGBM's values, which are
+// lowercase names, lists given as one value and non-positive numbers for no limit, are
+// turned into this crate's; others are kept.
+fn field_value(
+    name: &str,
+    value: Value,
+    parts: &Map<String, Value>,
+    warnings: &mut Vec<String>,
+) -> std::result::Result<Value, String> {
+    let lightgbm_name = match &value {
+        Value::String(text) if text.chars().all(|c| !c.is_uppercase()) => Some(text.as_str()),
+        _ => None,
+    };
+    let number = |key: &str, default: f32| {
+        parts
+            .get(key)
+            .and_then(Value::as_f64)
+            .map_or(default, |value| value as f32)
+    };
+    Ok(match name {
+        "objective" => match lightgbm_name {
+            Some(objective) => to_value(lightgbm_objective(objective, parts, number)?),
+            None => value,
+        },
+        "metrics" => {
+            let mut metrics = Vec::new();
+            for metric in list(value) {
+                match &metric {
+                    Value::String(text) if text.chars().all(|c| !c.is_uppercase()) => {
+                        match lightgbm_metrics(text, parts) {
+                            Some(types) => metrics.extend(types.into_iter().map(to_value)),
+                            None => warnings.push(format!("metric `{text}` isn't supported")),
+                        }
+                    }
+                    _ => metrics.push(metric),
+                }
+            }
+            Value::Array(metrics)
+        }
+        "boosting_type" => match lightgbm_name {
+            Some("gbdt" | "gbrt") => "Gbdt".into(),
+            Some("rf" | "random_forest") => "RandomForest".into(),
+            // set by the caller, which also sets the sample strategy
+            Some("goss") => "goss".into(),
+            Some(boosting) => return Err(format!("`{boosting}` isn't supported")),
+            None => value,
+        },
+        "sample_strategy" => match lightgbm_name {
+            Some("bagging") => "Bagging".into(),
+            Some("goss") => serde_json::json!({
+                "Goss": {
+                    "top_rate": number("top_rate", 0.2),
+                    "other_rate": number("other_rate", 0.1),
+                }
+            }),
+            Some(strategy) => return Err(format!("`{strategy}` isn't supported")),
+            None => value,
+        },
+        "device" => match lightgbm_name {
+            Some("cpu") => "Cpu".into(),
+            Some("gpu" | "cuda") => "Gpu".into(),
+            Some(device) => return Err(format!("`{device}` isn't supported")),
+            None => value,
+        },
+        "max_depth" | "num_threads" | "early_stopping_rounds" => match value.as_f64() {
+            Some(limit) if limit <= 0.0 => Value::Null,
+            _ => value,
+        },
+        "histogram_pool_size" => match value.as_f64() {
+            Some(limit) if limit < 0.0 => Value::Null,
+            _ => value,
+        },
+        "class_weights" | "monotone_constraints" | "categorical_features" => {
+            Value::Array(list(value))
+        }
+        _ => value,
+    })
+}
+
+fn lightgbm_objective(
+    name: &str,
+    parts: &Map<String, Value>,
+    number: impl Fn(&str, f32) -> f32,
+) -> std::result::Result<ObjectiveType, String> {
+    Ok(match name {
+        "regression"
+        | "regression_l2"
+        | "l2"
+        | "mean_squared_error"
+        | "mse"
+        | "l2_root"
+        | "root_mean_squared_error"
+        | "rmse" => ObjectiveType::SquaredError,
+        "binary" => ObjectiveType::BinaryLogistic,
+        "multiclass" | "softmax" => ObjectiveType::Multiclass {
+            num_class: parts
+                .get("num_class")
+                .and_then(Value::as_u64)
+                .ok_or("multiclass needs num_class")? as usize,
+        },
+        "lambdarank" => ObjectiveType::LambdaRank,
+        "quantile" => ObjectiveType::Quantile {
+            alpha: number("alpha", 0.9),
+        },
+        "huber" => ObjectiveType::Huber {
+            delta: number("alpha", 0.9),
+        },
+        "fair" => ObjectiveType::Fair {
+            c: number("fair_c", 1.0),
+        },
+        "poisson" => ObjectiveType::Poisson {
+            max_delta_step: number("poisson_max_delta_step", 0.7),
+        },
+        "gamma" => ObjectiveType::Gamma,
+        "tweedie" => ObjectiveType::Tweedie {
+            variance_power: number("tweedie_variance_power", 1.5),
+        },
+        _ => return Err(format!("the LightGBM objective `{name}` isn't supported")),
+    })
+}
+
+// the metrics of one of LightGBM's metric names, None for those this crate lacks; ranking
+// metrics are evaluated at each of `eval_at`
+fn lightgbm_metrics(name: &str, parts: &Map<String, Value>) -> Option<Vec<MetricType>> {
+    let eval_at = || -> Vec<usize> {
+        match parts.get("eval_at") {
+            Some(eval_at) => list(eval_at.clone())
+                .iter()
+                .filter_map(Value::as_u64)
+                .map(|k| k as usize)
+                .collect(),
+            None => vec![1, 2, 3, 4, 5],
+        }
+    };
+    Some(match name {
+        "" | "none" | "null" | "na" | "custom" => vec![],
+        "rmse" | "l2_root" | "root_mean_squared_error" => vec![MetricType::Rmse],
+        "l1" | "mae" | "mean_absolute_error" | "regression_l1" => vec![MetricType::Mae],
+        "auc" => vec![MetricType::Auc],
+        "binary_logloss" | "binary" => vec![MetricType::BinaryLogloss],
+        "ndcg" | "lambdarank" | "rank_xendcg" | "xendcg" => eval_at()
+            .into_iter()
+            .map(|k| MetricType::Ndcg { k })
+            .collect(),
+        "map" | "mean_average_precision" => eval_at()
+            .into_iter()
+            .map(|k| MetricType::Map { k })
+            .collect(),
+        _ => return None,
+    })
+}
+
+fn to_value<T: serde::Serialize>(value: T) -> Value {
+    serde_json::to_value(value).unwrap()
+}
+
+// a list given as one value is a list of that value
+fn list(value: Value) -> Vec<Value> {
+    match value {
+        Value::Array(values) => values,
+        Value::Null => vec![],
+        value => vec![value],
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(open)) if c == open => quote = None,
+            ('#', None) => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn unquote(text: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(inner) = text
+            .strip_prefix(quote)
+            .and_then(|text| text.strip_suffix(quote))
+        {
+            return inner;
+        }
+    }
+    text
+}
+
+fn parse_value(text: &str) -> Value {
+    if let Some(inner) = text
+        .strip_prefix('[')
+        .and_then(|text| text.strip_suffix(']'))
+    {
+        return Value::Array(
+            inner
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(parse_scalar)
+                .collect(),
+        );
+    }
+    if !text.starts_with(['"', '\'']) && text.contains(',') {
+        return Value::Array(
+            text.split(',')
+                .map(|item| parse_scalar(item.trim()))
+                .collect(),
+        );
+    }
+    parse_scalar(text)
+}
+
+fn parse_scalar(text: &str) -> Value {
+    if text.starts_with(['"', '\'']) {
+        return unquote(text).into();
+    }
+    match text {
+        "true" => return true.into(),
+        "false" => return false.into(),
+        _ => {}
+    }
+    if let Ok(integer) = text.parse::<i64>() {
+        return integer.into();
+    }
+    match text.parse::<f64>() {
+        Ok(number) if number.is_finite() => number.into(),
+        _ => text.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::BoostingType;
+    use crate::sampling::SampleStrategy;
+
+    #[test]
+    fn test_reads_lightgbm_config_files() {
+        let text = "\
+# a LightGBM training config
+task = train
+objective = multiclass
+num_class = 3
+boosting = goss
+metric = multi_logloss,auc
+num_trees = 50
+shrinkage_rate = 0.05   # per iteration
+max_depth = -1
+min_data_in_leaf = 20
+bagging_fraction = 0.8
+colsample_bytree = 0.5
+categorical_feature = 0,2
+early_stopping_round = 0
+data = train.txt
+";
+        let config = parse_lightgbm_config(text).unwrap();
+        let params = config.params;
+        assert_eq!(params.objective, ObjectiveType::Multiclass { num_class: 3 });
+        assert_eq!(params.boosting_type, BoostingType::Gbdt);
+        assert_eq!(
+            params.sample_strategy,
+            SampleStrategy::Goss {
+                top_rate: 0.2,
+                other_rate: 0.1
+            }
+        );
+        assert_eq!(params.metrics, [MetricType::Auc]);
+        assert_eq!(params.num_iterations, 50);
+        assert_eq!(params.learning_rate, 0.05);
+        assert_eq!(params.max_depth, None);
+        assert_eq!(params.min_data_in_leaf, 20);
+        assert_eq!(params.bagging_fraction, 0.8);
+        assert_eq!(params.feature_fraction, 0.5);
+        assert_eq!(params.categorical_features, [0, 2]);
+        assert_eq!(params.early_stopping_rounds, None);
+        // a string is no number
+        assert!(parse_lightgbm_config("num_leaves = \"7\"").is_err());
+        assert_eq!(
+            config.warnings,
+            [
+                "unknown parameter `task` is ignored",
+                "unknown parameter `data` is ignored",
+                "metric `multi_logloss` isn't supported",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reads_json_and_lightgbm_configs_with_either_names() {
+        let json = r#"{
+            "objective": "BinaryLogistic",
+            "metrics": ["Auc", {"Ndcg": {"k": 3}}, "binary_logloss"],
+            "num_leaves": 15,
+            "subsample": 0.5,
+            "max_bin": 63,
+            "max_depth": 6
+        }"#;
+        let config = parse_json_config(json).unwrap();
+        assert!(config.warnings.is_empty(), "{:?}", config.warnings);
+        let params = config.params;
+        assert_eq!(params.objective, ObjectiveType::BinaryLogistic);
+        assert_eq!(
+            params.metrics,
+            [
+                MetricType::Auc,
+                MetricType::Ndcg { k: 3 },
+                MetricType::BinaryLogloss
+            ]
+        );
+        assert_eq!(params.num_leaves, 15);
+        assert_eq!(params.bagging_fraction, 0.5);
+        assert_eq!(params.max_bins, 63);
+        assert_eq!(params.max_depth, Some(6));
+
+        let text = "\
+objective = 'quantile'
+alpha = 0.25
+metric = [\"ndcg\", 'map']
+eval_at = [1, 10]
+num_leaves = 7
+num_leaves = 9
+";
+        let config = parse_lightgbm_config(text).unwrap();
+        assert_eq!(
+            config.params.objective,
+            ObjectiveType::Quantile { alpha: 0.25 }
+        );
+        assert_eq!(
+            config.params.metrics,
+            [
+                MetricType::Ndcg { k: 1 },
+                MetricType::Ndcg { k: 10 },
+                MetricType::Map { k: 1 },
+                MetricType::Map { k: 10 }
+            ]
+        );
+        assert_eq!(config.params.num_leaves, 9);
+        assert_eq!(
+            config.warnings,
+            ["`num_leaves` sets num_leaves again, the last value counts"]
+        );
+    }
+
+    #[test]
+    fn test_rejects_malformed_configs() {
+        assert!(parse_lightgbm_config("[params]\nnum_leaves = 3").is_err());
+        assert!(parse_lightgbm_config("num_leaves 3").is_err());
+        assert!(parse_lightgbm_config("objective = multiclass").is_err());
+        assert!(parse_lightgbm_config("objective = cross_entropy").is_err());
+        assert!(parse_lightgbm_config("boosting = dart").is_err());
+        assert!(parse_json_config("[1, 2]").is_err());
+        assert!(parse_json_config("{\"num_leaves\": -3}").is_err());
+    }
+}
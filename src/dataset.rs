@@ -0,0 +1,1235 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::histogram::{Histogram, HistogramPrecision};
+use crate::io::{self, CsvOptions, LibSvmOptions};
+use crate::quantize::QuantizedGradients;
+use crate::sparse::{CsrMatrix, FeatureRow, SparseRow};
+
+#[derive(Debug)]
+pub struct Dataset {
+    features: FeatureStorage,
+    labels: Vec<f32>,
+    // Query i spans rows query_boundaries[i]..query_boundaries[i + 1]. Only set for
+    // ranking data.
+    query_boundaries: Option<Vec<usize>>,
+    // per-row sample weights, all rows count once when unset
+    weights: Option<Vec<f32>>,
+    // raw scores boosting starts from instead of zero, laid out output by output like the
+    // booster's scores
+    init_scores: Option<Vec<f32>>,
+    // one per feature, e.g. from the header of a CSV file
+    feature_names: Option<Vec<String>>,
+}
+
+impl Dataset {
+    pub fn new(features: Vec<Vec<f32>>, labels: Vec<f32>) -> Self {
+        assert_eq!(features.len(), labels.len());
+
+        Self::with_storage(FeatureStorage::Dense(features), labels)
+    }
+
+    // Features in CSR form, for data with mostly zero entries; see `CsrMatrix`.
+    pub fn from_csr(features: CsrMatrix, labels: Vec<f32>) -> Self {
+        assert_eq!(features.num_rows(), labels.len());
+
+        Self::with_storage(FeatureStorage::Sparse(features), labels)
+    }
+
+    fn with_storage(features: FeatureStorage, labels: Vec<f32>) -> Self {
+        Self {
+            features,
+            labels,
+            query_boundaries: None,
+            weights: None,
+            init_scores: None,
+            feature_names: None,
+        }
+    }
+
+    // Reads features and labels from a CSV file, see `CsvOptions` for the layouts it
+    // understands.
+    pub fn from_csv(path: impl AsRef<Path>, options: &CsvOptions) -> Result<Self> {
+        io::read_csv(path.as_ref(), options)
+    }
+
+    // Reads the columns of a Parquet file, see `ParquetOptions`. The codes of its string
+    // columns are dropped; `io::read_parquet` returns them with the dataset.
+    #[cfg(feature = "parquet")]
+    pub fn from_parquet(path: impl AsRef<Path>, options: &io::ParquetOptions) -> Result<Self> {
+        Ok(io::read_parquet(path.as_ref(), options)?.dataset)
+    }
+
+    // one row of `features` per label
+    #[cfg(feature = "ndarray")]
+    pub fn from_array(
+        features: ndarray::ArrayView2<f32>,
+        labels: ndarray::ArrayView1<f32>,
+    ) -> Self {
+        Self::new(
+            features
+                .rows()
+                .into_iter()
+                .map(|row| row.to_vec())
+                .collect(),
+            labels.to_vec(),
+        )
+    }
+
+    // Builds a dataset from a Polars data frame, see
+    // `interop::dataframe::dataset_from_dataframe`
+    #[cfg(feature = "polars")]
+    pub fn from_dataframe(df: &polars::prelude::DataFrame, label: &str) -> Result<Self> {
+        crate::interop::dataframe::dataset_from_dataframe(df, label)
+    }
+
+    // Builds a dataset from an Arrow record batch, see
+    // `interop::record_batch::dataset_from_record_batch`
+    #[cfg(feature = "arrow")]
+    pub fn from_record_batch(
+        batch: &arrow::record_batch::RecordBatch,
+        label: &str,
+    ) -> Result<Self> {
+        crate::interop::record_batch::dataset_from_record_batch(batch, label)
+    }
+
+    // Reads a sparse LibSVM/SVMLight file, e.g. the a9a or higgs benchmark data. Query ids
+    // in the file become ranking groups.
+    pub fn from_libsvm(path: impl AsRef<Path>, options: &LibSvmOptions) -> Result<Self> {
+        io::read_libsvm(path.as_ref(), options)
+    }
+
+    // Every row's gradient, hessian and metric contribution is scaled by its weight, and
+    // rows of zero weight are left out of the trees.
+    pub fn with_weights(mut self, weights: Vec<f32>) -> Self {
+        assert_eq!(
+            weights.len(),
+            self.labels.len(),
+            "there must be one weight per row"
+        );
+        assert!(
+            weights.iter().all(|&w| w >= 0.0 && w.is_finite()),
+            "weights must be finite and non-negative"
+        );
+
+        self.weights = Some(weights);
+        self
+    }
+
+    // `group_sizes` are the number of consecutive rows belonging to each query, in the same
+    // format LightGBM uses for its `group` field.
+    pub fn with_groups(mut self, group_sizes: &[usize]) -> Self {
+        let mut boundaries = Vec::with_capacity(group_sizes.len() + 1);
+        boundaries.push(0);
+        for size in group_sizes {
+            boundaries.push(boundaries[boundaries.len() - 1] + size);
+        }
+        assert_eq!(
+            boundaries[boundaries.len() - 1],
+            self.labels.len(),
+            "group sizes must add up to the number of rows"
+        );
+
+        self.query_boundaries = Some(boundaries);
+        self
+    }
+
+    // The rows of a dense dataset. Panics for sparse datasets, which have no dense rows; use
+    // `row` or `sparse_features` for those.
+    pub fn features(&self) -> &[Vec<f32>] {
+        match &self.features {
+            FeatureStorage::Dense(rows) => rows,
+            FeatureStorage::Sparse(_) => panic!("sparse datasets have no dense rows, use `row`"),
+        }
+    }
+
+    pub fn sparse_features(&self) -> Option<&CsrMatrix> {
+        match &self.features {
+            FeatureStorage::Dense(_) => None,
+            FeatureStorage::Sparse(matrix) => Some(matrix),
+        }
+    }
+
+    pub fn row(&self, row: usize) -> Row<'_> {
+        match &self.features {
+            FeatureStorage::Dense(rows) => Row::Dense(&rows[row]),
+            FeatureStorage::Sparse(matrix) => Row::Sparse(matrix.row(row)),
+        }
+    }
+
+    // The same rows with weights and feature names but other labels, e.g. a teacher's
+    // predictions; query groups and init scores belong to the old labels and are dropped.
+    pub(crate) fn with_labels(&self, labels: Vec<f32>) -> Dataset {
+        assert_eq!(labels.len(), self.num_rows());
+
+        Dataset {
+            weights: self.weights.clone(),
+            feature_names: self.feature_names.clone(),
+            ..Self::with_storage(self.features.clone(), labels)
+        }
+    }
+
+    pub fn labels(&self) -> &[f32] {
+        &self.labels
+    }
+
+    pub fn query_boundaries(&self) -> Option<&[usize]> {
+        self.query_boundaries.as_deref()
+    }
+
+    pub fn weights(&self) -> Option<&[f32]> {
+        self.weights.as_deref()
+    }
+
+    // Per-row base margins, e.g. log exposure for Poisson regression or the scores of another
+    // model when stacking. Multi-output models expect num_rows scores per output; the length
+    // is checked against the objective when training.
+    pub fn with_init_scores(mut self, init_scores: Vec<f32>) -> Self {
+        self.init_scores = Some(init_scores);
+        self
+    }
+
+    pub fn init_scores(&self) -> Option<&[f32]> {
+        self.init_scores.as_deref()
+    }
+
+    pub fn with_feature_names(mut self, feature_names: Vec<String>) -> Self {
+        assert!(
+            self.num_rows() == 0 || feature_names.len() == self.num_features(),
+            "there must be one name per feature"
+        );
+
+        self.feature_names = Some(feature_names);
+        self
+    }
+
+    pub fn feature_names(&self) -> Option<&[String]> {
+        self.feature_names.as_deref()
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn num_features(&self) -> usize {
+        match &self.features {
+            FeatureStorage::Dense(rows) => rows.first().map_or(0, |row| row.len()),
+            FeatureStorage::Sparse(matrix) => matrix.num_features(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum FeatureStorage {
+    // row-major: rows[i] holds all feature values of row i
+    Dense(Vec<Vec<f32>>),
+    Sparse(CsrMatrix),
+}
+
+// one row of a dataset, whichever way its features are stored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Row<'a> {
+    Dense(&'a [f32]),
+    Sparse(SparseRow<'a>),
+}
+
+impl FeatureRow for Row<'_> {
+    fn value(&self, feature: usize) -> f32 {
+        match self {
+            Row::Dense(values) => values[feature],
+            Row::Sparse(row) => row.value(feature),
+        }
+    }
+}
+
+// How a feature's bin edges are placed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum BinningStrategy {
+    // edges at evenly spaced weighted quantiles, so every bin holds about the same weight
+    #[default]
+    Quantile,
+    // edges evenly spaced between a feature's smallest and largest value
+    EqualWidth,
+    // the ascending edges of every feature by index; features past the end fall back to
+    // quantiles
+    Custom(Vec<Vec<f32>>),
+}
+
+// Per-feature binning settings, applied on top of the global `Params::max_bins`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DatasetParams {
+    pub binning_strategy: BinningStrategy,
+    // bin counts by feature index, e.g. more for skewed features and fewer for nearly
+    // constant ones; features past the end use `max_bins`
+    pub max_bins_by_feature: Vec<usize>,
+    // features binned at exactly these ascending edges whatever the strategy
+    pub forced_bins: BTreeMap<usize, Vec<f32>>,
+}
+
+impl DatasetParams {
+    // the empty histogram of `feature`, whose `values` count `weights[i]` times
+    fn template(
+        &self,
+        feature: usize,
+        values: &[f32],
+        weights: Option<&[f32]>,
+        max_bins: usize,
+    ) -> Histogram {
+        if let Some(edges) = self.forced_bins.get(&feature) {
+            return Histogram::from_edges(edges.clone());
+        }
+        let max_bins = self
+            .max_bins_by_feature
+            .get(feature)
+            .copied()
+            .unwrap_or(max_bins);
+        match &self.binning_strategy {
+            BinningStrategy::EqualWidth => Histogram::from_equal_width(values, max_bins),
+            BinningStrategy::Custom(edges) if feature < edges.len() => {
+                Histogram::from_edges(edges[feature].clone())
+            }
+            BinningStrategy::Quantile | BinningStrategy::Custom(_) => {
+                Histogram::from_weighted_feature(values, weights, max_bins)
+            }
+        }
+    }
+}
+
+// Training features binned once up front with the bin edges `DatasetParams` picks.
+// Bin indices are stored column by column, one byte per row for features with at most 256
+// bins and two otherwise, so filling a node's histograms indexes bins directly instead of
+// searching the edges for every row. Sparse datasets only store the bins of their non-zero
+// entries.
+pub struct BinnedDataset {
+    // column-major raw values, still needed to partition rows and fit linear leaves
+    columns: Vec<RawColumn>,
+    // the empty histogram of every feature, holding its bin edges
+    templates: Vec<Histogram>,
+    bins: Vec<FeatureBins>,
+    bundles: Vec<Bundle>,
+    num_rows: usize,
+    // what node histograms, bundles' included, accumulate in
+    precision: HistogramPrecision,
+    parallelism: HistogramParallelism,
+}
+
+// Rows a thread accumulates at a time when node histograms are built by rows. Chunks don't
+// depend on the number of threads, so neither do the merged sums.
+const ROWS_PER_CHUNK: usize = 16384;
+// `HistogramParallelism::Auto` only builds by rows below this many columns
+const ROW_PARALLEL_MAX_COLUMNS: usize = 8;
+
+// How a node's histograms are split across threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum HistogramParallelism {
+    // by rows for nodes with few columns (features or bundles) and many rows, by columns
+    // otherwise
+    #[default]
+    Auto,
+    // every thread accumulates whole columns, so there is nothing to merge but at most one
+    // thread per column is busy
+    Features,
+    // every thread accumulates all columns over chunks of rows into partial histograms,
+    // which are merged afterwards
+    Rows,
+}
+
+enum RawColumn {
+    Dense(Vec<f32>),
+    // the rows with non-zero entries, ascending, and their values
+    Sparse { rows: Vec<usize>, values: Vec<f32> },
+}
+
+// the bin index of every row for one feature
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeatureBins {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    // The bins of the stored entries of a sparse feature, in the order of `rows`. All other
+    // rows are 0 and fall into `zero_bin`, whose sums histograms get by subtracting the
+    // stored entries from the node's totals instead of visiting those rows.
+    Sparse {
+        rows: Vec<usize>,
+        bins: Vec<u16>,
+        zero_bin: usize,
+    },
+    // member `member` of `BinnedDataset::bundles[bundle]`, see `BinnedDataset::with_bundles`
+    Bundled {
+        bundle: usize,
+        member: usize,
+    },
+}
+
+// Features that are rarely away from their default bin at the same time, sharing one bin
+// column: bin 0 when every member is at its default bin, offsets[k] + b when member k is
+// in bin b.
+struct Bundle {
+    bins: FeatureBins,
+    offsets: Vec<usize>,
+    default_bins: Vec<usize>,
+    num_bins: usize,
+}
+
+impl BinnedDataset {
+    pub fn new(dataset: &Dataset, max_bins: usize) -> Self {
+        Self::new_with_params(dataset, max_bins, &DatasetParams::default())
+    }
+
+    // Like `new`, with the per-feature overrides of `params`. The bin edges are placed by
+    // the rows' sample weights; the hessians, which would weigh rows by how much they
+    // matter to the loss, aren't known before training.
+    pub fn new_with_params(dataset: &Dataset, max_bins: usize, params: &DatasetParams) -> Self {
+        match &dataset.features {
+            FeatureStorage::Dense(features) => {
+                let columns = (0..dataset.num_features())
+                    .map(|j| features.iter().map(|row| row[j]).collect())
+                    .collect();
+                Self::from_dense(columns, dataset.weights(), max_bins, params)
+            }
+            FeatureStorage::Sparse(matrix) => {
+                Self::from_sparse(matrix, dataset.weights(), max_bins, params)
+            }
+        }
+    }
+
+    // Rebins `features` with one bin per category, see `Histogram::from_categories`. Their
+    // values must be non-negative integers or NaN, with at most 65535 distinct ones per
+    // feature.
+    pub fn with_categorical_features(mut self, features: &[usize]) -> Result<Self> {
+        for &feature in features {
+            let Some(column) = self.columns.get(feature) else {
+                return Err(Error::invalid_parameter(
+                    "categorical_features",
+                    format!(
+                        "feature {feature} doesn't exist, there are {}",
+                        self.columns.len()
+                    ),
+                ));
+            };
+            let (values, has_zeros) = match column {
+                RawColumn::Dense(values) => (values, false),
+                RawColumn::Sparse { rows, values } => (values, rows.len() < self.num_rows),
+            };
+            if let Some(value) = values.iter().find(|&&value| {
+                !(value.is_nan()
+                    || value >= 0.0 && value.fract() == 0.0 && value <= u32::MAX as f32)
+            }) {
+                return Err(Error::invalid_data(format!(
+                    "categorical feature {feature} has value {value}, categories must be non-negative integers"
+                )));
+            }
+            let mut distinct = values.clone();
+            if has_zeros {
+                distinct.push(0.0);
+            }
+            let template = Histogram::from_categories(&distinct);
+            if template.num_bins() >= 1 << 16 {
+                return Err(Error::invalid_data(format!(
+                    "categorical feature {feature} has more than 65535 categories"
+                )));
+            }
+            self.bins[feature] = match column {
+                RawColumn::Dense(values) => dense_bins(&template, values),
+                RawColumn::Sparse { rows, values } => sparse_bins(&template, rows, values),
+            };
+            self.templates[feature] = template;
+        }
+        Ok(self)
+    }
+
+    // Exclusive feature bundling as in LightGBM: features that are away from their most
+    // common bin in disjoint rows are merged into one bin column, so one pass over that
+    // column fills all their histograms. A bundle tolerates `max_conflict_rate` of the rows
+    // having several members away from their default, which then only count for the last
+    // member.
+    pub fn with_bundles(mut self, max_conflict_rate: f32) -> Self {
+        let budget = (max_conflict_rate * self.num_rows as f32) as usize;
+        // per feature: its default bin and the rows away from it with their bins
+        let mut candidates: Vec<_> = (0..self.bins.len())
+            .map(|feature| {
+                let (default_bin, entries) = self.non_default_entries(feature);
+                (feature, default_bin, entries)
+            })
+            .collect();
+        // the densest features first, they are the hardest to place
+        candidates.sort_by_key(|(_, _, entries)| std::cmp::Reverse(entries.len()));
+
+        struct Group {
+            members: Vec<usize>,
+            occupied: Vec<bool>,
+            conflicts: usize,
+            num_bins: usize,
+        }
+        let mut groups: Vec<Group> = vec![];
+        for (index, (feature, _, entries)) in candidates.iter().enumerate() {
+            // the feature's bins and its missing bin
+            let num_bins = self.templates[*feature].num_bins() + 1;
+            let fits = |group: &Group| {
+                if group.num_bins + num_bins > 1 << 16 {
+                    return false;
+                }
+                let mut conflicts = group.conflicts;
+                for &(row, _) in entries {
+                    conflicts += group.occupied[row] as usize;
+                    if conflicts > budget {
+                        return false;
+                    }
+                }
+                true
+            };
+            let group = match groups.iter().position(fits) {
+                Some(group) => group,
+                None => {
+                    groups.push(Group {
+                        members: vec![],
+                        occupied: vec![false; self.num_rows],
+                        conflicts: 0,
+                        num_bins: 1,
+                    });
+                    groups.len() - 1
+                }
+            };
+            let group = &mut groups[group];
+            for &(row, _) in entries {
+                group.conflicts += group.occupied[row] as usize;
+                group.occupied[row] = true;
+            }
+            group.members.push(index);
+            group.num_bins += num_bins;
+        }
+
+        for group in groups.into_iter().filter(|group| group.members.len() > 1) {
+            let bundle = self.bundles.len();
+            let mut bins = vec![0u16; self.num_rows];
+            let mut offsets = vec![];
+            let mut default_bins = vec![];
+            let mut offset = 1;
+            for (member, &index) in group.members.iter().enumerate() {
+                let (feature, default_bin, entries) = &candidates[index];
+                for &(row, bin) in entries {
+                    bins[row] = (offset + bin) as u16;
+                }
+                offsets.push(offset);
+                default_bins.push(*default_bin);
+                offset += self.templates[*feature].num_bins() + 1;
+                self.bins[*feature] = FeatureBins::Bundled { bundle, member };
+            }
+            self.bundles.push(Bundle {
+                bins: if group.num_bins <= 1 << 8 {
+                    FeatureBins::U8(bins.into_iter().map(|bin| bin as u8).collect())
+                } else {
+                    FeatureBins::U16(bins)
+                },
+                offsets,
+                default_bins,
+                num_bins: group.num_bins,
+            });
+        }
+        self
+    }
+
+    // the most common bin of an unbundled feature, and the rows in other bins with their bin
+    fn non_default_entries(&self, feature: usize) -> (usize, Vec<(usize, usize)>) {
+        fn dense<T: Copy + Into<usize>>(
+            bins: &[T],
+            num_bins: usize,
+        ) -> (usize, Vec<(usize, usize)>) {
+            // the missing bin can be the most frequent one too
+            let mut counts = vec![0usize; num_bins + 1];
+            for &bin in bins {
+                counts[bin.into()] += 1;
+            }
+            let default_bin = (0..counts.len())
+                .max_by_key(|&bin| counts[bin])
+                .unwrap_or(0);
+            let entries = bins
+                .iter()
+                .enumerate()
+                .map(|(row, &bin)| (row, bin.into()))
+                .filter(|&(_, bin)| bin != default_bin)
+                .collect();
+            (default_bin, entries)
+        }
+
+        let num_bins = self.templates[feature].num_bins();
+        match &self.bins[feature] {
+            FeatureBins::U8(bins) => dense(bins, num_bins),
+            FeatureBins::U16(bins) => dense(bins, num_bins),
+            FeatureBins::Sparse {
+                rows,
+                bins,
+                zero_bin,
+            } => (
+                *zero_bin,
+                rows.iter()
+                    .zip(bins)
+                    .map(|(&row, &bin)| (row, bin as usize))
+                    .filter(|&(_, bin)| bin != *zero_bin)
+                    .collect(),
+            ),
+            FeatureBins::Bundled { .. } => unreachable!("features are only bundled once"),
+        }
+    }
+
+    // The histograms of the node holding `rows`, ascending, whose weighted gradient and
+    // hessian sums are `node_sums`. Histograms are indexed by feature, but only the ones in
+    // `features` are filled; the others stay empty.
+    pub fn histograms(
+        &self,
+        features: &[usize],
+        rows: &[usize],
+        gradients: &[f32],
+        hessians: &[f32],
+        weights: Option<&[f32]>,
+        node_sums: (f32, f32),
+    ) -> Vec<Histogram> {
+        let mut histograms = self.templates.to_vec();
+        self.fill_histograms(
+            &mut histograms,
+            features,
+            rows,
+            gradients,
+            hessians,
+            weights,
+            node_sums,
+        );
+        histograms
+    }
+
+    // Like `histograms`, accumulating into the empty `histograms` of every feature, e.g.
+    // ones recycled by a `HistogramPool`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn fill_histograms(
+        &self,
+        histograms: &mut [Histogram],
+        features: &[usize],
+        rows: &[usize],
+        gradients: &[f32],
+        hessians: &[f32],
+        weights: Option<&[f32]>,
+        node_sums: (f32, f32),
+    ) {
+        let weight = |row: usize| weights.map_or(1.0, |weights| weights[row]);
+        // a chunk as long as the node is the whole node
+        let sums = |chunk: &[usize]| {
+            if chunk.len() == rows.len() {
+                return node_sums;
+            }
+            chunk.iter().fold((0.0, 0.0), |(g, h), &row| {
+                (
+                    g + weight(row) * gradients[row],
+                    h + weight(row) * hessians[row],
+                )
+            })
+        };
+        self.fill_with(
+            histograms,
+            features,
+            rows,
+            node_sums,
+            sums,
+            |histogram, bins, chunk, chunk_sums| {
+                histogram.accumulate_bins(bins, chunk, gradients, hessians, weights, chunk_sums)
+            },
+        );
+    }
+
+    // Like `fill_histograms` from quantized gradients, see `Histogram::accumulate_quantized_bins`.
+    pub fn fill_quantized_histograms(
+        &self,
+        histograms: &mut [Histogram],
+        features: &[usize],
+        rows: &[usize],
+        quantized: &QuantizedGradients,
+    ) {
+        let quantized_sums = quantized.sums(rows);
+        let sums = |chunk: &[usize]| {
+            if chunk.len() == rows.len() {
+                quantized_sums
+            } else {
+                quantized.sums(chunk)
+            }
+        };
+        self.fill_with(
+            histograms,
+            features,
+            rows,
+            quantized.dequantize(quantized_sums),
+            sums,
+            |histogram, bins, chunk, chunk_sums| {
+                histogram.accumulate_quantized_bins(bins, chunk, quantized, chunk_sums)
+            },
+        );
+    }
+
+    // Fills the histograms of `features` by calling `accumulate` with the bins of every
+    // feature, or of its bundle once per bundle, some rows and `sums` of those rows.
+    // `node_sums` are the gradient and hessian sums of all `rows`. The work is spread over
+    // the current rayon thread pool by column, or by chunks of rows whose partial
+    // histograms are merged in order, see `HistogramParallelism`.
+    fn fill_with<S: Copy + Send + Sync>(
+        &self,
+        histograms: &mut [Histogram],
+        features: &[usize],
+        rows: &[usize],
+        node_sums: (f32, f32),
+        sums: impl Fn(&[usize]) -> S + Sync,
+        accumulate: impl Fn(&mut Histogram, &FeatureBins, &[usize], S) + Sync,
+    ) {
+        let mut wanted = vec![false; histograms.len()];
+        let mut wanted_bundles = vec![false; self.bundles.len()];
+        for &feature in features {
+            wanted[feature] = true;
+            if let FeatureBins::Bundled { bundle, .. } = self.bins[feature] {
+                wanted_bundles[bundle] = true;
+            }
+        }
+        let unbundled = |feature: usize| {
+            wanted[feature] && !matches!(self.bins[feature], FeatureBins::Bundled { .. })
+        };
+        let num_columns = wanted_bundles.iter().filter(|&&wanted| wanted).count()
+            + (0..histograms.len())
+                .filter(|&feature| unbundled(feature))
+                .count();
+        let by_rows = match self.parallelism {
+            HistogramParallelism::Features => false,
+            HistogramParallelism::Rows => true,
+            HistogramParallelism::Auto => {
+                num_columns < ROW_PARALLEL_MAX_COLUMNS && rows.len() >= 2 * ROWS_PER_CHUNK
+            }
+        };
+        let bundle_histogram = |bundle: &Bundle, chunk: &[usize], chunk_sums: S| {
+            let mut combined =
+                Histogram::with_num_bins(bundle.num_bins).with_precision(self.precision);
+            accumulate(&mut combined, &bundle.bins, chunk, chunk_sums);
+            combined
+        };
+
+        let mut bundle_histograms: Vec<Option<Histogram>>;
+        if by_rows {
+            let partials: Vec<_> = rows
+                .par_chunks(ROWS_PER_CHUNK)
+                .map(|chunk| {
+                    let chunk_sums = sums(chunk);
+                    let bundles: Vec<Option<Histogram>> = self
+                        .bundles
+                        .iter()
+                        .zip(&wanted_bundles)
+                        .map(|(bundle, &wanted)| {
+                            wanted.then(|| bundle_histogram(bundle, chunk, chunk_sums))
+                        })
+                        .collect();
+                    let features: Vec<Option<Histogram>> = (0..histograms.len())
+                        .map(|feature| {
+                            unbundled(feature).then(|| {
+                                let mut histogram = self.templates[feature].clone();
+                                accumulate(&mut histogram, &self.bins[feature], chunk, chunk_sums);
+                                histogram
+                            })
+                        })
+                        .collect();
+                    (bundles, features)
+                })
+                .collect();
+
+            bundle_histograms = vec![None; self.bundles.len()];
+            for (bundles, features) in partials {
+                for (total, partial) in bundle_histograms.iter_mut().zip(bundles) {
+                    match (total.as_mut(), partial) {
+                        (Some(total), Some(partial)) => {
+                            total.merge(&partial).expect("chunks share their bins")
+                        }
+                        (None, partial) => *total = partial,
+                        (Some(_), None) => {}
+                    }
+                }
+                for (histogram, partial) in histograms.iter_mut().zip(features) {
+                    if let Some(partial) = partial {
+                        histogram.merge(&partial).expect("chunks share their bins");
+                    }
+                }
+            }
+        } else {
+            let node = sums(rows);
+            bundle_histograms = self
+                .bundles
+                .par_iter()
+                .zip(wanted_bundles)
+                .map(|(bundle, wanted)| wanted.then(|| bundle_histogram(bundle, rows, node)))
+                .collect();
+            histograms
+                .par_iter_mut()
+                .enumerate()
+                .filter(|(feature, _)| unbundled(*feature))
+                .for_each(|(feature, histogram)| {
+                    accumulate(histogram, &self.bins[feature], rows, node)
+                });
+        }
+
+        histograms
+            .par_iter_mut()
+            .enumerate()
+            .filter(|(feature, _)| wanted[*feature])
+            .for_each(|(feature, histogram)| {
+                if let FeatureBins::Bundled { bundle, member } = self.bins[feature] {
+                    let Bundle {
+                        offsets,
+                        default_bins,
+                        ..
+                    } = &self.bundles[bundle];
+                    let combined = bundle_histograms[bundle]
+                        .as_ref()
+                        .expect("bundles of wanted features are accumulated");
+                    histogram.unbundle(
+                        combined,
+                        offsets[member],
+                        default_bins[member],
+                        node_sums,
+                        rows.len(),
+                    );
+                }
+            });
+    }
+
+    // How node histograms are spread over threads from here on.
+    pub fn with_parallelism(mut self, parallelism: HistogramParallelism) -> Self {
+        self.parallelism = parallelism;
+        self
+    }
+
+    // Node histograms accumulate in `precision` from here on.
+    pub fn with_precision(mut self, precision: HistogramPrecision) -> Self {
+        self.templates = self
+            .templates
+            .into_iter()
+            .map(|template| template.with_precision(precision))
+            .collect();
+        self.precision = precision;
+        self
+    }
+
+    pub fn num_bundles(&self) -> usize {
+        self.bundles.len()
+    }
+
+    // `max_bins` can be at most 65535, see `Params::max_bins`
+    pub fn from_columns(columns: Vec<Vec<f32>>, max_bins: usize) -> Self {
+        Self::from_dense(columns, None, max_bins, &DatasetParams::default())
+    }
+
+    fn from_dense(
+        columns: Vec<Vec<f32>>,
+        weights: Option<&[f32]>,
+        max_bins: usize,
+        params: &DatasetParams,
+    ) -> Self {
+        let templates: Vec<Histogram> = columns
+            .iter()
+            .enumerate()
+            .map(|(feature, column)| params.template(feature, column, weights, max_bins))
+            .collect();
+        let bins = columns
+            .iter()
+            .zip(&templates)
+            .map(|(column, template)| dense_bins(template, column))
+            .collect();
+
+        Self {
+            num_rows: columns.first().map_or(0, |column| column.len()),
+            columns: columns.into_iter().map(RawColumn::Dense).collect(),
+            templates,
+            bins,
+            bundles: vec![],
+            precision: HistogramPrecision::Single,
+            parallelism: HistogramParallelism::Auto,
+        }
+    }
+
+    fn from_sparse(
+        matrix: &CsrMatrix,
+        weights: Option<&[f32]>,
+        max_bins: usize,
+        params: &DatasetParams,
+    ) -> Self {
+        let weight = |row: usize| weights.map_or(1.0, |weights| weights[row]);
+        let total_weight: f32 = (0..matrix.num_rows()).map(weight).sum();
+        let mut columns = vec![];
+        let mut templates = vec![];
+        let mut bins = vec![];
+        for (feature, (rows, values)) in matrix.to_columns().into_iter().enumerate() {
+            // the zeros enter the sketch as one value carrying the weight of their rows
+            let mut sketched = values.clone();
+            let mut sketch_weights: Vec<f32> = rows.iter().map(|&row| weight(row)).collect();
+            if rows.len() < matrix.num_rows() {
+                sketched.push(0.0);
+                sketch_weights.push(total_weight - sketch_weights.iter().sum::<f32>());
+            }
+            let template = params.template(feature, &sketched, Some(&sketch_weights), max_bins);
+            bins.push(sparse_bins(&template, &rows, &values));
+            templates.push(template);
+            columns.push(RawColumn::Sparse { rows, values });
+        }
+
+        Self {
+            columns,
+            templates,
+            bins,
+            bundles: vec![],
+            num_rows: matrix.num_rows(),
+            precision: HistogramPrecision::Single,
+            parallelism: HistogramParallelism::Auto,
+        }
+    }
+
+    // the raw value of `feature` in `row`
+    pub fn value(&self, feature: usize, row: usize) -> f32 {
+        match &self.columns[feature] {
+            RawColumn::Dense(values) => values[row],
+            RawColumn::Sparse { rows, values } => match rows.binary_search(&row) {
+                Ok(entry) => values[entry],
+                Err(_) => 0.0,
+            },
+        }
+    }
+
+    pub fn templates(&self) -> &[Histogram] {
+        &self.templates
+    }
+
+    // The edges `feature` was binned at, or its categories for a categorical feature.
+    pub fn bin_edges(&self, feature: usize) -> &[f32] {
+        self.templates[feature].bins()
+    }
+
+    pub fn bins(&self, feature: usize) -> &FeatureBins {
+        &self.bins[feature]
+    }
+
+    pub fn num_features(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+}
+
+fn dense_bins(template: &Histogram, column: &[f32]) -> FeatureBins {
+    let indices = column.iter().map(|value| template.search_bin_index(value));
+    // bin indices go up to the missing bin at `num_bins`
+    if template.num_bins() < 1 << 8 {
+        FeatureBins::U8(indices.map(|bin| bin as u8).collect())
+    } else {
+        FeatureBins::U16(indices.map(|bin| bin as u16).collect())
+    }
+}
+
+fn sparse_bins(template: &Histogram, rows: &[usize], values: &[f32]) -> FeatureBins {
+    FeatureBins::Sparse {
+        rows: rows.to_vec(),
+        bins: values
+            .iter()
+            .map(|value| template.search_bin_index(value) as u16)
+            .collect(),
+        zero_bin: if template.num_bins() > 0 {
+            template.search_bin_index(&0.0)
+        } else {
+            0
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::Rng;
+
+    #[test]
+    fn test_with_groups_builds_boundaries() {
+        let features = vec![vec![0.0]; 6];
+        let labels = vec![0.0; 6];
+        let dataset = Dataset::new(features, labels).with_groups(&[2, 3, 1]);
+
+        assert_eq!(dataset.query_boundaries(), Some(&[0, 2, 5, 6][..]));
+        assert_eq!(dataset.num_rows(), 6);
+        assert_eq!(dataset.num_features(), 1);
+    }
+
+    #[test]
+    fn test_binned_dataset_stores_bin_indices() {
+        let narrow: Vec<f32> = (0..10).map(|i| (i % 5) as f32).collect();
+        let wide: Vec<f32> = (0..1000).map(|i| i as f32).collect();
+        let binned = BinnedDataset::from_columns(vec![narrow[..].repeat(100), wide], 1000);
+
+        let FeatureBins::U8(bins) = binned.bins(0) else {
+            panic!("4 bins fit in a byte");
+        };
+        assert_eq!(&bins[..5], &[0, 1, 2, 3, 3]);
+        assert!(matches!(binned.bins(1), FeatureBins::U16(bins) if bins[999] == 998));
+
+        // accumulating by bin index gives the histogram of searching the raw values
+        let rows: Vec<usize> = (0..1000).step_by(3).collect();
+        let gradients: Vec<f32> = (0..1000).map(|i| i as f32 * 0.5).collect();
+        let hessians = vec![1.0; 1000];
+        for feature in 0..2 {
+            let mut expected = binned.templates()[feature].clone();
+            expected.accumulate(
+                &rows
+                    .iter()
+                    .map(|&i| binned.value(feature, i))
+                    .collect::<Vec<_>>(),
+                &rows.iter().map(|&i| gradients[i]).collect::<Vec<_>>(),
+                &rows.iter().map(|&i| hessians[i]).collect::<Vec<_>>(),
+                None,
+            );
+            let mut histogram = binned.templates()[feature].clone();
+            let sums = (rows.iter().map(|&i| gradients[i]).sum(), rows.len() as f32);
+            histogram.accumulate_bins(
+                binned.bins(feature),
+                &rows,
+                &gradients,
+                &hessians,
+                None,
+                sums,
+            );
+            assert_eq!(histogram.gradients(), expected.gradients());
+            assert_eq!(histogram.counts(), expected.counts());
+        }
+    }
+
+    #[test]
+    fn test_dataset_params_override_binning_per_feature() {
+        let rows: Vec<Vec<f32>> = (0..100).map(|i| vec![i as f32; 3]).collect();
+        let dataset = Dataset::new(rows, vec![0.0; 100]);
+        let params = DatasetParams {
+            max_bins_by_feature: vec![4],
+            forced_bins: BTreeMap::from([(2, vec![0.0, 10.0, 50.0, 99.0])]),
+            ..DatasetParams::default()
+        };
+
+        let binned = BinnedDataset::new_with_params(&dataset, 16, &params);
+        assert_eq!(binned.templates()[0].num_bins(), 4);
+        assert_eq!(binned.templates()[1].num_bins(), 16);
+        assert_eq!(binned.templates()[2].bins(), &[0.0, 10.0, 50.0, 99.0]);
+        let FeatureBins::U8(bins) = binned.bins(2) else {
+            panic!("3 bins fit in a byte");
+        };
+        assert_eq!((bins[9], bins[10], bins[99]), (0, 1, 2));
+    }
+
+    #[test]
+    fn test_binning_strategies_place_edges() {
+        // a skewed feature: quantile edges crowd the dense low end, equal-width ones don't
+        let rows: Vec<Vec<f32>> = (0..100).map(|i| vec![(i * i) as f32; 2]).collect();
+        let dataset = Dataset::new(rows, vec![0.0; 100]);
+        let binned = |binning_strategy| {
+            let params = DatasetParams {
+                binning_strategy,
+                ..DatasetParams::default()
+            };
+            BinnedDataset::new_with_params(&dataset, 4, &params)
+        };
+
+        let quantile = binned(BinningStrategy::Quantile);
+        assert!(quantile.bin_edges(0)[1] < 9801.0 / 4.0);
+        let equal_width = binned(BinningStrategy::EqualWidth);
+        assert_eq!(
+            equal_width.bin_edges(0),
+            &[0.0, 2450.25, 4900.5, 7350.75, 9801.0]
+        );
+        let custom = binned(BinningStrategy::Custom(vec![vec![0.0, 100.0, 9801.0]]));
+        assert_eq!(custom.bin_edges(0), &[0.0, 100.0, 9801.0]);
+        assert_eq!(custom.bin_edges(1), quantile.bin_edges(1));
+    }
+
+    #[test]
+    fn test_row_parallel_histograms_match_feature_parallel() {
+        // enough rows for several chunks, with a bundle and sparse columns
+        let n = 3 * ROWS_PER_CHUNK;
+        let rows: Vec<Vec<f32>> = (0..n)
+            .map(|i| {
+                vec![
+                    (i % 4 == 0) as u8 as f32 * (i % 3 + 1) as f32,
+                    (i % 4 == 1) as u8 as f32 * (i % 5 + 1) as f32,
+                    (i % 7) as f32,
+                ]
+            })
+            .collect();
+        let dataset = Dataset::from_csr(CsrMatrix::from_dense(&rows), vec![0.0; n]);
+        let binned = |parallelism| {
+            BinnedDataset::new(&dataset, 255)
+                .with_bundles(0.0)
+                .with_parallelism(parallelism)
+        };
+        let (by_features, by_rows) = (
+            binned(HistogramParallelism::Features),
+            binned(HistogramParallelism::Rows),
+        );
+        assert_eq!(by_rows.num_bundles(), 1);
+
+        let node: Vec<usize> = (0..n).filter(|i| i % 3 != 1).collect();
+        // small integers, so every order of summing gives the same f32
+        let gradients: Vec<f32> = (0..n).map(|i| (i % 5) as f32 - 2.0).collect();
+        let hessians = vec![0.5; n];
+        let sums = (
+            node.iter().map(|&i| gradients[i]).sum(),
+            node.iter().map(|&i| hessians[i]).sum(),
+        );
+        let features = [0, 1, 2];
+        let expected = by_features.histograms(&features, &node, &gradients, &hessians, None, sums);
+        let histograms = by_rows.histograms(&features, &node, &gradients, &hessians, None, sums);
+        for (histogram, expected) in histograms.iter().zip(&expected) {
+            assert_eq!(histogram.gradients(), expected.gradients());
+            assert_eq!(histogram.hessians(), expected.hessians());
+            assert_eq!(histogram.counts(), expected.counts());
+        }
+    }
+
+    #[test]
+    fn test_bundled_histograms_match_unbundled() {
+        // four columns that are never non-zero together and one that overlaps with all of them
+        let columns: Vec<Vec<f32>> = (0..4)
+            .map(|j| {
+                (0..60)
+                    .map(|i| (i % 4 == j) as u8 as f32 * (i % 3 + 1) as f32)
+                    .collect()
+            })
+            .chain([(0..60).map(|i| (i % 3) as f32).collect()])
+            .collect();
+        let plain = BinnedDataset::from_columns(columns.clone(), 255);
+        let bundled = BinnedDataset::from_columns(columns, 255).with_bundles(0.0);
+        assert_eq!(bundled.num_bundles(), 1);
+        assert!(matches!(
+            bundled.bins(2),
+            FeatureBins::Bundled { bundle: 0, .. }
+        ));
+        assert!(matches!(bundled.bins(4), FeatureBins::U8(_)));
+
+        let rows: Vec<usize> = (0..60).filter(|i| i % 7 != 2).collect();
+        let gradients: Vec<f32> = (0..60).map(|i| (i % 5) as f32 - 1.5).collect();
+        let hessians = vec![0.5; 60];
+        let sums = (
+            rows.iter().map(|&i| gradients[i]).sum(),
+            rows.iter().map(|&i| hessians[i]).sum(),
+        );
+        let features: Vec<usize> = (0..5).collect();
+        let expected = plain.histograms(&features, &rows, &gradients, &hessians, None, sums);
+        let histograms = bundled.histograms(&features, &rows, &gradients, &hessians, None, sums);
+        for (histogram, expected) in histograms.iter().zip(&expected) {
+            assert_eq!(histogram.counts(), expected.counts());
+            for (value, expected) in histogram.gradients().iter().zip(expected.gradients()) {
+                assert!((value - expected).abs() < 1e-4);
+            }
+            for (value, expected) in histogram.hessians().iter().zip(expected.hessians()) {
+                assert!((value - expected).abs() < 1e-4);
+            }
+        }
+
+        // overlapping features are only bundled within the conflict budget
+        let overlapping = || {
+            BinnedDataset::from_columns(
+                vec![
+                    (0..60).map(|i| (i % 3) as f32).collect(),
+                    (0..60).map(|i| (i % 4) as f32).collect(),
+                ],
+                255,
+            )
+        };
+        assert_eq!(overlapping().with_bundles(0.0).num_bundles(), 0);
+        assert_eq!(overlapping().with_bundles(0.99).num_bundles(), 1);
+    }
+
+    #[test]
+    fn test_sparse_binning_matches_dense() {
+        let rows: Vec<Vec<f32>> = (0..40)
+            .map(|i| {
+                let one_hot = (0..4).map(|j| (i % 4 == j) as u8 as f32);
+                one_hot
+                    .chain([if i % 3 == 0 { i as f32 } else { 0.0 }])
+                    .collect()
+            })
+            .collect();
+        let labels = vec![0.0; 40];
+        let dense = BinnedDataset::new(&Dataset::new(rows.clone(), labels.clone()), 255);
+        let sparse = BinnedDataset::new(
+            &Dataset::from_csr(CsrMatrix::from_dense(&rows), labels),
+            255,
+        );
+
+        let node: Vec<usize> = (0..40).filter(|i| i % 5 != 1).collect();
+        let gradients: Vec<f32> = (0..40).map(|i| (i % 7) as f32 - 3.0).collect();
+        let hessians = vec![1.0; 40];
+        let weights: Vec<f32> = (0..40).map(|i| 1.0 + (i % 2) as f32).collect();
+        let sums = (
+            node.iter().map(|&i| weights[i] * gradients[i]).sum(),
+            node.iter().map(|&i| weights[i] * hessians[i]).sum(),
+        );
+        for (feature, &value) in rows[3].iter().enumerate() {
+            assert_eq!(
+                dense.templates()[feature].bins(),
+                sparse.templates()[feature].bins()
+            );
+            assert_eq!(sparse.value(feature, 3), value);
+
+            let mut expected = dense.templates()[feature].clone();
+            let mut histogram = expected.clone();
+            for (binned, histogram) in [(&dense, &mut expected), (&sparse, &mut histogram)] {
+                histogram.accumulate_bins(
+                    binned.bins(feature),
+                    &node,
+                    &gradients,
+                    &hessians,
+                    Some(&weights),
+                    sums,
+                );
+            }
+            assert_eq!(histogram.counts(), expected.counts());
+            for (value, expected) in histogram.gradients().iter().zip(expected.gradients()) {
+                assert!((value - expected).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_quantized_sparse_histograms_match_dense() {
+        let rows: Vec<Vec<f32>> = (0..40)
+            .map(|i| vec![if i % 3 == 0 { (i % 5) as f32 } else { 0.0 }])
+            .collect();
+        let labels = vec![0.0; 40];
+        let dense = BinnedDataset::new(&Dataset::new(rows.clone(), labels.clone()), 255);
+        let sparse = BinnedDataset::new(
+            &Dataset::from_csr(CsrMatrix::from_dense(&rows), labels),
+            255,
+        );
+        let gradients: Vec<f32> = (0..40).map(|i| (i % 7) as f32 - 3.0).collect();
+        let quantized = QuantizedGradients::new(&gradients, &[1.0; 40], None, 16, &mut Rng::new(1));
+
+        let node: Vec<usize> = (0..40).filter(|i| i % 5 != 1).collect();
+        let mut expected = dense.templates().to_vec();
+        dense.fill_quantized_histograms(&mut expected, &[0], &node, &quantized);
+        let mut histograms = sparse.templates().to_vec();
+        sparse.fill_quantized_histograms(&mut histograms, &[0], &node, &quantized);
+        // integer sums leave no rounding differences
+        assert_eq!(histograms[0].gradients(), expected[0].gradients());
+        assert_eq!(histograms[0].hessians(), expected[0].hessians());
+        assert_eq!(histograms[0].counts(), expected[0].counts());
+    }
+
+    #[test]
+    #[should_panic(expected = "one weight per row")]
+    fn test_with_weights_rejects_wrong_length() {
+        let dataset = Dataset::new(vec![vec![0.0]; 3], vec![0.0; 3]);
+        let _ = dataset.with_weights(vec![1.0; 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "group sizes")]
+    fn test_with_groups_rejects_wrong_total() {
+        let dataset = Dataset::new(vec![vec![0.0]; 3], vec![0.0; 3]);
+        let _ = dataset.with_groups(&[1, 1]);
+    }
+}
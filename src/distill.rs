@@ -0,0 +1,138 @@
+use crate::booster::Booster;
+use crate::dataset::Dataset;
+use crate::error::Result;
+use crate::model::Model;
+use crate::objective::ObjectiveType;
+use crate::params::Params;
+use crate::tree::{Tree, TreeNode};
+
+// Trains a student model on `teacher`'s predictions for the rows of `dataset`, e.g. a
+// smaller ensemble for serving with fewer iterations, fewer leaves or a lower `max_depth`
+// in `params`. The student learns the teacher's raw scores by squared error, one booster
+// per output, and predicts with the teacher's objective, so `params.objective` and its
+// class weights are ignored. The dataset's labels aren't used; its weights are.
+pub fn distill(teacher: &Model, dataset: &Dataset, params: &Params) -> Result<Model> {
+    let scores = teacher.raw_scores_of(dataset)?;
+    let student_params = Params {
+        objective: ObjectiveType::SquaredError,
+        class_weights: vec![],
+        is_unbalance: false,
+        ..params.clone()
+    };
+
+    let mut students = Vec::with_capacity(scores.len());
+    for output_scores in scores {
+        let mut booster = Booster::new();
+        booster.train(&dataset.with_labels(output_scores), &student_params)?;
+        students.push(booster.to_model());
+    }
+
+    // a round of trees for every output, students that stopped early (e.g. on
+    // `max_train_seconds`) padded with single leaves of zero
+    let num_rounds = students.iter().map(|student| student.trees.len()).max();
+    let num_rounds = num_rounds.unwrap_or(0);
+    let mut trees = Vec::with_capacity(num_rounds * students.len());
+    for round in 0..num_rounds {
+        for student in &students {
+            trees.push(match student.trees.get(round) {
+                Some(tree) => tree.clone(),
+                None => Tree::new(Box::new(TreeNode::Leaf { value: 0.0 })),
+            });
+        }
+    }
+
+    let base_scores = students
+        .iter()
+        .map(|student| student.base_scores[0])
+        .collect();
+    let mut model = students.swap_remove(0);
+    model.params = Params {
+        objective: params.objective,
+        ..student_params
+    };
+    model.objective = teacher.objective;
+    model.trees = trees;
+    model.num_outputs = teacher.num_outputs;
+    model.base_scores = base_scores;
+    model.best_iteration = None;
+    Ok(model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn train(dataset: &Dataset, params: &Params) -> Model {
+        let mut booster = Booster::new();
+        booster.train(dataset, params).unwrap();
+        booster.to_model()
+    }
+
+    #[test]
+    fn test_student_predicts_like_the_teacher() {
+        let features: Vec<Vec<f32>> = (0..200)
+            .map(|i| vec![(i % 20) as f32, ((i * 7) % 13) as f32])
+            .collect();
+        let labels: Vec<f32> = features
+            .iter()
+            .map(|x| if x[0] + x[1] > 15.0 { 1.0 } else { 0.0 })
+            .collect();
+        let dataset = Dataset::new(features.clone(), labels);
+        let teacher = train(
+            &dataset,
+            &Params {
+                objective: ObjectiveType::BinaryLogistic,
+                num_iterations: 50,
+                ..Params::default()
+            },
+        );
+
+        let params = Params {
+            num_iterations: 20,
+            learning_rate: 0.3,
+            num_leaves: 4,
+            ..Params::default()
+        };
+        let student = distill(&teacher, &dataset, &params).unwrap();
+        assert_eq!(student.trees().len(), 20);
+        assert_eq!(student.objective(), Some(ObjectiveType::BinaryLogistic));
+        assert!(student.summary().num_leaves < teacher.summary().num_leaves);
+        let (teacher, student) = (Booster::from_model(teacher), Booster::from_model(student));
+        let mean_error = features
+            .iter()
+            .map(|row| (teacher.predict(row) - student.predict(row)).abs())
+            .sum::<f32>()
+            / features.len() as f32;
+        assert!(mean_error < 0.05, "{mean_error}");
+    }
+
+    #[test]
+    fn test_interleaves_the_outputs_of_multiclass_teachers() {
+        let features: Vec<Vec<f32>> = (0..90).map(|i| vec![(i % 9) as f32]).collect();
+        let labels: Vec<f32> = (0..90).map(|i| ((i % 9) / 3) as f32).collect();
+        let dataset = Dataset::new(features, labels);
+        let objective = ObjectiveType::Multiclass { num_class: 3 };
+        let teacher = train(
+            &dataset,
+            &Params {
+                objective,
+                num_iterations: 10,
+                ..Params::default()
+            },
+        );
+
+        let params = Params {
+            num_iterations: 5,
+            ..Params::default()
+        };
+        let student = distill(&teacher, &dataset, &params).unwrap();
+        assert_eq!(student.trees().len(), 15);
+        assert_eq!(student.params().objective, ObjectiveType::SquaredError);
+        let student = Booster::from_model(student);
+        for (row, class) in [(0.0, 0), (4.0, 1), (8.0, 2)] {
+            let proba = student.predict_proba(&[row]);
+            let predicted = (0..3).max_by(|&i, &j| proba[i].total_cmp(&proba[j]));
+            assert_eq!(predicted, Some(class), "{proba:?}");
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::fmt::Write;
+
+use crate::tree::{Tree, TreeNode};
+
+// Graphviz DOT source drawing `tree`, e.g. for `dot -Tsvg`. Splits show their condition,
+// which sends rows left along the edge marked "yes", with their gain and the rows that
+// reached them when the tree records those; missing values follow the edge marked
+// "missing". Leaves show their value with the tree's shrinkage applied, which is what they
+// add to the score. Features are called by `feature_names` when given, `f{i}` otherwise.
+pub fn tree_to_dot(tree: &Tree, feature_names: Option<&[String]>) -> String {
+    let mut writer = DotWriter {
+        tree,
+        feature_names,
+        lines: Vec::new(),
+        num_nodes: 0,
+        leaf_covers: tree.leaf_covers().map(|covers| covers.iter()),
+        split_gains: tree.split_gains().map(|gains| gains.iter()),
+    };
+    writer.write_node(tree.root());
+
+    let mut dot = String::new();
+    writeln!(dot, "digraph tree {{").unwrap();
+    writeln!(dot, "    node [shape=box];").unwrap();
+    for line in writer.lines {
+        writeln!(dot, "    {line}").unwrap();
+    }
+    writeln!(dot, "}}").unwrap();
+    dot
+}
+
+struct DotWriter<'a> {
+    tree: &'a Tree,
+    feature_names: Option<&'a [String]>,
+    lines: Vec<String>,
+    num_nodes: usize,
+    // the covers of the leaves and the gains of the splits still to be written, since
+    // leaves are written left to right and splits in preorder
+    leaf_covers: Option<std::slice::Iter<'a, f32>>,
+    split_gains: Option<std::slice::Iter<'a, f32>>,
+}
+
+impl DotWriter<'_> {
+    // Writes `node` and the nodes below it; returns its id and the rows that reached it.
+    fn write_node(&mut self, node: &TreeNode) -> (usize, Option<f32>) {
+        let id = self.num_nodes;
+        self.num_nodes += 1;
+        let (condition, missing_left, left_child, right_child) = match node {
+            TreeNode::Leaf { value } => {
+                let label = format!("{}", self.tree.shrinkage() * value);
+                return (id, self.write_leaf(id, label));
+            }
+            TreeNode::LinearLeaf {
+                value,
+                intercept,
+                feature_indices,
+                coefficients,
+            } => {
+                let shrinkage = self.tree.shrinkage();
+                let mut label = format!("{}", shrinkage * intercept);
+                for (&feature_index, coefficient) in feature_indices.iter().zip(coefficients) {
+                    write!(
+                        label,
+                        " + {} * {}",
+                        shrinkage * coefficient,
+                        self.feature(feature_index)
+                    )
+                    .unwrap();
+                }
+                write!(label, "\nmissing: {}", shrinkage * value).unwrap();
+                return (id, self.write_leaf(id, label));
+            }
+            TreeNode::Split {
+                feature_index,
+                threshold,
+                default_left,
+                left_child,
+                right_child,
+            } => (
+                format!("{} < {threshold}", self.feature(*feature_index)),
+                *default_left,
+                left_child,
+                right_child,
+            ),
+            TreeNode::CategoricalSplit {
+                feature_index,
+                categories,
+                left_child,
+                right_child,
+            } => {
+                let categories: Vec<String> = categories.iter().map(u32::to_string).collect();
+                (
+                    format!(
+                        "{} in {{{}}}",
+                        self.feature(*feature_index),
+                        categories.join(", ")
+                    ),
+                    false,
+                    left_child,
+                    right_child,
+                )
+            }
+        };
+
+        let gain = self.split_gains.as_mut().and_then(Iterator::next).copied();
+        let (left, left_cover) = self.write_node(left_child);
+        let (right, right_cover) = self.write_node(right_child);
+        let cover = left_cover
+            .zip(right_cover)
+            .map(|(left, right)| left + right);
+        let mut label = condition;
+        if let Some(gain) = gain {
+            write!(label, "\ngain {gain}").unwrap();
+        }
+        if let Some(cover) = cover {
+            write!(label, "\n{cover} rows").unwrap();
+        }
+        self.lines
+            .push(format!("{id} [label=\"{}\"];", escape(&label)));
+        let [left_label, right_label] = if missing_left {
+            ["yes, missing", "no"]
+        } else {
+            ["yes", "no, missing"]
+        };
+        self.lines
+            .push(format!("{id} -> {left} [label=\"{left_label}\"];"));
+        self.lines
+            .push(format!("{id} -> {right} [label=\"{right_label}\"];"));
+        (id, cover)
+    }
+
+    fn write_leaf(&mut self, id: usize, mut label: String) -> Option<f32> {
+        let cover = self.leaf_covers.as_mut().and_then(Iterator::next).copied();
+        if let Some(cover) = cover {
+            write!(label, "\n{cover} rows").unwrap();
+        }
+        self.lines.push(format!(
+            "{id} [label=\"{}\", shape=ellipse];",
+            escape(&label)
+        ));
+        cover
+    }
+
+    fn feature(&self, feature_index: usize) -> String {
+        match self
+            .feature_names
+            .and_then(|names| names.get(feature_index))
+        {
+            Some(name) => name.clone(),
+            None => format!("f{feature_index}"),
+        }
+    }
+}
+
+// `text` inside a quoted DOT string, lines separated by DOT's centered line breaks
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_draws_splits_and_leaves() {
+        let tree = Tree::new(Box::new(TreeNode::Split {
+            feature_index: 0,
+            threshold: 2.5,
+            default_left: true,
+            left_child: Box::new(TreeNode::Leaf { value: 1.0 }),
+            right_child: Box::new(TreeNode::CategoricalSplit {
+                feature_index: 1,
+                categories: vec![3, 9],
+                left_child: Box::new(TreeNode::Leaf { value: -2.0 }),
+                right_child: Box::new(TreeNode::Leaf { value: 4.0 }),
+            }),
+        }))
+        .with_shrinkage(0.5)
+        .with_leaf_covers(vec![10.0, 20.0, 30.0])
+        .with_split_gains(vec![7.5, 1.25]);
+
+        let names = ["size".to_string(), "say \"color\"".to_string()];
+        assert_eq!(
+            tree_to_dot(&tree, Some(&names)),
+            "\
+digraph tree {
+    node [shape=box];
+    1 [label=\"0.5\\n10 rows\", shape=ellipse];
+    3 [label=\"-1\\n20 rows\", shape=ellipse];
+    4 [label=\"2\\n30 rows\", shape=ellipse];
+    2 [label=\"say \\\"color\\\" in {3, 9}\\ngain 1.25\\n50 rows\"];
+    2 -> 3 [label=\"yes\"];
+    2 -> 4 [label=\"no, missing\"];
+    0 [label=\"size < 2.5\\ngain 7.5\\n60 rows\"];
+    0 -> 1 [label=\"yes, missing\"];
+    0 -> 2 [label=\"no\"];
+}
+"
+        );
+
+        // without names, covers or gains
+        let tree = Tree::new(Box::new(TreeNode::Split {
+            feature_index: 3,
+            threshold: 1.0,
+            default_left: false,
+            left_child: Box::new(TreeNode::Leaf { value: 1.0 }),
+            right_child: Box::new(TreeNode::Leaf { value: 2.0 }),
+        }));
+        let dot = tree_to_dot(&tree, None);
+        assert!(dot.contains("0 [label=\"f3 < 1\"];"), "{dot}");
+        assert!(dot.contains("2 [label=\"2\", shape=ellipse];"), "{dot}");
+    }
+}
@@ -0,0 +1,193 @@
+use std::fmt::Write;
+
+use crate::tree::{Tree, TreeNode};
+
+// A text listing of `tree`, one node per line in preorder indented by a tab per level, in
+// the style of XGBoost's text dump:
+//
+//     0:[f0<2.5] yes=1,no=2,missing=1,gain=7.5,cover=60
+//         1:leaf=0.5,cover=10
+//         2:leaf=-1,cover=50
+//
+// Splits send rows to `yes` when they meet the condition; gains and covers (the rows that
+// reached the node) are listed when the tree records them. Leaf values have the tree's
+// shrinkage applied. Features are called by `feature_names` when given, `f{i}` otherwise.
+pub fn tree_to_text(tree: &Tree, feature_names: Option<&[String]>) -> String {
+    let mut dumper = TextDumper {
+        tree,
+        feature_names,
+        num_nodes: 0,
+        leaf_covers: tree.leaf_covers().map(|covers| covers.iter()),
+        split_gains: tree.split_gains().map(|gains| gains.iter()),
+    };
+    dumper.dump_node(tree.root(), 0).text
+}
+
+struct TextDumper<'a> {
+    tree: &'a Tree,
+    feature_names: Option<&'a [String]>,
+    num_nodes: usize,
+    // as in `dot::DotWriter`, leaves are visited left to right and splits in preorder
+    leaf_covers: Option<std::slice::Iter<'a, f32>>,
+    split_gains: Option<std::slice::Iter<'a, f32>>,
+}
+
+struct Dumped {
+    id: usize,
+    cover: Option<f32>,
+    text: String,
+}
+
+impl TextDumper<'_> {
+    fn dump_node(&mut self, node: &TreeNode, depth: usize) -> Dumped {
+        let id = self.num_nodes;
+        self.num_nodes += 1;
+        let indent = "\t".repeat(depth);
+        let shrinkage = self.tree.shrinkage();
+        let (condition, missing_left, left_child, right_child) = match node {
+            TreeNode::Leaf { value } => {
+                return self.dump_leaf(id, format!("{indent}{id}:leaf={}", shrinkage * value));
+            }
+            TreeNode::LinearLeaf {
+                value,
+                intercept,
+                feature_indices,
+                coefficients,
+            } => {
+                let mut line = format!("{indent}{id}:leaf={}", shrinkage * intercept);
+                for (&feature_index, coefficient) in feature_indices.iter().zip(coefficients) {
+                    write!(
+                        line,
+                        "+{}*{}",
+                        shrinkage * coefficient,
+                        self.feature(feature_index)
+                    )
+                    .unwrap();
+                }
+                write!(line, ",missing_leaf={}", shrinkage * value).unwrap();
+                return self.dump_leaf(id, line);
+            }
+            TreeNode::Split {
+                feature_index,
+                threshold,
+                default_left,
+                left_child,
+                right_child,
+            } => (
+                format!("{}<{threshold}", self.feature(*feature_index)),
+                *default_left,
+                left_child,
+                right_child,
+            ),
+            TreeNode::CategoricalSplit {
+                feature_index,
+                categories,
+                left_child,
+                right_child,
+            } => {
+                let categories: Vec<String> = categories.iter().map(u32::to_string).collect();
+                (
+                    format!(
+                        "{} in {{{}}}",
+                        self.feature(*feature_index),
+                        categories.join(",")
+                    ),
+                    false,
+                    left_child,
+                    right_child,
+                )
+            }
+        };
+
+        let gain = self.split_gains.as_mut().and_then(Iterator::next).copied();
+        let left = self.dump_node(left_child, depth + 1);
+        let right = self.dump_node(right_child, depth + 1);
+        let cover = left
+            .cover
+            .zip(right.cover)
+            .map(|(left, right)| left + right);
+        let missing = if missing_left { left.id } else { right.id };
+        let mut text = format!(
+            "{indent}{id}:[{condition}] yes={},no={},missing={missing}",
+            left.id, right.id
+        );
+        if let Some(gain) = gain {
+            write!(text, ",gain={gain}").unwrap();
+        }
+        if let Some(cover) = cover {
+            write!(text, ",cover={cover}").unwrap();
+        }
+        text.push('\n');
+        text.push_str(&left.text);
+        text.push_str(&right.text);
+        Dumped { id, cover, text }
+    }
+
+    fn dump_leaf(&mut self, id: usize, mut text: String) -> Dumped {
+        let cover = self.leaf_covers.as_mut().and_then(Iterator::next).copied();
+        if let Some(cover) = cover {
+            write!(text, ",cover={cover}").unwrap();
+        }
+        text.push('\n');
+        Dumped { id, cover, text }
+    }
+
+    fn feature(&self, feature_index: usize) -> String {
+        match self
+            .feature_names
+            .and_then(|names| names.get(feature_index))
+        {
+            Some(name) => name.clone(),
+            None => format!("f{feature_index}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lists_nodes_in_preorder() {
+        let tree = Tree::new(Box::new(TreeNode::Split {
+            feature_index: 0,
+            threshold: 2.5,
+            default_left: true,
+            left_child: Box::new(TreeNode::Leaf { value: 1.0 }),
+            right_child: Box::new(TreeNode::CategoricalSplit {
+                feature_index: 1,
+                categories: vec![3, 9],
+                left_child: Box::new(TreeNode::Leaf { value: -2.0 }),
+                right_child: Box::new(TreeNode::Leaf { value: 4.0 }),
+            }),
+        }))
+        .with_shrinkage(0.5)
+        .with_leaf_covers(vec![10.0, 20.0, 30.0])
+        .with_split_gains(vec![7.5, 1.25]);
+
+        let names = ["size".to_string(), "color".to_string()];
+        assert_eq!(
+            tree_to_text(&tree, Some(&names)),
+            "\
+0:[size<2.5] yes=1,no=2,missing=1,gain=7.5,cover=60
+\t1:leaf=0.5,cover=10
+\t2:[color in {3,9}] yes=3,no=4,missing=4,gain=1.25,cover=50
+\t\t3:leaf=-1,cover=20
+\t\t4:leaf=2,cover=30
+"
+        );
+
+        // without names, covers or gains
+        let tree = Tree::new(Box::new(TreeNode::Split {
+            feature_index: 3,
+            threshold: 1.0,
+            default_left: false,
+            left_child: Box::new(TreeNode::Leaf { value: 1.0 }),
+            right_child: Box::new(TreeNode::Leaf { value: 2.0 }),
+        }));
+        assert_eq!(
+            tree.to_string(),
+            "0:[f3<1] yes=1,no=2,missing=2\n\t1:leaf=1\n\t2:leaf=2\n"
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    // a parameter is out of range or inconsistent with another parameter
+    InvalidParameter { name: &'static str, reason: String },
+    // the training or validation data doesn't fit the model being trained
+    InvalidData { reason: String },
+    // reading or writing a file failed
+    Io { reason: String },
+    // a file was read but doesn't hold what it should, e.g. a corrupt checkpoint
+    Format { reason: String },
+}
+
+impl Error {
+    pub(crate) fn invalid_parameter(name: &'static str, reason: impl Into<String>) -> Self {
+        Error::InvalidParameter {
+            name,
+            reason: reason.into(),
+        }
+    }
+
+    pub(crate) fn invalid_data(reason: impl Into<String>) -> Self {
+        Error::InvalidData {
+            reason: reason.into(),
+        }
+    }
+
+    pub(crate) fn io(reason: impl Into<String>) -> Self {
+        Error::Io {
+            reason: reason.into(),
+        }
+    }
+
+    pub(crate) fn format(reason: impl Into<String>) -> Self {
+        Error::Format {
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidParameter { name, reason } => {
+                write!(f, "invalid parameter `{name}`: {reason}")
+            }
+            Error::InvalidData { reason } => write!(f, "invalid data: {reason}"),
+            Error::Io { reason } => write!(f, "i/o error: {reason}"),
+            Error::Format { reason } => write!(f, "malformed file: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,602 @@
+use std::borrow::Cow;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::dataset::{BinnedDataset, FeatureBins};
+use crate::histogram::Histogram;
+use crate::tree::{self, FlatTree, Tree};
+
+#[cfg(all(feature = "metal", target_os = "macos"))]
+mod metal_backend;
+#[cfg(feature = "wgpu")]
+mod wgpu_backend;
+#[cfg(not(all(feature = "metal", target_os = "macos")))]
+use unavailable as metal_backend;
+#[cfg(not(feature = "wgpu"))]
+use unavailable as wgpu_backend;
+
+// Where node histograms are built.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Device {
+    #[default]
+    Cpu,
+    // on a GPU of the chosen `Backend`; training falls back to the CPU when no GPU can be
+    // used
+    Gpu,
+}
+
+// Which API `Device::Gpu` runs its kernels through. Each needs its crate feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Backend {
+    // Metal when it is usable, wgpu otherwise
+    #[default]
+    Auto,
+    // Apple GPUs, with the `metal` feature on macOS
+    Metal,
+    // Vulkan, DX12 or Metal through wgpu, with the `wgpu` feature
+    Wgpu,
+}
+
+// Time spent building node histograms on every device during a training, and how many
+// nodes each built. When the CPU builds some nodes of a level while the GPU builds the
+// others, both times count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DeviceTimings {
+    pub cpu: Duration,
+    pub cpu_nodes: usize,
+    pub gpu: Duration,
+    pub gpu_nodes: usize,
+}
+
+impl DeviceTimings {
+    pub fn add(&mut self, device: Device, elapsed: Duration, nodes: usize) {
+        match device {
+            Device::Cpu => {
+                self.cpu += elapsed;
+                self.cpu_nodes += nodes;
+            }
+            Device::Gpu => {
+                self.gpu += elapsed;
+                self.gpu_nodes += nodes;
+            }
+        }
+    }
+}
+
+// Features with more bins than this, the missing one included, are built on the CPU: every
+// threadgroup keeps a whole feature's histogram in its threadgroup memory.
+const MAX_BINS: usize = 1024;
+
+// The binned features of one dataset on the GPU. Dense features are uploaded once as 16-bit
+// columns and kept for every tree trained on them; sparse and bundled ones and those with
+// more than `MAX_BINS` bins are left to the CPU. The columns are written straight into
+// buffers the GPU reads: with unified memory, as on Apple Silicon, those are the same pages
+// the CPU wrote, so nothing is copied.
+pub struct GpuDataset {
+    bins: Box<dyn DeviceBins>,
+    // the uploaded column of every feature, None for features built on the CPU
+    columns: Vec<Option<usize>>,
+    // where every column's bins start in the histograms the GPU returns
+    offsets: Vec<usize>,
+}
+
+impl GpuDataset {
+    // Uploads the bins of `data`. None when there is no GPU to run on through `backend`,
+    // e.g. because the crate was built without its feature.
+    pub fn new(data: &BinnedDataset, backend: Backend) -> Option<Self> {
+        let num_rows = data.num_rows();
+        u32::try_from(num_rows).ok()?;
+        let mut columns = vec![None; data.num_features()];
+        let mut features = vec![];
+        let mut num_bins = vec![];
+        let mut offsets = vec![0];
+        for (feature, column) in columns.iter_mut().enumerate() {
+            // the missing bin comes after the others
+            let feature_bins = data.templates()[feature].num_bins() + 1;
+            if feature_bins > MAX_BINS
+                || !matches!(data.bins(feature), FeatureBins::U8(_) | FeatureBins::U16(_))
+            {
+                continue;
+            }
+            *column = Some(features.len());
+            features.push(feature);
+            num_bins.push(feature_bins as u32);
+            offsets.push(offsets[offsets.len() - 1] + feature_bins);
+        }
+        let layout = ColumnLayout {
+            num_bins,
+            offsets: offsets.iter().map(|&offset| offset as u32).collect(),
+            num_rows,
+        };
+        let fill = |column: usize, out: &mut [u16]| match data.bins(features[column]) {
+            FeatureBins::U8(bins) => {
+                for (out, &bin) in out.iter_mut().zip(bins) {
+                    *out = bin as u16;
+                }
+            }
+            FeatureBins::U16(bins) => out.copy_from_slice(bins),
+            FeatureBins::Sparse { .. } | FeatureBins::Bundled { .. } => {
+                unreachable!("only dense features are uploaded")
+            }
+        };
+        let bins = match backend {
+            Backend::Auto => metal_backend::upload(&layout, &fill)
+                .or_else(|| wgpu_backend::upload(&layout, &fill)),
+            Backend::Metal => metal_backend::upload(&layout, &fill),
+            Backend::Wgpu => wgpu_backend::upload(&layout, &fill),
+        }?;
+        Some(Self {
+            bins,
+            columns,
+            offsets,
+        })
+    }
+
+    // the number of features whose histograms are built on the GPU
+    pub fn num_uploaded_features(&self) -> usize {
+        self.columns.iter().flatten().count()
+    }
+}
+
+// One training run's use of a `GpuDataset`: the current gradients and hessians and the
+// buffers every node's histograms are built in, all reused from tree to tree. The GPU adds
+// gradients in no particular order, so its sums may differ from the CPU's in the last bits.
+pub struct GpuContext<'a> {
+    dataset: &'a GpuDataset,
+    kernel: Box<dyn HistogramKernel>,
+}
+
+impl<'a> GpuContext<'a> {
+    pub fn new(dataset: &'a GpuDataset) -> Self {
+        Self {
+            kernel: dataset.bins.kernel(),
+            dataset,
+        }
+    }
+
+    // Uploads the gradients and hessians of the next tree, scaled by `weights`.
+    pub fn upload_gradients(
+        &mut self,
+        gradients: &[f32],
+        hessians: &[f32],
+        weights: Option<&[f32]>,
+    ) {
+        let weighted = |values: &[f32]| -> Vec<f32> {
+            match weights {
+                Some(weights) => values.iter().zip(weights).map(|(v, w)| v * w).collect(),
+                None => values.to_vec(),
+            }
+        };
+        self.kernel
+            .upload_gradients(&weighted(gradients), &weighted(hessians));
+    }
+
+    // Like `BinnedDataset::fill_histograms`, building the uploaded features on the GPU from
+    // the last uploaded gradients and the rest on the CPU from `gradients` and `hessians`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn fill_histograms(
+        &self,
+        data: &BinnedDataset,
+        histograms: &mut [Histogram],
+        features: &[usize],
+        rows: &[usize],
+        gradients: &[f32],
+        hessians: &[f32],
+        weights: Option<&[f32]>,
+        node_sums: (f32, f32),
+    ) {
+        let GpuDataset {
+            columns, offsets, ..
+        } = self.dataset;
+        let (on_gpu, on_cpu): (Vec<usize>, Vec<usize>) = features
+            .iter()
+            .partition(|&&feature| columns[feature].is_some());
+        if !on_cpu.is_empty() {
+            data.fill_histograms(
+                histograms, &on_cpu, rows, gradients, hessians, weights, node_sums,
+            );
+        }
+        if on_gpu.is_empty() || rows.is_empty() {
+            return;
+        }
+
+        let columns: Vec<u32> = on_gpu
+            .iter()
+            .map(|&feature| columns[feature].expect("uploaded") as u32)
+            .collect();
+        let rows: Vec<u32> = rows.iter().map(|&row| row as u32).collect();
+        let (sum_gradients, sum_hessians, counts) = self.kernel.histograms(&rows, &columns);
+        for (&feature, &column) in on_gpu.iter().zip(&columns) {
+            let column = column as usize;
+            let histogram = &mut histograms[feature];
+            for (bin, at) in (offsets[column]..offsets[column + 1]).enumerate() {
+                histogram.add_rounded(
+                    bin,
+                    (sum_gradients[at], sum_hessians[at], counts[at] as usize),
+                );
+            }
+        }
+    }
+}
+
+// kinds of `FlatNode`
+const LEAF: u32 = 0;
+const SPLIT_MISSING_RIGHT: u32 = 1;
+const SPLIT_MISSING_LEFT: u32 = 2;
+const CATEGORICAL_SPLIT: u32 = 3;
+
+// one node of a `FlatForest`, laid out like `Node` in the prediction shader
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FlatNode {
+    kind: u32,
+    feature: u32,
+    // the threshold of a split, the shrunken value of a leaf
+    value: f32,
+    left: u32,
+    right: u32,
+    // where a categorical split's categories are in `FlatForest::categories`
+    categories_start: u32,
+    categories_len: u32,
+}
+
+// Trees laid out in flat arrays for batch prediction, every tree's nodes in preorder, so
+// rows follow indices instead of pointers: on the GPU, and on the CPU where that is kinder
+// to the cache. Tree i adds to output i % num_outputs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlatForest {
+    nodes: Vec<FlatNode>,
+    // the first node of every tree
+    roots: Vec<u32>,
+    categories: Vec<u32>,
+    num_outputs: usize,
+    // one more than the highest feature split on
+    num_features: usize,
+}
+
+impl FlatForest {
+    // None when a tree has linear leaves, which only `Tree::predict_row` evaluates.
+    pub fn new(trees: &[Tree], num_outputs: usize) -> Option<Self> {
+        let mut forest = Self {
+            nodes: vec![],
+            roots: vec![],
+            categories: vec![],
+            num_outputs,
+            num_features: 0,
+        };
+        for tree in trees {
+            // trees from models saved without their flat form are flattened here
+            let flat = match tree.flat() {
+                Some(flat) => Cow::Borrowed(flat),
+                None => Cow::Owned(FlatTree::new(tree.root())?),
+            };
+            forest.push(&flat, tree.shrinkage());
+        }
+        Some(forest)
+    }
+
+    // Appends the nodes of `tree`, which keep its preorder.
+    fn push(&mut self, tree: &FlatTree, shrinkage: f32) {
+        let offset = self.nodes.len() as u32;
+        let categories_offset = self.categories.len() as u32;
+        self.roots.push(offset);
+        for index in 0..tree.num_nodes() {
+            let node = if tree.left[index] == 0 {
+                FlatNode {
+                    kind: LEAF,
+                    feature: 0,
+                    value: shrinkage * tree.leaf_value[index],
+                    left: 0,
+                    right: 0,
+                    categories_start: 0,
+                    categories_len: 0,
+                }
+            } else {
+                let feature = tree.feature_index[index];
+                self.num_features = self.num_features.max(feature as usize + 1);
+                let kind = if tree.categorical[index] {
+                    CATEGORICAL_SPLIT
+                } else if tree.default_left[index] {
+                    SPLIT_MISSING_LEFT
+                } else {
+                    SPLIT_MISSING_RIGHT
+                };
+                FlatNode {
+                    kind,
+                    feature,
+                    value: tree.threshold[index],
+                    left: offset + tree.left[index],
+                    right: offset + tree.right[index],
+                    categories_start: categories_offset + tree.category_start[index],
+                    categories_len: tree.category_start[index + 1] - tree.category_start[index],
+                }
+            };
+            self.nodes.push(node);
+        }
+        self.categories.extend_from_slice(&tree.categories);
+    }
+
+    // rows must have at least this many features
+    pub fn num_features(&self) -> usize {
+        self.num_features
+    }
+
+    // Adds the outputs of all trees for `row` to its `scores`, the sums `Tree::predict_row`
+    // gives in the same order.
+    pub fn predict_row(&self, row: &[f32], scores: &mut [f32]) {
+        for (tree, &root) in self.roots.iter().enumerate() {
+            let mut node = &self.nodes[root as usize];
+            while node.kind != LEAF {
+                node = &self.nodes[self.child(node, row[node.feature as usize]) as usize];
+            }
+            scores[tree % self.num_outputs] += node.value;
+        }
+    }
+
+    // Like `predict_row` for every row of the row-major `rows`, `num_features` values per row,
+    // and their `scores` laid out row by row. Blocks of rows walk every tree together, one
+    // level per pass, so a node is loaded once for all the block's rows at it rather than
+    // once per row, and rows are independent so their comparisons overlap. The sums are
+    // those of `predict_row`.
+    pub fn predict_block(&self, rows: &[f32], num_features: usize, scores: &mut [f32]) {
+        let mut indices = [0u32; BLOCK_ROWS];
+        for (block, block_scores) in rows
+            .chunks(BLOCK_ROWS * num_features)
+            .zip(scores.chunks_mut(BLOCK_ROWS * self.num_outputs))
+        {
+            let indices = &mut indices[..block.len() / num_features];
+            for (tree, &root) in self.roots.iter().enumerate() {
+                indices.fill(root);
+                let mut moved = true;
+                while moved {
+                    moved = false;
+                    for (row, index) in block.chunks_exact(num_features).zip(indices.iter_mut()) {
+                        let node = &self.nodes[*index as usize];
+                        if node.kind != LEAF {
+                            *index = self.child(node, row[node.feature as usize]);
+                            moved = true;
+                        }
+                    }
+                }
+                for (row_scores, &index) in block_scores
+                    .chunks_exact_mut(self.num_outputs)
+                    .zip(indices.iter())
+                {
+                    row_scores[tree % self.num_outputs] += self.nodes[index as usize].value;
+                }
+            }
+        }
+    }
+
+    // the child of the split `node` a row with `value` goes to
+    fn child(&self, node: &FlatNode, value: f32) -> u32 {
+        let left = match node.kind {
+            CATEGORICAL_SPLIT => {
+                let start = node.categories_start as usize;
+                let categories = &self.categories[start..start + node.categories_len as usize];
+                tree::in_categories(categories, value)
+            }
+            _ if value.is_nan() => node.kind == SPLIT_MISSING_LEFT,
+            _ => value < node.value,
+        };
+        if left { node.left } else { node.right }
+    }
+}
+
+// rows `FlatForest::predict_block` walks the trees with together
+const BLOCK_ROWS: usize = 64;
+
+// Rows the GPU scores per dispatch, which bounds the size of its buffers.
+const ROWS_PER_BATCH: usize = 1 << 20;
+
+// A `FlatForest` on the GPU, scoring one thread per row.
+pub struct GpuPredictor {
+    kernel: Box<dyn PredictKernel>,
+    num_outputs: usize,
+    num_features: usize,
+}
+
+impl GpuPredictor {
+    // Uploads `forest`. None when there is no GPU to run on through `backend`.
+    pub fn new(forest: &FlatForest, backend: Backend) -> Option<Self> {
+        let kernel = match backend {
+            Backend::Auto => {
+                metal_backend::predictor(forest).or_else(|| wgpu_backend::predictor(forest))
+            }
+            Backend::Metal => metal_backend::predictor(forest),
+            Backend::Wgpu => wgpu_backend::predictor(forest),
+        }?;
+        Some(Self {
+            kernel,
+            num_outputs: forest.num_outputs,
+            num_features: forest.num_features,
+        })
+    }
+
+    // The tree outputs for every row of the row-major `features`, `num_features` values per
+    // row, laid out row by row like `FlatForest::predict_row` fills them.
+    pub fn predict(&self, features: &[f32], num_features: usize) -> Vec<f32> {
+        assert!(num_features > 0, "rows need at least one feature");
+        assert!(
+            num_features >= self.num_features,
+            "rows are missing features the trees split on"
+        );
+        features
+            .chunks(ROWS_PER_BATCH * num_features)
+            .flat_map(|batch| self.kernel.predict(batch, num_features, self.num_outputs))
+            .collect()
+    }
+}
+
+// The dense columns `GpuDataset` uploads, every one `num_rows` bins long and stored one
+// after the other: for every column its number of bins and where they start in the sums a
+// kernel returns, which end at `offsets[num_columns]`.
+#[cfg_attr(
+    not(any(all(feature = "metal", target_os = "macos"), feature = "wgpu")),
+    allow(dead_code)
+)]
+struct ColumnLayout {
+    num_bins: Vec<u32>,
+    offsets: Vec<u32>,
+    num_rows: usize,
+}
+
+// Writes the bins of a column into the `num_rows` values given.
+type FillColumn<'a> = dyn Fn(usize, &mut [u16]) + 'a;
+
+// What every backend implements for `GpuDataset`: the uploaded bins.
+trait DeviceBins: Send + Sync {
+    // a kernel over these bins, with gradients of its own
+    fn kernel(&self) -> Box<dyn HistogramKernel>;
+}
+
+// What every backend implements for `GpuContext`.
+trait HistogramKernel: Send + Sync {
+    // the weighted gradients and hessians of every row for the next tree
+    fn upload_gradients(&mut self, gradients: &[f32], hessians: &[f32]);
+
+    // The gradient and hessian sums and counts of `rows` in every bin of `columns`, laid
+    // out by `ColumnLayout::offsets`.
+    fn histograms(&self, rows: &[u32], columns: &[u32]) -> (Vec<f32>, Vec<f32>, Vec<u32>);
+}
+
+// What every backend implements for `GpuPredictor`.
+trait PredictKernel: Send + Sync {
+    // the summed tree outputs of every row of `features`, row by row
+    fn predict(&self, features: &[f32], num_features: usize, num_outputs: usize) -> Vec<f32>;
+}
+
+// stands in for the backends the crate was built without
+#[cfg(any(
+    not(all(feature = "metal", target_os = "macos")),
+    not(feature = "wgpu")
+))]
+mod unavailable {
+    use super::{ColumnLayout, DeviceBins, FillColumn, FlatForest, PredictKernel};
+
+    pub fn upload(_layout: &ColumnLayout, _fill: &FillColumn) -> Option<Box<dyn DeviceBins>> {
+        None
+    }
+
+    pub fn predictor(_forest: &FlatForest) -> Option<Box<dyn PredictKernel>> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::Dataset;
+    use crate::tree::TreeNode;
+
+    #[test]
+    fn test_gpu_dataset_histograms_match_cpu() {
+        // the second feature has more bins than fit on the GPU
+        let rows: Vec<Vec<f32>> = (0..3000)
+            .map(|i| {
+                let first = if i % 11 == 0 {
+                    f32::NAN
+                } else {
+                    (i % 10) as f32
+                };
+                vec![first, i as f32]
+            })
+            .collect();
+        let binned = BinnedDataset::new(&Dataset::new(rows, vec![0.0; 3000]), 2000);
+        let node: Vec<usize> = (0..3000).filter(|i| i % 3 != 1).collect();
+        let weights: Vec<f32> = (0..3000).map(|i| 1.0 + (i % 2) as f32).collect();
+
+        for backend in [Backend::Metal, Backend::Wgpu] {
+            let Some(dataset) = GpuDataset::new(&binned, backend) else {
+                continue;
+            };
+            assert_eq!(dataset.num_uploaded_features(), 1);
+            // every training run reuses the uploaded bins with gradients of its own
+            for run in 0..2 {
+                let gradients: Vec<f32> = (0..3000).map(|i| ((i + run) % 7) as f32 - 3.0).collect();
+                let hessians = vec![1.0; 3000];
+                let sums = (
+                    node.iter().map(|&i| weights[i] * gradients[i]).sum(),
+                    node.iter().map(|&i| weights[i] * hessians[i]).sum(),
+                );
+                let expected =
+                    binned.histograms(&[0, 1], &node, &gradients, &hessians, Some(&weights), sums);
+
+                let mut gpu = GpuContext::new(&dataset);
+                gpu.upload_gradients(&gradients, &hessians, Some(&weights));
+                let mut histograms = binned.templates().to_vec();
+                gpu.fill_histograms(
+                    &binned,
+                    &mut histograms,
+                    &[0, 1],
+                    &node,
+                    &gradients,
+                    &hessians,
+                    Some(&weights),
+                    sums,
+                );
+                for (histogram, expected) in histograms.iter().zip(&expected) {
+                    assert_eq!(histogram.counts(), expected.counts());
+                    assert_eq!(histogram.missing().2, expected.missing().2);
+                    for (value, expected) in histogram.gradients().iter().zip(expected.gradients())
+                    {
+                        assert!((value - expected).abs() < 1e-3, "{value} vs {expected}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_predict_block_matches_predict_row() {
+        let leaf = |value| Box::new(TreeNode::Leaf { value });
+        let split = |feature_index, threshold, default_left, left_child, right_child| {
+            Box::new(TreeNode::Split {
+                feature_index,
+                threshold,
+                default_left,
+                left_child,
+                right_child,
+            })
+        };
+        let categorical = Box::new(TreeNode::CategoricalSplit {
+            feature_index: 1,
+            categories: vec![0, 3],
+            left_child: leaf(0.5),
+            right_child: split(0, 40.0, true, leaf(1.5), leaf(2.5)),
+        });
+        // rows reach leaves at different depths, and two outputs take turns
+        let trees = [
+            Tree::new(split(0, 10.0, false, leaf(1.0), categorical)),
+            Tree::new(leaf(0.25)).with_shrinkage(0.5),
+            Tree::new(split(
+                1,
+                2.0,
+                true,
+                split(0, 5.0, false, leaf(-1.0), leaf(1.0)),
+                leaf(3.0),
+            )),
+        ];
+        let forest = FlatForest::new(&trees, 2).unwrap();
+
+        // more rows than a block, and not a whole number of blocks
+        let rows: Vec<f32> = (0..150)
+            .flat_map(|i| {
+                let first = if i % 13 == 0 {
+                    f32::NAN
+                } else {
+                    (i % 50) as f32
+                };
+                [first, (i % 5) as f32]
+            })
+            .collect();
+        let mut expected = vec![0.0; 300];
+        for (row, scores) in rows.chunks(2).zip(expected.chunks_mut(2)) {
+            forest.predict_row(row, scores);
+        }
+        let mut scores = vec![0.0; 300];
+        forest.predict_block(&rows, 2, &mut scores);
+        assert_eq!(scores, expected);
+    }
+}
@@ -0,0 +1,496 @@
+use std::ffi::c_void;
+use std::sync::Mutex;
+
+use metal::{
+    Buffer, BufferRef, CommandQueue, CompileOptions, ComputePipelineState, DeviceRef,
+    MTLResourceOptions, MTLSize,
+};
+
+use super::{
+    ColumnLayout, DeviceBins, FillColumn, FlatForest, HistogramKernel, MAX_BINS, PredictKernel,
+};
+
+pub fn upload(layout: &ColumnLayout, fill: &FillColumn) -> Option<Box<dyn DeviceBins>> {
+    Some(Box::new(Bins::new(layout, fill)?))
+}
+
+pub fn predictor(forest: &FlatForest) -> Option<Box<dyn PredictKernel>> {
+    Some(Box::new(Predictor::new(forest)?))
+}
+
+// One threadgroup per column and block of the node's rows. Its threads add the rows'
+// gradients into a histogram in threadgroup memory, which is then added to the column's
+// histogram in device memory. Atomic floats need Metal 3, i.e. an Apple7 GPU or newer.
+const HISTOGRAM_SHADER: &str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+struct Params {
+    uint num_rows;
+    uint num_node_rows;
+    uint rows_per_group;
+};
+
+kernel void build_histograms(
+    device const ushort *bins [[buffer(0)]],
+    device const uint *num_bins [[buffer(1)]],
+    device const uint *offsets [[buffer(2)]],
+    device const float *gradients [[buffer(3)]],
+    device const float *hessians [[buffer(4)]],
+    device const uint *rows [[buffer(5)]],
+    device const uint *columns [[buffer(6)]],
+    device atomic_float *out_gradients [[buffer(7)]],
+    device atomic_float *out_hessians [[buffer(8)]],
+    device atomic_uint *out_counts [[buffer(9)]],
+    constant Params &params [[buffer(10)]],
+    uint2 group [[threadgroup_position_in_grid]],
+    uint lane [[thread_index_in_threadgroup]],
+    uint threads [[threads_per_threadgroup]])
+{
+    threadgroup atomic_float local_gradients[MAX_BINS];
+    threadgroup atomic_float local_hessians[MAX_BINS];
+    threadgroup atomic_uint local_counts[MAX_BINS];
+
+    uint column = columns[group.y];
+    uint n = num_bins[column];
+    for (uint bin = lane; bin < n; bin += threads) {
+        atomic_store_explicit(&local_gradients[bin], 0.0f, memory_order_relaxed);
+        atomic_store_explicit(&local_hessians[bin], 0.0f, memory_order_relaxed);
+        atomic_store_explicit(&local_counts[bin], 0u, memory_order_relaxed);
+    }
+    threadgroup_barrier(mem_flags::mem_threadgroup);
+
+    device const ushort *column_bins = bins + ulong(column) * params.num_rows;
+    uint begin = group.x * params.rows_per_group;
+    uint end = min(begin + params.rows_per_group, params.num_node_rows);
+    for (uint i = begin + lane; i < end; i += threads) {
+        uint row = rows[i];
+        uint bin = column_bins[row];
+        atomic_fetch_add_explicit(&local_gradients[bin], gradients[row], memory_order_relaxed);
+        atomic_fetch_add_explicit(&local_hessians[bin], hessians[row], memory_order_relaxed);
+        atomic_fetch_add_explicit(&local_counts[bin], 1u, memory_order_relaxed);
+    }
+    threadgroup_barrier(mem_flags::mem_threadgroup);
+
+    uint offset = offsets[column];
+    for (uint bin = lane; bin < n; bin += threads) {
+        uint count = atomic_load_explicit(&local_counts[bin], memory_order_relaxed);
+        if (count == 0) {
+            continue;
+        }
+        float gradient = atomic_load_explicit(&local_gradients[bin], memory_order_relaxed);
+        float hessian = atomic_load_explicit(&local_hessians[bin], memory_order_relaxed);
+        atomic_fetch_add_explicit(&out_gradients[offset + bin], gradient, memory_order_relaxed);
+        atomic_fetch_add_explicit(&out_hessians[offset + bin], hessian, memory_order_relaxed);
+        atomic_fetch_add_explicit(&out_counts[offset + bin], count, memory_order_relaxed);
+    }
+}
+"#;
+
+// node rows every threadgroup accumulates
+const ROWS_PER_GROUP: usize = 4096;
+const THREADS_PER_GROUP: u64 = 256;
+
+// matches `Params` in the shader
+#[repr(C)]
+struct Params {
+    num_rows: u32,
+    num_node_rows: u32,
+    rows_per_group: u32,
+}
+
+// The uploaded columns along with the device they are on. Metal objects are reference
+// counted, so every kernel shares them.
+struct Bins {
+    device: metal::Device,
+    pipeline: ComputePipelineState,
+    // shared with the CPU, which wrote the columns into it in place
+    bins: Buffer,
+    num_bins: Buffer,
+    offsets: Buffer,
+    num_rows: usize,
+    num_columns: usize,
+    // bins of all columns together
+    total_bins: usize,
+}
+
+impl Bins {
+    fn new(layout: &ColumnLayout, fill: &FillColumn) -> Option<Self> {
+        let ColumnLayout {
+            num_bins,
+            offsets,
+            num_rows,
+        } = layout;
+        let device = metal::Device::system_default()?;
+        let source = format!("#define MAX_BINS {MAX_BINS}\n{HISTOGRAM_SHADER}");
+        let pipeline = pipeline(&device, &source, "build_histograms")?;
+        let num_columns = num_bins.len();
+        let bins = zeroed::<u16>(&device, num_columns * num_rows);
+        if *num_rows > 0 {
+            // SAFETY: the buffer was just created with room for every column and nothing
+            // else uses it yet
+            let values = unsafe {
+                std::slice::from_raw_parts_mut(bins.contents() as *mut u16, num_columns * num_rows)
+            };
+            for (column, out) in values.chunks_exact_mut(*num_rows).enumerate() {
+                fill(column, out);
+            }
+        }
+        Some(Self {
+            pipeline,
+            bins,
+            num_bins: buffer(&device, num_bins),
+            offsets: buffer(&device, offsets),
+            num_rows: *num_rows,
+            num_columns,
+            total_bins: offsets[num_columns] as usize,
+            device,
+        })
+    }
+}
+
+impl DeviceBins for Bins {
+    fn kernel(&self) -> Box<dyn HistogramKernel> {
+        let device = &self.device;
+        Box::new(Histograms {
+            queue: device.new_command_queue(),
+            pipeline: self.pipeline.clone(),
+            bins: self.bins.clone(),
+            num_bins: self.num_bins.clone(),
+            offsets: self.offsets.clone(),
+            gradients: zeroed::<f32>(device, self.num_rows),
+            hessians: zeroed::<f32>(device, self.num_rows),
+            scratch: Mutex::new(Scratch {
+                rows: zeroed::<u32>(device, self.num_rows),
+                columns: zeroed::<u32>(device, self.num_columns),
+                gradients: zeroed::<f32>(device, self.total_bins),
+                hessians: zeroed::<f32>(device, self.total_bins),
+                counts: zeroed::<u32>(device, self.total_bins),
+            }),
+            num_rows: self.num_rows,
+            total_bins: self.total_bins,
+        })
+    }
+}
+
+// The buffers of one histogram dispatch, sized for the most rows and columns a node can
+// have and reused by every node.
+struct Scratch {
+    rows: Buffer,
+    columns: Buffer,
+    gradients: Buffer,
+    hessians: Buffer,
+    counts: Buffer,
+}
+
+struct Histograms {
+    queue: CommandQueue,
+    pipeline: ComputePipelineState,
+    bins: Buffer,
+    num_bins: Buffer,
+    offsets: Buffer,
+    gradients: Buffer,
+    hessians: Buffer,
+    scratch: Mutex<Scratch>,
+    num_rows: usize,
+    // bins of all columns together
+    total_bins: usize,
+}
+
+impl HistogramKernel for Histograms {
+    fn upload_gradients(&mut self, gradients: &[f32], hessians: &[f32]) {
+        assert_eq!(gradients.len(), self.num_rows);
+        assert_eq!(hessians.len(), self.num_rows);
+        // SAFETY: both buffers are shared with the CPU and hold `num_rows` floats, and no
+        // command is running since `histograms` waits for its own
+        unsafe {
+            let contents = self.gradients.contents() as *mut f32;
+            std::ptr::copy_nonoverlapping(gradients.as_ptr(), contents, self.num_rows);
+            let contents = self.hessians.contents() as *mut f32;
+            std::ptr::copy_nonoverlapping(hessians.as_ptr(), contents, self.num_rows);
+        }
+    }
+
+    fn histograms(&self, rows: &[u32], columns: &[u32]) -> (Vec<f32>, Vec<f32>, Vec<u32>) {
+        objc::rc::autoreleasepool(|| {
+            let scratch = self.scratch.lock().expect("no dispatch panicked");
+            write(&scratch.rows, rows);
+            write(&scratch.columns, columns);
+            for out in [&scratch.gradients, &scratch.hessians, &scratch.counts] {
+                // SAFETY: every output holds `total_bins` 32-bit values and no command is
+                // running while the lock is held
+                unsafe { std::ptr::write_bytes(out.contents() as *mut u32, 0, self.total_bins) };
+            }
+            let params = Params {
+                num_rows: self.num_rows as u32,
+                num_node_rows: rows.len() as u32,
+                rows_per_group: ROWS_PER_GROUP as u32,
+            };
+
+            let command_buffer = self.queue.new_command_buffer();
+            let encoder = command_buffer.new_compute_command_encoder();
+            encoder.set_compute_pipeline_state(&self.pipeline);
+            let buffers: [&BufferRef; 10] = [
+                &self.bins,
+                &self.num_bins,
+                &self.offsets,
+                &self.gradients,
+                &self.hessians,
+                &scratch.rows,
+                &scratch.columns,
+                &scratch.gradients,
+                &scratch.hessians,
+                &scratch.counts,
+            ];
+            for (index, buffer) in buffers.into_iter().enumerate() {
+                encoder.set_buffer(index as u64, Some(buffer), 0);
+            }
+            encoder.set_bytes(
+                buffers.len() as u64,
+                std::mem::size_of::<Params>() as u64,
+                &params as *const Params as *const c_void,
+            );
+            let threads = THREADS_PER_GROUP.min(self.pipeline.max_total_threads_per_threadgroup());
+            encoder.dispatch_thread_groups(
+                MTLSize {
+                    width: rows.len().div_ceil(ROWS_PER_GROUP) as u64,
+                    height: columns.len() as u64,
+                    depth: 1,
+                },
+                MTLSize {
+                    width: threads,
+                    height: 1,
+                    depth: 1,
+                },
+            );
+            encoder.end_encoding();
+            command_buffer.commit();
+            command_buffer.wait_until_completed();
+
+            (
+                read(&scratch.gradients, self.total_bins),
+                read(&scratch.hessians, self.total_bins),
+                read(&scratch.counts, self.total_bins),
+            )
+        })
+    }
+}
+
+// Evaluates every tree for one row per thread, walking the nodes of a `FlatForest`.
+const PREDICT_SHADER: &str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+constant uint LEAF = 0;
+constant uint SPLIT_MISSING_LEFT = 2;
+constant uint CATEGORICAL_SPLIT = 3;
+
+struct Node {
+    uint kind;
+    uint feature;
+    float value;
+    uint left;
+    uint right;
+    uint categories_start;
+    uint categories_len;
+};
+
+struct Params {
+    uint num_rows;
+    uint num_features;
+    uint num_trees;
+    uint num_outputs;
+};
+
+// like `tree::in_categories`
+bool in_categories(device const uint *categories, uint len, float value) {
+    if (!(value >= 0.0f) || fract(value) != 0.0f) {
+        return false;
+    }
+    uint category = value >= 4294967296.0f ? 0xffffffffu : uint(value);
+    uint low = 0;
+    uint high = len;
+    while (low < high) {
+        uint mid = (low + high) / 2;
+        if (categories[mid] < category) {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    return low < len && categories[low] == category;
+}
+
+kernel void predict(
+    device const float *features [[buffer(0)]],
+    device const Node *nodes [[buffer(1)]],
+    device const uint *roots [[buffer(2)]],
+    device const uint *categories [[buffer(3)]],
+    device float *scores [[buffer(4)]],
+    constant Params &params [[buffer(5)]],
+    uint row [[thread_position_in_grid]])
+{
+    if (row >= params.num_rows) {
+        return;
+    }
+    device const float *values = features + ulong(row) * params.num_features;
+    device float *out = scores + ulong(row) * params.num_outputs;
+    for (uint k = 0; k < params.num_outputs; k++) {
+        out[k] = 0.0f;
+    }
+    for (uint tree = 0; tree < params.num_trees; tree++) {
+        uint index = roots[tree];
+        while (nodes[index].kind != LEAF) {
+            Node node = nodes[index];
+            float value = values[node.feature];
+            bool left;
+            if (node.kind == CATEGORICAL_SPLIT) {
+                left = in_categories(categories + node.categories_start, node.categories_len, value);
+            } else if (isnan(value)) {
+                left = node.kind == SPLIT_MISSING_LEFT;
+            } else {
+                left = value < node.value;
+            }
+            index = left ? node.left : node.right;
+        }
+        out[tree % params.num_outputs] += nodes[index].value;
+    }
+}
+"#;
+
+// matches `Params` in the prediction shader
+#[repr(C)]
+struct PredictParams {
+    num_rows: u32,
+    num_features: u32,
+    num_trees: u32,
+    num_outputs: u32,
+}
+
+struct Predictor {
+    device: metal::Device,
+    queue: CommandQueue,
+    pipeline: ComputePipelineState,
+    nodes: Buffer,
+    roots: Buffer,
+    categories: Buffer,
+    num_trees: usize,
+}
+
+impl Predictor {
+    fn new(forest: &FlatForest) -> Option<Self> {
+        let device = metal::Device::system_default()?;
+        let pipeline = pipeline(&device, PREDICT_SHADER, "predict")?;
+        Some(Self {
+            queue: device.new_command_queue(),
+            pipeline,
+            nodes: buffer(&device, &forest.nodes),
+            roots: buffer(&device, &forest.roots),
+            categories: buffer(&device, &forest.categories),
+            num_trees: forest.roots.len(),
+            device,
+        })
+    }
+}
+
+impl PredictKernel for Predictor {
+    fn predict(&self, features: &[f32], num_features: usize, num_outputs: usize) -> Vec<f32> {
+        let num_rows = features.len() / num_features;
+        objc::rc::autoreleasepool(|| {
+            let features_buffer = buffer(&self.device, features);
+            let scores = zeroed::<f32>(&self.device, num_rows * num_outputs);
+            let params = PredictParams {
+                num_rows: num_rows as u32,
+                num_features: num_features as u32,
+                num_trees: self.num_trees as u32,
+                num_outputs: num_outputs as u32,
+            };
+
+            let command_buffer = self.queue.new_command_buffer();
+            let encoder = command_buffer.new_compute_command_encoder();
+            encoder.set_compute_pipeline_state(&self.pipeline);
+            let buffers: [&BufferRef; 5] = [
+                &features_buffer,
+                &self.nodes,
+                &self.roots,
+                &self.categories,
+                &scores,
+            ];
+            for (index, buffer) in buffers.into_iter().enumerate() {
+                encoder.set_buffer(index as u64, Some(buffer), 0);
+            }
+            encoder.set_bytes(
+                buffers.len() as u64,
+                std::mem::size_of::<PredictParams>() as u64,
+                &params as *const PredictParams as *const c_void,
+            );
+            let threads = THREADS_PER_GROUP.min(self.pipeline.max_total_threads_per_threadgroup());
+            encoder.dispatch_thread_groups(
+                MTLSize {
+                    width: (num_rows as u64).div_ceil(threads),
+                    height: 1,
+                    depth: 1,
+                },
+                MTLSize {
+                    width: threads,
+                    height: 1,
+                    depth: 1,
+                },
+            );
+            encoder.end_encoding();
+            command_buffer.commit();
+            command_buffer.wait_until_completed();
+
+            read(&scores, num_rows * num_outputs)
+        })
+    }
+}
+
+// Compiles `function` of `source`. Fast math stays off so NaN checks aren't optimized
+// away.
+fn pipeline(device: &DeviceRef, source: &str, function: &str) -> Option<ComputePipelineState> {
+    let options = CompileOptions::new();
+    options.set_fast_math_enabled(false);
+    let library = device.new_library_with_source(source, &options).ok()?;
+    let function = library.get_function(function, None).ok()?;
+    device
+        .new_compute_pipeline_state_with_function(&function)
+        .ok()
+}
+
+// a buffer shared with the CPU holding a copy of `data`
+fn buffer<T>(device: &DeviceRef, data: &[T]) -> Buffer {
+    if data.is_empty() {
+        // Metal doesn't allow empty buffers
+        return zeroed::<u32>(device, 1);
+    }
+    device.new_buffer_with_data(
+        data.as_ptr() as *const c_void,
+        std::mem::size_of_val(data) as u64,
+        MTLResourceOptions::StorageModeShared,
+    )
+}
+
+// a buffer shared with the CPU holding `len` zeroed values of `T`
+fn zeroed<T>(device: &DeviceRef, len: usize) -> Buffer {
+    let size = (len.max(1) * std::mem::size_of::<T>()) as u64;
+    let buffer = device.new_buffer(size, MTLResourceOptions::StorageModeShared);
+    // SAFETY: the buffer was just created with `size` bytes
+    unsafe { std::ptr::write_bytes(buffer.contents() as *mut u8, 0, size as usize) };
+    buffer
+}
+
+// copies `data` to the start of a buffer shared with the CPU that has room for it
+fn write<T: Copy>(buffer: &Buffer, data: &[T]) {
+    assert!(std::mem::size_of_val(data) as u64 <= buffer.length());
+    // SAFETY: the buffer is large enough and no command using it is running
+    unsafe {
+        std::ptr::copy_nonoverlapping(data.as_ptr(), buffer.contents() as *mut T, data.len())
+    };
+}
+
+fn read<T: Copy>(buffer: &Buffer, len: usize) -> Vec<T> {
+    // SAFETY: the buffer holds at least `len` values of `T` and the command writing them
+    // has completed
+    unsafe { std::slice::from_raw_parts(buffer.contents() as *const T, len).to_vec() }
+}
@@ -0,0 +1,561 @@
+use wgpu::util::DeviceExt;
+
+use super::{
+    ColumnLayout, DeviceBins, FillColumn, FlatForest, HistogramKernel, MAX_BINS, PredictKernel,
+};
+
+pub fn upload(layout: &ColumnLayout, fill: &FillColumn) -> Option<Box<dyn DeviceBins>> {
+    Some(Box::new(Bins::new(layout, fill)?))
+}
+
+pub fn predictor(forest: &FlatForest) -> Option<Box<dyn PredictKernel>> {
+    Some(Box::new(Predictor::new(forest)?))
+}
+
+// Like the Metal histogram shader. WGSL has no atomic floats, so sums are kept as their
+// bits and added with compare-exchange loops.
+const HISTOGRAM_SHADER: &str = r#"
+struct Params {
+    num_rows: u32,
+    num_node_rows: u32,
+    rows_per_group: u32,
+    padding: u32,
+}
+
+// the bins of two rows per word, the first in the low half
+@group(0) @binding(0) var<storage, read> bins: array<u32>;
+// the number of bins of every column and where they start in `sums`
+@group(0) @binding(1) var<storage, read> column_bins: array<vec2<u32>>;
+// the gradient and hessian of every row
+@group(0) @binding(2) var<storage, read> gradients: array<vec2<f32>>;
+@group(0) @binding(3) var<storage, read> rows: array<u32>;
+@group(0) @binding(4) var<storage, read> columns: array<u32>;
+// the gradient sum, hessian sum and count of every bin
+@group(0) @binding(5) var<storage, read_write> sums: array<atomic<u32>>;
+@group(0) @binding(6) var<uniform> params: Params;
+
+var<workgroup> local_sums: array<atomic<u32>, 3 * MAX_BINS>;
+
+fn add_local(index: u32, value: f32) {
+    var old = atomicLoad(&local_sums[index]);
+    loop {
+        let result = atomicCompareExchangeWeak(&local_sums[index], old, bitcast<u32>(bitcast<f32>(old) + value));
+        if result.exchanged {
+            break;
+        }
+        old = result.old_value;
+    }
+}
+
+fn add_global(index: u32, value: f32) {
+    var old = atomicLoad(&sums[index]);
+    loop {
+        let result = atomicCompareExchangeWeak(&sums[index], old, bitcast<u32>(bitcast<f32>(old) + value));
+        if result.exchanged {
+            break;
+        }
+        old = result.old_value;
+    }
+}
+
+@compute @workgroup_size(256)
+fn build_histograms(
+    @builtin(workgroup_id) group: vec3<u32>,
+    @builtin(local_invocation_index) lane: u32,
+) {
+    let column = columns[group.y];
+    let n = column_bins[column].x;
+    let offset = column_bins[column].y;
+    for (var i = lane; i < 3u * n; i += 256u) {
+        atomicStore(&local_sums[i], 0u);
+    }
+    workgroupBarrier();
+
+    let column_start = column * params.num_rows;
+    let begin = group.x * params.rows_per_group;
+    let end = min(begin + params.rows_per_group, params.num_node_rows);
+    for (var i = begin + lane; i < end; i += 256u) {
+        let row = rows[i];
+        let index = column_start + row;
+        let bin = (bins[index / 2u] >> (16u * (index % 2u))) & 0xffffu;
+        let gradient = gradients[row];
+        add_local(3u * bin, gradient.x);
+        add_local(3u * bin + 1u, gradient.y);
+        atomicAdd(&local_sums[3u * bin + 2u], 1u);
+    }
+    workgroupBarrier();
+
+    for (var bin = lane; bin < n; bin += 256u) {
+        let count = atomicLoad(&local_sums[3u * bin + 2u]);
+        if count == 0u {
+            continue;
+        }
+        let at = 3u * (offset + bin);
+        add_global(at, bitcast<f32>(atomicLoad(&local_sums[3u * bin])));
+        add_global(at + 1u, bitcast<f32>(atomicLoad(&local_sums[3u * bin + 1u])));
+        atomicAdd(&sums[at + 2u], count);
+    }
+}
+"#;
+
+// Like the Metal prediction shader.
+const PREDICT_SHADER: &str = r#"
+struct Node {
+    kind: u32,
+    feature: u32,
+    value: f32,
+    left: u32,
+    right: u32,
+    categories_start: u32,
+    categories_len: u32,
+}
+
+struct Params {
+    num_rows: u32,
+    num_features: u32,
+    num_trees: u32,
+    num_outputs: u32,
+}
+
+@group(0) @binding(0) var<storage, read> features: array<f32>;
+@group(0) @binding(1) var<storage, read> nodes: array<Node>;
+@group(0) @binding(2) var<storage, read> roots: array<u32>;
+@group(0) @binding(3) var<storage, read> categories: array<u32>;
+@group(0) @binding(4) var<storage, read_write> scores: array<f32>;
+@group(0) @binding(5) var<uniform> params: Params;
+
+const LEAF: u32 = 0u;
+const SPLIT_MISSING_LEFT: u32 = 2u;
+const CATEGORICAL_SPLIT: u32 = 3u;
+
+// WGSL may assume floats are never NaN, so the bits are checked instead
+fn is_nan(value: f32) -> bool {
+    return (bitcast<u32>(value) & 0x7fffffffu) > 0x7f800000u;
+}
+
+// like `tree::in_categories`
+fn in_categories(start: u32, len: u32, value: f32) -> bool {
+    if is_nan(value) || value < 0.0 || fract(value) != 0.0 {
+        return false;
+    }
+    var category = 0xffffffffu;
+    if value < 4294967296.0 {
+        category = u32(value);
+    }
+    var low = 0u;
+    var high = len;
+    while low < high {
+        let mid = (low + high) / 2u;
+        if categories[start + mid] < category {
+            low = mid + 1u;
+        } else {
+            high = mid;
+        }
+    }
+    return low < len && categories[start + low] == category;
+}
+
+@compute @workgroup_size(256)
+fn predict(@builtin(global_invocation_id) id: vec3<u32>) {
+    let row = id.x;
+    if row >= params.num_rows {
+        return;
+    }
+    let values = row * params.num_features;
+    let out = row * params.num_outputs;
+    for (var k = 0u; k < params.num_outputs; k++) {
+        scores[out + k] = 0.0;
+    }
+    for (var tree = 0u; tree < params.num_trees; tree++) {
+        var index = roots[tree];
+        while nodes[index].kind != LEAF {
+            let node = nodes[index];
+            let value = features[values + node.feature];
+            var left: bool;
+            if node.kind == CATEGORICAL_SPLIT {
+                left = in_categories(node.categories_start, node.categories_len, value);
+            } else if is_nan(value) {
+                left = node.kind == SPLIT_MISSING_LEFT;
+            } else {
+                left = value < node.value;
+            }
+            index = select(node.right, node.left, left);
+        }
+        scores[out + tree % params.num_outputs] += nodes[index].value;
+    }
+}
+"#;
+
+// node rows every workgroup accumulates
+const ROWS_PER_GROUP: u32 = 4096;
+// matches `@workgroup_size` in both shaders
+const THREADS_PER_GROUP: u32 = 256;
+
+// A device of the adapter with the most compute limits, and `entry_point` of `source`
+// compiled for it. None when there is no adapter or the shader doesn't validate on it.
+fn pipeline(
+    source: &str,
+    entry_point: &str,
+) -> Option<(wgpu::Device, wgpu::Queue, wgpu::ComputePipeline)> {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::HighPerformance,
+        ..Default::default()
+    }))
+    .ok()?;
+    let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+        label: Some("metalgbm"),
+        required_limits: adapter.limits(),
+        ..Default::default()
+    }))
+    .ok()?;
+
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(entry_point),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(entry_point),
+        layout: None,
+        module: &module,
+        entry_point: Some(entry_point),
+        compilation_options: Default::default(),
+        cache: None,
+    });
+    if pollster::block_on(device.pop_error_scope()).is_some() {
+        return None;
+    }
+    Some((device, queue, pipeline))
+}
+
+// A storage buffer holding `words`. Bindings can't be empty, so empty data gets a few zeros.
+fn storage(device: &wgpu::Device, words: &[u32], usage: wgpu::BufferUsages) -> wgpu::Buffer {
+    let padded;
+    let words = if words.len() < 4 {
+        padded = [words, &[0; 4][words.len()..]].concat();
+        &padded
+    } else {
+        words
+    };
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: None,
+        contents: bytemuck::cast_slice(words),
+        usage: wgpu::BufferUsages::STORAGE | usage,
+    })
+}
+
+fn uniform(device: &wgpu::Device, params: [u32; 4]) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: None,
+        contents: bytemuck::cast_slice(&params),
+        usage: wgpu::BufferUsages::UNIFORM,
+    })
+}
+
+// Runs `pipeline` over `workgroups` with `buffers` bound in order, then reads `output` back.
+fn run(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    pipeline: &wgpu::ComputePipeline,
+    buffers: &[&wgpu::Buffer],
+    workgroups: (u32, u32),
+    output: &wgpu::Buffer,
+) -> Vec<u32> {
+    let entries: Vec<wgpu::BindGroupEntry> = buffers
+        .iter()
+        .enumerate()
+        .map(|(binding, buffer)| wgpu::BindGroupEntry {
+            binding: binding as u32,
+            resource: buffer.as_entire_binding(),
+        })
+        .collect();
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &entries,
+    });
+    let staging = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: output.size(),
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+    {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups(workgroups.0, workgroups.1, 1);
+    }
+    encoder.copy_buffer_to_buffer(output, 0, &staging, 0, output.size());
+    queue.submit([encoder.finish()]);
+
+    let slice = staging.slice(..);
+    slice.map_async(wgpu::MapMode::Read, |_| {});
+    device
+        .poll(wgpu::PollType::Wait)
+        .expect("the GPU finishes the dispatch");
+    let words = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+    staging.unmap();
+    words
+}
+
+// The uploaded columns along with the device they are on. wgpu handles are reference
+// counted, so every kernel shares them.
+struct Bins {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    bins: wgpu::Buffer,
+    column_bins: wgpu::Buffer,
+    num_rows: usize,
+    // bins of all columns together
+    total_bins: usize,
+}
+
+impl Bins {
+    fn new(layout: &ColumnLayout, fill: &FillColumn) -> Option<Self> {
+        let source = HISTOGRAM_SHADER.replace("MAX_BINS", &format!("{MAX_BINS}u"));
+        let (device, queue, pipeline) = pipeline(&source, "build_histograms")?;
+        let limits = device.limits();
+        let num_columns = layout.num_bins.len();
+        // two bytes per bin, rounded up to whole words of at least a few
+        let size = (num_columns * layout.num_rows * 2)
+            .next_multiple_of(4)
+            .max(16);
+        if size > limits.max_storage_buffer_binding_size as usize
+            || num_columns > limits.max_compute_workgroups_per_dimension as usize
+        {
+            return None;
+        }
+
+        // The columns are written into the mapped buffer rather than a copy on the heap,
+        // which is the GPU's own memory when it shares it with the CPU. As 16-bit values
+        // the bins land in the low half of a word first, the order the shader reads them in.
+        let bins = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("bins"),
+            size: size as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: true,
+        });
+        {
+            let mut mapped = bins.slice(..).get_mapped_range_mut();
+            let values: &mut [u16] = bytemuck::cast_slice_mut(&mut mapped);
+            if layout.num_rows > 0 {
+                for (column, out) in values[..num_columns * layout.num_rows]
+                    .chunks_exact_mut(layout.num_rows)
+                    .enumerate()
+                {
+                    fill(column, out);
+                }
+            }
+        }
+        bins.unmap();
+
+        let column_bins: Vec<u32> = layout
+            .num_bins
+            .iter()
+            .zip(&layout.offsets)
+            .flat_map(|(&num_bins, &offset)| [num_bins, offset])
+            .collect();
+        Some(Self {
+            bins,
+            column_bins: storage(&device, &column_bins, wgpu::BufferUsages::empty()),
+            num_rows: layout.num_rows,
+            total_bins: layout.offsets[num_columns] as usize,
+            device,
+            queue,
+            pipeline,
+        })
+    }
+}
+
+impl DeviceBins for Bins {
+    fn kernel(&self) -> Box<dyn HistogramKernel> {
+        Box::new(Histograms {
+            device: self.device.clone(),
+            queue: self.queue.clone(),
+            pipeline: self.pipeline.clone(),
+            bins: self.bins.clone(),
+            column_bins: self.column_bins.clone(),
+            gradients: storage(
+                &self.device,
+                &vec![0; 2 * self.num_rows],
+                wgpu::BufferUsages::COPY_DST,
+            ),
+            num_rows: self.num_rows,
+            total_bins: self.total_bins,
+        })
+    }
+}
+
+struct Histograms {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    bins: wgpu::Buffer,
+    column_bins: wgpu::Buffer,
+    // interleaved gradients and hessians
+    gradients: wgpu::Buffer,
+    num_rows: usize,
+    // bins of all columns together
+    total_bins: usize,
+}
+
+impl HistogramKernel for Histograms {
+    fn upload_gradients(&mut self, gradients: &[f32], hessians: &[f32]) {
+        assert_eq!(gradients.len(), self.num_rows);
+        assert_eq!(hessians.len(), self.num_rows);
+        let interleaved: Vec<f32> = gradients
+            .iter()
+            .zip(hessians)
+            .flat_map(|(&gradient, &hessian)| [gradient, hessian])
+            .collect();
+        self.queue
+            .write_buffer(&self.gradients, 0, bytemuck::cast_slice(&interleaved));
+    }
+
+    fn histograms(&self, rows: &[u32], columns: &[u32]) -> (Vec<f32>, Vec<f32>, Vec<u32>) {
+        let rows_buffer = storage(&self.device, rows, wgpu::BufferUsages::empty());
+        let columns_buffer = storage(&self.device, columns, wgpu::BufferUsages::empty());
+        let sums = storage(
+            &self.device,
+            &vec![0; 3 * self.total_bins],
+            wgpu::BufferUsages::COPY_SRC,
+        );
+        let params = uniform(
+            &self.device,
+            [self.num_rows as u32, rows.len() as u32, ROWS_PER_GROUP, 0],
+        );
+        let words = run(
+            &self.device,
+            &self.queue,
+            &self.pipeline,
+            &[
+                &self.bins,
+                &self.column_bins,
+                &self.gradients,
+                &rows_buffer,
+                &columns_buffer,
+                &sums,
+                &params,
+            ],
+            (
+                (rows.len() as u32).div_ceil(ROWS_PER_GROUP),
+                columns.len() as u32,
+            ),
+            &sums,
+        );
+
+        let bins = &words[..3 * self.total_bins];
+        (
+            bins.iter()
+                .step_by(3)
+                .map(|&bits| f32::from_bits(bits))
+                .collect(),
+            bins[1..]
+                .iter()
+                .step_by(3)
+                .map(|&bits| f32::from_bits(bits))
+                .collect(),
+            bins[2..].iter().step_by(3).copied().collect(),
+        )
+    }
+}
+
+struct Predictor {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    nodes: wgpu::Buffer,
+    roots: wgpu::Buffer,
+    categories: wgpu::Buffer,
+    num_trees: usize,
+}
+
+impl Predictor {
+    fn new(forest: &FlatForest) -> Option<Self> {
+        let (device, queue, pipeline) = pipeline(PREDICT_SHADER, "predict")?;
+        let nodes: Vec<u32> = forest
+            .nodes
+            .iter()
+            .flat_map(|node| {
+                [
+                    node.kind,
+                    node.feature,
+                    node.value.to_bits(),
+                    node.left,
+                    node.right,
+                    node.categories_start,
+                    node.categories_len,
+                ]
+            })
+            .collect();
+        Some(Self {
+            nodes: storage(&device, &nodes, wgpu::BufferUsages::empty()),
+            roots: storage(&device, &forest.roots, wgpu::BufferUsages::empty()),
+            categories: storage(&device, &forest.categories, wgpu::BufferUsages::empty()),
+            num_trees: forest.roots.len(),
+            device,
+            queue,
+            pipeline,
+        })
+    }
+}
+
+impl PredictKernel for Predictor {
+    fn predict(&self, features: &[f32], num_features: usize, num_outputs: usize) -> Vec<f32> {
+        // rows that fit in one binding, and in one dimension of workgroups
+        let limits = self.device.limits();
+        let max_rows = (limits.max_storage_buffer_binding_size as usize / (4 * num_features))
+            .min(limits.max_compute_workgroups_per_dimension as usize * THREADS_PER_GROUP as usize)
+            .max(1);
+
+        let mut scores = Vec::with_capacity(features.len() / num_features * num_outputs);
+        for batch in features.chunks(max_rows * num_features) {
+            let num_rows = batch.len() / num_features;
+            let features_buffer = storage(
+                &self.device,
+                bytemuck::cast_slice(batch),
+                wgpu::BufferUsages::empty(),
+            );
+            let batch_scores = storage(
+                &self.device,
+                &vec![0; num_rows * num_outputs],
+                wgpu::BufferUsages::COPY_SRC,
+            );
+            let params = uniform(
+                &self.device,
+                [
+                    num_rows as u32,
+                    num_features as u32,
+                    self.num_trees as u32,
+                    num_outputs as u32,
+                ],
+            );
+            let words = run(
+                &self.device,
+                &self.queue,
+                &self.pipeline,
+                &[
+                    &features_buffer,
+                    &self.nodes,
+                    &self.roots,
+                    &self.categories,
+                    &batch_scores,
+                    &params,
+                ],
+                ((num_rows as u32).div_ceil(THREADS_PER_GROUP), 1),
+                &batch_scores,
+            );
+            scores.extend(
+                words[..num_rows * num_outputs]
+                    .iter()
+                    .map(|&bits| f32::from_bits(bits)),
+            );
+        }
+        scores
+    }
+}
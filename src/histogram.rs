@@ -1,53 +1,167 @@
+use serde::{Deserialize, Serialize};
+
+use crate::dataset::FeatureBins;
+use crate::error::{Error, Result};
+use crate::quantize::QuantizedGradients;
+use crate::sketch::QuantileSketch;
+
+// summary entries the quantile sketch keeps per bin, trading memory for edge accuracy
+const SKETCH_ENTRIES_PER_BIN: usize = 8;
+
+// What a histogram sums gradients and hessians in while accumulating rows. The sums are
+// stored as f32 either way, so only the rounding errors of long sums shrink.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum HistogramPrecision {
+    #[default]
+    Single,
+    // f64 accumulators, one pair per bin, for very many rows per bin where f32 sums drift
+    // far enough to change the chosen splits
+    Double,
+}
+
+#[derive(Clone)]
 pub struct Histogram {
     bins: Vec<f32>,
     gradients: Vec<f32>,
     hessians: Vec<f32>, // first derivative of loss function
+    counts: Vec<usize>, // number of samples per bin
+    // NaN values, kept apart from the bins so splits can choose which side they go to
+    missing_gradients: f32,
+    missing_hessians: f32,
+    missing_count: usize,
+    // f64 gradient and hessian sums of the rows added since the last `flush`, the missing
+    // bin last; empty in single precision
+    wide: Vec<(f64, f64)>,
 }
 
 impl Histogram {
     pub fn from_feature(feature_values: &[f32], max_bins: usize) -> Self {
-        // this functions defines the bins of the histogram
-        let mut sorted_values: Vec<f32> = feature_values.iter().copied().collect();
-        sorted_values.sort_by(|a, b| a.partial_cmp(b).unwrap());
-        sorted_values.dedup();
-
-        let n_unique = sorted_values.len();
+        Self::from_weighted_feature(feature_values, None, max_bins)
+    }
 
-        if n_unique == 0 {
-            return Self {
-                bins: vec![],
-                gradients: vec![],
-                hessians: vec![],
-            };
+    // Bin edges at evenly spaced quantiles of the values, each counting `weights[i]` times,
+    // so every bin holds about the same weight. Features with at most `max_bins + 1`
+    // distinct values get an edge at every value instead. NaN has a bin of its own.
+    pub fn from_weighted_feature(
+        feature_values: &[f32],
+        weights: Option<&[f32]>,
+        max_bins: usize,
+    ) -> Self {
+        let mut sketch = QuantileSketch::new(SKETCH_ENTRIES_PER_BIN * max_bins);
+        for (i, &value) in feature_values.iter().enumerate() {
+            sketch.push(value, weights.map_or(1.0, |weights| weights[i]));
         }
 
-        if n_unique == 1 {
-            return Self {
-                bins: vec![sorted_values[0]],
-                gradients: vec![0.0],
-                hessians: vec![0.0],
-            };
-        }
+        let bins = match sketch.distinct_values() {
+            Some(distinct) if distinct.len() <= max_bins + 1 => distinct,
+            _ => {
+                let fractions: Vec<f64> =
+                    (0..=max_bins).map(|i| i as f64 / max_bins as f64).collect();
+                let mut edges = sketch.quantiles(&fractions).unwrap_or_default();
+                // the extremes bound the outer bins whatever their weight
+                if let (Some(first), Some(min)) = (edges.first_mut(), sketch.min()) {
+                    *first = min;
+                }
+                if let (Some(last), Some(max)) = (edges.last_mut(), sketch.max()) {
+                    *last = max;
+                }
+                edges.dedup();
+                edges
+            }
+        };
+        Self::from_edges(bins)
+    }
 
-        let num_bins = max_bins.min(n_unique - 1);
+    // `max_bins` bins of the same width between the smallest and largest value, however
+    // the values spread over them.
+    pub fn from_equal_width(feature_values: &[f32], max_bins: usize) -> Self {
+        let (min, max) = feature_values
+            .iter()
+            .filter(|value| !value.is_nan())
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &value| {
+                (min.min(value), max.max(value))
+            });
+        let mut edges = if min > max {
+            vec![]
+        } else if min == max {
+            vec![min]
+        } else {
+            let width = (max as f64 - min as f64) / max_bins as f64;
+            let mut edges: Vec<f32> = (0..max_bins)
+                .map(|i| (min as f64 + i as f64 * width) as f32)
+                .collect();
+            edges.push(max);
+            edges
+        };
+        // neighbouring edges can round to the same f32 for very narrow ranges
+        edges.dedup();
+        Self::from_edges(edges)
+    }
+
+    pub fn with_precision(mut self, precision: HistogramPrecision) -> Self {
+        self.wide = match precision {
+            HistogramPrecision::Single => vec![],
+            HistogramPrecision::Double => vec![(0.0, 0.0); self.num_bins() + 1],
+        };
+        self
+    }
 
-        let mut bins = Vec::new();
-        for i in 0..=num_bins {
-            let idx = (i * (n_unique - 1)) / num_bins;
-            bins.push(sorted_values[idx]);
+    pub fn precision(&self) -> HistogramPrecision {
+        if self.wide.is_empty() {
+            HistogramPrecision::Single
+        } else {
+            HistogramPrecision::Double
         }
+    }
+
+    // Bins between the given ascending `edges`; a single edge still gets one bin.
+    pub fn from_edges(edges: Vec<f32>) -> Self {
+        let num_bins = edges.len().saturating_sub(1).max(edges.len().min(1));
+        Self {
+            bins: edges,
+            gradients: vec![0.0; num_bins],
+            hessians: vec![0.0; num_bins],
+            counts: vec![0; num_bins],
+            missing_gradients: 0.0,
+            missing_hessians: 0.0,
+            missing_count: 0,
+            wide: vec![],
+        }
+    }
 
-        let gradients = vec![0.0; num_bins];
-        let hessians = vec![0.0; num_bins];
+    // One bin per distinct category, so `bins` holds the categories themselves rather than
+    // edges, and every category lands in its own bin in `search_bin_index`.
+    pub fn from_categories(feature_values: &[f32]) -> Self {
+        let mut categories: Vec<f32> = feature_values
+            .iter()
+            .copied()
+            .filter(|value| !value.is_nan())
+            .collect();
+        categories.sort_by(f32::total_cmp);
+        categories.dedup();
 
+        let n = categories.len();
         Self {
-            bins,
-            gradients,
-            hessians,
+            bins: categories,
+            gradients: vec![0.0; n],
+            hessians: vec![0.0; n],
+            counts: vec![0; n],
+            missing_gradients: 0.0,
+            missing_hessians: 0.0,
+            missing_count: 0,
+            wide: vec![],
         }
     }
 
-    pub fn accumulate(&mut self, feature_values: &[f32], gradients: &[f32], hessians: &[f32]) {
+    // With `weights`, every sample's gradient and hessian count `weights[i]` times. `counts`
+    // stay the number of samples.
+    pub fn accumulate(
+        &mut self,
+        feature_values: &[f32],
+        gradients: &[f32],
+        hessians: &[f32],
+        weights: Option<&[f32]>,
+    ) {
         // For each sample:
         //  1. Find which bin the feature value falls into
         //  2. Add the sample's gradient to that bin's gradient sum
@@ -61,12 +175,383 @@ impl Histogram {
 
         for i in 0..feature_values.len() {
             let bin_idx = self.search_bin_index(&feature_values[i]);
-            self.gradients[bin_idx] += gradients[i];
-            self.hessians[bin_idx] += hessians[i];
+            let weight = weights.map_or(1.0, |weights| weights[i]);
+            self.add(bin_idx, weight * gradients[i], weight * hessians[i], 1);
+        }
+        self.flush();
+    }
+
+    // Like `accumulate` for the `rows` of a feature binned up front, see `BinnedDataset`.
+    // Here `gradients`, `hessians` and `weights` are indexed by row. `rows` must be
+    // ascending, and `node_sums` are their weighted gradient and hessian sums, which sparse
+    // features derive their zero bin from.
+    pub fn accumulate_bins(
+        &mut self,
+        bins: &FeatureBins,
+        rows: &[usize],
+        gradients: &[f32],
+        hessians: &[f32],
+        weights: Option<&[f32]>,
+        node_sums: (f32, f32),
+    ) {
+        match bins {
+            FeatureBins::U8(bins) => self.accumulate_rows(bins, rows, gradients, hessians, weights),
+            FeatureBins::U16(bins) => {
+                self.accumulate_rows(bins, rows, gradients, hessians, weights)
+            }
+            FeatureBins::Bundled { .. } => {
+                panic!("bundled features are accumulated through their bundle")
+            }
+            FeatureBins::Sparse {
+                rows: entry_rows,
+                bins,
+                zero_bin,
+            } => {
+                debug_assert!(rows.is_sorted(), "rows must be ascending");
+                let (mut zero_gradients, mut zero_hessians) = node_sums;
+                let mut zero_count = rows.len();
+                for_node_entries(entry_rows, rows, |entry, row| {
+                    let bin_idx = bins[entry] as usize;
+                    let weight = weights.map_or(1.0, |weights| weights[row]);
+                    self.add(bin_idx, weight * gradients[row], weight * hessians[row], 1);
+                    zero_gradients -= weight * gradients[row];
+                    zero_hessians -= weight * hessians[row];
+                    zero_count -= 1;
+                });
+                if zero_count > 0 {
+                    self.gradients[*zero_bin] += zero_gradients;
+                    self.hessians[*zero_bin] += zero_hessians;
+                    self.counts[*zero_bin] += zero_count;
+                }
+            }
+        }
+        self.flush();
+    }
+
+    // Like `accumulate_bins` for a column of bin indices the caller binned itself, e.g. with
+    // `search_bin_index`, and compact u32 row indices. Only `rows` are read, in any order, so
+    // a node's histogram costs the node's rows rather than the whole column.
+    pub fn accumulate_subset(
+        &mut self,
+        rows: &[u32],
+        binned_column: &[u16],
+        gradients: &[f32],
+        hessians: &[f32],
+    ) {
+        for &row in rows {
+            let row = row as usize;
+            self.add(
+                binned_column[row] as usize,
+                gradients[row],
+                hessians[row],
+                1,
+            );
+        }
+        self.flush();
+    }
+
+    // Like `accumulate_bins` with quantized gradients and hessians, which already carry the
+    // sample weights. Each bin sums integer levels, converted back to gradient and hessian
+    // sums once all rows are in; `node_sums` are the integer sums of `rows`.
+    pub fn accumulate_quantized_bins(
+        &mut self,
+        bins: &FeatureBins,
+        rows: &[usize],
+        quantized: &QuantizedGradients,
+        node_sums: (i64, i64),
+    ) {
+        let (gradients, hessians) = (quantized.gradients(), quantized.hessians());
+        let mut buckets = vec![(0i64, 0i64, 0usize); self.num_bins() + 1];
+        let mut add = |bin: usize, row: usize| {
+            let bucket = &mut buckets[bin.min(self.gradients.len())];
+            bucket.0 += gradients[row] as i64;
+            bucket.1 += hessians[row] as i64;
+            bucket.2 += 1;
+        };
+        match bins {
+            FeatureBins::U8(bins) => rows.iter().for_each(|&row| add(bins[row] as usize, row)),
+            FeatureBins::U16(bins) => rows.iter().for_each(|&row| add(bins[row] as usize, row)),
+            FeatureBins::Bundled { .. } => {
+                panic!("bundled features are accumulated through their bundle")
+            }
+            FeatureBins::Sparse {
+                rows: entry_rows,
+                bins,
+                zero_bin,
+            } => {
+                debug_assert!(rows.is_sorted(), "rows must be ascending");
+                for_node_entries(entry_rows, rows, |entry, row| {
+                    add(bins[entry] as usize, row)
+                });
+                let entries = buckets.iter().fold((0, 0, 0), |sums, bucket| {
+                    (sums.0 + bucket.0, sums.1 + bucket.1, sums.2 + bucket.2)
+                });
+                let zero = &mut buckets[*zero_bin];
+                zero.0 += node_sums.0 - entries.0;
+                zero.1 += node_sums.1 - entries.1;
+                zero.2 += rows.len() - entries.2;
+            }
+        }
+        for (bin, &(gradient, hessian, count)) in buckets.iter().enumerate() {
+            let (gradient, hessian) = quantized.dequantize((gradient, hessian));
+            self.add_rounded(bin, (gradient, hessian, count));
+        }
+    }
+
+    fn accumulate_rows<T: Copy + Into<usize>>(
+        &mut self,
+        bins: &[T],
+        rows: &[usize],
+        gradients: &[f32],
+        hessians: &[f32],
+        weights: Option<&[f32]>,
+    ) {
+        #[cfg(target_arch = "x86_64")]
+        if self.wide.is_empty() && std::arch::is_x86_feature_detected!("avx2") {
+            // SAFETY: the CPU supports AVX2
+            unsafe { self.accumulate_rows_avx2(bins, rows, gradients, hessians, weights) };
+            return;
+        }
+        for &row in rows {
+            let bin_idx = bins[row].into();
+            let weight = weights.map_or(1.0, |weights| weights[row]);
+            self.add(bin_idx, weight * gradients[row], weight * hessians[row], 1);
+        }
+    }
+
+    // `accumulate_rows` with the gradients, hessians and weights of 8 rows at a time gathered
+    // and weighted in AVX2 registers. The bins are still added one row at a time, since rows
+    // of the same bin would collide in a vector scatter.
+    //
+    // SAFETY: the CPU must support AVX2.
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "avx2")]
+    unsafe fn accumulate_rows_avx2<T: Copy + Into<usize>>(
+        &mut self,
+        bins: &[T],
+        rows: &[usize],
+        gradients: &[f32],
+        hessians: &[f32],
+        weights: Option<&[f32]>,
+    ) {
+        use std::arch::x86_64::*;
+
+        // gathers index with i32, and every row must be in bounds of all three slices
+        let len = gradients
+            .len()
+            .min(hessians.len())
+            .min(weights.map_or(usize::MAX, <[f32]>::len))
+            .min(i32::MAX as usize);
+        let mut chunks = rows.chunks_exact(8);
+        let (mut weighted_gradients, mut weighted_hessians) = ([0.0f32; 8], [0.0f32; 8]);
+        for chunk in &mut chunks {
+            assert!(chunk.iter().all(|&row| row < len), "row out of bounds");
+            let index = |lane: usize| chunk[lane] as i32;
+            let indices = _mm256_setr_epi32(
+                index(0),
+                index(1),
+                index(2),
+                index(3),
+                index(4),
+                index(5),
+                index(6),
+                index(7),
+            );
+            // SAFETY: every index was checked to be in bounds of the gathered slices above
+            unsafe {
+                let mut g = _mm256_i32gather_ps::<4>(gradients.as_ptr(), indices);
+                let mut h = _mm256_i32gather_ps::<4>(hessians.as_ptr(), indices);
+                if let Some(weights) = weights {
+                    let w = _mm256_i32gather_ps::<4>(weights.as_ptr(), indices);
+                    g = _mm256_mul_ps(g, w);
+                    h = _mm256_mul_ps(h, w);
+                }
+                _mm256_storeu_ps(weighted_gradients.as_mut_ptr(), g);
+                _mm256_storeu_ps(weighted_hessians.as_mut_ptr(), h);
+            }
+            for lane in 0..8 {
+                let bin_idx = bins[chunk[lane]].into();
+                self.add(
+                    bin_idx,
+                    weighted_gradients[lane],
+                    weighted_hessians[lane],
+                    1,
+                );
+            }
         }
+        for &row in chunks.remainder() {
+            let bin_idx = bins[row].into();
+            let weight = weights.map_or(1.0, |weights| weights[row]);
+            self.add(bin_idx, weight * gradients[row], weight * hessians[row], 1);
+        }
+    }
+
+    // `bin` past the last one is the missing bin. In double precision the sums wait in
+    // `wide` until the next `flush`.
+    fn add(&mut self, bin: usize, gradient: f32, hessian: f32, count: usize) {
+        match self.wide.get_mut(bin.min(self.gradients.len())) {
+            Some(wide) => {
+                wide.0 += gradient as f64;
+                wide.1 += hessian as f64;
+                self.add_rounded(bin, (0.0, 0.0, count));
+            }
+            None => self.add_rounded(bin, (gradient, hessian, count)),
+        }
+    }
+
+    pub(crate) fn add_rounded(
+        &mut self,
+        bin: usize,
+        (gradient, hessian, count): (f32, f32, usize),
+    ) {
+        if bin < self.gradients.len() {
+            self.gradients[bin] += gradient;
+            self.hessians[bin] += hessian;
+            self.counts[bin] += count;
+        } else {
+            self.missing_gradients += gradient;
+            self.missing_hessians += hessian;
+            self.missing_count += count;
+        }
+    }
+
+    // moves the double precision sums into the f32 ones
+    fn flush(&mut self) {
+        for bin in 0..self.wide.len() {
+            let (gradient, hessian) = std::mem::take(&mut self.wide[bin]);
+            self.add_rounded(bin, (gradient as f32, hessian as f32, 0));
+        }
+    }
+
+    // gradient sum, hessian sum and count of `bin`, the missing bin past the last one
+    fn get(&self, bin: usize) -> (f32, f32, usize) {
+        if bin < self.gradients.len() {
+            (self.gradients[bin], self.hessians[bin], self.counts[bin])
+        } else {
+            self.missing()
+        }
+    }
+
+    // Zeroes the sums and counts but keeps the bin edges, so the same histogram can be
+    // accumulated again, e.g. for the next boosting iteration, without rebinning the feature.
+    pub fn reset(&mut self) {
+        self.gradients.fill(0.0);
+        self.hessians.fill(0.0);
+        self.counts.fill(0);
+        self.missing_gradients = 0.0;
+        self.missing_hessians = 0.0;
+        self.missing_count = 0;
+        self.wide.fill((0.0, 0.0));
+    }
+
+    // Adds in the rows of `other`, e.g. the partial histogram of another thread or worker
+    // over different rows of the same feature. Both must have the same bin edges.
+    pub fn merge(&mut self, other: &Histogram) -> Result<()> {
+        if self.bins != other.bins || self.num_bins() != other.num_bins() {
+            return Err(Error::invalid_data(format!(
+                "can't merge histograms with different bins, {} vs {} edges",
+                self.bins.len(),
+                other.bins.len()
+            )));
+        }
+        for bin in 0..=self.num_bins() {
+            self.add_rounded(bin, other.get(bin));
+        }
+        Ok(())
+    }
+
+    // Takes out the rows of `other`, a histogram over a subset of this one's rows with the
+    // same bins, e.g. to get one child's histogram from its parent's and its sibling's.
+    pub(crate) fn subtract(&mut self, other: &Histogram) {
+        for (gradient, other) in self.gradients.iter_mut().zip(&other.gradients) {
+            *gradient -= other;
+        }
+        for (hessian, other) in self.hessians.iter_mut().zip(&other.hessians) {
+            *hessian -= other;
+        }
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count -= other;
+        }
+        self.missing_gradients -= other.missing_gradients;
+        self.missing_hessians -= other.missing_hessians;
+        self.missing_count -= other.missing_count;
+    }
+
+    // A histogram of `num_bins` bins without edges, for the combined bins of a feature
+    // bundle.
+    pub(crate) fn with_num_bins(num_bins: usize) -> Self {
+        Self {
+            bins: vec![],
+            gradients: vec![0.0; num_bins],
+            hessians: vec![0.0; num_bins],
+            counts: vec![0; num_bins],
+            missing_gradients: 0.0,
+            missing_hessians: 0.0,
+            missing_count: 0,
+            wide: vec![],
+        }
+    }
+
+    // Fills this feature's bins from the histogram of its bundle, where bin b is at
+    // `offset + b` and the missing bin right after the last one. The default bin, which the
+    // bundle doesn't track, gets what's left of the node's sums and `node_count` rows.
+    pub(crate) fn unbundle(
+        &mut self,
+        bundle: &Histogram,
+        offset: usize,
+        default_bin: usize,
+        node_sums: (f32, f32),
+        node_count: usize,
+    ) {
+        let (mut default_gradients, mut default_hessians) = node_sums;
+        let mut default_count = node_count;
+        for bin in (0..=self.num_bins()).filter(|&bin| bin != default_bin) {
+            let (gradient, hessian, count) = bundle.get(offset + bin);
+            self.add_rounded(bin, (gradient, hessian, count));
+            default_gradients -= gradient;
+            default_hessians -= hessian;
+            default_count -= count;
+        }
+        self.add_rounded(
+            default_bin,
+            (default_gradients, default_hessians, default_count),
+        );
+    }
+
+    pub fn num_bins(&self) -> usize {
+        self.gradients.len()
+    }
+
+    pub fn bins(&self) -> &[f32] {
+        &self.bins
     }
 
-    fn search_bin_index(&self, feature_value: &f32) -> usize {
+    pub fn gradients(&self) -> &[f32] {
+        &self.gradients
+    }
+
+    pub fn hessians(&self) -> &[f32] {
+        &self.hessians
+    }
+
+    pub fn counts(&self) -> &[usize] {
+        &self.counts
+    }
+
+    // gradient sum, hessian sum and count of the NaN values
+    pub fn missing(&self) -> (f32, f32, usize) {
+        (
+            self.missing_gradients,
+            self.missing_hessians,
+            self.missing_count,
+        )
+    }
+
+    // NaN goes to the missing bin, numbered one past the last bin
+    pub fn search_bin_index(&self, feature_value: &f32) -> usize {
+        if feature_value.is_nan() {
+            return self.gradients.len();
+        }
         // Find the first bin boundary that is strictly greater than feature_value
         let idx = self
             .bins
@@ -80,6 +565,24 @@ impl Histogram {
     }
 }
 
+// Calls `f` with the entry and row of every sparse entry among the ascending `rows`,
+// looking the shorter list up in the longer one.
+fn for_node_entries(entry_rows: &[usize], rows: &[usize], mut f: impl FnMut(usize, usize)) {
+    if entry_rows.len() < rows.len() {
+        for (entry, row) in entry_rows.iter().enumerate() {
+            if rows.binary_search(row).is_ok() {
+                f(entry, *row);
+            }
+        }
+    } else {
+        for &row in rows {
+            if let Ok(entry) = entry_rows.binary_search(&row) {
+                f(entry, row);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -92,7 +595,8 @@ mod tests {
         let hist = Histogram::from_feature(&feature_vec, 4);
 
         assert_eq!(hist.bins.len(), 5);
-        assert_eq!(hist.bins, [0.0, 2.0, 4.0, 6.0, 9.0]);
+        // a quarter of the values each, as far as 10 values split
+        assert_eq!(hist.bins, [0.0, 2.0, 4.0, 7.0, 9.0]);
         assert_eq!(hist.gradients.len(), 4);
         assert_eq!(hist.hessians.len(), 4);
     }
@@ -133,6 +637,189 @@ mod tests {
         assert_eq!(hist.hessians.len(), 0);
     }
 
+    #[test]
+    fn test_weighted_edges_follow_the_weight() {
+        let feature_vec: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        // the last ten values carry as much weight as the other ninety
+        let weights: Vec<f32> = (0..100).map(|i| if i < 90 { 1.0 } else { 9.0 }).collect();
+
+        let plain = Histogram::from_feature(&feature_vec, 2);
+        let weighted = Histogram::from_weighted_feature(&feature_vec, Some(&weights), 2);
+
+        // the sketch is approximate at 8 entries per bin
+        assert_eq!((plain.bins[0], plain.bins[2]), (0.0, 99.0));
+        assert!((45.0..=55.0).contains(&plain.bins[1]));
+        assert!((85.0..=93.0).contains(&weighted.bins[1]));
+    }
+
+    #[test]
+    fn test_from_categories_gives_every_category_a_bin() {
+        let hist = Histogram::from_categories(&[3.0, 0.0, 7.0, 3.0, 0.0]);
+
+        assert_eq!(hist.bins, [0.0, 3.0, 7.0]);
+        assert_eq!(hist.num_bins(), 3);
+        assert_eq!(hist.search_bin_index(&0.0), 0);
+        assert_eq!(hist.search_bin_index(&3.0), 1);
+        assert_eq!(hist.search_bin_index(&7.0), 2);
+    }
+
+    #[test]
+    fn test_from_equal_width_ignores_value_spread() {
+        // most values crowd the low end, which quantile edges would follow
+        let feature_vec = vec![0.0, 0.1, 0.2, 0.3, 0.4, f32::NAN, 8.0];
+        let hist = Histogram::from_equal_width(&feature_vec, 4);
+        assert_eq!(hist.bins(), &[0.0, 2.0, 4.0, 6.0, 8.0]);
+        assert_eq!(hist.search_bin_index(&0.4), 0);
+        assert_eq!(hist.search_bin_index(&8.0), 3);
+
+        assert_eq!(Histogram::from_equal_width(&[3.0, 3.0], 4).bins(), &[3.0]);
+        assert_eq!(Histogram::from_equal_width(&[f32::NAN], 4).num_bins(), 0);
+    }
+
+    #[test]
+    fn test_merge_adds_partial_histograms() {
+        let feature_values = vec![0.0, 1.0, 2.0, 3.0, f32::NAN, 1.0];
+        let gradients = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let hessians = vec![1.0; 6];
+        let template = Histogram::from_feature(&feature_values, 3);
+
+        let mut whole = template.clone();
+        whole.accumulate(&feature_values, &gradients, &hessians, None);
+        // two workers with half the rows each
+        let mut merged = template.clone();
+        merged.accumulate(&feature_values[..3], &gradients[..3], &hessians[..3], None);
+        let mut other = template.clone();
+        other.accumulate(&feature_values[3..], &gradients[3..], &hessians[3..], None);
+        merged.merge(&other).unwrap();
+
+        assert_eq!(merged.gradients(), whole.gradients());
+        assert_eq!(merged.counts(), whole.counts());
+        assert_eq!(merged.missing(), whole.missing());
+
+        let err = merged
+            .merge(&Histogram::from_feature(&[0.0, 5.0], 3))
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidData { .. }));
+    }
+
+    #[test]
+    fn test_reset_keeps_edges_for_the_next_round() {
+        let feature_values = vec![0.0, 1.0, 2.0, f32::NAN];
+        let mut hist = Histogram::from_feature(&feature_values, 2);
+        let fresh = hist.clone();
+        hist.accumulate(&feature_values, &[1.0, 2.0, 3.0, 4.0], &[1.0; 4], None);
+
+        hist.reset();
+        assert_eq!(hist.bins(), fresh.bins());
+        assert_eq!(hist.gradients(), &[0.0, 0.0]);
+        assert_eq!(hist.hessians(), &[0.0, 0.0]);
+        assert_eq!(hist.counts(), &[0, 0]);
+        assert_eq!(hist.missing(), (0.0, 0.0, 0));
+
+        // the next round's gradients, as if the histogram had just been built
+        hist.accumulate(&feature_values, &[-1.0; 4], &[1.0; 4], None);
+        assert_eq!(hist.gradients(), &[-1.0, -2.0]);
+    }
+
+    #[test]
+    fn test_accumulate_bins_matches_accumulate() {
+        // enough rows for a few vector chunks and a remainder
+        let n = 45;
+        let feature_values: Vec<f32> = (0..n)
+            .map(|i| {
+                if i % 11 == 0 {
+                    f32::NAN
+                } else {
+                    (i % 6) as f32
+                }
+            })
+            .collect();
+        let gradients: Vec<f32> = (0..n).map(|i| (i as f32 * 0.37).sin()).collect();
+        let hessians: Vec<f32> = (0..n).map(|i| 0.5 + (i % 3) as f32).collect();
+        let weights: Vec<f32> = (0..n).map(|i| 1.0 + (i % 4) as f32 * 0.25).collect();
+        let template = Histogram::from_feature(&feature_values, 8);
+        let bins = FeatureBins::U8(
+            feature_values
+                .iter()
+                .map(|value| template.search_bin_index(value) as u8)
+                .collect(),
+        );
+
+        let rows: Vec<usize> = (0..n).filter(|i| i % 4 != 3).collect();
+        let mut binned = template.clone();
+        binned.accumulate_bins(
+            &bins,
+            &rows,
+            &gradients,
+            &hessians,
+            Some(&weights),
+            (0.0, 0.0),
+        );
+        let pick = |values: &[f32]| -> Vec<f32> { rows.iter().map(|&i| values[i]).collect() };
+        let mut expected = template.clone();
+        expected.accumulate(
+            &pick(&feature_values),
+            &pick(&gradients),
+            &pick(&hessians),
+            Some(&pick(&weights)),
+        );
+
+        // the same products added in the same order
+        assert_eq!(binned.gradients(), expected.gradients());
+        assert_eq!(binned.hessians(), expected.hessians());
+        assert_eq!(binned.counts(), expected.counts());
+        assert_eq!(binned.missing(), expected.missing());
+    }
+
+    #[test]
+    fn test_accumulate_subset_matches_accumulate_on_those_rows() {
+        let feature_values = vec![0.5, 1.5, 2.5, f32::NAN, 0.5, 2.5];
+        let gradients = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let hessians = vec![1.0; 6];
+        let template = Histogram::from_feature(&[0.0, 1.0, 2.0, 3.0], 3);
+        let binned_column: Vec<u16> = feature_values
+            .iter()
+            .map(|value| template.search_bin_index(value) as u16)
+            .collect();
+
+        let rows = [5, 0, 3];
+        let mut subset = template.clone();
+        subset.accumulate_subset(&rows, &binned_column, &gradients, &hessians);
+        let mut expected = template.clone();
+        let pick =
+            |values: &[f32]| -> Vec<f32> { rows.iter().map(|&i| values[i as usize]).collect() };
+        expected.accumulate(
+            &pick(&feature_values),
+            &pick(&gradients),
+            &pick(&hessians),
+            None,
+        );
+
+        assert_eq!(subset.gradients(), &[1.0, 0.0, 6.0]);
+        assert_eq!(subset.gradients(), expected.gradients());
+        assert_eq!(subset.counts(), expected.counts());
+        assert_eq!(subset.missing(), (4.0, 1.0, 1));
+    }
+
+    #[test]
+    fn test_double_precision_keeps_long_sums_exact() {
+        let n = 1_000_000;
+        let feature_values = vec![1.0; n];
+        let gradients = vec![0.1; n];
+        let hessians = vec![1.0; n];
+
+        let mut single = Histogram::from_feature(&[0.0, 1.0], 2);
+        let mut double = single.clone().with_precision(HistogramPrecision::Double);
+        single.accumulate(&feature_values, &gradients, &hessians, None);
+        double.accumulate(&feature_values, &gradients, &hessians, None);
+
+        // f32 drifts by several hundred over a million additions of 0.1
+        assert!((single.gradients()[0] - 100_000.0).abs() > 100.0);
+        assert_abs_diff_eq!(double.gradients()[0], 100_000.0, epsilon = 0.01);
+        assert_eq!(double.counts(), &[n]);
+        assert_eq!(double.precision(), HistogramPrecision::Double);
+    }
+
     #[test]
     fn test_search_bin_index() {
         let feature_values = vec![0.0, 2.0, 4.0, 6.0, 9.0];
@@ -160,12 +847,49 @@ mod tests {
         let gradients = vec![-0.5, 0.3, -0.2, 0.4, 0.1];
         let hessians = vec![1.0, 1.2, 0.9, 1.1, 1.0];
 
-        hist.accumulate(&feature_values, &gradients, &hessians);
+        hist.accumulate(&feature_values, &gradients, &hessians, None);
 
         assert_abs_diff_eq!(hist.gradients[0], -0.2, epsilon = 1e-6);
         assert_abs_diff_eq!(hist.gradients[1], 0.3, epsilon = 1e-6);
 
         assert_abs_diff_eq!(hist.hessians[0], 2.2, epsilon = 1e-6);
         assert_abs_diff_eq!(hist.hessians[1], 3.0, epsilon = 1e-6);
+
+        assert_eq!(hist.counts, [2, 3]);
+    }
+
+    #[test]
+    fn test_accumulate_weighted() {
+        let feature_values = vec![1.0, 2.0, 3.0, 5.0, 7.0];
+        let mut hist = Histogram::from_feature(&feature_values, 2);
+
+        let gradients = vec![-0.5, 0.3, -0.2, 0.4, 0.1];
+        let hessians = vec![1.0; 5];
+        let weights = vec![2.0, 0.0, 1.0, 0.5, 1.0];
+
+        hist.accumulate(&feature_values, &gradients, &hessians, Some(&weights));
+
+        assert_abs_diff_eq!(hist.gradients[0], -1.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(hist.gradients[1], 0.1, epsilon = 1e-6);
+        assert_abs_diff_eq!(hist.hessians[0], 2.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(hist.hessians[1], 2.5, epsilon = 1e-6);
+        // zero weights still count as samples
+        assert_eq!(hist.counts, [2, 3]);
+    }
+
+    #[test]
+    fn test_nan_goes_to_missing_bin() {
+        let feature_values = vec![1.0, f32::NAN, 3.0, 5.0, f32::NAN];
+        let mut hist = Histogram::from_feature(&feature_values, 2);
+        assert_eq!(hist.bins, [1.0, 3.0, 5.0]);
+        assert_eq!(hist.search_bin_index(&f32::NAN), 2);
+
+        let gradients = vec![-0.5, 0.3, -0.2, 0.4, 0.1];
+        hist.accumulate(&feature_values, &gradients, &[1.0; 5], None);
+
+        assert_eq!(hist.counts, [1, 2]);
+        let (missing_gradients, missing_hessians, missing_count) = hist.missing();
+        assert_abs_diff_eq!(missing_gradients, 0.4, epsilon = 1e-6);
+        assert_eq!((missing_hessians, missing_count), (2.0, 2));
     }
 }
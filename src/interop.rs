@@ -0,0 +1,8 @@
+// Conversions from and to the data types of other crates, each behind its own feature.
+
+#[cfg(feature = "ndarray")]
+pub mod arrays;
+#[cfg(feature = "polars")]
+pub mod dataframe;
+#[cfg(feature = "arrow")]
+pub mod record_batch;
@@ -0,0 +1,111 @@
+// Batch prediction on ndarray matrices, behind the `ndarray` feature. Training data comes in
+// through `Dataset::from_array`.
+
+use std::borrow::Cow;
+
+use ndarray::{Array1, Array2, ArrayView1, ArrayView2};
+
+use crate::booster::Booster;
+use crate::error::Result;
+
+// Each of these checks first that `features` has as many columns as the model has features,
+// see `Booster::check_features`.
+impl Booster {
+    // `predict` for every row of `features`
+    pub fn predict_array(&self, features: ArrayView2<f32>) -> Result<Array1<f32>> {
+        self.check_features(features.ncols(), None)?;
+        Ok(features
+            .rows()
+            .into_iter()
+            .map(|row| self.predict(&as_slice(row)))
+            .collect())
+    }
+
+    // `predict_raw` for every row, one column per output
+    pub fn predict_raw_array(&self, features: ArrayView2<f32>) -> Result<Array2<f32>> {
+        self.predict_rows(features, |row| self.predict_raw(row))
+    }
+
+    // `predict_proba` for every row, one column per output
+    pub fn predict_proba_array(&self, features: ArrayView2<f32>) -> Result<Array2<f32>> {
+        self.predict_rows(features, |row| self.predict_proba(row))
+    }
+
+    fn predict_rows(
+        &self,
+        features: ArrayView2<f32>,
+        predict: impl Fn(&[f32]) -> Vec<f32>,
+    ) -> Result<Array2<f32>> {
+        self.check_features(features.ncols(), None)?;
+        let scores: Vec<f32> = features
+            .rows()
+            .into_iter()
+            .flat_map(|row| predict(&as_slice(row)))
+            .collect();
+        Ok(
+            Array2::from_shape_vec((features.nrows(), self.num_outputs()), scores)
+                .expect("one score per row and output"),
+        )
+    }
+}
+
+// rows of a transposed or sliced view aren't contiguous and get copied
+fn as_slice(row: ArrayView1<'_, f32>) -> Cow<'_, [f32]> {
+    match row.to_slice() {
+        Some(slice) => Cow::Borrowed(slice),
+        None => Cow::Owned(row.to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::booster::Booster;
+    use crate::dataset::Dataset;
+    use crate::error::Error;
+    use crate::objective::ObjectiveType;
+    use crate::params::Params;
+    use ndarray::{Array1, Array2};
+
+    #[test]
+    fn test_train_and_predict_on_arrays() {
+        let features = Array2::from_shape_fn((20, 2), |(i, j)| (i * (j + 1)) as f32);
+        let labels = Array1::from_shape_fn(20, |i| (i % 3) as f32);
+        let dataset = Dataset::from_array(features.view(), labels.view());
+        assert_eq!(dataset.features()[3], vec![3.0, 6.0]);
+
+        let mut booster = Booster::new();
+        let params = Params {
+            num_iterations: 5,
+            ..Params::default()
+        };
+        booster.train(&dataset, &params).unwrap();
+        let predictions = booster.predict_array(features.view()).unwrap();
+        assert_eq!(predictions.len(), 20);
+        assert_eq!(predictions[3], booster.predict(&[3.0, 6.0]));
+        // column-major views predict the same as row-major ones
+        let transposed = features.t().to_owned();
+        assert_eq!(booster.predict_array(transposed.t()).unwrap(), predictions);
+        // a column too few or too many
+        for columns in [1, 3] {
+            let other = Array2::zeros((4, columns));
+            assert!(matches!(
+                booster.predict_array(other.view()),
+                Err(Error::InvalidData { .. })
+            ));
+            assert!(booster.predict_raw_array(other.view()).is_err());
+        }
+
+        booster
+            .train(
+                &dataset,
+                &Params {
+                    objective: ObjectiveType::Multiclass { num_class: 3 },
+                    ..params
+                },
+            )
+            .unwrap();
+        let probabilities = booster.predict_proba_array(features.view()).unwrap();
+        assert_eq!(probabilities.dim(), (20, 3));
+        assert!((probabilities.row(0).sum() - 1.0).abs() < 1e-5);
+    }
+}
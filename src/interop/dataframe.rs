@@ -0,0 +1,113 @@
+// Polars `DataFrame` input, behind the `polars` feature.
+
+use polars::prelude::{Column, DataFrame, DataType};
+
+use crate::dataset::Dataset;
+use crate::error::{Error, Result};
+
+// The `label` column becomes the labels and every other column a feature named after it.
+// Numeric and boolean columns are cast to f32, enum columns contribute their category codes,
+// the positions of their categories. Categorical columns are rejected: their codes come from
+// polars' string cache and change from one process to the next, so cast them to an enum with
+// the categories in a fixed order. Nulls become NaN.
+pub fn dataset_from_dataframe(df: &DataFrame, label: &str) -> Result<Dataset> {
+    let label_column = df
+        .column(label)
+        .map_err(|_| Error::invalid_data(format!("no column named `{label}`")))?;
+    let labels = float_column(label_column)?;
+    if labels.iter().any(|label| label.is_nan()) {
+        return Err(Error::invalid_data(format!(
+            "column `{label}` has missing labels"
+        )));
+    }
+
+    let mut names = vec![];
+    let mut columns = vec![];
+    for column in df.get_columns() {
+        if column.name().as_str() != label {
+            names.push(column.name().to_string());
+            columns.push(float_column(column)?);
+        }
+    }
+    let features = (0..df.height())
+        .map(|i| columns.iter().map(|column| column[i]).collect())
+        .collect();
+    Ok(Dataset::new(features, labels).with_feature_names(names))
+}
+
+fn float_column(column: &Column) -> Result<Vec<f32>> {
+    let unreadable = |err| {
+        Error::invalid_data(format!(
+            "column `{}` can't be read as f32: {err}",
+            column.name()
+        ))
+    };
+    let dtype = column.dtype();
+    if dtype.is_categorical() {
+        return Err(Error::invalid_data(format!(
+            "column `{}` is categorical, whose codes aren't stable; cast it to an enum",
+            column.name()
+        )));
+    }
+    let values = if dtype.is_enum() {
+        column.to_physical_repr()
+    } else {
+        column.clone()
+    };
+    let floats = values.cast(&DataType::Float32).map_err(unreadable)?;
+    Ok(floats
+        .f32()
+        .map_err(unreadable)?
+        .into_iter()
+        .map(|value| value.unwrap_or(f32::NAN))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::prelude::{Categories, FrozenCategories, IntoColumn, NamedFrom, Series};
+
+    #[test]
+    fn test_dataset_from_dataframe() {
+        let colors = DataType::from_frozen_categories(
+            FrozenCategories::new(["red", "green", "blue"]).unwrap(),
+        );
+        let df = DataFrame::new(vec![
+            Series::new("x".into(), &[Some(1.5f64), None, Some(3.0)]).into_column(),
+            Series::new("flag".into(), &[true, false, true]).into_column(),
+            Series::new("color".into(), &[Some("blue"), Some("red"), None])
+                .cast(&colors)
+                .unwrap()
+                .into_column(),
+            Series::new("y".into(), &[0i32, 1, 1]).into_column(),
+        ])
+        .unwrap();
+
+        let dataset = dataset_from_dataframe(&df, "y").unwrap();
+        assert_eq!(dataset.labels(), &[0.0, 1.0, 1.0]);
+        assert_eq!(dataset.features()[0], vec![1.5, 1.0, 2.0]);
+        assert!(dataset.features()[1][0].is_nan());
+        // an enum's codes are the positions of its categories
+        assert_eq!(dataset.features()[1][2], 0.0);
+        assert!(dataset.features()[2][2].is_nan());
+        assert_eq!(
+            dataset.feature_names(),
+            Some(&["x".to_string(), "flag".to_string(), "color".to_string()][..])
+        );
+        assert!(dataset_from_dataframe(&df, "z").is_err());
+
+        let categorical = DataFrame::new(vec![
+            Series::new("color".into(), &["blue", "red"])
+                .cast(&DataType::from_categories(Categories::global()))
+                .unwrap()
+                .into_column(),
+            Series::new("y".into(), &[0i32, 1]).into_column(),
+        ])
+        .unwrap();
+        assert!(matches!(
+            dataset_from_dataframe(&categorical, "y"),
+            Err(Error::InvalidData { .. })
+        ));
+    }
+}
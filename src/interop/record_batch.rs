@@ -0,0 +1,159 @@
+// Arrow `RecordBatch` input, behind the `arrow` feature. Columns are read in place when they
+// already hold f32 values and cast otherwise, and the features are gathered from them
+// straight into the row-major layout of `Dataset`.
+
+use arrow::array::{Array, ArrayRef, AsArray, Float32Array};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Float32Type};
+use arrow::record_batch::RecordBatch;
+
+use crate::booster::Booster;
+use crate::dataset::Dataset;
+use crate::error::{Error, Result};
+
+// The `label` column becomes the labels and every other column a feature named after it.
+// Any column Arrow can cast to f32 works, nulls become NaN.
+pub fn dataset_from_record_batch(batch: &RecordBatch, label: &str) -> Result<Dataset> {
+    let schema = batch.schema();
+    let label_index = schema
+        .index_of(label)
+        .map_err(|_| Error::invalid_data(format!("no column named `{label}`")))?;
+    let labels = float_column(batch, label_index)?;
+    if labels.iter().any(|label| label.is_nan()) {
+        return Err(Error::invalid_data(format!(
+            "column `{label}` has missing labels"
+        )));
+    }
+
+    let feature_indices: Vec<usize> = (0..batch.num_columns())
+        .filter(|&index| index != label_index)
+        .collect();
+    let features = rows(batch, &feature_indices)?;
+    let names = feature_indices
+        .iter()
+        .map(|&index| schema.field(index).name().clone())
+        .collect();
+    Ok(Dataset::new(features, labels).with_feature_names(names))
+}
+
+// Every column of `batch` as one feature, in order, e.g. to predict on.
+pub fn features_from_record_batch(batch: &RecordBatch) -> Result<Vec<Vec<f32>>> {
+    rows(batch, &(0..batch.num_columns()).collect::<Vec<_>>())
+}
+
+impl Booster {
+    // `predict_proba` for every row of `batch`, whose columns are the features in training
+    // order, after checking them by number and name with `check_features`
+    pub fn predict_record_batch(&self, batch: &RecordBatch) -> Result<Vec<Vec<f32>>> {
+        let names: Vec<String> = batch
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| field.name().clone())
+            .collect();
+        self.check_features(names.len(), Some(&names))?;
+        Ok(features_from_record_batch(batch)?
+            .iter()
+            .map(|row| self.predict_proba(row))
+            .collect())
+    }
+}
+
+fn rows(batch: &RecordBatch, columns: &[usize]) -> Result<Vec<Vec<f32>>> {
+    let arrays = columns
+        .iter()
+        .map(|&index| float_array(batch, index))
+        .collect::<Result<Vec<_>>>()?;
+    let columns: Vec<&Float32Array> = arrays
+        .iter()
+        .map(|array| array.as_primitive::<Float32Type>())
+        .collect();
+    Ok((0..batch.num_rows())
+        .map(|i| columns.iter().map(|column| value(column, i)).collect())
+        .collect())
+}
+
+fn float_column(batch: &RecordBatch, index: usize) -> Result<Vec<f32>> {
+    let array = float_array(batch, index)?;
+    let floats = array.as_primitive::<Float32Type>();
+    Ok((0..floats.len()).map(|i| value(floats, i)).collect())
+}
+
+// the column as f32 values, the column itself when it already holds them
+fn float_array(batch: &RecordBatch, index: usize) -> Result<ArrayRef> {
+    let schema = batch.schema();
+    let name = schema.field(index).name();
+    cast(batch.column(index), &DataType::Float32)
+        .map_err(|err| Error::invalid_data(format!("column `{name}` can't be read as f32: {err}")))
+}
+
+// the value at row `i`, NaN when it's null
+fn value(floats: &Float32Array, i: usize) -> f32 {
+    if floats.is_null(i) {
+        f32::NAN
+    } else {
+        floats.value(i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objective::ObjectiveType;
+    use crate::params::Params;
+    use arrow::array::{Float64Array, Int32Array};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_dataset_from_record_batch() {
+        let batch = RecordBatch::try_from_iter([
+            (
+                "x",
+                Arc::new(Float64Array::from(vec![Some(1.5), None, Some(3.0)])) as ArrayRef,
+            ),
+            ("y", Arc::new(Int32Array::from(vec![0, 1, 1])) as ArrayRef),
+        ])
+        .unwrap();
+
+        let dataset = dataset_from_record_batch(&batch, "y").unwrap();
+        assert_eq!(dataset.labels(), &[0.0, 1.0, 1.0]);
+        assert_eq!(dataset.features()[0], vec![1.5]);
+        assert!(dataset.features()[1][0].is_nan());
+        assert_eq!(dataset.feature_names(), Some(&["x".to_string()][..]));
+
+        assert_eq!(
+            features_from_record_batch(&batch).unwrap()[2],
+            vec![3.0, 1.0]
+        );
+        assert!(dataset_from_record_batch(&batch, "z").is_err());
+    }
+
+    #[test]
+    fn test_predict_record_batch_checks_the_columns() {
+        let x: Vec<f64> = (0..20).map(f64::from).collect();
+        let labels: Vec<i32> = (0..20).map(|i| i32::from(i > 9)).collect();
+        let batch = RecordBatch::try_from_iter([
+            ("x", Arc::new(Float64Array::from(x)) as ArrayRef),
+            ("y", Arc::new(Int32Array::from(labels)) as ArrayRef),
+        ])
+        .unwrap();
+        let params = Params {
+            objective: ObjectiveType::BinaryLogistic,
+            num_iterations: 5,
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster
+            .train(&dataset_from_record_batch(&batch, "y").unwrap(), &params)
+            .unwrap();
+
+        let features = batch.project(&[0]).unwrap();
+        let predictions = booster.predict_record_batch(&features).unwrap();
+        assert_eq!(predictions[15], vec![booster.predict(&[15.0])]);
+        // the label column is one column too many
+        assert!(matches!(
+            booster.predict_record_batch(&batch),
+            Err(Error::InvalidData { .. })
+        ));
+    }
+}
@@ -0,0 +1,450 @@
+// Loaders turning data files into a `Dataset`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::dataset::Dataset;
+use crate::error::{Error, Result};
+use crate::sparse::CsrMatrix;
+
+#[cfg(feature = "parquet")]
+mod parquet_file;
+#[cfg(feature = "parquet")]
+pub use parquet_file::{ParquetData, ParquetOptions, read_parquet};
+
+// which column of a CSV file holds the labels, the others are features
+#[derive(Debug, Clone, PartialEq)]
+pub enum LabelColumn {
+    Index(usize),
+    // needs a header
+    Name(String),
+    // every column is a feature, e.g. in data to predict; the labels are all 0
+    Unlabeled,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvOptions {
+    // the first line names the columns, which become the feature names
+    pub has_header: bool,
+    pub label: LabelColumn,
+    pub delimiter: char,
+    // Fields read as missing (NaN) besides empty ones. Features may be missing, labels
+    // may not.
+    pub na_values: Vec<String>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            has_header: true,
+            label: LabelColumn::Index(0),
+            delimiter: ',',
+            na_values: ["NA", "NaN", "nan", "N/A", "null"]
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+}
+
+pub fn read_csv(path: &Path, options: &CsvOptions) -> Result<Dataset> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| Error::io(format!("can't read {}: {err}", path.display())))?;
+    parse_csv(&text, options)
+}
+
+// `read_csv` on the contents of a file
+pub fn parse_csv(text: &str, options: &CsvOptions) -> Result<Dataset> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+
+    let header = if options.has_header {
+        lines
+            .next()
+            .map(|(_, line)| split_fields(line, options.delimiter))
+    } else {
+        None
+    };
+    let label_index = match (&options.label, &header) {
+        (LabelColumn::Index(index), _) => Some(*index),
+        (LabelColumn::Name(name), Some(header)) => Some(
+            header
+                .iter()
+                .position(|column| column == name)
+                .ok_or_else(|| Error::format(format!("no column named `{name}` in the header")))?,
+        ),
+        (LabelColumn::Name(name), None) => {
+            return Err(Error::format(format!(
+                "the label column `{name}` can only be found by name with a header"
+            )));
+        }
+        (LabelColumn::Unlabeled, _) => None,
+    };
+
+    let mut num_columns = header.as_ref().map(|header| header.len());
+    if let Some(label_index) = label_index
+        && num_columns.is_some_and(|num_columns| label_index >= num_columns)
+    {
+        return Err(Error::format(format!(
+            "label column {label_index} is out of range for {} columns",
+            num_columns.unwrap_or_default()
+        )));
+    }
+    let mut features = vec![];
+    let mut labels = vec![];
+    for (line_index, line) in lines {
+        let line_number = line_index + 1;
+        let fields = split_fields(line, options.delimiter);
+        let expected = *num_columns.get_or_insert(fields.len());
+        if fields.len() != expected {
+            return Err(Error::format(format!(
+                "line {line_number} has {} fields, expected {expected}",
+                fields.len()
+            )));
+        }
+        if let Some(label_index) = label_index
+            && label_index >= fields.len()
+        {
+            return Err(Error::format(format!(
+                "label column {label_index} is out of range for {} columns",
+                fields.len()
+            )));
+        }
+
+        let mut row = Vec::with_capacity(fields.len() - 1);
+        for (column, field) in fields.iter().enumerate() {
+            let value = parse_value(field, &options.na_values).ok_or_else(|| {
+                Error::format(format!(
+                    "line {line_number}, column {}: `{field}` isn't a number",
+                    column + 1
+                ))
+            })?;
+            if Some(column) == label_index {
+                if value.is_nan() {
+                    return Err(Error::format(format!(
+                        "line {line_number} is missing its label"
+                    )));
+                }
+                labels.push(value);
+            } else {
+                row.push(value);
+            }
+        }
+        if label_index.is_none() {
+            labels.push(0.0);
+        }
+        features.push(row);
+    }
+
+    let dataset = Dataset::new(features, labels);
+    Ok(match header {
+        Some(mut names) => {
+            if let Some(label_index) = label_index {
+                names.remove(label_index);
+            }
+            dataset.with_feature_names(names)
+        }
+        None => dataset,
+    })
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LibSvmOptions {
+    // Feature indices start at 0 instead of the usual 1.
+    pub zero_based: bool,
+    // Pads rows to this many features, e.g. so a test file whose highest features are all
+    // zero lines up with the training file. By default it's the highest index seen.
+    pub num_features: Option<usize>,
+    // keep the features in CSR form instead of storing the zeros, see `Dataset::from_csr`
+    pub sparse: bool,
+}
+
+pub fn read_libsvm(path: &Path, options: &LibSvmOptions) -> Result<Dataset> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| Error::io(format!("can't read {}: {err}", path.display())))?;
+    parse_libsvm(&text, options)
+}
+
+// Parses LibSVM/SVMLight lines, `label [qid:query] index:value ...` with `#` starting a
+// comment. Features left out of a line are 0. Consecutive lines with the same query id
+// become one ranking group.
+pub fn parse_libsvm(text: &str, options: &LibSvmOptions) -> Result<Dataset> {
+    let mut labels = vec![];
+    let mut sparse_rows: Vec<Vec<(usize, f32)>> = vec![];
+    let mut query_ids: Vec<u64> = vec![];
+    let mut num_features = options.num_features.unwrap_or(0);
+
+    for (line_index, line) in text.lines().enumerate() {
+        let line_number = line_index + 1;
+        let line = line.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+        let Some(label) = tokens.next() else {
+            continue;
+        };
+        labels.push(
+            label.parse().map_err(|_| {
+                Error::format(format!("line {line_number}: `{label}` isn't a label"))
+            })?,
+        );
+
+        let mut row = vec![];
+        for token in tokens {
+            let malformed =
+                || Error::format(format!("line {line_number}: `{token}` isn't index:value"));
+            let (key, value) = token.split_once(':').ok_or_else(malformed)?;
+            if key == "qid" {
+                query_ids.push(value.parse().map_err(|_| malformed())?);
+                continue;
+            }
+            let index: usize = key.parse().map_err(|_| malformed())?;
+            let index = if options.zero_based {
+                index
+            } else {
+                index.checked_sub(1).ok_or_else(|| {
+                    Error::format(format!("line {line_number}: feature indices start at 1"))
+                })?
+            };
+            match options.num_features {
+                Some(limit) if index >= limit => {
+                    return Err(Error::format(format!(
+                        "line {line_number}: feature {key} is beyond the {limit} features"
+                    )));
+                }
+                _ => num_features = num_features.max(index + 1),
+            }
+            row.push((index, value.parse().map_err(|_| malformed())?));
+        }
+        sparse_rows.push(row);
+    }
+
+    let dataset = if options.sparse {
+        let mut indptr = vec![0];
+        let mut indices = vec![];
+        let mut values = vec![];
+        for mut entries in sparse_rows {
+            entries.sort_by_key(|&(index, _)| index);
+            indices.extend(entries.iter().map(|&(index, _)| index));
+            values.extend(entries.iter().map(|&(_, value)| value));
+            indptr.push(indices.len());
+        }
+        // only fails for features given twice in a line
+        let matrix = CsrMatrix::new(indptr, indices, values, num_features)
+            .map_err(|err| Error::format(err.to_string()))?;
+        Dataset::from_csr(matrix, labels)
+    } else {
+        let features = sparse_rows
+            .into_iter()
+            .map(|entries| {
+                let mut row = vec![0.0; num_features];
+                for (index, value) in entries {
+                    row[index] = value;
+                }
+                row
+            })
+            .collect();
+        Dataset::new(features, labels)
+    };
+    if query_ids.is_empty() {
+        return Ok(dataset);
+    }
+    if query_ids.len() != dataset.num_rows() {
+        return Err(Error::format("either every line or none has a query id"));
+    }
+    Ok(dataset.with_groups(&group_sizes(&query_ids)))
+}
+
+// the metadata entry `save_categories` writes
+const CATEGORIES_KEY: &str = "categories";
+
+// Keeps the codes of string columns, as `read_parquet` returns them in `ParquetData`, in a
+// model's metadata (e.g. `Booster::metadata_mut`), so the data it scores can be read with the
+// same codes.
+pub fn save_categories(
+    categories: &BTreeMap<String, Vec<String>>,
+    metadata: &mut BTreeMap<String, String>,
+) {
+    let json = serde_json::to_string(categories).expect("string lists serialize");
+    metadata.insert(CATEGORIES_KEY.to_string(), json);
+}
+
+// the codes `save_categories` kept in a model's metadata, none when it has none
+pub fn load_categories(
+    metadata: &BTreeMap<String, String>,
+) -> Result<BTreeMap<String, Vec<String>>> {
+    match metadata.get(CATEGORIES_KEY) {
+        Some(json) => serde_json::from_str(json).map_err(|err| {
+            Error::format(format!(
+                "metadata `{CATEGORIES_KEY}` isn't a list of codes: {err}"
+            ))
+        }),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+// the lengths of the runs of equal ids, i.e. the sizes of consecutive query groups
+fn group_sizes<T: PartialEq>(query_ids: &[T]) -> Vec<usize> {
+    let mut sizes: Vec<usize> = vec![];
+    for (i, query_id) in query_ids.iter().enumerate() {
+        if i > 0 && query_ids[i - 1] == *query_id {
+            *sizes.last_mut().unwrap() += 1;
+        } else {
+            sizes.push(1);
+        }
+    }
+    sizes
+}
+
+// Splits on `delimiter` outside of double quotes, where "" stands for a literal quote.
+fn split_fields(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+        .iter()
+        .map(|field| field.trim().to_string())
+        .collect()
+}
+
+// None when the field is neither a number nor missing
+fn parse_value(field: &str, na_values: &[String]) -> Option<f32> {
+    if field.is_empty() || na_values.iter().any(|na| na == field) {
+        Some(f32::NAN)
+    } else {
+        field.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_with_header_and_named_label() {
+        let text = "x0;\"price; usd\";x1\n1.5;10;NA\n\n-2;20;\"3\"\n";
+        let options = CsvOptions {
+            label: LabelColumn::Name("price; usd".to_string()),
+            delimiter: ';',
+            ..CsvOptions::default()
+        };
+        let dataset = parse_csv(text, &options).unwrap();
+
+        assert_eq!(dataset.labels(), &[10.0, 20.0]);
+        assert_eq!(dataset.features()[0][0], 1.5);
+        assert!(dataset.features()[0][1].is_nan());
+        assert_eq!(dataset.features()[1], vec![-2.0, 3.0]);
+        assert_eq!(
+            dataset.feature_names(),
+            Some(&["x0".to_string(), "x1".to_string()][..])
+        );
+    }
+
+    #[test]
+    fn test_parse_libsvm() {
+        let text = "1 qid:3 1:0.5 3:2 # comment\n0 qid:3 2:-1\n\n# blank\n1 qid:7\n";
+        let dataset = parse_libsvm(text, &LibSvmOptions::default()).unwrap();
+        assert_eq!(dataset.labels(), &[1.0, 0.0, 1.0]);
+        assert_eq!(
+            dataset.features(),
+            &[
+                vec![0.5, 0.0, 2.0],
+                vec![0.0, -1.0, 0.0],
+                vec![0.0, 0.0, 0.0]
+            ]
+        );
+        assert_eq!(dataset.query_boundaries(), Some(&[0, 2, 3][..]));
+
+        let options = LibSvmOptions {
+            zero_based: true,
+            num_features: Some(5),
+            ..LibSvmOptions::default()
+        };
+        let dataset = parse_libsvm("2 0:1 4:3", &options).unwrap();
+        assert_eq!(dataset.features(), &[vec![1.0, 0.0, 0.0, 0.0, 3.0]]);
+        let sparse = LibSvmOptions {
+            sparse: true,
+            ..options.clone()
+        };
+        let dataset = parse_libsvm("2 4:3 0:1", &sparse).unwrap();
+        assert_eq!(
+            dataset.sparse_features(),
+            Some(&CsrMatrix::new(vec![0, 2], vec![0, 4], vec![1.0, 3.0], 5).unwrap())
+        );
+        assert!(parse_libsvm("2 5:1", &options).is_err());
+        let err = parse_libsvm("1 0:1", &LibSvmOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("start at 1"));
+        let err = parse_libsvm("1 2=1", &LibSvmOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("`2=1` isn't index:value"));
+    }
+
+    #[test]
+    fn test_parse_csv_without_header() {
+        let options = CsvOptions {
+            has_header: false,
+            label: LabelColumn::Index(2),
+            ..CsvOptions::default()
+        };
+        let dataset = parse_csv("1,2,0\n3,4,1\n", &options).unwrap();
+        assert_eq!(dataset.labels(), &[0.0, 1.0]);
+        assert_eq!(dataset.features(), &[vec![1.0, 2.0], vec![3.0, 4.0]]);
+        assert_eq!(dataset.feature_names(), None);
+
+        let err = parse_csv("1,2,0\n3,4\n", &options).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "malformed file: line 2 has 2 fields, expected 3"
+        );
+        let err = parse_csv("1,x,0\n", &options).unwrap_err();
+        assert!(err.to_string().contains("`x` isn't a number"));
+        let err = parse_csv("1,2,\n", &options).unwrap_err();
+        assert!(err.to_string().contains("missing its label"));
+    }
+
+    #[test]
+    fn test_parse_unlabeled_csv() {
+        let options = CsvOptions {
+            label: LabelColumn::Unlabeled,
+            ..CsvOptions::default()
+        };
+        let dataset = parse_csv("a,b\n1,\n3,4\n", &options).unwrap();
+        assert_eq!(dataset.labels(), &[0.0, 0.0]);
+        assert!(dataset.features()[0][1].is_nan());
+        assert_eq!(dataset.features()[1], vec![3.0, 4.0]);
+        assert_eq!(
+            dataset.feature_names(),
+            Some(&["a".to_string(), "b".to_string()][..])
+        );
+    }
+
+    #[test]
+    fn test_categories_round_trip_through_metadata() {
+        let mut metadata = BTreeMap::new();
+        assert_eq!(load_categories(&metadata).unwrap(), BTreeMap::new());
+        let categories = BTreeMap::from([(
+            "color".to_string(),
+            vec!["blue".to_string(), "red".to_string()],
+        )]);
+        save_categories(&categories, &mut metadata);
+        assert_eq!(load_categories(&metadata).unwrap(), categories);
+
+        metadata.insert(CATEGORIES_KEY.to_string(), "[1, 2]".to_string());
+        assert!(matches!(
+            load_categories(&metadata),
+            Err(Error::Format { .. })
+        ));
+    }
+}
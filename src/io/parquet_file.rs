@@ -0,0 +1,377 @@
+// Parquet loading, behind the `parquet` feature. Files are read a column chunk at a time
+// through parquet's Arrow reader.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::fs::File;
+use std::path::Path;
+
+use arrow::array::{Array, AsArray};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Float64Type};
+use arrow::error::ArrowError;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+use super::group_sizes;
+use crate::dataset::Dataset;
+use crate::error::{Error, Result};
+
+// Names the columns with a special role; every other column is a feature.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParquetOptions {
+    // None for data without labels, e.g. to predict on; the labels are then all 0
+    pub label: Option<String>,
+    // per-row sample weights
+    pub weight: Option<String>,
+    // query ids, consecutive rows with the same id form one ranking group
+    pub group: Option<String>,
+    // The codes of string columns: each column's strings, the code of a string being its
+    // position. Data to score is read with the codes `read_parquet` returned for the
+    // training data, and strings missing from a column's list are read as missing.
+    pub categories: BTreeMap<String, Vec<String>>,
+}
+
+// a Parquet file read by `read_parquet`
+#[derive(Debug)]
+pub struct ParquetData {
+    pub dataset: Dataset,
+    // The codes of every string feature, to save with the model (see `io::save_categories`)
+    // and read the data it scores with.
+    pub categories: BTreeMap<String, Vec<String>>,
+    // indices of the string features, whose codes are categories and not ordered values,
+    // for `Params::categorical_features`
+    pub categorical_features: Vec<usize>,
+}
+
+impl ParquetOptions {
+    pub fn new(label: impl Into<String>) -> Self {
+        Self {
+            label: Some(label.into()),
+            weight: None,
+            group: None,
+            categories: BTreeMap::new(),
+        }
+    }
+}
+
+// the values of one column
+#[derive(Debug, Clone, PartialEq)]
+enum Column {
+    // None where the column is null
+    Numbers(Vec<Option<f64>>),
+    // each row's index among the column's distinct strings, numbered as they first appear
+    Strings {
+        rows: Vec<Option<u32>>,
+        indices: HashMap<String, u32>,
+    },
+}
+
+impl Column {
+    fn len(&self) -> usize {
+        match self {
+            Column::Numbers(values) => values.len(),
+            Column::Strings { rows, .. } => rows.len(),
+        }
+    }
+
+    fn new(name: &str, data_type: &DataType) -> Result<Self> {
+        let is_string = |data_type: &DataType| {
+            matches!(
+                data_type,
+                DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View
+            )
+        };
+        match data_type {
+            DataType::Dictionary(_, values) if is_string(values) => Ok(Column::strings()),
+            data_type if is_string(data_type) => Ok(Column::strings()),
+            DataType::Boolean => Ok(Column::Numbers(vec![])),
+            data_type if data_type.is_numeric() => Ok(Column::Numbers(vec![])),
+            _ => Err(Error::format(format!(
+                "column `{name}` has unsupported values of type {data_type}"
+            ))),
+        }
+    }
+
+    fn strings() -> Self {
+        Column::Strings {
+            rows: vec![],
+            indices: HashMap::new(),
+        }
+    }
+
+    // appends one column chunk, of a type `new` accepted for the column
+    fn extend(&mut self, array: &dyn Array) -> std::result::Result<(), ArrowError> {
+        match self {
+            Column::Numbers(values) => {
+                let numbers = cast(array, &DataType::Float64)?;
+                values.extend(numbers.as_primitive::<Float64Type>().iter());
+            }
+            Column::Strings { rows, indices } => {
+                let strings = cast(array, &DataType::Utf8)?;
+                for string in strings.as_string::<i32>().iter() {
+                    rows.push(string.map(|string| match indices.get(string) {
+                        Some(&index) => index,
+                        None => {
+                            let index = indices.len() as u32;
+                            indices.insert(string.to_string(), index);
+                            index
+                        }
+                    }));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Numeric and boolean columns become features as they are, nulls become NaN. String columns
+// are categorical and coded with `options.categories`; one it has no codes for is coded by
+// the rank of each string among the column's strings.
+pub fn read_parquet(path: &Path, options: &ParquetOptions) -> Result<ParquetData> {
+    let file = File::open(path)
+        .map_err(|err| Error::io(format!("can't read {}: {err}", path.display())))?;
+    let malformed = |err: &dyn Display| Error::format(format!("{}: {err}", path.display()));
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file).map_err(|err| malformed(&err))?;
+
+    let mut columns = builder
+        .schema()
+        .fields()
+        .iter()
+        .map(|field| {
+            let column = Column::new(field.name(), field.data_type())?;
+            Ok((field.name().clone(), column))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for batch in builder.build().map_err(|err| malformed(&err))? {
+        let batch = batch.map_err(|err| malformed(&err))?;
+        for ((_, column), array) in columns.iter_mut().zip(batch.columns()) {
+            column
+                .extend(array.as_ref())
+                .map_err(|err| malformed(&err))?;
+        }
+    }
+    from_columns(columns, options)
+}
+
+fn from_columns(
+    mut columns: Vec<(String, Column)>,
+    options: &ParquetOptions,
+) -> Result<ParquetData> {
+    let mut take = |name: &str| -> Result<Column> {
+        let index = columns
+            .iter()
+            .position(|(column, _)| column == name)
+            .ok_or_else(|| Error::format(format!("no column named `{name}`")))?;
+        Ok(columns.remove(index).1)
+    };
+    let num_rows = columns.first().map_or(0, |(_, column)| column.len());
+    let labels = match &options.label {
+        Some(name) => numbers(name, take(name)?)?,
+        None => vec![0.0; num_rows],
+    };
+    let weights = match &options.weight {
+        Some(name) => Some(numbers(name, take(name)?)?),
+        None => None,
+    };
+    let query_ids = match &options.group {
+        Some(name) => Some(take(name)?),
+        None => None,
+    };
+
+    let mut categories = BTreeMap::new();
+    let mut categorical_features = vec![];
+    let mut feature_columns = vec![];
+    let mut names = vec![];
+    for (name, column) in columns {
+        if matches!(column, Column::Strings { .. }) {
+            categorical_features.push(feature_columns.len());
+        }
+        feature_columns.push(feature(&name, column, options, &mut categories));
+        names.push(name);
+    }
+    let features = (0..labels.len())
+        .map(|i| feature_columns.iter().map(|column| column[i]).collect())
+        .collect();
+
+    let mut dataset = Dataset::new(features, labels);
+    if !names.is_empty() {
+        dataset = dataset.with_feature_names(names);
+    }
+    if let Some(weights) = weights {
+        if !weights.iter().all(|&w| w >= 0.0 && w.is_finite()) {
+            return Err(Error::invalid_data(
+                "weights must be finite and non-negative",
+            ));
+        }
+        dataset = dataset.with_weights(weights);
+    }
+    if let Some(query_ids) = query_ids {
+        let sizes = match &query_ids {
+            Column::Numbers(values) => group_sizes(values),
+            Column::Strings { rows, .. } => group_sizes(rows),
+        };
+        dataset = dataset.with_groups(&sizes);
+    }
+    Ok(ParquetData {
+        dataset,
+        categories,
+        categorical_features,
+    })
+}
+
+// the values of a label or weight column, which can't be missing
+fn numbers(name: &str, column: Column) -> Result<Vec<f32>> {
+    let only_numbers = || Error::format(format!("column `{name}` must only hold numbers"));
+    match column {
+        Column::Numbers(values) => values
+            .into_iter()
+            .map(|value| value.map(|value| value as f32).ok_or_else(only_numbers))
+            .collect(),
+        Column::Strings { .. } => Err(only_numbers()),
+    }
+}
+
+// A string column's codes go to `categories`: the ones `options` has for it or else the
+// column's strings in sorted order.
+fn feature(
+    name: &str,
+    column: Column,
+    options: &ParquetOptions,
+    categories: &mut BTreeMap<String, Vec<String>>,
+) -> Vec<f32> {
+    let (rows, indices) = match column {
+        Column::Numbers(values) => {
+            return values
+                .into_iter()
+                .map(|value| value.map_or(f32::NAN, |value| value as f32))
+                .collect();
+        }
+        Column::Strings { rows, indices } => (rows, indices),
+    };
+    let strings = options.categories.get(name).cloned().unwrap_or_else(|| {
+        let mut strings: Vec<String> = indices.keys().cloned().collect();
+        strings.sort_unstable();
+        strings
+    });
+    let codes: HashMap<&str, usize> = strings
+        .iter()
+        .enumerate()
+        .map(|(code, string)| (string.as_str(), code))
+        .collect();
+    // the code of each of the column's strings by its index, NaN for ones without
+    let mut coded = vec![f32::NAN; indices.len()];
+    for (string, &index) in &indices {
+        if let Some(&code) = codes.get(string.as_str()) {
+            coded[index as usize] = code as f32;
+        }
+    }
+    categories.insert(name.to_string(), strings);
+    rows.iter()
+        .map(|index| index.map_or(f32::NAN, |index| coded[index as usize]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{ArrayRef, Float64Array, StringArray};
+    use arrow::record_batch::RecordBatch;
+    use parquet::arrow::ArrowWriter;
+    use std::sync::Arc;
+
+    fn string_column(values: &[&str]) -> Column {
+        let mut column = Column::strings();
+        column.extend(&StringArray::from(values.to_vec())).unwrap();
+        column
+    }
+
+    #[test]
+    fn test_from_columns_assigns_column_roles() {
+        let number = |values: &[f64]| Column::Numbers(values.iter().map(|&v| Some(v)).collect());
+        let columns = vec![
+            (
+                "x".to_string(),
+                Column::Numbers(vec![Some(1.0), None, Some(3.0)]),
+            ),
+            ("y".to_string(), number(&[0.0, 1.0, 0.0])),
+            ("color".to_string(), string_column(&["red", "blue", "red"])),
+            ("w".to_string(), number(&[1.0, 2.0, 1.0])),
+            ("query".to_string(), string_column(&["a", "a", "b"])),
+        ];
+        let options = ParquetOptions {
+            weight: Some("w".to_string()),
+            group: Some("query".to_string()),
+            ..ParquetOptions::new("y")
+        };
+        let data = from_columns(columns, &options).unwrap();
+        let dataset = data.dataset;
+
+        assert_eq!(dataset.labels(), &[0.0, 1.0, 0.0]);
+        assert_eq!(dataset.weights(), Some(&[1.0, 2.0, 1.0][..]));
+        assert_eq!(dataset.query_boundaries(), Some(&[0, 2, 3][..]));
+        assert_eq!(
+            dataset.feature_names(),
+            Some(&["x".to_string(), "color".to_string()][..])
+        );
+        assert_eq!(dataset.features()[0], vec![1.0, 1.0]);
+        assert!(dataset.features()[1][0].is_nan());
+        assert_eq!(dataset.features()[1][1], 0.0);
+        assert_eq!(data.categorical_features, [1]);
+        assert_eq!(
+            data.categories,
+            BTreeMap::from([(
+                "color".to_string(),
+                vec!["blue".to_string(), "red".to_string()]
+            )])
+        );
+
+        let err = from_columns(vec![], &options).unwrap_err();
+        assert_eq!(err.to_string(), "malformed file: no column named `y`");
+        let unlabeled = from_columns(vec![], &ParquetOptions::default()).unwrap();
+        assert_eq!(unlabeled.dataset.num_rows(), 0);
+    }
+
+    #[test]
+    fn test_scoring_data_is_read_with_the_training_codes() {
+        // the data to score has no label column
+        let write = |name: &str, colors: Vec<&str>, labeled: bool| {
+            let path = std::env::temp_dir()
+                .join(format!("metalgbm-{}-{name}.parquet", std::process::id()));
+            let labels: Vec<f64> = (0..colors.len()).map(|i| i as f64).collect();
+            let mut columns = vec![("color", Arc::new(StringArray::from(colors)) as ArrayRef)];
+            if labeled {
+                columns.push(("y", Arc::new(Float64Array::from(labels)) as ArrayRef));
+            }
+            let batch = RecordBatch::try_from_iter(columns).unwrap();
+            let mut writer =
+                ArrowWriter::try_new(File::create(&path).unwrap(), batch.schema(), None).unwrap();
+            writer.write(&batch).unwrap();
+            writer.close().unwrap();
+            path
+        };
+        let train = write("train", vec!["red", "blue", "green"], true);
+        let score = write("score", vec!["green", "purple", "red"], false);
+
+        let training = read_parquet(&train, &ParquetOptions::new("y")).unwrap();
+        assert_eq!(training.categories["color"], ["blue", "green", "red"]);
+        assert_eq!(training.categorical_features, [0]);
+        assert_eq!(training.dataset.features()[0], vec![2.0]);
+        assert_eq!(training.dataset.labels(), &[0.0, 1.0, 2.0]);
+
+        // coded on its own, the file would have green as 0 and purple as 1
+        let err = read_parquet(&score, &ParquetOptions::new("y")).unwrap_err();
+        assert!(matches!(err, Error::Format { .. }));
+        let options = ParquetOptions {
+            categories: training.categories,
+            ..ParquetOptions::default()
+        };
+        let scoring = read_parquet(&score, &options).unwrap().dataset;
+        assert_eq!(scoring.labels(), &[0.0, 0.0, 0.0]);
+        assert_eq!(scoring.feature_names(), Some(&["color".to_string()][..]));
+        assert_eq!(scoring.features()[0], vec![1.0]);
+        assert!(scoring.features()[1][0].is_nan());
+        assert_eq!(scoring.features()[2], vec![2.0]);
+        std::fs::remove_file(train).unwrap();
+        std::fs::remove_file(score).unwrap();
+    }
+}
@@ -1,2 +1,35 @@
+pub mod booster;
+pub mod callback;
+pub mod capi;
+pub mod checkpoint;
+pub mod codegen;
+pub mod config;
+pub mod dataset;
+pub mod distill;
+pub mod dot;
+pub mod dump;
+pub mod error;
+pub mod gpu;
 pub mod histogram;
+pub mod interop;
+pub mod io;
+pub mod linear;
+pub mod metrics;
+pub mod model;
+pub mod node_builder;
+pub mod objective;
+pub mod params;
+pub mod pool;
+pub mod prune;
+pub mod quantize;
+pub mod random;
+pub mod ranking;
+pub mod rules;
+pub mod sampling;
+pub mod schedule;
+pub mod shap;
+pub mod sketch;
+pub mod sparse;
+pub mod split;
 pub mod tree;
+pub mod tree_builder;
@@ -0,0 +1,142 @@
+// Leaf-local least squares for linear trees. A leaf's model z = b + w . x minimizes the
+// second order approximation of the loss, sum_i g_i z_i + h_i z_i^2 / 2, plus
+// lambda / 2 * |w|^2, which gives the normal equations
+// (sum_i h_i x_i x_i^T + lambda I') beta = -sum_i g_i x_i over x_i = (1, features of row i)
+// with the intercept left unregularized.
+
+// Fits the intercept and one coefficient per entry of `features` on the leaf's `rows`, whose
+// gradients and hessians are scaled by their `weights`. `value(feature, row)` reads the
+// training data. Rows with a NaN feature are left out, the leaf's constant value predicts
+// them. Returns None when the system is singular, e.g. with fewer rows than coefficients.
+pub fn fit_leaf(
+    value: &dyn Fn(usize, usize) -> f32,
+    rows: &[usize],
+    features: &[usize],
+    gradients: &[f32],
+    hessians: &[f32],
+    weights: Option<&[f32]>,
+    lambda: f32,
+) -> Option<(f32, Vec<f32>)> {
+    let n = features.len() + 1;
+    let mut a = vec![vec![0.0f64; n]; n];
+    let mut b = vec![0.0f64; n];
+
+    let mut x = vec![0.0f64; n];
+    for &row in rows {
+        x[0] = 1.0;
+        for (j, &feature) in features.iter().enumerate() {
+            x[j + 1] = value(feature, row) as f64;
+        }
+        if x.iter().any(|x| x.is_nan()) {
+            continue;
+        }
+        let weight = weights.map_or(1.0, |weights| weights[row] as f64);
+        let (g, h) = (
+            weight * gradients[row] as f64,
+            weight * hessians[row] as f64,
+        );
+        for j in 0..n {
+            b[j] -= g * x[j];
+            for k in 0..n {
+                a[j][k] += h * x[j] * x[k];
+            }
+        }
+    }
+    for (j, a_row) in a.iter_mut().enumerate().skip(1) {
+        a_row[j] += lambda as f64;
+    }
+
+    let beta = solve(a, b)?;
+    Some((
+        beta[0] as f32,
+        beta[1..].iter().map(|&value| value as f32).collect(),
+    ))
+}
+
+// Gaussian elimination with partial pivoting
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-10 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        let (upper, lower) = a.split_at_mut(col + 1);
+        let pivot_row = &upper[col];
+        for (offset, row) in lower.iter_mut().enumerate() {
+            let factor = row[col] / pivot_row[col];
+            for (value, pivot_value) in row[col..].iter_mut().zip(&pivot_row[col..]) {
+                *value -= factor * pivot_value;
+            }
+            b[col + 1 + offset] -= factor * b[col];
+        }
+    }
+
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let tail: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - tail) / a[row][row];
+    }
+    Some(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_fit_leaf_recovers_linear_targets() {
+        // squared error at zero predictions: g = -y, h = 1, so the fit is ordinary least
+        // squares on y = 1 + 2 x0 - x1
+        let columns = [[0.0, 1.0, 2.0, 3.0, 4.0], [1.0, 0.0, 3.0, 1.0, 2.0]];
+        let value = |feature: usize, row: usize| columns[feature][row];
+        let labels: Vec<f32> = (0..5)
+            .map(|i| 1.0 + 2.0 * columns[0][i] - columns[1][i])
+            .collect();
+        let gradients: Vec<f32> = labels.iter().map(|y| -y).collect();
+        let hessians = vec![1.0; 5];
+        let rows: Vec<usize> = (0..5).collect();
+
+        let (intercept, coefficients) =
+            fit_leaf(&value, &rows, &[0, 1], &gradients, &hessians, None, 0.0).unwrap();
+
+        assert_abs_diff_eq!(intercept, 1.0, epsilon = 1e-4);
+        assert_abs_diff_eq!(coefficients[0], 2.0, epsilon = 1e-4);
+        assert_abs_diff_eq!(coefficients[1], -1.0, epsilon = 1e-4);
+
+        let (_, shrunk) =
+            fit_leaf(&value, &rows, &[0, 1], &gradients, &hessians, None, 10.0).unwrap();
+        assert!(shrunk[0].abs() < 2.0);
+
+        // zero weights drop rows from the fit, so corrupting them changes nothing
+        let mut corrupted = gradients.clone();
+        corrupted[4] = 100.0;
+        let weights = [1.0, 1.0, 1.0, 1.0, 0.0];
+        let (intercept, coefficients) = fit_leaf(
+            &value,
+            &rows,
+            &[0, 1],
+            &corrupted,
+            &hessians,
+            Some(&weights),
+            0.0,
+        )
+        .unwrap();
+        assert_abs_diff_eq!(intercept, 1.0, epsilon = 1e-3);
+        assert_abs_diff_eq!(coefficients[0], 2.0, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_fit_leaf_rejects_singular_systems() {
+        // a constant feature can't be told apart from the intercept
+        let columns = [[3.0; 4]];
+        let value = |feature: usize, row: usize| columns[feature][row];
+        let rows: Vec<usize> = (0..4).collect();
+
+        assert!(fit_leaf(&value, &rows, &[0], &[1.0; 4], &[1.0; 4], None, 0.0).is_none());
+    }
+}
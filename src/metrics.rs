@@ -0,0 +1,500 @@
+use serde::{Deserialize, Serialize};
+
+use crate::dataset::Dataset;
+use crate::ranking;
+
+pub trait EvalMetric {
+    fn name(&self) -> &str;
+
+    // `preds` are transformed predictions (probabilities, means, ...) laid out like the raw
+    // scores of the booster. Metrics weight rows by the dataset's sample weights, if any.
+    fn evaluate(&self, preds: &[f32], dataset: &Dataset) -> f32;
+
+    fn higher_is_better(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MetricType {
+    Rmse,
+    Mae,
+    R2,
+    Auc,
+    BinaryLogloss,
+    // ranking metrics evaluated per query and averaged; without query groups the whole
+    // dataset counts as one query. Sample weights don't apply to them.
+    Ndcg { k: usize },
+    Map { k: usize },
+}
+
+impl MetricType {
+    pub fn create(&self) -> Box<dyn EvalMetric> {
+        match self {
+            MetricType::Rmse => Box::new(Rmse),
+            MetricType::Mae => Box::new(Mae),
+            MetricType::R2 => Box::new(R2),
+            MetricType::Auc => Box::new(Auc),
+            MetricType::BinaryLogloss => Box::new(BinaryLogloss),
+            MetricType::Ndcg { k } => Box::new(Ndcg::new(*k)),
+            MetricType::Map { k } => Box::new(Map::new(*k)),
+        }
+    }
+}
+
+pub struct Rmse;
+
+impl EvalMetric for Rmse {
+    fn name(&self) -> &str {
+        "rmse"
+    }
+
+    fn evaluate(&self, preds: &[f32], dataset: &Dataset) -> f32 {
+        weighted_rmse(preds, dataset.labels(), dataset.weights())
+    }
+}
+
+pub struct Mae;
+
+impl EvalMetric for Mae {
+    fn name(&self) -> &str {
+        "mae"
+    }
+
+    fn evaluate(&self, preds: &[f32], dataset: &Dataset) -> f32 {
+        weighted_mae(preds, dataset.labels(), dataset.weights())
+    }
+}
+
+pub struct R2;
+
+impl EvalMetric for R2 {
+    fn name(&self) -> &str {
+        "r2"
+    }
+
+    fn evaluate(&self, preds: &[f32], dataset: &Dataset) -> f32 {
+        weighted_r2(preds, dataset.labels(), dataset.weights())
+    }
+
+    fn higher_is_better(&self) -> bool {
+        true
+    }
+}
+
+pub struct Auc;
+
+impl EvalMetric for Auc {
+    fn name(&self) -> &str {
+        "auc"
+    }
+
+    fn evaluate(&self, preds: &[f32], dataset: &Dataset) -> f32 {
+        weighted_auc(preds, dataset.labels(), dataset.weights())
+    }
+
+    fn higher_is_better(&self) -> bool {
+        true
+    }
+}
+
+pub struct BinaryLogloss;
+
+impl EvalMetric for BinaryLogloss {
+    fn name(&self) -> &str {
+        "binary_logloss"
+    }
+
+    fn evaluate(&self, preds: &[f32], dataset: &Dataset) -> f32 {
+        weighted_binary_logloss(preds, dataset.labels(), dataset.weights())
+    }
+}
+
+pub struct Ndcg {
+    k: usize,
+    name: String,
+}
+
+impl Ndcg {
+    pub fn new(k: usize) -> Self {
+        Self {
+            k,
+            name: format!("ndcg@{k}"),
+        }
+    }
+}
+
+impl EvalMetric for Ndcg {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn evaluate(&self, preds: &[f32], dataset: &Dataset) -> f32 {
+        let labels = dataset.labels();
+        let boundaries = query_boundaries(dataset);
+        ranking::mean_ndcg_at(preds, labels, &boundaries, self.k)
+    }
+
+    fn higher_is_better(&self) -> bool {
+        true
+    }
+}
+
+pub struct Map {
+    k: usize,
+    name: String,
+}
+
+impl Map {
+    pub fn new(k: usize) -> Self {
+        Self {
+            k,
+            name: format!("map@{k}"),
+        }
+    }
+}
+
+impl EvalMetric for Map {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn evaluate(&self, preds: &[f32], dataset: &Dataset) -> f32 {
+        let labels = dataset.labels();
+        let boundaries = query_boundaries(dataset);
+        ranking::mean_average_precision_at(preds, labels, &boundaries, self.k)
+    }
+
+    fn higher_is_better(&self) -> bool {
+        true
+    }
+}
+
+fn query_boundaries(dataset: &Dataset) -> Vec<usize> {
+    match dataset.query_boundaries() {
+        Some(boundaries) => boundaries.to_vec(),
+        None => vec![0, dataset.num_rows()],
+    }
+}
+
+// Metric values after every boosting iteration, per evaluated dataset and metric
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EvalHistory {
+    entries: Vec<EvalEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalEntry {
+    pub dataset: String,
+    pub metric: String,
+    pub values: Vec<f32>,
+}
+
+impl EvalHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, dataset: &str, metric: &str, value: f32) {
+        match self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.dataset == dataset && entry.metric == metric)
+        {
+            Some(entry) => entry.values.push(value),
+            None => self.entries.push(EvalEntry {
+                dataset: dataset.to_string(),
+                metric: metric.to_string(),
+                values: vec![value],
+            }),
+        }
+    }
+
+    pub fn get(&self, dataset: &str, metric: &str) -> Option<&[f32]> {
+        self.entries
+            .iter()
+            .find(|entry| entry.dataset == dataset && entry.metric == metric)
+            .map(|entry| entry.values.as_slice())
+    }
+
+    pub fn entries(&self) -> &[EvalEntry] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+pub fn rmse(preds: &[f32], labels: &[f32]) -> f32 {
+    weighted_rmse(preds, labels, None)
+}
+
+pub fn mae(preds: &[f32], labels: &[f32]) -> f32 {
+    weighted_mae(preds, labels, None)
+}
+
+pub fn r2(preds: &[f32], labels: &[f32]) -> f32 {
+    weighted_r2(preds, labels, None)
+}
+
+pub fn auc(scores: &[f32], labels: &[f32]) -> f32 {
+    weighted_auc(scores, labels, None)
+}
+
+pub fn binary_logloss(probs: &[f32], labels: &[f32]) -> f32 {
+    weighted_binary_logloss(probs, labels, None)
+}
+
+fn weight(weights: Option<&[f32]>, i: usize) -> f32 {
+    weights.map_or(1.0, |weights| weights[i])
+}
+
+fn total_weight(weights: Option<&[f32]>, n: usize) -> f32 {
+    weights.map_or(n as f32, |weights| weights.iter().sum())
+}
+
+pub fn weighted_rmse(preds: &[f32], labels: &[f32], weights: Option<&[f32]>) -> f32 {
+    let squared_error: f32 = preds
+        .iter()
+        .zip(labels)
+        .enumerate()
+        .map(|(i, (p, y))| weight(weights, i) * (p - y) * (p - y))
+        .sum();
+    (squared_error / total_weight(weights, labels.len())).sqrt()
+}
+
+pub fn weighted_mae(preds: &[f32], labels: &[f32], weights: Option<&[f32]>) -> f32 {
+    let absolute_error: f32 = preds
+        .iter()
+        .zip(labels)
+        .enumerate()
+        .map(|(i, (p, y))| weight(weights, i) * (p - y).abs())
+        .sum();
+    absolute_error / total_weight(weights, labels.len())
+}
+
+// 1 - SS_res / SS_tot. Constant labels give 1 when predicted exactly and 0 otherwise, as in
+// scikit-learn.
+pub fn weighted_r2(preds: &[f32], labels: &[f32], weights: Option<&[f32]>) -> f32 {
+    let weighted_sum: f32 = labels
+        .iter()
+        .enumerate()
+        .map(|(i, y)| weight(weights, i) * y)
+        .sum();
+    let mean = weighted_sum / total_weight(weights, labels.len());
+    let ss_res: f32 = preds
+        .iter()
+        .zip(labels)
+        .enumerate()
+        .map(|(i, (p, y))| weight(weights, i) * (y - p) * (y - p))
+        .sum();
+    let ss_tot: f32 = labels
+        .iter()
+        .enumerate()
+        .map(|(i, y)| weight(weights, i) * (y - mean) * (y - mean))
+        .sum();
+
+    if ss_tot == 0.0 {
+        if ss_res == 0.0 { 1.0 } else { 0.0 }
+    } else {
+        1.0 - ss_res / ss_tot
+    }
+}
+
+// ROC-AUC as the Mann-Whitney U statistic: the probability that a random positive is scored
+// above a random negative, with rows drawn in proportion to their weight. Each
+// positive/negative tie counts one half. Labels > 0.5 are positives. Returns 0.5 if either
+// class is missing.
+pub fn weighted_auc(scores: &[f32], labels: &[f32], weights: Option<&[f32]>) -> f32 {
+    let mut order: Vec<usize> = (0..scores.len()).collect();
+    order.sort_by(|&a, &b| scores[a].total_cmp(&scores[b]));
+
+    // pairs with the positive above the negative, ties counting one half
+    let mut area = 0.0f64;
+    let mut negatives_below = 0.0f64;
+    let mut positives = 0.0f64;
+    let mut start = 0;
+    while start < order.len() {
+        let mut end = start + 1;
+        while end < order.len() && scores[order[end]] == scores[order[start]] {
+            end += 1;
+        }
+
+        let (mut tied_positives, mut tied_negatives) = (0.0f64, 0.0f64);
+        for &i in &order[start..end] {
+            if labels[i] > 0.5 {
+                tied_positives += weight(weights, i) as f64;
+            } else {
+                tied_negatives += weight(weights, i) as f64;
+            }
+        }
+        area += tied_positives * (negatives_below + tied_negatives / 2.0);
+        negatives_below += tied_negatives;
+        positives += tied_positives;
+        start = end;
+    }
+
+    if positives == 0.0 || negatives_below == 0.0 {
+        return 0.5;
+    }
+    (area / (positives * negatives_below)) as f32
+}
+
+// mean negative log-likelihood of labels in {0, 1} under the predicted probabilities
+pub fn weighted_binary_logloss(probs: &[f32], labels: &[f32], weights: Option<&[f32]>) -> f32 {
+    const EPSILON: f64 = 1e-15;
+
+    let total: f64 = probs
+        .iter()
+        .zip(labels)
+        .enumerate()
+        .map(|(i, (&p, &y))| {
+            let p = (p as f64).clamp(EPSILON, 1.0 - EPSILON);
+            let y = y as f64;
+            -(y * p.ln() + (1.0 - y) * (1.0 - p).ln()) * weight(weights, i) as f64
+        })
+        .sum();
+    (total / total_weight(weights, labels.len()) as f64) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_regression_metrics() {
+        let preds = vec![1.0, 2.0, 3.0, 4.0];
+        let labels = vec![1.0, 2.0, 3.0, 6.0];
+
+        assert_abs_diff_eq!(rmse(&preds, &labels), 1.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(mae(&preds, &labels), 0.5, epsilon = 1e-6);
+        // ss_res = 4, ss_tot = 4 + 1 + 0 + 9
+        assert_abs_diff_eq!(r2(&preds, &labels), 1.0 - 4.0 / 14.0, epsilon = 1e-6);
+        assert_eq!(r2(&[2.0, 2.0], &[2.0, 2.0]), 1.0);
+        assert_eq!(r2(&[1.0, 3.0], &[2.0, 2.0]), 0.0);
+    }
+
+    #[test]
+    fn test_metric_types_evaluate_on_dataset() {
+        let dataset = Dataset::new(vec![vec![0.0]; 3], vec![0.0, 1.0, 2.0]);
+        let preds = vec![0.0, 1.0, 2.0];
+
+        let rmse = MetricType::Rmse.create();
+        assert_eq!(rmse.name(), "rmse");
+        assert_abs_diff_eq!(rmse.evaluate(&preds, &dataset), 0.0, epsilon = 1e-6);
+        assert!(!rmse.higher_is_better());
+
+        let r2 = MetricType::R2.create();
+        assert_abs_diff_eq!(r2.evaluate(&preds, &dataset), 1.0, epsilon = 1e-6);
+        assert!(r2.higher_is_better());
+    }
+
+    #[test]
+    fn test_weighted_metrics() {
+        let preds = vec![1.0, 2.0, 3.0, 4.0];
+        let labels = vec![1.0, 2.0, 3.0, 6.0];
+        let weights = vec![1.0, 1.0, 1.0, 0.0];
+
+        // the only wrong prediction has no weight
+        assert_eq!(weighted_rmse(&preds, &labels, Some(&weights)), 0.0);
+        assert_eq!(weighted_mae(&preds, &labels, Some(&weights)), 0.0);
+        assert_eq!(weighted_r2(&preds, &labels, Some(&weights)), 1.0);
+        // integer weights act like repeated rows
+        assert_abs_diff_eq!(
+            weighted_mae(&preds, &labels, Some(&[1.0, 1.0, 1.0, 2.0])),
+            mae(&[1.0, 2.0, 3.0, 4.0, 4.0], &[1.0, 2.0, 3.0, 6.0, 6.0]),
+            epsilon = 1e-6
+        );
+
+        // one misordered pair out of four, its negative weighted 3
+        let scores = vec![0.1, 0.6, 0.4, 0.9];
+        let classes = vec![0.0, 0.0, 1.0, 1.0];
+        assert_abs_diff_eq!(auc(&scores, &classes), 0.75, epsilon = 1e-6);
+        assert_abs_diff_eq!(
+            weighted_auc(&scores, &classes, Some(&[1.0, 3.0, 1.0, 1.0])),
+            5.0 / 8.0,
+            epsilon = 1e-6
+        );
+
+        let dataset = Dataset::new(vec![vec![0.0]; 4], labels).with_weights(weights);
+        assert_eq!(MetricType::Rmse.create().evaluate(&preds, &dataset), 0.0);
+    }
+
+    #[test]
+    fn test_auc() {
+        let labels = vec![0.0, 0.0, 1.0, 1.0];
+
+        assert_abs_diff_eq!(auc(&[0.1, 0.2, 0.8, 0.9], &labels), 1.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(auc(&[0.9, 0.8, 0.2, 0.1], &labels), 0.0, epsilon = 1e-6);
+        // one of four pairs is misordered
+        assert_abs_diff_eq!(auc(&[0.1, 0.5, 0.4, 0.9], &labels), 0.75, epsilon = 1e-6);
+        assert_abs_diff_eq!(auc(&[0.5, 0.5, 0.5, 0.5], &labels), 0.5, epsilon = 1e-6);
+        assert_abs_diff_eq!(auc(&[0.1, 0.2], &[1.0, 1.0]), 0.5, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_auc_counts_ties_as_half() {
+        // the tie between the negative at 0.5 and the positive at 0.5 counts one half
+        let scores = vec![0.1, 0.5, 0.5, 0.9];
+        let labels = vec![0.0, 0.0, 1.0, 1.0];
+
+        assert_abs_diff_eq!(auc(&scores, &labels), 3.5 / 4.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_binary_logloss() {
+        let probs = vec![0.5, 0.5];
+        let labels = vec![0.0, 1.0];
+        assert_abs_diff_eq!(binary_logloss(&probs, &labels), 2f32.ln(), epsilon = 1e-6);
+
+        let confident = binary_logloss(&[0.0, 1.0], &[1.0, 0.0]);
+        assert!(confident.is_finite() && confident > 30.0);
+
+        let dataset = Dataset::new(vec![vec![0.0]; 2], labels);
+        assert_eq!(MetricType::BinaryLogloss.create().name(), "binary_logloss");
+        assert_abs_diff_eq!(
+            MetricType::BinaryLogloss
+                .create()
+                .evaluate(&[0.9, 0.9], &dataset),
+            -(0.1f32.ln() + 0.9f32.ln()) / 2.0,
+            epsilon = 1e-5
+        );
+    }
+
+    #[test]
+    fn test_ranking_metrics_respect_query_boundaries() {
+        let labels = vec![1.0, 0.0, 1.0, 0.0];
+        let preds = vec![2.0, 1.0, 1.0, 2.0];
+        let grouped = Dataset::new(vec![vec![0.0]; 4], labels.clone()).with_groups(&[2, 2]);
+        let ungrouped = Dataset::new(vec![vec![0.0]; 4], labels);
+
+        let ndcg = MetricType::Ndcg { k: 1 }.create();
+        assert_eq!(ndcg.name(), "ndcg@1");
+        assert!(ndcg.higher_is_better());
+        // first query ranked right, second wrong
+        assert_abs_diff_eq!(ndcg.evaluate(&preds, &grouped), 0.5, epsilon = 1e-6);
+        // as a single query the tie at the top resolves to row 0, which is relevant
+        assert_abs_diff_eq!(ndcg.evaluate(&preds, &ungrouped), 1.0, epsilon = 1e-6);
+
+        let map = MetricType::Map { k: 2 }.create();
+        assert_eq!(map.name(), "map@2");
+        assert_abs_diff_eq!(map.evaluate(&preds, &grouped), 0.75, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_eval_history_groups_values() {
+        let mut history = EvalHistory::new();
+        assert!(history.is_empty());
+
+        history.push("training", "rmse", 2.0);
+        history.push("valid", "rmse", 3.0);
+        history.push("training", "rmse", 1.0);
+
+        assert_eq!(history.get("training", "rmse"), Some(&[2.0, 1.0][..]));
+        assert_eq!(history.get("valid", "rmse"), Some(&[3.0][..]));
+        assert_eq!(history.get("valid", "mae"), None);
+        assert_eq!(history.entries().len(), 2);
+    }
+}
@@ -0,0 +1,1242 @@
+// Trained models as they are saved to disk, see `Booster::to_model` and
+// `Booster::from_model`. JSON is readable and diffable; the binary form is smaller and
+// faster to load.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::checkpoint::write_atomically;
+use crate::dataset::Dataset;
+use crate::distill;
+use crate::error::{Error, Result};
+use crate::metrics::MetricType;
+use crate::objective::ObjectiveType;
+use crate::params::Params;
+use crate::prune;
+use crate::rules::{self, RuleSet, TreeRules};
+use crate::shap;
+use crate::sparse::FeatureRow;
+use crate::tree::{LeafStats, Tree, TreeNode};
+
+mod binary;
+mod lightgbm;
+mod lite;
+mod pmml;
+mod xgboost;
+
+pub use lite::load_lite;
+pub use metalgbm_predict::lite::{FormatError, LiteModel, LitePrecision};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Model {
+    // the parameters of the training that built the model
+    pub(crate) params: Params,
+    // None for a user supplied objective, whose scores are loaded untransformed
+    pub(crate) objective: Option<ObjectiveType>,
+    pub(crate) trees: Vec<Tree>,
+    pub(crate) num_outputs: usize,
+    pub(crate) base_scores: Vec<f32>,
+    pub(crate) best_iteration: Option<usize>,
+    pub(crate) average_output: bool,
+    pub(crate) feature_names: Option<Vec<String>>,
+    // columns of the training data; None for models that don't record them
+    #[serde(default)]
+    pub(crate) num_features: Option<usize>,
+    // the crate version that trained the model
+    #[serde(default)]
+    pub(crate) crate_version: Option<String>,
+    #[serde(default)]
+    pub(crate) metadata: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeatureType {
+    Numeric,
+    Categorical,
+}
+
+// How the predictions depend on one feature, see `Model::partial_dependence`. Predictions
+// have a value per output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartialDependence {
+    // the values the feature was set to
+    pub grid: Vec<f32>,
+    // the partial dependence: for every grid value, the predictions averaged over the rows
+    pub average: Vec<Vec<f32>>,
+    // the individual conditional expectation curves: for every row, its predictions at
+    // every grid value
+    pub individual: Vec<Vec<Vec<f32>>>,
+}
+
+// The shape of the ensemble, see `Model::summary`. Covers every tree, including any after
+// the best iteration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelSummary {
+    pub num_trees: usize,
+    pub num_leaves: usize,
+    pub average_depth: f64,
+    pub max_depth: usize,
+    // the spread of the values of all the leaves, None without trees
+    pub leaf_stats: Option<LeafStats>,
+}
+
+// How pruning traded accuracy for size, see `Model::prune_report`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PruneReport {
+    pub num_leaves_before: usize,
+    pub num_leaves_after: usize,
+    // the size of the models as `to_bytes` writes them
+    pub bytes_before: usize,
+    pub bytes_after: usize,
+    // the metric on the dataset the report was made on
+    pub metric_before: f32,
+    pub metric_after: f32,
+}
+
+// what `Model::feature_importance` counts for a feature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportanceType {
+    // the number of splits on it
+    Split,
+    // the total gain of the splits on it
+    Gain,
+}
+
+impl Model {
+    pub fn save_json(&self, path: impl AsRef<Path>) -> Result<()> {
+        let json = serde_json::to_vec(self)
+            .map_err(|err| Error::format(format!("can't serialize model: {err}")))?;
+        write_atomically(path.as_ref(), &json)
+    }
+
+    // the versioned binary format described in `binary`, which later versions keep reading
+    pub fn save_binary(&self, path: impl AsRef<Path>) -> Result<()> {
+        write_atomically(path.as_ref(), &self.to_bytes()?)
+    }
+
+    // `save_binary` with every threshold stored as an index into a table of its feature's
+    // thresholds, which makes large models smaller and loses nothing
+    pub fn save_binary_quantized(&self, path: impl AsRef<Path>) -> Result<()> {
+        write_atomically(path.as_ref(), &binary::to_bytes(self, true)?)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        binary::to_bytes(self, false)
+    }
+
+    // Reads what `save_binary` and `save_binary_quantized` write, and the unversioned
+    // bincode files of crate versions before the format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if binary::is_versioned(bytes) {
+            return binary::from_bytes(bytes);
+        }
+        let (model, _): (Self, usize) =
+            bincode::serde::decode_from_slice(bytes, bincode::config::standard())
+                .map_err(|err| Error::format(format!("not a model: {err}")))?;
+        model.validate()
+    }
+
+    pub fn load_json(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let model: Self = serde_json::from_slice(&read(path)?)
+            .map_err(|err| Error::format(format!("{} isn't a model: {err}", path.display())))?;
+        model.validate()
+    }
+
+    pub fn load_binary(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        Self::from_bytes(&read(path)?).map_err(|err| match err {
+            Error::Format { reason } => Error::format(format!("{}: {reason}", path.display())),
+            err => err,
+        })
+    }
+
+    // LightGBM's `model.txt` format, see `lightgbm` for what the conversion changes.
+    pub fn save_lightgbm(&self, path: impl AsRef<Path>) -> Result<()> {
+        write_atomically(path.as_ref(), self.to_lightgbm_string()?.as_bytes())
+    }
+
+    pub fn load_lightgbm(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = String::from_utf8(read(path)?)
+            .map_err(|_| Error::format(format!("{} isn't a text file", path.display())))?;
+        Self::from_lightgbm_str(&text)
+    }
+
+    pub fn to_lightgbm_string(&self) -> Result<String> {
+        lightgbm::to_string(self)
+    }
+
+    pub fn from_lightgbm_str(text: &str) -> Result<Self> {
+        lightgbm::from_str(text)
+    }
+
+    // PMML 4.4, write only, see `pmml` for how the ensemble is laid out.
+    pub fn save_pmml(&self, path: impl AsRef<Path>) -> Result<()> {
+        write_atomically(path.as_ref(), self.to_pmml_string()?.as_bytes())
+    }
+
+    pub fn to_pmml_string(&self) -> Result<String> {
+        pmml::to_string(self)
+    }
+
+    // XGBoost's JSON model format, read only, see `xgboost` for what the conversion changes.
+    pub fn load_xgboost(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = String::from_utf8(read(path)?)
+            .map_err(|_| Error::format(format!("{} isn't a text file", path.display())))?;
+        Self::from_xgboost_json(&json)
+    }
+
+    pub fn from_xgboost_json(json: &str) -> Result<Self> {
+        xgboost::from_json(json)
+    }
+
+    // The model made of the trees of `iterations` only, e.g. `..100` to score as if
+    // training had stopped after 100 iterations. The base scores stay, so slices starting
+    // past the first iteration show what their trees add to them. The best iteration is
+    // kept when it falls in the slice.
+    pub fn slice(&self, iterations: impl RangeBounds<usize>) -> Result<Self> {
+        let num_iterations = self.trees.len() / self.num_outputs;
+        let start = match iterations.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match iterations.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => num_iterations,
+        };
+        if start > end || end > num_iterations {
+            return Err(Error::invalid_parameter(
+                "iterations",
+                format!("{start}..{end} isn't within the model's {num_iterations} iterations"),
+            ));
+        }
+        let trees = self.trees[start * self.num_outputs..end * self.num_outputs].to_vec();
+        let best_iteration = self
+            .best_iteration
+            .filter(|&best_iteration| best_iteration > start && best_iteration <= end)
+            .map(|best_iteration| best_iteration - start);
+        Ok(Self {
+            trees,
+            best_iteration,
+            ..self.clone()
+        })
+    }
+
+    // The predictions for `row` after each iteration, like `Booster::predict_proba`, ending
+    // with those of all the trees whether or not training stopped early, so how a row's
+    // prediction develops can be followed and a point to truncate the model at chosen, see
+    // `slice`. Models with a user supplied objective give raw scores.
+    pub fn staged_predict<'a, R: FeatureRow + ?Sized>(
+        &'a self,
+        row: &'a R,
+    ) -> impl Iterator<Item = Vec<f32>> + 'a {
+        let objective = self.objective.as_ref().map(ObjectiveType::create);
+        let mut sums = vec![0.0; self.num_outputs];
+        self.trees
+            .chunks(self.num_outputs)
+            .enumerate()
+            .map(move |(iteration, trees)| {
+                for (sum, tree) in sums.iter_mut().zip(trees) {
+                    *sum += tree.predict_row(row);
+                }
+                let num_iterations = (iteration + 1) as f32;
+                let scores: Vec<f32> = sums
+                    .iter()
+                    .zip(&self.base_scores)
+                    .map(|(&sum, base_score)| {
+                        let sum = if self.average_output {
+                            sum / num_iterations
+                        } else {
+                            sum
+                        };
+                        sum + base_score
+                    })
+                    .collect();
+                match &objective {
+                    Some(objective) => objective.transform_scores(&scores),
+                    None => scores,
+                }
+            })
+    }
+
+    // Each feature's SHAP value for the raw scores of `row`, followed by the bias, the score
+    // expected when nothing about the row is known; together they add up to the raw score,
+    // see `shap`. Multi-output models give `num_features + 1` values for every output, one
+    // output after the other, like LightGBM's `pred_contrib`. Features are numbered as in
+    // training, or by the highest one split on for models that don't record it.
+    pub fn predict_contrib<R: FeatureRow + ?Sized>(&self, row: &R) -> Result<Vec<f32>> {
+        let num_values = self.num_columns() + 1;
+        self.contributions(num_values, |tree, scale, contributions| {
+            shap::add_contributions(tree, row, scale, contributions)
+        })
+    }
+
+    // Saabas' approximation of `predict_contrib`, crediting every split on the row's path
+    // with how much it changes the expected score; much faster for deep trees, but not the
+    // SHAP values, see `shap`. They add up to the raw scores all the same.
+    pub fn predict_contrib_approx<R: FeatureRow + ?Sized>(&self, row: &R) -> Result<Vec<f32>> {
+        let num_values = self.num_columns() + 1;
+        self.contributions(num_values, |tree, scale, contributions| {
+            shap::add_approximate_contributions(tree, row, scale, contributions)
+        })
+    }
+
+    // The SHAP interaction values of every pair of features for the raw scores of `row`, a
+    // `(num_features + 1) x (num_features + 1)` matrix row by row for every output: each
+    // pair's interaction, split evenly between its two entries, with each feature's main
+    // effect on the diagonal and the bias of `predict_contrib` in the last entry, its row
+    // and column otherwise zero. A feature's row adds up to its `predict_contrib` value,
+    // like XGBoost's `pred_interactions`. Takes a TreeSHAP walk per feature each tree
+    // splits on.
+    pub fn predict_interactions<R: FeatureRow + ?Sized>(&self, row: &R) -> Result<Vec<f32>> {
+        let size = self.num_columns() + 1;
+        self.contributions(size * size, |tree, scale, interactions| {
+            shap::add_interactions(tree, row, scale, interactions)
+        })
+    }
+
+    // `Tree::to_dot` of tree `index`, features called by the model's names when it has them
+    pub fn tree_to_dot(&self, index: usize) -> Result<String> {
+        let tree = self.trees.get(index).ok_or_else(|| {
+            Error::invalid_parameter(
+                "index",
+                format!(
+                    "the model has {} trees, not {}",
+                    self.trees.len(),
+                    index + 1
+                ),
+            )
+        })?;
+        Ok(tree.to_dot(self.feature_names.as_deref()))
+    }
+
+    pub fn summary(&self) -> ModelSummary {
+        let depths: Vec<usize> = self.trees.iter().map(Tree::depth).collect();
+        let leaf_values: Vec<f32> = self.trees.iter().flat_map(Tree::leaf_values).collect();
+        ModelSummary {
+            num_trees: self.trees.len(),
+            num_leaves: leaf_values.len(),
+            average_depth: depths.iter().sum::<usize>() as f64 / depths.len().max(1) as f64,
+            max_depth: depths.iter().copied().max().unwrap_or(0),
+            leaf_stats: (!leaf_values.is_empty()).then(|| LeafStats::of(&leaf_values)),
+        }
+    }
+
+    // The trees prediction uses as rules, see `rules::tree_rules`. Averaging models have
+    // their rule values divided by the number of rounds, so a row's raw score is the sum of
+    // the rules it meets either way.
+    pub fn rule_set(&self) -> Result<RuleSet> {
+        let trees = self.predicting_trees();
+        let scale = if self.average_output && !trees.is_empty() {
+            self.num_outputs as f32 / trees.len() as f32
+        } else {
+            1.0
+        };
+        let trees = trees
+            .iter()
+            .enumerate()
+            .map(|(i, tree)| {
+                let rules = rules::tree_rules(tree, scale)
+                    .map_err(|err| Error::invalid_data(format!("tree {i} of the model: {err}")))?;
+                Ok(TreeRules {
+                    output: i % self.num_outputs,
+                    rules,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(RuleSet {
+            feature_names: self.feature_names.clone(),
+            base_scores: self.base_scores.clone(),
+            trees,
+        })
+    }
+
+    // The model with every tree pruned by `prune::prune_tree`, e.g. to fit a size budget;
+    // `prune_report` shows what it costs. Pruning by gain needs trees that recorded
+    // their gains.
+    pub fn pruned(&self, min_gain: f32, epsilon: f32) -> Result<Self> {
+        if min_gain > 0.0
+            && let Some(i) = self
+                .trees
+                .iter()
+                .position(|tree| tree.num_leaves() > 1 && tree.split_gains().is_none())
+        {
+            return Err(Error::invalid_parameter(
+                "min_gain",
+                format!("tree {i} of the model didn't record its split gains"),
+            ));
+        }
+        let mut model = self.clone();
+        model.trees = self
+            .trees
+            .iter()
+            .map(|tree| prune::prune_tree(tree, min_gain, epsilon))
+            .collect();
+        Ok(model)
+    }
+
+    // Compares the model with `pruned`, the model `pruned` returned, by size and by
+    // `metric` on `dataset`.
+    pub fn prune_report(
+        &self,
+        pruned: &Model,
+        dataset: &Dataset,
+        metric: MetricType,
+    ) -> Result<PruneReport> {
+        let num_leaves = |model: &Model| model.trees.iter().map(Tree::num_leaves).sum();
+        let metric = metric.create();
+        Ok(PruneReport {
+            num_leaves_before: num_leaves(self),
+            num_leaves_after: num_leaves(pruned),
+            bytes_before: self.to_bytes()?.len(),
+            bytes_after: pruned.to_bytes()?.len(),
+            metric_before: metric.evaluate(&self.predictions(dataset)?, dataset),
+            metric_after: metric.evaluate(&pruned.predictions(dataset)?, dataset),
+        })
+    }
+
+    // A smaller model trained on this one's predictions, see `distill::distill`
+    pub fn distill(&self, dataset: &Dataset, params: &Params) -> Result<Model> {
+        distill::distill(self, dataset, params)
+    }
+
+    // `Tree::to_text` of every tree under a `booster[i]:` header, features called by the
+    // model's names when it has them
+    pub fn dump_text(&self) -> String {
+        let mut text = String::new();
+        for (i, tree) in self.trees.iter().enumerate() {
+            text.push_str(&format!("booster[{i}]:\n"));
+            text.push_str(&tree.to_text(self.feature_names.as_deref()));
+        }
+        text
+    }
+
+    // The importance of every feature by `importance_type`, keyed by its name, or by
+    // `Column_{i}` like LightGBM when the features aren't named, in feature order. Counts
+    // the trees `predict` uses, up to the best iteration. Gains are only known for trees
+    // from training or tree files that record them.
+    pub fn feature_importance(
+        &self,
+        importance_type: ImportanceType,
+    ) -> Result<Vec<(String, f64)>> {
+        let mut importance = vec![0.0; self.num_columns()];
+        for (i, tree) in self.predicting_trees().iter().enumerate() {
+            let features = tree.split_features();
+            match importance_type {
+                ImportanceType::Split => {
+                    for feature_index in features {
+                        importance[feature_index] += 1.0;
+                    }
+                }
+                ImportanceType::Gain => {
+                    let gains = match tree.split_gains() {
+                        Some(gains) => gains,
+                        None if features.is_empty() => &[],
+                        None => {
+                            return Err(Error::invalid_data(format!(
+                                "tree {i} of the model doesn't record its split gains"
+                            )));
+                        }
+                    };
+                    for (feature_index, &gain) in features.into_iter().zip(gains) {
+                        importance[feature_index] += f64::from(gain);
+                    }
+                }
+            }
+        }
+        Ok(importance
+            .into_iter()
+            .enumerate()
+            .map(|(i, importance)| {
+                let name = match &self.feature_names {
+                    Some(names) => names[i].clone(),
+                    None => format!("Column_{i}"),
+                };
+                (name, importance)
+            })
+            .collect())
+    }
+
+    // Sets `feature` of every row of `dataset` to each value of `grid` in turn and predicts,
+    // like `Booster::predict_proba`, giving each row's curve and their average, weighted by
+    // the dataset's weights when it has them. The rest of the row stays as it is, so rows
+    // are also scored at combinations of values that never occur together. Checks the
+    // dataset's columns like `Booster::check_features` does.
+    pub fn partial_dependence(
+        &self,
+        dataset: &Dataset,
+        feature: usize,
+        grid: &[f32],
+    ) -> Result<PartialDependence> {
+        check_features(
+            (self.num_features, self.feature_names.as_deref()),
+            dataset.num_features(),
+            dataset.feature_names(),
+        )?;
+        if feature >= dataset.num_features() {
+            return Err(Error::invalid_parameter(
+                "feature",
+                format!(
+                    "{feature} isn't one of the {} features",
+                    dataset.num_features()
+                ),
+            ));
+        }
+        if dataset.num_features() < self.num_columns() {
+            return Err(Error::invalid_data(format!(
+                "rows have {} features, the model splits on {}",
+                dataset.num_features(),
+                self.num_columns()
+            )));
+        }
+
+        let objective = self.objective.as_ref().map(ObjectiveType::create);
+        let individual: Vec<Vec<Vec<f32>>> = (0..dataset.num_rows())
+            .into_par_iter()
+            .map(|i| {
+                let row = dataset.row(i);
+                grid.iter()
+                    .map(|&value| {
+                        let scores = self.raw_scores(&WithValue {
+                            row: &row,
+                            feature,
+                            value,
+                        });
+                        match &objective {
+                            Some(objective) => objective.transform_scores(&scores),
+                            None => scores,
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut average = vec![vec![0.0f64; self.num_outputs]; grid.len()];
+        let mut total_weight = 0.0;
+        for (i, curve) in individual.iter().enumerate() {
+            let weight = dataset
+                .weights()
+                .map_or(1.0, |weights| f64::from(weights[i]));
+            total_weight += weight;
+            for (sums, predictions) in average.iter_mut().zip(curve) {
+                for (sum, &prediction) in sums.iter_mut().zip(predictions) {
+                    *sum += weight * f64::from(prediction);
+                }
+            }
+        }
+        Ok(PartialDependence {
+            grid: grid.to_vec(),
+            average: average
+                .into_iter()
+                .map(|sums| {
+                    sums.into_iter()
+                        .map(|sum| (sum / total_weight) as f32)
+                        .collect()
+                })
+                .collect(),
+            individual,
+        })
+    }
+
+    // the transformed predictions for every row laid out like `EvalMetric::evaluate` takes
+    // them, all the rows' values for one output after another
+    fn predictions(&self, dataset: &Dataset) -> Result<Vec<f32>> {
+        check_features(
+            (self.num_features, self.feature_names.as_deref()),
+            dataset.num_features(),
+            dataset.feature_names(),
+        )?;
+        let objective = self.objective.as_ref().map(ObjectiveType::create);
+        let rows: Vec<Vec<f32>> = (0..dataset.num_rows())
+            .into_par_iter()
+            .map(|i| {
+                let scores = self.raw_scores(&dataset.row(i));
+                match &objective {
+                    Some(objective) => objective.transform_scores(&scores),
+                    None => scores,
+                }
+            })
+            .collect();
+        Ok((0..self.num_outputs)
+            .flat_map(|output| rows.iter().map(move |row| row[output]))
+            .collect())
+    }
+
+    // the raw scores of every row for each output in turn
+    pub(crate) fn raw_scores_of(&self, dataset: &Dataset) -> Result<Vec<Vec<f32>>> {
+        check_features(
+            (self.num_features, self.feature_names.as_deref()),
+            dataset.num_features(),
+            dataset.feature_names(),
+        )?;
+        let rows: Vec<Vec<f32>> = (0..dataset.num_rows())
+            .into_par_iter()
+            .map(|i| self.raw_scores(&dataset.row(i)))
+            .collect();
+        Ok((0..self.num_outputs)
+            .map(|output| rows.iter().map(|row| row[output]).collect())
+            .collect())
+    }
+
+    // `Booster::predict_raw` of the model
+    fn raw_scores<R: FeatureRow + ?Sized>(&self, row: &R) -> Vec<f32> {
+        let trees = self.predicting_trees();
+        let mut scores = vec![0.0; self.num_outputs];
+        for (i, tree) in trees.iter().enumerate() {
+            scores[i % self.num_outputs] += tree.predict_row(row);
+        }
+        if self.average_output && !trees.is_empty() {
+            let num_iterations = (trees.len() / self.num_outputs) as f32;
+            for score in &mut scores {
+                *score /= num_iterations;
+            }
+        }
+        for (score, base_score) in scores.iter_mut().zip(&self.base_scores) {
+            *score += base_score;
+        }
+        scores
+    }
+
+    // The values `add` gives every tree, `num_values` per output, laid out output after
+    // output; the base score of an output is its last value.
+    fn contributions(
+        &self,
+        num_values: usize,
+        add: impl Fn(&Tree, f64, &mut [f64]) -> Result<()>,
+    ) -> Result<Vec<f32>> {
+        let trees = self.predicting_trees();
+        let scale = if self.average_output && !trees.is_empty() {
+            (self.num_outputs as f64) / (trees.len() as f64)
+        } else {
+            1.0
+        };
+        let mut contributions = vec![0.0; self.num_outputs * num_values];
+        let mut outputs: Vec<&mut [f64]> = contributions.chunks_mut(num_values).collect();
+        for (output, base_score) in outputs.iter_mut().zip(&self.base_scores) {
+            output[num_values - 1] = f64::from(*base_score);
+        }
+        for (i, tree) in trees.iter().enumerate() {
+            add(tree, scale, outputs[i % self.num_outputs])
+                .map_err(|err| Error::invalid_data(format!("tree {i} of the model: {err}")))?;
+        }
+        Ok(contributions
+            .into_iter()
+            .map(|value| value as f32)
+            .collect())
+    }
+
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    pub fn objective(&self) -> Option<ObjectiveType> {
+        self.objective
+    }
+
+    pub fn trees(&self) -> &[Tree] {
+        &self.trees
+    }
+
+    pub fn feature_names(&self) -> Option<&[String]> {
+        self.feature_names.as_deref()
+    }
+
+    pub fn num_features(&self) -> Option<usize> {
+        self.num_features
+    }
+
+    // the type of every column, taken from `Params::categorical_features`
+    pub fn feature_types(&self) -> Option<Vec<FeatureType>> {
+        let num_features = self.num_features?;
+        Some(
+            (0..num_features)
+                .map(|i| {
+                    if self.params.categorical_features.contains(&i) {
+                        FeatureType::Categorical
+                    } else {
+                        FeatureType::Numeric
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    pub fn crate_version(&self) -> Option<&str> {
+        self.crate_version.as_deref()
+    }
+
+    pub fn metadata(&self) -> &BTreeMap<String, String> {
+        &self.metadata
+    }
+
+    // the trees prediction uses, like `Booster::predicting_trees`
+    fn predicting_trees(&self) -> &[Tree] {
+        match self.best_iteration {
+            Some(best_iteration) => &self.trees[..best_iteration * self.num_outputs],
+            None => &self.trees,
+        }
+    }
+
+    // the number of columns the model was trained on, named, or else used by the trees
+    fn num_columns(&self) -> usize {
+        match (self.num_features, &self.feature_names) {
+            (Some(num_features), _) => num_features,
+            (None, Some(names)) => names.len(),
+            (None, None) => self
+                .trees
+                .iter()
+                .filter_map(|tree| max_feature_index(tree.root()))
+                .max()
+                .map_or(0, |index| index + 1),
+        }
+    }
+
+    // A file can be well-formed and still not describe a model prediction can use.
+    fn validate(self) -> Result<Self> {
+        if self.num_outputs == 0 || self.base_scores.len() != self.num_outputs {
+            return Err(Error::invalid_data(format!(
+                "model has {} base scores for {} outputs",
+                self.base_scores.len(),
+                self.num_outputs
+            )));
+        }
+        if let Some(objective) = self.objective
+            && objective.create().num_outputs() != self.num_outputs
+        {
+            return Err(Error::invalid_data(format!(
+                "model has {} outputs, its objective {objective:?} {}",
+                self.num_outputs,
+                objective.create().num_outputs()
+            )));
+        }
+        if !self.trees.len().is_multiple_of(self.num_outputs) {
+            return Err(Error::invalid_data(format!(
+                "model has {} trees, not a whole number of rounds of {} outputs",
+                self.trees.len(),
+                self.num_outputs
+            )));
+        }
+        if let (Some(num_features), Some(names)) = (self.num_features, &self.feature_names)
+            && names.len() != num_features
+        {
+            return Err(Error::invalid_data(format!(
+                "model has {} feature names for {num_features} features",
+                names.len()
+            )));
+        }
+        if let Some(num_features) = self.num_features
+            && let Some(feature_index) = self
+                .trees
+                .iter()
+                .filter_map(|tree| max_feature_index(tree.root()))
+                .max()
+            && feature_index >= num_features
+        {
+            return Err(Error::invalid_data(format!(
+                "model splits on feature {feature_index} of {num_features}"
+            )));
+        }
+        if let Some(i) = self.trees.iter().position(|tree| {
+            tree.leaf_covers()
+                .is_some_and(|covers| covers.len() != tree.num_leaves())
+                || tree
+                    .split_gains()
+                    .is_some_and(|gains| gains.len() != tree.num_leaves() - 1)
+        }) {
+            return Err(Error::invalid_data(format!(
+                "tree {i} of the model has leaf covers or split gains that don't match its nodes"
+            )));
+        }
+        if self
+            .best_iteration
+            .is_some_and(|best_iteration| best_iteration * self.num_outputs > self.trees.len())
+        {
+            return Err(Error::invalid_data(
+                "model's best iteration is past its trees",
+            ));
+        }
+        Ok(self)
+    }
+}
+
+// `Booster::check_features` for a model trained on `expected`, its number of columns and
+// their names when it records them
+pub(crate) fn check_features(
+    (expected_num_features, expected_names): (Option<usize>, Option<&[String]>),
+    num_features: usize,
+    feature_names: Option<&[String]>,
+) -> Result<()> {
+    if let Some(expected) = expected_num_features
+        && num_features != expected
+    {
+        return Err(Error::invalid_data(format!(
+            "rows have {num_features} features, the model was trained on {expected}"
+        )));
+    }
+    if let (Some(names), Some(expected)) = (feature_names, expected_names)
+        && let Some(i) =
+            (0..names.len().max(expected.len())).find(|&i| names.get(i) != expected.get(i))
+    {
+        return Err(Error::invalid_data(format!(
+            "feature {i} is {:?}, the model's is {:?}",
+            names.get(i),
+            expected.get(i)
+        )));
+    }
+    Ok(())
+}
+
+// `row` with `feature` set to `value`
+struct WithValue<'a, R: ?Sized> {
+    row: &'a R,
+    feature: usize,
+    value: f32,
+}
+
+impl<R: FeatureRow + ?Sized> FeatureRow for WithValue<'_, R> {
+    fn value(&self, feature: usize) -> f32 {
+        if feature == self.feature {
+            self.value
+        } else {
+            self.row.value(feature)
+        }
+    }
+}
+
+fn max_feature_index(root: &TreeNode) -> Option<usize> {
+    let mut max = None;
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        match node {
+            TreeNode::Split {
+                feature_index,
+                left_child,
+                right_child,
+                ..
+            }
+            | TreeNode::CategoricalSplit {
+                feature_index,
+                left_child,
+                right_child,
+                ..
+            } => {
+                max = max.max(Some(*feature_index));
+                stack.extend([left_child.as_ref(), right_child.as_ref()]);
+            }
+            TreeNode::LinearLeaf {
+                feature_indices, ..
+            } => max = max.max(feature_indices.iter().copied().max()),
+            TreeNode::Leaf { .. } => {}
+        }
+    }
+    max
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).map_err(|err| Error::io(format!("can't read {}: {err}", path.display())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::booster::Booster;
+    use crate::dataset::Dataset;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("metalgbm-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn test_saved_models_predict_like_the_booster() {
+        let features: Vec<Vec<f32>> = (0..90)
+            .map(|i| vec![(i % 3) as f32, if i % 8 == 0 { f32::NAN } else { i as f32 }])
+            .collect();
+        let labels: Vec<f32> = (0..90).map(|i| ((i / 30 + i % 2) % 3) as f32).collect();
+        let dataset = Dataset::new(features.clone(), labels)
+            .with_feature_names(vec!["kind".to_string(), "size".to_string()]);
+        let params = Params {
+            objective: ObjectiveType::Multiclass { num_class: 3 },
+            num_iterations: 6,
+            categorical_features: vec![0],
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.train(&dataset, &params).unwrap();
+
+        let json = temp_path("model.json");
+        let binary = temp_path("model.bin");
+        booster.to_model().save_json(&json).unwrap();
+        booster.to_model().save_binary(&binary).unwrap();
+        for model in [
+            Model::load_json(&json).unwrap(),
+            Model::load_binary(&binary).unwrap(),
+        ] {
+            assert_eq!(model.trees(), booster.to_model().trees());
+            assert_eq!(model.objective(), Some(params.objective));
+            assert_eq!(model.params().num_iterations, 6);
+            let loaded = Booster::from_model(model);
+            assert_eq!(loaded.feature_names(), booster.feature_names());
+            for x in &features {
+                assert_eq!(loaded.predict_proba(x), booster.predict_proba(x));
+            }
+        }
+        std::fs::remove_file(&json).unwrap();
+        std::fs::remove_file(&binary).unwrap();
+    }
+
+    #[test]
+    fn test_slices_score_like_their_first_iterations() {
+        let features: Vec<Vec<f32>> = (0..60).map(|i| vec![i as f32, (i % 7) as f32]).collect();
+        let labels: Vec<f32> = (0..60).map(|i| (i % 5) as f32).collect();
+        let dataset = Dataset::new(features.clone(), labels);
+        let params = Params {
+            num_iterations: 10,
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.train(&dataset, &params).unwrap();
+
+        let model = booster.to_model();
+        let first = Booster::from_model(model.slice(..4).unwrap());
+        assert_eq!(first.num_iterations(), 4);
+        let predictions = booster.predict_with_num_iterations(&features, 4).unwrap();
+        for (x, prediction) in features.iter().zip(&predictions) {
+            assert_eq!(&first.predict_raw(x), prediction);
+        }
+        let all = booster.predict_with_num_iterations(&features, 10).unwrap();
+        assert_eq!(all, booster.predict_batch(&features, None).unwrap());
+
+        // the later trees add to the base score like they do in the whole model
+        let last = Booster::from_model(model.slice(4..).unwrap());
+        assert_eq!(last.num_iterations(), 6);
+        for (x, prediction) in features.iter().zip(&predictions) {
+            let whole = booster.predict(x);
+            let parts = prediction[0] + last.predict(x) - booster.base_scores()[0];
+            assert!((parts - whole).abs() < 1e-4, "{parts} != {whole}");
+        }
+
+        let staged: Vec<Vec<f32>> = model.staged_predict(features[5].as_slice()).collect();
+        assert_eq!(staged.len(), 10);
+        assert_eq!(staged[3], predictions[5]);
+        assert_eq!(staged[9], booster.predict_raw(&features[5]));
+
+        assert!(matches!(
+            model.slice(3..11),
+            Err(Error::InvalidParameter { .. })
+        ));
+        assert!(matches!(
+            booster.predict_with_num_iterations(&features, 11),
+            Err(Error::InvalidParameter { .. })
+        ));
+    }
+
+    #[test]
+    fn test_contributions_add_up_to_the_raw_scores() {
+        let features: Vec<Vec<f32>> = (0..90)
+            .map(|i| {
+                let size = if i % 11 == 0 { f32::NAN } else { i as f32 };
+                vec![(i % 4) as f32, size, 1.0]
+            })
+            .collect();
+        for objective in [
+            ObjectiveType::SquaredError,
+            ObjectiveType::Multiclass { num_class: 3 },
+        ] {
+            let labels: Vec<f32> = (0..90).map(|i| ((i / 30 + i % 4) % 3) as f32).collect();
+            let params = Params {
+                objective,
+                num_iterations: 6,
+                categorical_features: vec![0],
+                ..Params::default()
+            };
+            let mut booster = Booster::new();
+            booster
+                .train(&Dataset::new(features.clone(), labels), &params)
+                .unwrap();
+            let model = booster.to_model();
+
+            for x in &features {
+                for contributions in [
+                    model.predict_contrib(x.as_slice()).unwrap(),
+                    model.predict_contrib_approx(x.as_slice()).unwrap(),
+                ] {
+                    assert_eq!(contributions.len(), 4 * model.num_outputs);
+                    for (output, raw) in contributions.chunks(4).zip(booster.predict_raw(x)) {
+                        let total: f32 = output.iter().sum();
+                        assert!((total - raw).abs() < 1e-4, "{total} != {raw}");
+                        // the constant feature is never split on
+                        assert_eq!(output[2], 0.0);
+                    }
+                }
+            }
+
+            // each feature's row of interactions adds up to its contribution
+            let x = features[7].as_slice();
+            let interactions = model.predict_interactions(x).unwrap();
+            assert_eq!(interactions.len(), 16 * model.num_outputs);
+            let contributions = model.predict_contrib(x).unwrap();
+            for (row, contribution) in interactions.chunks(4).zip(contributions) {
+                let total: f32 = row.iter().sum();
+                assert!(
+                    (total - contribution).abs() < 1e-4,
+                    "{total} != {contribution}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_feature_importance() {
+        // the label depends on the first feature only, the second is noise
+        let features: Vec<Vec<f32>> = (0..80)
+            .map(|i| vec![(i % 8) as f32, ((i * 37) % 11) as f32])
+            .collect();
+        let labels: Vec<f32> = (0..80)
+            .map(|i| if i % 8 < 4 { 0.0 } else { 10.0 })
+            .collect();
+        let dataset = Dataset::new(features, labels)
+            .with_feature_names(vec!["signal".to_string(), "noise".to_string()]);
+        let params = Params {
+            num_iterations: 5,
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.train(&dataset, &params).unwrap();
+        let model = booster.to_model();
+
+        let splits = model.feature_importance(ImportanceType::Split).unwrap();
+        let num_splits: usize = model.trees().iter().map(|tree| tree.num_leaves() - 1).sum();
+        assert_eq!(splits[0].0, "signal");
+        assert_eq!(splits[0].1 + splits[1].1, num_splits as f64);
+        let gains = model.feature_importance(ImportanceType::Gain).unwrap();
+        assert_eq!(gains[1].0, "noise");
+        assert!(gains[0].1 > 100.0 * gains[1].1, "{gains:?}");
+
+        // unnamed features, and trees that didn't record their gains
+        let mut model = model;
+        model.feature_names = None;
+        model.trees = vec![Tree::new(Box::new(TreeNode::Split {
+            feature_index: 1,
+            threshold: 0.5,
+            default_left: false,
+            left_child: Box::new(TreeNode::Leaf { value: 0.0 }),
+            right_child: Box::new(TreeNode::Leaf { value: 1.0 }),
+        }))];
+        model.best_iteration = None;
+        assert_eq!(
+            model.feature_importance(ImportanceType::Split).unwrap(),
+            vec![("Column_0".to_string(), 0.0), ("Column_1".to_string(), 1.0)]
+        );
+        assert!(model.feature_importance(ImportanceType::Gain).is_err());
+    }
+
+    #[test]
+    fn test_dump_text_and_dot_name_features() {
+        let features: Vec<Vec<f32>> = (0..40).map(|i| vec![(i % 8) as f32]).collect();
+        let labels: Vec<f32> = (0..40).map(|i| (i % 8) as f32).collect();
+        let dataset = Dataset::new(features, labels).with_feature_names(vec!["x".to_string()]);
+        let params = Params {
+            num_iterations: 2,
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.train(&dataset, &params).unwrap();
+        let model = booster.to_model();
+
+        let text = model.dump_text();
+        assert!(text.starts_with("booster[0]:\n0:[x<"), "{text}");
+        assert!(text.contains("\nbooster[1]:\n"), "{text}");
+        assert!(model.tree_to_dot(1).unwrap().contains("label=\"x <"));
+        assert!(model.tree_to_dot(2).is_err());
+    }
+
+    #[test]
+    fn test_rule_set_sums_to_the_raw_score() {
+        let features: Vec<Vec<f32>> = (0..60)
+            .map(|i| vec![(i % 10) as f32, (i % 3) as f32])
+            .collect();
+        let labels: Vec<f32> = features.iter().map(|x| x[0] - 2.0 * x[1]).collect();
+        let dataset = Dataset::new(features.clone(), labels)
+            .with_feature_names(vec!["a".to_string(), "b".to_string()]);
+        let params = Params {
+            num_iterations: 5,
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.train(&dataset, &params).unwrap();
+        let model = booster.to_model();
+
+        let rule_set = model.rule_set().unwrap();
+        assert_eq!(rule_set.trees.len(), 5);
+        for row in &features {
+            let score: f32 = rule_set.base_scores[0]
+                + rule_set
+                    .trees
+                    .iter()
+                    .flat_map(|tree| &tree.rules)
+                    .filter(|rule| rule.matches(&row[..]))
+                    .map(|rule| rule.value)
+                    .sum::<f32>();
+            assert!((score - model.raw_scores(&row[..])[0]).abs() < 1e-4);
+        }
+        assert!(rule_set.to_text().contains("\n    a < "));
+    }
+
+    #[test]
+    fn test_pruning_trades_accuracy_for_size() {
+        let features: Vec<Vec<f32>> = (0..200)
+            .map(|i| vec![(i % 20) as f32, ((i * 7) % 13) as f32])
+            .collect();
+        // mostly a step in the first feature, with a little from the second
+        let labels: Vec<f32> = features
+            .iter()
+            .map(|x| if x[0] < 10.0 { 0.0 } else { 10.0 } + 0.01 * x[1])
+            .collect();
+        let dataset = Dataset::new(features, labels);
+        let params = Params {
+            num_iterations: 10,
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.train(&dataset, &params).unwrap();
+        let model = booster.to_model();
+
+        let pruned = model.pruned(0.0, 0.05).unwrap();
+        let report = model
+            .prune_report(&pruned, &dataset, MetricType::Rmse)
+            .unwrap();
+        assert!(
+            report.num_leaves_after < report.num_leaves_before,
+            "{report:?}"
+        );
+        assert!(report.bytes_after < report.bytes_before, "{report:?}");
+        assert!(
+            report.metric_after < report.metric_before + 0.1,
+            "{report:?}"
+        );
+
+        // pruning by gain keeps the splits on the step
+        let pruned = model.pruned(1.0, 0.0).unwrap();
+        assert!(pruned.trees()[0].num_leaves() >= 2);
+        assert_eq!(pruned.trees()[0].split_features()[0], 0);
+
+        let mut ungained = model;
+        ungained.trees[0] = Tree::new(Box::new(ungained.trees[0].root().clone()));
+        assert!(ungained.pruned(1.0, 0.0).is_err());
+        assert!(ungained.pruned(0.0, 0.05).is_ok());
+    }
+
+    #[test]
+    fn test_summary_covers_every_tree() {
+        let stump = |value: f32| {
+            Tree::new(Box::new(TreeNode::Split {
+                feature_index: 0,
+                threshold: 0.5,
+                default_left: false,
+                left_child: Box::new(TreeNode::Leaf { value: -value }),
+                right_child: Box::new(TreeNode::Leaf { value }),
+            }))
+        };
+        let mut model = Booster::new().to_model();
+        assert_eq!(model.summary().leaf_stats, None);
+        model.trees = vec![
+            stump(1.0),
+            Tree::new(Box::new(TreeNode::Leaf { value: 4.0 })),
+            stump(2.0),
+        ];
+        model.best_iteration = Some(1);
+
+        let summary = model.summary();
+        assert_eq!(summary.num_trees, 3);
+        assert_eq!(summary.num_leaves, 5);
+        assert!((summary.average_depth - 2.0 / 3.0).abs() < 1e-12);
+        assert_eq!(summary.max_depth, 1);
+        assert_eq!(
+            summary.leaf_stats,
+            Some(LeafStats {
+                min: -2.0,
+                max: 4.0,
+                mean: 0.8
+            })
+        );
+    }
+
+    #[test]
+    fn test_partial_dependence_sweeps_the_feature() {
+        let features: Vec<Vec<f32>> = (0..60)
+            .map(|i| vec![(i % 10) as f32, (i % 3) as f32])
+            .collect();
+        let labels: Vec<f32> = features.iter().map(|x| 2.0 * x[0] + x[1]).collect();
+        let dataset = Dataset::new(features.clone(), labels);
+        let params = Params {
+            num_iterations: 20,
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.train(&dataset, &params).unwrap();
+        let model = booster.to_model();
+
+        let grid = [1.0, 4.0, 8.0];
+        let dependence = model.partial_dependence(&dataset, 0, &grid).unwrap();
+        assert_eq!(dependence.grid, grid);
+        assert_eq!(dependence.individual.len(), 60);
+        for (x, curve) in features.iter().zip(&dependence.individual) {
+            for (&value, predictions) in grid.iter().zip(curve) {
+                assert_eq!(predictions, &booster.predict_proba(&[value, x[1]]));
+            }
+        }
+        for (j, average) in dependence.average.iter().enumerate() {
+            let mean = dependence
+                .individual
+                .iter()
+                .map(|curve| curve[j][0])
+                .sum::<f32>()
+                / 60.0;
+            assert!((average[0] - mean).abs() < 1e-4);
+        }
+        // the label grows with the feature, and so does its partial dependence
+        assert!(dependence.average[0][0] < dependence.average[1][0]);
+        assert!(dependence.average[1][0] < dependence.average[2][0]);
+
+        assert!(matches!(
+            model.partial_dependence(&dataset, 2, &grid),
+            Err(Error::InvalidParameter { .. })
+        ));
+        let narrow = Dataset::new(vec![vec![1.0]], vec![0.0]);
+        assert!(model.partial_dependence(&narrow, 0, &grid).is_err());
+    }
+
+    #[test]
+    fn test_loading_rejects_what_is_not_a_model() {
+        let path = temp_path("not-a-model");
+        std::fs::write(&path, b"{\"trees\": []}").unwrap();
+        assert!(matches!(Model::load_json(&path), Err(Error::Format { .. })));
+        assert!(matches!(
+            Model::load_binary(&path),
+            Err(Error::Format { .. })
+        ));
+
+        let mut model = Booster::new().to_model();
+        model.base_scores = vec![];
+        model.save_json(&path).unwrap();
+        assert!(matches!(
+            Model::load_json(&path),
+            Err(Error::InvalidData { .. })
+        ));
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            Model::load_json(temp_path("missing")),
+            Err(Error::Io { .. })
+        ));
+    }
+}
@@ -0,0 +1,744 @@
+// The binary model format of `Model::save_binary`, laid out by hand rather than derived
+// from the structs so it doesn't change when they do.
+//
+// A file starts with the magic bytes `MGBM`, a format version and flags, all little endian
+// like the rest. Every version of the crate reads the files of every earlier format
+// version: a new version is only introduced to add to the format, never to reinterpret
+// what an earlier version wrote, and files of a version newer than the reader are refused
+// with an error naming both versions. Files saved before the format had a version, which
+// are bincode encodings of `Model`, still load as version 0.
+//
+// Version 1, after the header:
+// - outputs (u32), objective (u8 tag, then a u32 parameter), average output (u8), best
+//   iteration (u32, `u32::MAX` for none) and one f32 base score per output;
+// - the number of features (u32) and whether they are named (u8), then their names as a
+//   u32 length and UTF-8 bytes each;
+// - the training parameters as a u32 length and JSON, which tolerates parameters added
+//   or removed since;
+// - with `THRESHOLD_TABLES` set, each feature's ascending split thresholds;
+// - the trees (u32 count), each its shrinkage (f32), whether it has a lookup table (u8),
+//   its number of nodes (u32) and the nodes in preorder, left child first.
+//
+// Version 2 adds, after the trees:
+// - whether the number of features is that of the training data (u8), rather than only
+//   what the names or the trees tell;
+// - the crate version that trained the model, empty when unknown;
+// - the user's metadata (u32 count), a key and a value string per entry.
+//
+// Version 3 adds, after the metadata:
+// - for every tree, whether it has leaf covers (u8), then one f32 per leaf, left to right.
+//
+// Version 4 adds, after those:
+// - for every tree, whether it has split gains (u8), then one f32 per split in preorder.
+//
+// Thresholds are stored as f32 or, with threshold tables, as a u16 index into the table of
+// their feature. Split thresholds are bin boundaries, so a feature has few of them and the
+// tables shrink models with many trees without changing a single threshold.
+
+use std::collections::BTreeMap;
+
+use crate::error::{Error, Result};
+use crate::objective::ObjectiveType;
+use crate::params::Params;
+use crate::tree::{Tree, TreeNode};
+
+use super::Model;
+
+const MAGIC: [u8; 4] = *b"MGBM";
+const FORMAT_VERSION: u32 = 4;
+// flags: what changes how the rest of the file is read
+const THRESHOLD_TABLES: u32 = 1;
+const KNOWN_FLAGS: u32 = THRESHOLD_TABLES;
+
+const NO_BEST_ITERATION: u32 = u32::MAX;
+
+// node tags
+const LEAF: u8 = 0;
+const SPLIT: u8 = 1;
+const CATEGORICAL_SPLIT: u8 = 2;
+const LINEAR_LEAF: u8 = 3;
+
+pub(super) fn to_bytes(model: &Model, threshold_tables: bool) -> Result<Vec<u8>> {
+    let mut out = Writer::default();
+    out.bytes(&MAGIC);
+    out.u32(FORMAT_VERSION);
+    out.u32(if threshold_tables {
+        THRESHOLD_TABLES
+    } else {
+        0
+    });
+
+    out.u32(model.num_outputs as u32);
+    let (tag, parameter) = objective_code(model.objective);
+    out.u8(tag);
+    out.u32(parameter);
+    out.u8(model.average_output.into());
+    out.u32(
+        model
+            .best_iteration
+            .map_or(NO_BEST_ITERATION, |best_iteration| best_iteration as u32),
+    );
+    for &base_score in &model.base_scores {
+        out.f32(base_score);
+    }
+
+    let num_features = model.num_columns();
+    out.u32(num_features as u32);
+    out.u8(model.feature_names.is_some().into());
+    for name in model.feature_names.iter().flatten() {
+        out.str(name);
+    }
+
+    let params = serde_json::to_vec(&model.params)
+        .map_err(|err| Error::format(format!("can't serialize parameters: {err}")))?;
+    out.u32(params.len() as u32);
+    out.bytes(&params);
+
+    let tables = if threshold_tables {
+        let tables = threshold_tables_of(&model.trees, num_features);
+        for (feature_index, table) in tables.iter().enumerate() {
+            if table.len() > usize::from(u16::MAX) + 1 {
+                return Err(Error::invalid_data(format!(
+                    "feature {feature_index} has {} thresholds, more than a table holds",
+                    table.len()
+                )));
+            }
+            out.u32(table.len() as u32);
+            for &threshold in table {
+                out.f32(threshold);
+            }
+        }
+        Some(tables)
+    } else {
+        None
+    };
+
+    out.u32(model.trees.len() as u32);
+    for tree in &model.trees {
+        write_tree(&mut out, tree, tables.as_deref());
+    }
+
+    out.u8(model.num_features.is_some().into());
+    out.str(model.crate_version.as_deref().unwrap_or_default());
+    out.u32(model.metadata.len() as u32);
+    for (key, value) in &model.metadata {
+        out.str(key);
+        out.str(value);
+    }
+    for tree in &model.trees {
+        out.u8(tree.leaf_covers().is_some().into());
+        for &cover in tree.leaf_covers().into_iter().flatten() {
+            out.f32(cover);
+        }
+    }
+    for tree in &model.trees {
+        out.u8(tree.split_gains().is_some().into());
+        for &gain in tree.split_gains().into_iter().flatten() {
+            out.f32(gain);
+        }
+    }
+    Ok(out.0)
+}
+
+fn write_tree(out: &mut Writer, tree: &Tree, tables: Option<&[Vec<f32>]>) {
+    let nodes = preorder(tree.root());
+    out.f32(tree.shrinkage());
+    out.u8(tree.lookup_table().is_some().into());
+    out.u32(nodes.len() as u32);
+    for node in nodes {
+        match node {
+            TreeNode::Leaf { value } => {
+                out.u8(LEAF);
+                out.f32(*value);
+            }
+            TreeNode::Split {
+                feature_index,
+                threshold,
+                default_left,
+                ..
+            } => {
+                out.u8(SPLIT);
+                out.u32(*feature_index as u32);
+                match tables {
+                    Some(tables) => {
+                        let index = tables[*feature_index]
+                            .binary_search_by(|probe| probe.total_cmp(threshold))
+                            .unwrap();
+                        out.u16(index as u16);
+                    }
+                    None => out.f32(*threshold),
+                }
+                out.u8((*default_left).into());
+            }
+            TreeNode::CategoricalSplit {
+                feature_index,
+                categories,
+                ..
+            } => {
+                out.u8(CATEGORICAL_SPLIT);
+                out.u32(*feature_index as u32);
+                out.u32(categories.len() as u32);
+                for &category in categories {
+                    out.u32(category);
+                }
+            }
+            TreeNode::LinearLeaf {
+                value,
+                intercept,
+                feature_indices,
+                coefficients,
+            } => {
+                out.u8(LINEAR_LEAF);
+                out.f32(*value);
+                out.f32(*intercept);
+                out.u32(feature_indices.len() as u32);
+                for (&feature_index, &coefficient) in feature_indices.iter().zip(coefficients) {
+                    out.u32(feature_index as u32);
+                    out.f32(coefficient);
+                }
+            }
+        }
+    }
+}
+
+pub(super) fn preorder(root: &TreeNode) -> Vec<&TreeNode> {
+    let mut nodes = Vec::new();
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        nodes.push(node);
+        if let TreeNode::Split {
+            left_child,
+            right_child,
+            ..
+        }
+        | TreeNode::CategoricalSplit {
+            left_child,
+            right_child,
+            ..
+        } = node
+        {
+            stack.extend([right_child.as_ref(), left_child.as_ref()]);
+        }
+    }
+    nodes
+}
+
+// the distinct thresholds of each feature's numeric splits, ascending
+pub(super) fn threshold_tables_of(trees: &[Tree], num_features: usize) -> Vec<Vec<f32>> {
+    let mut tables = vec![Vec::new(); num_features];
+    for tree in trees {
+        for node in preorder(tree.root()) {
+            if let TreeNode::Split {
+                feature_index,
+                threshold,
+                ..
+            } = node
+            {
+                tables[*feature_index].push(*threshold);
+            }
+        }
+    }
+    for table in &mut tables {
+        table.sort_by(f32::total_cmp);
+        table.dedup_by(|a, b| a.to_bits() == b.to_bits());
+    }
+    tables
+}
+
+pub(super) fn objective_code(objective: Option<ObjectiveType>) -> (u8, u32) {
+    match objective {
+        None => (0, 0),
+        Some(ObjectiveType::SquaredError) => (1, 0),
+        Some(ObjectiveType::BinaryLogistic) => (2, 0),
+        Some(ObjectiveType::Multiclass { num_class }) => (3, num_class as u32),
+        Some(ObjectiveType::LambdaRank) => (4, 0),
+        Some(ObjectiveType::Quantile { alpha }) => (5, alpha.to_bits()),
+        Some(ObjectiveType::Huber { delta }) => (6, delta.to_bits()),
+        Some(ObjectiveType::Fair { c }) => (7, c.to_bits()),
+        Some(ObjectiveType::Poisson { max_delta_step }) => (8, max_delta_step.to_bits()),
+        Some(ObjectiveType::Gamma) => (9, 0),
+        Some(ObjectiveType::Tweedie { variance_power }) => (10, variance_power.to_bits()),
+    }
+}
+
+fn objective_of(tag: u8, parameter: u32) -> Result<Option<ObjectiveType>> {
+    let value = f32::from_bits(parameter);
+    Ok(Some(match tag {
+        0 => return Ok(None),
+        1 => ObjectiveType::SquaredError,
+        2 => ObjectiveType::BinaryLogistic,
+        3 => ObjectiveType::Multiclass {
+            num_class: parameter as usize,
+        },
+        4 => ObjectiveType::LambdaRank,
+        5 => ObjectiveType::Quantile { alpha: value },
+        6 => ObjectiveType::Huber { delta: value },
+        7 => ObjectiveType::Fair { c: value },
+        8 => ObjectiveType::Poisson {
+            max_delta_step: value,
+        },
+        9 => ObjectiveType::Gamma,
+        10 => ObjectiveType::Tweedie {
+            variance_power: value,
+        },
+        _ => return Err(Error::format(format!("unknown objective {tag}"))),
+    }))
+}
+
+// Whether `bytes` start like a versioned model file; those that don't are read as the
+// bincode files of before.
+pub(super) fn is_versioned(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+pub(super) fn from_bytes(bytes: &[u8]) -> Result<Model> {
+    let mut input = Reader { bytes };
+    if input.take(MAGIC.len())? != MAGIC {
+        return Err(Error::format("not a metalgbm model"));
+    }
+    let version = input.u32()?;
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(Error::format(format!(
+            "model format version {version} isn't one this version of metalgbm reads, \
+             which are 1 to {FORMAT_VERSION}"
+        )));
+    }
+    let flags = input.u32()?;
+    if flags & !KNOWN_FLAGS != 0 {
+        return Err(Error::format(format!(
+            "model format flags {flags:#x} aren't known"
+        )));
+    }
+
+    let num_outputs = input.u32()? as usize;
+    let (tag, parameter) = (input.u8()?, input.u32()?);
+    let objective = objective_of(tag, parameter)?;
+    let average_output = input.u8()? != 0;
+    let best_iteration = match input.u32()? {
+        NO_BEST_ITERATION => None,
+        best_iteration => Some(best_iteration as usize),
+    };
+    let base_scores = (0..num_outputs)
+        .map(|_| input.f32())
+        .collect::<Result<Vec<_>>>()?;
+
+    let num_features = input.u32()? as usize;
+    let feature_names = if input.u8()? != 0 {
+        Some(
+            (0..num_features)
+                .map(|_| input.str())
+                .collect::<Result<Vec<_>>>()?,
+        )
+    } else {
+        None
+    };
+
+    let params_len = input.u32()? as usize;
+    let params: Params = serde_json::from_slice(input.take(params_len)?)
+        .map_err(|err| Error::format(format!("model parameters aren't readable: {err}")))?;
+
+    let tables = if flags & THRESHOLD_TABLES != 0 {
+        let mut tables = Vec::new();
+        for _ in 0..num_features {
+            let len = input.u32()? as usize;
+            tables.push((0..len).map(|_| input.f32()).collect::<Result<Vec<_>>>()?);
+        }
+        Some(tables)
+    } else {
+        None
+    };
+
+    let num_trees = input.u32()? as usize;
+    let mut trees = Vec::with_capacity(num_trees.min(input.bytes.len()));
+    for i in 0..num_trees {
+        let tree = read_tree(&mut input, tables.as_deref(), num_features)
+            .map_err(|err| Error::format(format!("tree {i} of the model: {err}")))?;
+        trees.push(tree);
+    }
+
+    let mut num_columns_known = feature_names.is_some();
+    let mut crate_version = None;
+    let mut metadata = BTreeMap::new();
+    if version >= 2 {
+        num_columns_known = input.u8()? != 0;
+        crate_version = Some(input.str()?).filter(|version| !version.is_empty());
+        for _ in 0..input.u32()? {
+            let key = input.str()?;
+            metadata.insert(key, input.str()?);
+        }
+    }
+    if version >= 3 {
+        trees = trees
+            .into_iter()
+            .map(|tree| {
+                if input.u8()? == 0 {
+                    return Ok(tree);
+                }
+                let leaf_covers = (0..tree.num_leaves())
+                    .map(|_| input.f32())
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(tree.with_leaf_covers(leaf_covers))
+            })
+            .collect::<Result<_>>()?;
+    }
+    if version >= 4 {
+        trees = trees
+            .into_iter()
+            .map(|tree| {
+                if input.u8()? == 0 {
+                    return Ok(tree);
+                }
+                let split_gains = (1..tree.num_leaves())
+                    .map(|_| input.f32())
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(tree.with_split_gains(split_gains))
+            })
+            .collect::<Result<_>>()?;
+    }
+    if !input.bytes.is_empty() {
+        return Err(Error::format("model file has bytes past its trees"));
+    }
+
+    Model {
+        params,
+        objective,
+        trees,
+        num_outputs,
+        base_scores,
+        best_iteration,
+        average_output,
+        feature_names,
+        num_features: num_columns_known.then_some(num_features),
+        crate_version,
+        metadata,
+    }
+    .validate()
+}
+
+// a node as read, before its children are
+enum Pending {
+    Leaf(TreeNode),
+    Split {
+        feature_index: usize,
+        threshold: f32,
+        default_left: bool,
+    },
+    CategoricalSplit {
+        feature_index: usize,
+        categories: Vec<u32>,
+    },
+}
+
+fn read_tree(input: &mut Reader, tables: Option<&[Vec<f32>]>, num_features: usize) -> Result<Tree> {
+    let shrinkage = input.f32()?;
+    let lookup_table = input.u8()? != 0;
+    let num_nodes = input.u32()? as usize;
+    let feature = |feature_index: u32| -> Result<usize> {
+        let feature_index = feature_index as usize;
+        if feature_index >= num_features {
+            return Err(Error::format(format!(
+                "split on feature {feature_index} of {num_features}"
+            )));
+        }
+        Ok(feature_index)
+    };
+
+    let mut pending = Vec::with_capacity(num_nodes.min(input.bytes.len()));
+    for _ in 0..num_nodes {
+        let node = match input.u8()? {
+            LEAF => Pending::Leaf(TreeNode::Leaf {
+                value: input.f32()?,
+            }),
+            SPLIT => {
+                let feature_index = feature(input.u32()?)?;
+                let threshold = match tables {
+                    Some(tables) => {
+                        let index = usize::from(input.u16()?);
+                        *tables[feature_index].get(index).ok_or_else(|| {
+                            Error::format(format!(
+                                "threshold {index} of feature {feature_index} isn't in its table"
+                            ))
+                        })?
+                    }
+                    None => input.f32()?,
+                };
+                Pending::Split {
+                    feature_index,
+                    threshold,
+                    default_left: input.u8()? != 0,
+                }
+            }
+            CATEGORICAL_SPLIT => {
+                let feature_index = feature(input.u32()?)?;
+                let len = input.u32()? as usize;
+                let categories = (0..len).map(|_| input.u32()).collect::<Result<Vec<_>>>()?;
+                if !categories.is_sorted_by(|a, b| a < b) {
+                    return Err(Error::format("categories aren't ascending"));
+                }
+                Pending::CategoricalSplit {
+                    feature_index,
+                    categories,
+                }
+            }
+            LINEAR_LEAF => {
+                let value = input.f32()?;
+                let intercept = input.f32()?;
+                let len = input.u32()? as usize;
+                let mut feature_indices = Vec::new();
+                let mut coefficients = Vec::new();
+                for _ in 0..len {
+                    feature_indices.push(feature(input.u32()?)?);
+                    coefficients.push(input.f32()?);
+                }
+                Pending::Leaf(TreeNode::LinearLeaf {
+                    value,
+                    intercept,
+                    feature_indices,
+                    coefficients,
+                })
+            }
+            tag => return Err(Error::format(format!("unknown node {tag}"))),
+        };
+        pending.push(node);
+    }
+
+    // Nodes come in preorder, so assembling them from the last one leaves both children of
+    // a split on top of the stack, the left one above the right.
+    let mut stack: Vec<Box<TreeNode>> = Vec::new();
+    for node in pending.into_iter().rev() {
+        let node = match node {
+            Pending::Leaf(leaf) => leaf,
+            split => {
+                let (Some(left_child), Some(right_child)) = (stack.pop(), stack.pop()) else {
+                    return Err(Error::format("split without two children"));
+                };
+                match split {
+                    Pending::Split {
+                        feature_index,
+                        threshold,
+                        default_left,
+                    } => TreeNode::Split {
+                        feature_index,
+                        threshold,
+                        default_left,
+                        left_child,
+                        right_child,
+                    },
+                    Pending::CategoricalSplit {
+                        feature_index,
+                        categories,
+                    } => TreeNode::CategoricalSplit {
+                        feature_index,
+                        categories,
+                        left_child,
+                        right_child,
+                    },
+                    Pending::Leaf(_) => unreachable!(),
+                }
+            }
+        };
+        stack.push(Box::new(node));
+    }
+    let (Some(root), true) = (stack.pop(), stack.is_empty()) else {
+        return Err(Error::format("nodes don't form one tree"));
+    };
+    let tree = Tree::new(root).with_shrinkage(shrinkage);
+    Ok(if lookup_table {
+        tree.with_lookup_table()
+    } else {
+        tree
+    })
+}
+
+#[derive(Default)]
+pub(super) struct Writer(pub(super) Vec<u8>);
+
+impl Writer {
+    pub(super) fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    pub(super) fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    pub(super) fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub(super) fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub(super) fn f32(&mut self, value: f32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes(value.as_bytes());
+    }
+}
+
+// reads from the front of `bytes`, failing on files that end early
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(Error::format("model file ends early"));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    fn str(&mut self) -> Result<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| Error::format("string isn't UTF-8"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::booster::Booster;
+    use crate::dataset::Dataset;
+    use crate::tree_builder::GrowthStrategy;
+
+    fn trained_model(growth_strategy: GrowthStrategy) -> (Model, Vec<Vec<f32>>) {
+        let features: Vec<Vec<f32>> = (0..90)
+            .map(|i| vec![(i % 3) as f32, if i % 8 == 0 { f32::NAN } else { i as f32 }])
+            .collect();
+        let labels: Vec<f32> = (0..90).map(|i| (i % 5) as f32 + (i % 3) as f32).collect();
+        let dataset = Dataset::new(features.clone(), labels)
+            .with_feature_names(vec!["kind".to_string(), "size".to_string()]);
+        let params = Params {
+            num_iterations: 8,
+            // oblivious trees split only on numeric features
+            categorical_features: if growth_strategy == GrowthStrategy::Oblivious {
+                Vec::new()
+            } else {
+                vec![0]
+            },
+            growth_strategy,
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.train(&dataset, &params).unwrap();
+        (booster.to_model(), features)
+    }
+
+    #[test]
+    fn test_round_trip_with_and_without_threshold_tables() {
+        for growth_strategy in [GrowthStrategy::default(), GrowthStrategy::Oblivious] {
+            let (mut model, features) = trained_model(growth_strategy);
+            model
+                .metadata
+                .insert("source".to_string(), "warehouse 7".to_string());
+            let plain = to_bytes(&model, false).unwrap();
+            let tabled = to_bytes(&model, true).unwrap();
+            assert!(tabled.len() < plain.len());
+            for bytes in [plain, tabled] {
+                let loaded = from_bytes(&bytes).unwrap();
+                assert_eq!(loaded.trees(), model.trees());
+                assert_eq!(loaded.objective(), model.objective());
+                assert_eq!(loaded.feature_names(), model.feature_names());
+                assert_eq!(loaded.num_features(), Some(2));
+                assert_eq!(loaded.metadata(), model.metadata());
+                assert_eq!(loaded.crate_version(), Some(env!("CARGO_PKG_VERSION")));
+                assert_eq!(loaded.params().num_iterations, 8);
+                let (loaded, booster) = (
+                    Booster::from_model(loaded),
+                    Booster::from_model(model.clone()),
+                );
+                for x in &features {
+                    assert_eq!(loaded.predict(x), booster.predict(x));
+                }
+            }
+        }
+    }
+
+    // A version 1 file written by hand: it has to load for as long as this crate exists.
+    #[test]
+    fn test_reads_version_1() {
+        let mut bytes = b"MGBM".to_vec();
+        for word in [1u32, 0, 1] {
+            bytes.extend(word.to_le_bytes()); // version, flags, outputs
+        }
+        bytes.push(1); // squared error
+        bytes.extend(0u32.to_le_bytes());
+        bytes.push(0); // not averaged
+        bytes.extend(u32::MAX.to_le_bytes()); // no best iteration
+        bytes.extend(0.5f32.to_le_bytes()); // base score
+        bytes.extend(1u32.to_le_bytes()); // one unnamed feature
+        bytes.push(0);
+        bytes.extend(2u32.to_le_bytes()); // parameters, all defaults
+        bytes.extend(b"{}");
+        bytes.extend(1u32.to_le_bytes()); // one tree
+        bytes.extend(1.0f32.to_le_bytes());
+        bytes.push(0);
+        bytes.extend(3u32.to_le_bytes());
+        bytes.push(SPLIT);
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend(2.0f32.to_le_bytes());
+        bytes.push(1);
+        bytes.push(LEAF);
+        bytes.extend((-1.0f32).to_le_bytes());
+        bytes.push(LEAF);
+        bytes.extend(1.0f32.to_le_bytes());
+
+        let booster = Booster::from_model(from_bytes(&bytes).unwrap());
+        assert_eq!(booster.predict(&[1.0]), -0.5);
+        assert_eq!(booster.predict(&[f32::NAN]), -0.5);
+        assert_eq!(booster.predict(&[3.0]), 1.5);
+
+        for len in [0, 10, bytes.len() - 1] {
+            assert!(matches!(
+                from_bytes(&bytes[..len]),
+                Err(Error::Format { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_reads_unversioned_bincode_files() {
+        let (model, _) = trained_model(GrowthStrategy::default());
+        let bytes = bincode::serde::encode_to_vec(&model, bincode::config::standard()).unwrap();
+        assert!(!is_versioned(&bytes));
+        assert_eq!(Model::from_bytes(&bytes).unwrap().trees(), model.trees());
+    }
+
+    #[test]
+    fn test_newer_versions_are_refused() {
+        let (model, _) = trained_model(GrowthStrategy::default());
+        let mut bytes = to_bytes(&model, false).unwrap();
+        bytes[4..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        let Err(Error::Format { reason }) = from_bytes(&bytes) else {
+            panic!("a newer version loaded");
+        };
+        assert!(reason.contains(&format!("version {}", FORMAT_VERSION + 1)));
+    }
+}
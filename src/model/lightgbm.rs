@@ -0,0 +1,798 @@
+// LightGBM's text model format, the `model.txt` written by `Booster.save_model`, so models
+// can move between LightGBM tooling and this crate.
+//
+// The formats don't say quite the same thing, and the conversion bridges the gap:
+// - a numeric split in LightGBM sends `value <= threshold` left where ours send
+//   `value < threshold`, so thresholds are moved to the neighbouring f32;
+// - missing values in LightGBM are either NaN, sent the default direction as here, or
+//   "none", treated as zero; splits treating zeros as missing (missing type "zero") have no
+//   equivalent here and aren't read;
+// - LightGBM truncates non-integer values before looking up their category, where our
+//   categorical splits send them right;
+// - LightGBM has no base scores, so ours are added to the leaves of the first tree of each
+//   output, or of every tree when outputs are averaged, and read models have none;
+// - linear leaves aren't converted either way;
+// - leaf covers are LightGBM's leaf counts, written rounded to whole rows.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::error::{Error, Result};
+use crate::objective::ObjectiveType;
+use crate::params::Params;
+use crate::tree::{Tree, TreeNode};
+
+use super::Model;
+
+// missing types of LightGBM's `decision_type`, bits 2 and 3
+const MISSING_NONE: u8 = 0;
+const MISSING_ZERO: u8 = 1;
+const MISSING_NAN: u8 = 2;
+const CATEGORICAL_MASK: u8 = 1;
+const DEFAULT_LEFT_MASK: u8 = 2;
+
+pub(super) fn to_string(model: &Model) -> Result<String> {
+    let num_outputs = model.num_outputs;
+    let trees = model.predicting_trees();
+    let num_features = model.num_columns().max(1);
+    let feature_names: Vec<String> = match &model.feature_names {
+        // names are separated by spaces in the file
+        Some(names) => names
+            .iter()
+            .map(|name| name.replace(char::is_whitespace, "_"))
+            .collect(),
+        None => (0..num_features).map(|i| format!("Column_{i}")).collect(),
+    };
+
+    let mut text = String::new();
+    writeln!(text, "tree").unwrap();
+    writeln!(text, "version=v4").unwrap();
+    writeln!(text, "num_class={num_outputs}").unwrap();
+    writeln!(text, "num_tree_per_iteration={num_outputs}").unwrap();
+    writeln!(text, "label_index=0").unwrap();
+    writeln!(text, "max_feature_idx={}", num_features - 1).unwrap();
+    if let Some(objective) = model.objective {
+        writeln!(text, "objective={}", objective_string(objective)).unwrap();
+    }
+    if model.average_output {
+        writeln!(text, "average_output").unwrap();
+    }
+    writeln!(text, "feature_names={}", feature_names.join(" ")).unwrap();
+    writeln!(
+        text,
+        "feature_infos={}",
+        vec!["none"; num_features].join(" ")
+    )
+    .unwrap();
+    writeln!(text).unwrap();
+
+    if trees.is_empty() {
+        // a model without trees still predicts its base scores
+        for (i, &base_score) in model.base_scores.iter().enumerate() {
+            write_tree(
+                &mut text,
+                i,
+                &Tree::new(Box::new(TreeNode::Leaf { value: 0.0 })),
+                base_score,
+            )?;
+        }
+    }
+    for (i, tree) in trees.iter().enumerate() {
+        let base_score = if model.average_output || i < num_outputs {
+            model.base_scores[i % num_outputs]
+        } else {
+            0.0
+        };
+        write_tree(&mut text, i, tree, base_score)?;
+    }
+    writeln!(text, "end of trees").unwrap();
+    Ok(text)
+}
+
+// Writes the `Tree={index}` block of `tree`, `offset` added to its leaf values. Nodes are
+// numbered in preorder, leaves by the order they are reached in.
+fn write_tree(text: &mut String, index: usize, tree: &Tree, offset: f32) -> Result<()> {
+    let shrinkage = tree.shrinkage();
+    let mut split_feature = Vec::new();
+    let mut threshold = Vec::new();
+    let mut decision_type = Vec::new();
+    let mut left_child: Vec<i64> = Vec::new();
+    let mut right_child: Vec<i64> = Vec::new();
+    let mut leaf_value = Vec::new();
+    let mut leaf_count = Vec::new();
+    let mut cat_boundaries = vec![0];
+    let mut cat_threshold = Vec::new();
+    let mut leaf_covers = tree.leaf_covers().map(|covers| covers.iter());
+
+    // node and where to record its index: the parent's split and whether it's the left child
+    let mut stack: Vec<(&TreeNode, Option<(usize, bool)>)> = vec![(tree.root(), None)];
+    while let Some((node, parent)) = stack.pop() {
+        let node_index = match node {
+            TreeNode::Leaf { value } => {
+                leaf_value.push(f64::from(shrinkage * value) + f64::from(offset));
+                if let Some(cover) = leaf_covers.as_mut().and_then(Iterator::next) {
+                    leaf_count.push(cover.round() as i64);
+                }
+                // leaves are referred to as the complement of their index
+                !(leaf_value.len() as i64 - 1)
+            }
+            TreeNode::LinearLeaf { .. } => {
+                return Err(Error::invalid_data(
+                    "LightGBM models can't be written with linear leaves",
+                ));
+            }
+            TreeNode::Split {
+                feature_index,
+                threshold: split_threshold,
+                default_left,
+                left_child: left,
+                right_child: right,
+            } => {
+                split_feature.push(*feature_index);
+                threshold.push(format!("{:?}", f64::from(split_threshold.next_down())));
+                let mut decision = MISSING_NAN << 2;
+                if *default_left {
+                    decision |= DEFAULT_LEFT_MASK;
+                }
+                decision_type.push(decision);
+                stack.push((right, Some((split_feature.len() - 1, false))));
+                stack.push((left, Some((split_feature.len() - 1, true))));
+                split_feature.len() as i64 - 1
+            }
+            TreeNode::CategoricalSplit {
+                feature_index,
+                categories,
+                left_child: left,
+                right_child: right,
+            } => {
+                split_feature.push(*feature_index);
+                threshold.push((cat_boundaries.len() - 1).to_string());
+                decision_type.push(MISSING_NAN << 2 | CATEGORICAL_MASK);
+                let num_words = categories.last().map_or(1, |&last| last as usize / 32 + 1);
+                let mut bitset = vec![0u32; num_words];
+                for &category in categories {
+                    bitset[category as usize / 32] |= 1 << (category % 32);
+                }
+                cat_threshold.extend(bitset);
+                cat_boundaries.push(cat_threshold.len());
+                stack.push((right, Some((split_feature.len() - 1, false))));
+                stack.push((left, Some((split_feature.len() - 1, true))));
+                split_feature.len() as i64 - 1
+            }
+        };
+        if let TreeNode::Split { .. } | TreeNode::CategoricalSplit { .. } = node {
+            left_child.push(0);
+            right_child.push(0);
+        }
+        match parent {
+            Some((parent, true)) => left_child[parent] = node_index,
+            Some((parent, false)) => right_child[parent] = node_index,
+            None => {}
+        }
+    }
+
+    let num_cat = cat_boundaries.len() - 1;
+    writeln!(text, "Tree={index}").unwrap();
+    writeln!(text, "num_leaves={}", leaf_value.len()).unwrap();
+    writeln!(text, "num_cat={num_cat}").unwrap();
+    writeln!(text, "split_feature={}", join(&split_feature)).unwrap();
+    match tree.split_gains() {
+        Some(gains) => {
+            let gains: Vec<String> = gains.iter().map(|gain| format!("{gain:?}")).collect();
+            writeln!(text, "split_gain={}", gains.join(" ")).unwrap();
+        }
+        None => writeln!(text, "split_gain={}", join(&vec![0; split_feature.len()])).unwrap(),
+    }
+    writeln!(text, "threshold={}", threshold.join(" ")).unwrap();
+    writeln!(text, "decision_type={}", join(&decision_type)).unwrap();
+    writeln!(text, "left_child={}", join(&left_child)).unwrap();
+    writeln!(text, "right_child={}", join(&right_child)).unwrap();
+    let leaf_value: Vec<String> = leaf_value
+        .iter()
+        .map(|value| format!("{value:?}"))
+        .collect();
+    writeln!(text, "leaf_value={}", leaf_value.join(" ")).unwrap();
+    if !leaf_count.is_empty() {
+        // splits come before their children, so the counts can be summed from the last
+        let mut internal_count = vec![0; split_feature.len()];
+        for i in (0..split_feature.len()).rev() {
+            internal_count[i] = [left_child[i], right_child[i]]
+                .iter()
+                .map(|&child| {
+                    if child >= 0 {
+                        internal_count[child as usize]
+                    } else {
+                        leaf_count[!child as usize]
+                    }
+                })
+                .sum();
+        }
+        writeln!(text, "leaf_count={}", join(&leaf_count)).unwrap();
+        writeln!(text, "internal_count={}", join(&internal_count)).unwrap();
+    }
+    if num_cat > 0 {
+        writeln!(text, "cat_boundaries={}", join(&cat_boundaries)).unwrap();
+        writeln!(text, "cat_threshold={}", join(&cat_threshold)).unwrap();
+    }
+    writeln!(text, "is_linear=0").unwrap();
+    writeln!(text, "shrinkage=1").unwrap();
+    writeln!(text).unwrap();
+    Ok(())
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    let values: Vec<String> = values.iter().map(T::to_string).collect();
+    values.join(" ")
+}
+
+// LightGBM's name of an objective with the parameters its prediction depends on
+fn objective_string(objective: ObjectiveType) -> String {
+    match objective {
+        ObjectiveType::SquaredError => "regression".to_string(),
+        ObjectiveType::BinaryLogistic => "binary sigmoid:1".to_string(),
+        ObjectiveType::Multiclass { num_class } => format!("multiclass num_class:{num_class}"),
+        ObjectiveType::LambdaRank => "lambdarank".to_string(),
+        ObjectiveType::Quantile { alpha } => format!("quantile alpha:{alpha}"),
+        ObjectiveType::Huber { delta } => format!("huber alpha:{delta}"),
+        ObjectiveType::Fair { c } => format!("fair fair_c:{c}"),
+        ObjectiveType::Poisson { max_delta_step } => {
+            format!("poisson max_delta_step:{max_delta_step}")
+        }
+        ObjectiveType::Gamma => "gamma".to_string(),
+        ObjectiveType::Tweedie { variance_power } => {
+            format!("tweedie tweedie_variance_power:{variance_power}")
+        }
+    }
+}
+
+// The objective of an `objective=` line; None for LightGBM's custom objectives. Parameters
+// missing from the line take LightGBM's defaults.
+fn parse_objective(line: &str) -> Result<Option<ObjectiveType>> {
+    let mut words = line.split_whitespace();
+    let name = words.next().unwrap_or("custom");
+    let mut parameters = HashMap::new();
+    let mut flags = Vec::new();
+    for word in words {
+        match word.split_once(':') {
+            Some((key, value)) => {
+                parameters.insert(key, value);
+            }
+            None => flags.push(word),
+        }
+    }
+    let parameter = |key: &str, default: f32| -> Result<f32> {
+        parameters.get(key).map_or(Ok(default), |value| {
+            value.parse().map_err(|_| {
+                Error::format(format!("objective parameter {key} isn't a number: {value}"))
+            })
+        })
+    };
+    let objective = match name {
+        "custom" | "none" => return Ok(None),
+        // `sqrt` trains on square roots of the labels and squares predictions
+        "regression" if !flags.contains(&"sqrt") => ObjectiveType::SquaredError,
+        "binary" if parameter("sigmoid", 1.0)? == 1.0 => ObjectiveType::BinaryLogistic,
+        "multiclass" => ObjectiveType::Multiclass {
+            num_class: parameters
+                .get("num_class")
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(|| Error::format("multiclass objective without num_class"))?,
+        },
+        "lambdarank" => ObjectiveType::LambdaRank,
+        "quantile" => ObjectiveType::Quantile {
+            alpha: parameter("alpha", 0.9)?,
+        },
+        "huber" => ObjectiveType::Huber {
+            delta: parameter("alpha", 0.9)?,
+        },
+        "fair" => ObjectiveType::Fair {
+            c: parameter("fair_c", 1.0)?,
+        },
+        "poisson" => ObjectiveType::Poisson {
+            max_delta_step: parameter("max_delta_step", 0.7)?,
+        },
+        "gamma" => ObjectiveType::Gamma,
+        "tweedie" => ObjectiveType::Tweedie {
+            variance_power: parameter("tweedie_variance_power", 1.5)?,
+        },
+        _ => {
+            return Err(Error::format(format!(
+                "unsupported LightGBM objective: {line}"
+            )));
+        }
+    };
+    Ok(Some(objective))
+}
+
+// `key=value` lines of the header or of one tree; bare keys like `average_output` map to ""
+type Section<'a> = HashMap<&'a str, &'a str>;
+
+pub(super) fn from_str(text: &str) -> Result<Model> {
+    let mut lines = text.lines().map(str::trim);
+    let mut header = Section::new();
+    let mut tree_sections: Vec<Section> = Vec::new();
+    for line in lines.by_ref() {
+        if line == "end of trees" {
+            break;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let (key, value) = line.split_once('=').unwrap_or((line, ""));
+        if key == "Tree" {
+            tree_sections.push(Section::new());
+        } else if let Some(section) = tree_sections.last_mut() {
+            section.insert(key, value);
+        } else {
+            header.insert(key, value);
+        }
+    }
+    if !header.contains_key("tree") {
+        return Err(Error::format("not a LightGBM model: no `tree` header"));
+    }
+
+    let num_outputs: usize = parse_value(&header, "num_tree_per_iteration")?;
+    if num_outputs == 0 {
+        return Err(Error::format("LightGBM model with no trees per iteration"));
+    }
+    let num_features = parse_value::<usize>(&header, "max_feature_idx")? + 1;
+    let objective = match header.get("objective") {
+        Some(line) => parse_objective(line)?,
+        None => None,
+    };
+    let feature_names: Vec<String> = required(&header, "feature_names")?
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+    if feature_names.len() != num_features {
+        return Err(Error::format(format!(
+            "LightGBM model has {} feature names for {num_features} features",
+            feature_names.len()
+        )));
+    }
+
+    let trees = tree_sections
+        .iter()
+        .enumerate()
+        .map(|(i, section)| {
+            parse_tree(section, num_features)
+                .map_err(|err| Error::format(format!("tree {i} of the LightGBM model: {err}")))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut params = Params::default();
+    if let Some(objective) = objective {
+        params.objective = objective;
+    }
+    params.num_iterations = trees.len() / num_outputs;
+    Model {
+        params,
+        objective,
+        trees,
+        num_outputs,
+        base_scores: vec![0.0; num_outputs],
+        best_iteration: None,
+        average_output: header.contains_key("average_output"),
+        feature_names: Some(feature_names),
+        num_features: Some(num_features),
+        crate_version: None,
+        metadata: Default::default(),
+    }
+    .validate()
+}
+
+fn required<'a>(section: &Section<'a>, key: &str) -> Result<&'a str> {
+    section
+        .get(key)
+        .copied()
+        .ok_or_else(|| Error::format(format!("missing {key}")))
+}
+
+fn parse_value<T: std::str::FromStr>(section: &Section, key: &str) -> Result<T> {
+    let value = required(section, key)?;
+    value
+        .parse()
+        .map_err(|_| Error::format(format!("{key} isn't a number: {value}")))
+}
+
+// the `len` space separated numbers of `key`
+fn parse_array<T: std::str::FromStr>(section: &Section, key: &str, len: usize) -> Result<Vec<T>> {
+    let values = required(section, key)?
+        .split_whitespace()
+        .map(|value| {
+            value.parse().map_err(|_| {
+                Error::format(format!("{key} has a value that isn't a number: {value}"))
+            })
+        })
+        .collect::<Result<Vec<T>>>()?;
+    if values.len() != len {
+        return Err(Error::format(format!(
+            "{key} has {} values, expected {len}",
+            values.len()
+        )));
+    }
+    Ok(values)
+}
+
+fn parse_tree(section: &Section, num_features: usize) -> Result<Tree> {
+    if section.get("is_linear").is_some_and(|value| *value != "0") {
+        return Err(Error::format("linear trees aren't supported"));
+    }
+    let num_leaves: usize = parse_value(section, "num_leaves")?;
+    if num_leaves == 0 {
+        return Err(Error::format("tree without leaves"));
+    }
+    // leaf values already include the tree's shrinkage, which is only recorded
+    let leaf_value: Vec<f64> = parse_array(section, "leaf_value", num_leaves)?;
+    let mut leaves: Vec<Option<Box<TreeNode>>> = leaf_value
+        .iter()
+        .map(|&value| {
+            Some(Box::new(TreeNode::Leaf {
+                value: value as f32,
+            }))
+        })
+        .collect();
+    let leaf_count: Option<Vec<f32>> = match section.get("leaf_count") {
+        Some(_) => Some(parse_array(section, "leaf_count", num_leaves)?),
+        None => None,
+    };
+    if num_leaves == 1 {
+        let tree = Tree::new(leaves[0].take().unwrap());
+        return Ok(match leaf_count {
+            Some(leaf_count) => tree.with_leaf_covers(leaf_count),
+            None => tree,
+        });
+    }
+
+    let num_splits = num_leaves - 1;
+    let split_feature: Vec<usize> = parse_array(section, "split_feature", num_splits)?;
+    let threshold: Vec<f64> = parse_array(section, "threshold", num_splits)?;
+    let decision_type: Vec<u8> = match section.get("decision_type") {
+        Some(_) => parse_array(section, "decision_type", num_splits)?,
+        None => vec![0; num_splits],
+    };
+    let left_child: Vec<i64> = parse_array(section, "left_child", num_splits)?;
+    let right_child: Vec<i64> = parse_array(section, "right_child", num_splits)?;
+    let num_cat: usize = match section.get("num_cat") {
+        Some(_) => parse_value(section, "num_cat")?,
+        None => 0,
+    };
+    let (cat_boundaries, cat_threshold): (Vec<usize>, Vec<u32>) = if num_cat > 0 {
+        let cat_boundaries: Vec<usize> = parse_array(section, "cat_boundaries", num_cat + 1)?;
+        let num_words = *cat_boundaries.last().unwrap();
+        let cat_threshold = parse_array(section, "cat_threshold", num_words)?;
+        if cat_boundaries.windows(2).any(|pair| pair[0] > pair[1]) {
+            return Err(Error::format("cat_boundaries isn't ascending"));
+        }
+        (cat_boundaries, cat_threshold)
+    } else {
+        (vec![0], Vec::new())
+    };
+
+    // Every node but the root is the child of exactly one split and splits come after
+    // their parents, so the splits can be assembled from the last to the first.
+    let mut referenced = vec![false; num_splits + num_leaves];
+    for (parent, &child) in left_child.iter().chain(&right_child).enumerate() {
+        let parent = parent % num_splits;
+        let slot = if child >= 0 {
+            let child = child as usize;
+            if child <= parent || child >= num_splits {
+                return Err(Error::format(format!(
+                    "split {parent} has child split {child}"
+                )));
+            }
+            child
+        } else {
+            let leaf = !child as usize;
+            if leaf >= num_leaves {
+                return Err(Error::format(format!(
+                    "split {parent} has child leaf {leaf}"
+                )));
+            }
+            num_splits + leaf
+        };
+        if std::mem::replace(&mut referenced[slot], true) {
+            return Err(Error::format("a node is the child of two splits"));
+        }
+    }
+
+    let mut splits: Vec<Option<Box<TreeNode>>> = vec![None; num_splits];
+    for i in (0..num_splits).rev() {
+        let mut take = |child: i64| -> Box<TreeNode> {
+            if child >= 0 {
+                splits[child as usize].take().unwrap()
+            } else {
+                leaves[!child as usize].take().unwrap()
+            }
+        };
+        let left = take(left_child[i]);
+        let right = take(right_child[i]);
+        let feature_index = split_feature[i];
+        if feature_index >= num_features {
+            return Err(Error::format(format!(
+                "split on feature {feature_index} of {num_features}"
+            )));
+        }
+        let decision = decision_type[i];
+        let node = if decision & CATEGORICAL_MASK != 0 {
+            let cat_index = threshold[i] as usize;
+            if threshold[i] < 0.0 || cat_index >= num_cat {
+                return Err(Error::format(format!(
+                    "categorical split {i} refers to bitset {}",
+                    threshold[i]
+                )));
+            }
+            let bitset = &cat_threshold[cat_boundaries[cat_index]..cat_boundaries[cat_index + 1]];
+            let categories = (0..bitset.len() as u32 * 32)
+                .filter(|&category| bitset[category as usize / 32] & (1 << (category % 32)) != 0)
+                .collect();
+            TreeNode::CategoricalSplit {
+                feature_index,
+                categories,
+                left_child: left,
+                right_child: right,
+            }
+        } else {
+            let default_left = match (decision >> 2) & 3 {
+                // LightGBM scores missing values as zero
+                MISSING_NONE => 0.0 <= threshold[i],
+                MISSING_NAN => decision & DEFAULT_LEFT_MASK != 0,
+                MISSING_ZERO => {
+                    return Err(Error::format(
+                        "splits treating zeros as missing aren't supported",
+                    ));
+                }
+                missing_type => {
+                    return Err(Error::format(format!(
+                        "unknown missing type {missing_type}"
+                    )));
+                }
+            };
+            TreeNode::Split {
+                feature_index,
+                threshold: threshold_above(threshold[i]),
+                default_left,
+                left_child: left,
+                right_child: right,
+            }
+        };
+        splits[i] = Some(Box::new(node));
+    }
+    let mut tree = Tree::new(splits[0].take().unwrap());
+
+    // LightGBM numbers its nodes as they were split, ours go in preorder and leaves from
+    // left to right
+    let (mut preorder_splits, mut ordered_leaves) = (Vec::new(), Vec::new());
+    let mut stack = vec![0];
+    while let Some(node) = stack.pop() {
+        if node >= 0 {
+            preorder_splits.push(node as usize);
+            stack.extend([right_child[node as usize], left_child[node as usize]]);
+        } else {
+            ordered_leaves.push(!node as usize);
+        }
+    }
+    if let Some(leaf_count) = leaf_count {
+        tree = tree.with_leaf_covers(
+            ordered_leaves
+                .iter()
+                .map(|&leaf| leaf_count[leaf])
+                .collect(),
+        );
+    }
+    if section.contains_key("split_gain") {
+        let split_gain: Vec<f32> = parse_array(section, "split_gain", num_splits)?;
+        tree = tree.with_split_gains(
+            preorder_splits
+                .iter()
+                .map(|&split| split_gain[split])
+                .collect(),
+        );
+    }
+    Ok(tree)
+}
+
+// the smallest f32 above `threshold`, so f32 values below it are those `<= threshold`
+fn threshold_above(threshold: f64) -> f32 {
+    let nearest = threshold as f32;
+    if f64::from(nearest) <= threshold {
+        nearest.next_up()
+    } else {
+        nearest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::booster::Booster;
+    use crate::dataset::Dataset;
+    use crate::model::ImportanceType;
+
+    #[test]
+    fn test_lightgbm_round_trip_predicts_like_the_booster() {
+        let features: Vec<Vec<f32>> = (0..120)
+            .map(|i| {
+                vec![
+                    (i % 4) as f32,
+                    if i % 7 == 0 {
+                        f32::NAN
+                    } else {
+                        (i as f32) * 0.37
+                    },
+                    ((i * 13) % 11) as f32,
+                ]
+            })
+            .collect();
+        let labels: Vec<f32> = (0..120).map(|i| ((i / 40 + i % 2) % 3) as f32).collect();
+        let dataset = Dataset::new(features.clone(), labels).with_feature_names(vec![
+            "kind".to_string(),
+            "size".to_string(),
+            "shelf life".to_string(),
+        ]);
+        let params = Params {
+            objective: ObjectiveType::Multiclass { num_class: 3 },
+            num_iterations: 5,
+            categorical_features: vec![0],
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.train(&dataset, &params).unwrap();
+
+        let text = booster.to_model().to_lightgbm_string().unwrap();
+        assert!(text.contains("objective=multiclass num_class:3"));
+        assert!(text.contains("feature_names=kind size shelf_life"));
+        let model = Model::from_lightgbm_str(&text).unwrap();
+        assert_eq!(model.objective(), Some(params.objective));
+        assert_eq!(model.trees().len(), booster.to_model().trees().len());
+        assert_eq!(
+            model.trees()[4].leaf_covers(),
+            booster.to_model().trees()[4].leaf_covers()
+        );
+        let loaded = Booster::from_model(model);
+        for x in &features {
+            let expected = booster.predict_raw(x);
+            for (actual, expected) in loaded.predict_raw(x).iter().zip(expected) {
+                assert!((actual - expected).abs() < 1e-5, "{actual} != {expected}");
+            }
+        }
+        // the imported model writes the same trees back
+        let model = Model::from_lightgbm_str(&text).unwrap();
+        let again = Model::from_lightgbm_str(&model.to_lightgbm_string().unwrap()).unwrap();
+        assert_eq!(again.trees(), model.trees());
+    }
+
+    // a LightGBM binary model with a numeric split per missing type and a categorical split
+    const LIGHTGBM_MODEL: &str = "\
+tree
+version=v4
+num_class=1
+num_tree_per_iteration=1
+label_index=0
+max_feature_idx=1
+objective=binary sigmoid:1
+feature_names=age color
+feature_infos=[18:90] 1:2:5
+tree_sizes=400 300
+
+Tree=0
+num_leaves=3
+num_cat=0
+split_feature=0 0
+split_gain=10.5 2.25
+threshold=30.500000000000004 60
+decision_type=2 8
+left_child=-1 -2
+right_child=1 -3
+leaf_value=-0.5 0.25 0.75
+leaf_weight=10 20 30
+leaf_count=10 20 30
+internal_value=0 0.1
+internal_weight=60 50
+internal_count=60 50
+is_linear=0
+shrinkage=1
+
+Tree=1
+num_leaves=2
+num_cat=1
+split_feature=1
+split_gain=3
+threshold=0
+decision_type=1
+left_child=-1
+right_child=-2
+leaf_value=0.125 -0.125
+leaf_weight=5 5
+leaf_count=5 5
+internal_value=0
+internal_weight=10
+internal_count=10
+cat_boundaries=0 2
+cat_threshold=34 1
+is_linear=0
+shrinkage=0.1
+
+
+end of trees
+
+feature_importances:
+age=2
+color=1
+
+parameters:
+[boosting: gbdt]
+end of parameters
+
+pandas_categorical:null
+";
+
+    #[test]
+    fn test_reads_lightgbm_model() {
+        let model = Model::from_lightgbm_str(LIGHTGBM_MODEL).unwrap();
+        assert_eq!(model.objective(), Some(ObjectiveType::BinaryLogistic));
+        assert_eq!(
+            model.feature_names(),
+            Some(&["age".to_string(), "color".to_string()][..])
+        );
+        assert_eq!(
+            model.trees()[0].leaf_covers(),
+            Some(&[10.0, 20.0, 30.0][..])
+        );
+        assert_eq!(
+            model.feature_importance(ImportanceType::Gain).unwrap(),
+            vec![("age".to_string(), 12.75), ("color".to_string(), 3.0)]
+        );
+        let contributions = model.predict_contrib(&[45.0, 5.0][..]).unwrap();
+        assert!((contributions.iter().sum::<f32>() - (0.25 + 0.125)).abs() < 1e-6);
+        let booster = Booster::from_model(model);
+        // tree 0: age <= 30.5 (NaN counts as zero) left, else age <= 60 (NaN right)
+        // tree 1: colors 1, 5 and 32 left
+        for (x, expected) in [
+            (vec![30.5, 1.0], -0.5 + 0.125),
+            (vec![30.500002, 2.0], 0.25 - 0.125),
+            (vec![60.0, 5.0], 0.25 + 0.125),
+            (vec![60.000004, 32.0], 0.75 + 0.125),
+            (vec![f32::NAN, f32::NAN], -0.5 - 0.125),
+        ] {
+            assert_eq!(booster.predict_raw(&x)[0], expected, "{x:?}");
+        }
+    }
+
+    #[test]
+    fn test_writes_base_scores_into_the_trees() {
+        let mut model = Booster::new().to_model();
+        model.base_scores = vec![1.5];
+        let text = model.to_lightgbm_string().unwrap();
+        assert!(text.contains("leaf_value=1.5\n"));
+        let booster = Booster::from_model(Model::from_lightgbm_str(&text).unwrap());
+        assert_eq!(booster.predict(&[0.0]), 1.5);
+    }
+
+    #[test]
+    fn test_rejects_what_lightgbm_and_this_crate_dont_share() {
+        let zero_missing = LIGHTGBM_MODEL.replace("decision_type=2 8", "decision_type=2 4");
+        let linear = LIGHTGBM_MODEL.replace("is_linear=0", "is_linear=1");
+        let objective = LIGHTGBM_MODEL.replace("binary sigmoid:1", "cross_entropy");
+        let cycle = LIGHTGBM_MODEL.replace("right_child=1 -3", "right_child=1 1");
+        for text in [&zero_missing, &linear, &objective, &cycle, "not a model"] {
+            assert!(matches!(
+                Model::from_lightgbm_str(text),
+                Err(Error::Format { .. })
+            ));
+        }
+
+        let linear_leaf = Tree::new(Box::new(TreeNode::LinearLeaf {
+            value: 0.0,
+            intercept: 1.0,
+            feature_indices: vec![0],
+            coefficients: vec![2.0],
+        }));
+        let mut model = Booster::new().to_model();
+        model.trees = vec![linear_leaf];
+        assert!(matches!(
+            model.to_lightgbm_string(),
+            Err(Error::InvalidData { .. })
+        ));
+    }
+}
@@ -0,0 +1,270 @@
+// Writing models in the lite format of `metalgbm_predict::lite` for embedded targets, whose
+// `LiteModel` predicts without the standard library. Leaf values are rounded to a
+// `LitePrecision` while thresholds stay exact, and the file records the largest error that
+// the rounding adds to a raw score.
+
+use std::fs;
+use std::path::Path;
+
+use metalgbm_predict::lite::{
+    CATEGORICAL_SPLIT, FORMAT_VERSION, LEAF, LiteModel, LitePrecision, MAGIC, SPLIT,
+    SPLIT_DEFAULT_LEFT,
+};
+
+use crate::checkpoint::write_atomically;
+use crate::error::{Error, Result};
+use crate::tree::TreeNode;
+
+use super::Model;
+use super::binary::{self, Writer};
+
+impl Model {
+    // The trees prediction uses as a `LiteModel` with leaf values rounded to `precision`,
+    // see `to_lite_bytes`.
+    pub fn to_lite(&self, precision: LitePrecision) -> Result<LiteModel> {
+        let bytes = self.to_lite_bytes(precision)?;
+        LiteModel::from_bytes(&bytes).map_err(|err| Error::format(err.reason))
+    }
+
+    pub fn save_lite(&self, path: impl AsRef<Path>, precision: LitePrecision) -> Result<()> {
+        write_atomically(path.as_ref(), &self.to_lite_bytes(precision)?)
+    }
+
+    // The trees prediction uses in the lite format, with leaf values rounded to `precision`.
+    // Fails for models with linear leaves, more than 65536 features, thresholds for one
+    // feature or category sets, or leaf values too large for f16.
+    pub fn to_lite_bytes(&self, precision: LitePrecision) -> Result<Vec<u8>> {
+        let trees = self.predicting_trees();
+        let num_features = self.num_columns();
+        if num_features > usize::from(u16::MAX) + 1 {
+            return Err(Error::invalid_data(format!(
+                "lite models have at most 65536 features, not {num_features}"
+            )));
+        }
+        let averaging = if self.average_output && !trees.is_empty() {
+            self.num_outputs as f32 / trees.len() as f32
+        } else {
+            1.0
+        };
+
+        let mut leaf_values = Vec::new();
+        for (i, tree) in trees.iter().enumerate() {
+            for node in binary::preorder(tree.root()) {
+                match node {
+                    TreeNode::Leaf { value } => {
+                        leaf_values.push(tree.shrinkage() * value * averaging)
+                    }
+                    TreeNode::LinearLeaf { .. } => {
+                        return Err(Error::invalid_data(format!(
+                            "tree {i} of the model has linear leaves, which lite models lack"
+                        )));
+                    }
+                    TreeNode::Split { .. } | TreeNode::CategoricalSplit { .. } => {}
+                }
+            }
+        }
+        let largest = leaf_values.iter().fold(0.0f32, |max, v| max.max(v.abs()));
+        let scale = if largest > 0.0 {
+            largest / f32::from(i16::MAX)
+        } else {
+            1.0
+        };
+        if precision == LitePrecision::F16
+            && precision
+                .decode(precision.encode(largest, scale), scale)
+                .is_infinite()
+        {
+            return Err(Error::invalid_data(format!(
+                "leaf value {largest} is too large for f16, use LitePrecision::Fixed"
+            )));
+        }
+
+        let tables = binary::threshold_tables_of(trees, num_features);
+        if let Some(feature) = tables
+            .iter()
+            .position(|table| table.len() > usize::from(u16::MAX) + 1)
+        {
+            return Err(Error::invalid_data(format!(
+                "feature {feature} has {} thresholds, more than a lite model holds",
+                tables[feature].len()
+            )));
+        }
+
+        // the trees are written last, once their category sets and error bound are known
+        let mut category_sets: Vec<&[u32]> = Vec::new();
+        let mut error_bounds = vec![0.0f64; self.num_outputs];
+        let mut leaf_values = leaf_values.into_iter();
+        let mut tree_bytes = Writer::default();
+        tree_bytes.u32(trees.len() as u32);
+        for (i, tree) in trees.iter().enumerate() {
+            let mut largest_error = 0.0f32;
+            let nodes = binary::preorder(tree.root());
+            tree_bytes.u32(nodes.len() as u32);
+            for node in nodes {
+                match node {
+                    TreeNode::Split {
+                        feature_index,
+                        threshold,
+                        default_left,
+                        ..
+                    } => {
+                        let index = tables[*feature_index]
+                            .binary_search_by(|probe| probe.total_cmp(threshold))
+                            .unwrap();
+                        tree_bytes.u8(if *default_left {
+                            SPLIT_DEFAULT_LEFT
+                        } else {
+                            SPLIT
+                        });
+                        tree_bytes.u16(*feature_index as u16);
+                        tree_bytes.u16(index as u16);
+                    }
+                    TreeNode::CategoricalSplit {
+                        feature_index,
+                        categories,
+                        ..
+                    } => {
+                        let set = match category_sets.iter().position(|&set| set == &categories[..])
+                        {
+                            Some(set) => set,
+                            None => {
+                                category_sets.push(categories.as_slice());
+                                category_sets.len() - 1
+                            }
+                        };
+                        let set = u16::try_from(set).map_err(|_| {
+                            Error::invalid_data(
+                                "the model has more than 65536 category sets for a lite model",
+                            )
+                        })?;
+                        tree_bytes.u8(CATEGORICAL_SPLIT);
+                        tree_bytes.u16(*feature_index as u16);
+                        tree_bytes.u16(set);
+                    }
+                    TreeNode::Leaf { .. } | TreeNode::LinearLeaf { .. } => {
+                        let value = leaf_values.next().unwrap();
+                        let bits = precision.encode(value, scale);
+                        let error = (precision.decode(bits, scale) - value).abs();
+                        largest_error = largest_error.max(error);
+                        tree_bytes.u8(LEAF);
+                        tree_bytes.u16(bits);
+                    }
+                }
+            }
+            error_bounds[i % self.num_outputs] += f64::from(largest_error);
+        }
+        let raw_error_bound = error_bounds.into_iter().fold(0.0, f64::max) as f32;
+
+        let mut out = Writer::default();
+        out.bytes(&MAGIC);
+        out.u32(FORMAT_VERSION);
+        out.u8(precision.code());
+        out.f32(scale);
+        out.u32(self.num_outputs as u32);
+        let (tag, parameter) = binary::objective_code(self.objective);
+        out.u8(tag);
+        out.u32(parameter);
+        for &base_score in &self.base_scores {
+            out.f32(base_score);
+        }
+        out.u32(num_features as u32);
+        out.f32(raw_error_bound);
+        for table in &tables {
+            out.u32(table.len() as u32);
+            for &threshold in table {
+                out.f32(threshold);
+            }
+        }
+        out.u32(category_sets.len() as u32);
+        for set in category_sets {
+            out.u32(set.len() as u32);
+            for &category in set {
+                out.u32(category);
+            }
+        }
+        out.bytes(&tree_bytes.0);
+        Ok(out.0)
+    }
+}
+
+// Reads a lite model saved by `Model::save_lite`.
+pub fn load_lite(path: impl AsRef<Path>) -> Result<LiteModel> {
+    let path = path.as_ref();
+    let bytes =
+        fs::read(path).map_err(|err| Error::io(format!("can't read {}: {err}", path.display())))?;
+    LiteModel::from_bytes(&bytes)
+        .map_err(|err| Error::format(format!("{}: {}", path.display(), err.reason)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::booster::Booster;
+    use crate::dataset::Dataset;
+    use crate::params::Params;
+
+    fn trained_model() -> (Model, Vec<Vec<f32>>) {
+        let features: Vec<Vec<f32>> = (0..300)
+            .map(|i| {
+                vec![
+                    (i % 30) as f32 * 0.37,
+                    if i % 7 == 0 {
+                        f32::NAN
+                    } else {
+                        (i % 11) as f32
+                    },
+                    (i % 4) as f32,
+                ]
+            })
+            .collect();
+        let labels: Vec<f32> = features
+            .iter()
+            .map(|x| x[0] * 3.0 + if x[2] == 2.0 { 5.0 } else { 0.0 })
+            .collect();
+        let dataset = Dataset::new(features.clone(), labels);
+        let params = Params {
+            num_iterations: 30,
+            categorical_features: vec![2],
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster.train(&dataset, &params).unwrap();
+        (booster.to_model(), features)
+    }
+
+    #[test]
+    fn test_predicts_within_the_error_bound() {
+        let (model, features) = trained_model();
+        let booster = Booster::from_model(model.clone());
+        for precision in [LitePrecision::F16, LitePrecision::Fixed] {
+            let lite = model.to_lite(precision).unwrap();
+            assert!(lite.raw_error_bound() > 0.0);
+            assert!(lite.raw_error_bound() < 0.05, "{}", lite.raw_error_bound());
+            for row in &features {
+                let error = (lite.predict_raw(&row[..])[0] - booster.predict_raw(row)[0]).abs();
+                assert!(error <= lite.raw_error_bound() + 1e-4, "{error}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_file_round_trip_is_much_smaller() {
+        let (model, features) = trained_model();
+        let lite = model.to_lite(LitePrecision::Fixed).unwrap();
+        let bytes = model.to_lite_bytes(LitePrecision::Fixed).unwrap();
+        assert!(bytes.len() * 5 < model.to_bytes().unwrap().len() * 2);
+
+        let path = std::env::temp_dir().join(format!("metalgbm-lite-{}", std::process::id()));
+        model.save_lite(&path, LitePrecision::Fixed).unwrap();
+        let loaded = load_lite(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, lite);
+        for row in &features {
+            assert_eq!(loaded.predict(&row[..]), lite.predict(&row[..]));
+        }
+
+        assert!(LiteModel::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(LiteModel::from_bytes(&model.to_bytes().unwrap()).is_err());
+        assert!(matches!(load_lite(&path), Err(Error::Io { .. })));
+    }
+}
@@ -0,0 +1,532 @@
+// PMML 4.4 export, for scoring engines that take nothing else.
+//
+// The document is a `modelChain`: one `MiningModel` per output summing (or averaging) its
+// trees into a raw score, then a `RegressionModel` adding the base scores and applying the
+// objective's transform: `logit` for binary logistic, `softmax` for multiclass, `exp` for
+// the log-link regressions. Trees use `missingValueStrategy="defaultChild"`, which sends
+// missing values where ours go. Categorical features are integer fields; PMML treats
+// non-integer values of them as invalid where our splits send them right. Linear leaves
+// aren't exported.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use crate::error::{Error, Result};
+use crate::objective::ObjectiveType;
+use crate::tree::{Tree, TreeNode};
+
+use super::Model;
+
+const TARGET: &str = "target";
+
+pub(super) fn to_string(model: &Model) -> Result<String> {
+    let num_outputs = model.num_outputs;
+    let trees = model.predicting_trees();
+    let num_features = model.num_columns();
+    let names: Vec<String> = match &model.feature_names {
+        Some(names) => names.iter().map(|name| escape(name)).collect(),
+        None => (0..num_features).map(|i| format!("Column_{i}")).collect(),
+    };
+    let categorical: BTreeSet<usize> = trees
+        .iter()
+        .flat_map(|tree| categorical_features(tree.root()))
+        .collect();
+    let (function_name, normalization) = match model.objective {
+        Some(ObjectiveType::BinaryLogistic) => ("classification", "logit"),
+        Some(ObjectiveType::Multiclass { .. }) => ("classification", "softmax"),
+        Some(
+            ObjectiveType::Poisson { .. } | ObjectiveType::Gamma | ObjectiveType::Tweedie { .. },
+        ) => ("regression", "exp"),
+        _ => ("regression", "none"),
+    };
+    let classes: Vec<usize> = match model
+        .objective
+        .and_then(|objective| objective.num_classes())
+    {
+        Some(num_classes) if function_name == "classification" => (0..num_classes).collect(),
+        _ => Vec::new(),
+    };
+
+    let mut pmml = String::new();
+    writeln!(pmml, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(
+        pmml,
+        r#"<PMML xmlns="http://www.dmg.org/PMML-4_4" version="4.4">"#
+    )
+    .unwrap();
+    writeln!(
+        pmml,
+        r#"  <Header><Application name="metalgbm" version="{}"/></Header>"#,
+        env!("CARGO_PKG_VERSION")
+    )
+    .unwrap();
+
+    writeln!(
+        pmml,
+        r#"  <DataDictionary numberOfFields="{}">"#,
+        num_features + 1
+    )
+    .unwrap();
+    for (i, name) in names.iter().enumerate() {
+        if categorical.contains(&i) {
+            writeln!(
+                pmml,
+                r#"    <DataField name="{name}" optype="categorical" dataType="integer"/>"#
+            )
+            .unwrap();
+        } else {
+            writeln!(
+                pmml,
+                r#"    <DataField name="{name}" optype="continuous" dataType="float"/>"#
+            )
+            .unwrap();
+        }
+    }
+    if classes.is_empty() {
+        writeln!(
+            pmml,
+            r#"    <DataField name="{TARGET}" optype="continuous" dataType="double"/>"#
+        )
+        .unwrap();
+    } else {
+        writeln!(
+            pmml,
+            r#"    <DataField name="{TARGET}" optype="categorical" dataType="integer">"#
+        )
+        .unwrap();
+        for class in &classes {
+            writeln!(pmml, r#"      <Value value="{class}"/>"#).unwrap();
+        }
+        writeln!(pmml, "    </DataField>").unwrap();
+    }
+    writeln!(pmml, "  </DataDictionary>").unwrap();
+
+    writeln!(pmml, r#"  <MiningModel functionName="{function_name}">"#).unwrap();
+    writeln!(pmml, "    <MiningSchema>").unwrap();
+    writeln!(
+        pmml,
+        r#"      <MiningField name="{TARGET}" usageType="target"/>"#
+    )
+    .unwrap();
+    for name in &names {
+        writeln!(pmml, r#"      <MiningField name="{name}"/>"#).unwrap();
+    }
+    writeln!(pmml, "    </MiningSchema>").unwrap();
+    writeln!(
+        pmml,
+        r#"    <Segmentation multipleModelMethod="modelChain">"#
+    )
+    .unwrap();
+
+    let method = if model.average_output {
+        "average"
+    } else {
+        "sum"
+    };
+    // a model without trees still predicts its base scores
+    let no_tree = [Tree::new(Box::new(TreeNode::Leaf { value: 0.0 }))];
+    for output in 0..num_outputs {
+        let output_trees: Vec<&Tree> = if trees.is_empty() {
+            no_tree.iter().collect()
+        } else {
+            trees.iter().skip(output).step_by(num_outputs).collect()
+        };
+        writeln!(pmml, r#"      <Segment id="{}">"#, output + 1).unwrap();
+        writeln!(pmml, "        <True/>").unwrap();
+        writeln!(pmml, r#"        <MiningModel functionName="regression">"#).unwrap();
+        write_mining_schema(&mut pmml, 10, &names, 0..num_features);
+        writeln!(pmml, "          <Output>").unwrap();
+        writeln!(
+            pmml,
+            r#"            <OutputField name="raw_{output}" optype="continuous" dataType="double" feature="predictedValue" isFinalResult="false"/>"#
+        )
+        .unwrap();
+        writeln!(pmml, "          </Output>").unwrap();
+        writeln!(
+            pmml,
+            r#"          <Segmentation multipleModelMethod="{method}">"#
+        )
+        .unwrap();
+        for (i, tree) in output_trees.into_iter().enumerate() {
+            write_tree(&mut pmml, i + 1, tree, &names)?;
+        }
+        writeln!(pmml, "          </Segmentation>").unwrap();
+        writeln!(pmml, "        </MiningModel>").unwrap();
+        writeln!(pmml, "      </Segment>").unwrap();
+    }
+
+    writeln!(pmml, r#"      <Segment id="{}">"#, num_outputs + 1).unwrap();
+    writeln!(pmml, "        <True/>").unwrap();
+    writeln!(
+        pmml,
+        r#"        <RegressionModel functionName="{function_name}" normalizationMethod="{normalization}">"#
+    )
+    .unwrap();
+    writeln!(pmml, "          <MiningSchema>").unwrap();
+    writeln!(
+        pmml,
+        r#"            <MiningField name="{TARGET}" usageType="target"/>"#
+    )
+    .unwrap();
+    for output in 0..num_outputs {
+        writeln!(pmml, r#"            <MiningField name="raw_{output}"/>"#).unwrap();
+    }
+    writeln!(pmml, "          </MiningSchema>").unwrap();
+    if !classes.is_empty() {
+        writeln!(pmml, "          <Output>").unwrap();
+        for class in &classes {
+            writeln!(
+                pmml,
+                r#"            <OutputField name="probability({class})" optype="continuous" dataType="double" feature="probability" value="{class}"/>"#
+            )
+            .unwrap();
+        }
+        writeln!(pmml, "          </Output>").unwrap();
+    }
+    for (output, &base_score) in model.base_scores.iter().enumerate() {
+        // a binary model's single score is that of class 1
+        let target_category = if classes.is_empty() {
+            String::new()
+        } else if num_outputs == 1 {
+            r#" targetCategory="1""#.to_string()
+        } else {
+            format!(r#" targetCategory="{output}""#)
+        };
+        writeln!(
+            pmml,
+            r#"          <RegressionTable intercept="{}"{target_category}>"#,
+            number(base_score)
+        )
+        .unwrap();
+        writeln!(
+            pmml,
+            r#"            <NumericPredictor name="raw_{output}" coefficient="1"/>"#
+        )
+        .unwrap();
+        writeln!(pmml, "          </RegressionTable>").unwrap();
+    }
+    if !classes.is_empty() && num_outputs == 1 {
+        writeln!(
+            pmml,
+            r#"          <RegressionTable intercept="0" targetCategory="0"/>"#
+        )
+        .unwrap();
+    }
+    writeln!(pmml, "        </RegressionModel>").unwrap();
+    writeln!(pmml, "      </Segment>").unwrap();
+    writeln!(pmml, "    </Segmentation>").unwrap();
+    writeln!(pmml, "  </MiningModel>").unwrap();
+    writeln!(pmml, "</PMML>").unwrap();
+    Ok(pmml)
+}
+
+fn write_mining_schema(
+    pmml: &mut String,
+    depth: usize,
+    names: &[String],
+    features: impl IntoIterator<Item = usize>,
+) {
+    let indent = " ".repeat(depth);
+    writeln!(pmml, "{indent}<MiningSchema>").unwrap();
+    for feature_index in features {
+        writeln!(
+            pmml,
+            r#"{indent}  <MiningField name="{}"/>"#,
+            names[feature_index]
+        )
+        .unwrap();
+    }
+    writeln!(pmml, "{indent}</MiningSchema>").unwrap();
+}
+
+// what is left to write of a tree: a node with its id and predicate, or a closing tag
+enum Part<'a> {
+    Node(&'a TreeNode, usize, String),
+    Text(&'static str),
+}
+
+// Writes the `Segment` of a tree. The first child of a split is the left one, chosen when
+// its predicate holds; the second always holds. Nodes are written from a stack rather than
+// recursively, so deep trees can't overflow it.
+fn write_tree(pmml: &mut String, id: usize, tree: &Tree, names: &[String]) -> Result<()> {
+    let used_features: BTreeSet<usize> = features(tree.root()).collect();
+    writeln!(pmml, r#"            <Segment id="{id}">"#).unwrap();
+    writeln!(pmml, "              <True/>").unwrap();
+    writeln!(
+        pmml,
+        r#"              <TreeModel functionName="regression" missingValueStrategy="defaultChild" noTrueChildStrategy="returnLastPrediction" splitCharacteristic="binarySplit">"#
+    )
+    .unwrap();
+    write_mining_schema(pmml, 16, names, used_features);
+
+    let shrinkage = tree.shrinkage();
+    let mut next_id = 1;
+    let mut parts = vec![(Part::Node(tree.root(), 0, "<True/>".to_string()), 16)];
+    while let Some((part, depth)) = parts.pop() {
+        let indent = " ".repeat(depth);
+        let (node, node_id, predicate) = match part {
+            Part::Text(text) => {
+                writeln!(pmml, "{indent}{text}").unwrap();
+                continue;
+            }
+            Part::Node(node, node_id, predicate) => (node, node_id, predicate),
+        };
+        let (left_predicate, default_left, left_child, right_child) = match node {
+            TreeNode::Leaf { value } => {
+                writeln!(
+                    pmml,
+                    r#"{indent}<Node id="{node_id}" score="{}">{predicate}</Node>"#,
+                    number(shrinkage * value)
+                )
+                .unwrap();
+                continue;
+            }
+            TreeNode::LinearLeaf { .. } => {
+                return Err(Error::invalid_data(
+                    "PMML models can't be written with linear leaves",
+                ));
+            }
+            TreeNode::Split {
+                feature_index,
+                threshold,
+                default_left,
+                left_child,
+                right_child,
+            } => (
+                format!(
+                    r#"<SimplePredicate field="{}" operator="lessThan" value="{}"/>"#,
+                    names[*feature_index],
+                    number(*threshold)
+                ),
+                *default_left,
+                left_child,
+                right_child,
+            ),
+            // the predicate is unknown for missing values, which go right like ours
+            TreeNode::CategoricalSplit {
+                feature_index,
+                categories,
+                left_child,
+                right_child,
+            } => {
+                let categories: Vec<String> = categories.iter().map(u32::to_string).collect();
+                (
+                    format!(
+                        r#"<SimpleSetPredicate field="{}" booleanOperator="isIn"><Array type="int" n="{}">{}</Array></SimpleSetPredicate>"#,
+                        names[*feature_index],
+                        categories.len(),
+                        categories.join(" ")
+                    ),
+                    false,
+                    left_child,
+                    right_child,
+                )
+            }
+        };
+        let (left_id, right_id) = (next_id, next_id + 1);
+        next_id += 2;
+        let default_child = if default_left { left_id } else { right_id };
+        writeln!(
+            pmml,
+            r#"{indent}<Node id="{node_id}" defaultChild="{default_child}">{predicate}"#
+        )
+        .unwrap();
+        parts.push((Part::Text("</Node>"), depth));
+        parts.push((
+            Part::Node(right_child, right_id, "<True/>".to_string()),
+            depth + 2,
+        ));
+        parts.push((Part::Node(left_child, left_id, left_predicate), depth + 2));
+    }
+
+    writeln!(pmml, "              </TreeModel>").unwrap();
+    writeln!(pmml, "            </Segment>").unwrap();
+    Ok(())
+}
+
+// the features the splits of a tree use
+fn features(root: &TreeNode) -> impl Iterator<Item = usize> + '_ {
+    splits(root).map(|(feature_index, _)| feature_index)
+}
+
+fn categorical_features(root: &TreeNode) -> impl Iterator<Item = usize> + '_ {
+    splits(root)
+        .filter(|&(_, categorical)| categorical)
+        .map(|(feature_index, _)| feature_index)
+}
+
+// the feature of every split and whether the split is categorical
+fn splits(root: &TreeNode) -> impl Iterator<Item = (usize, bool)> + '_ {
+    let mut stack = vec![root];
+    std::iter::from_fn(move || {
+        while let Some(node) = stack.pop() {
+            match node {
+                TreeNode::Split {
+                    feature_index,
+                    left_child,
+                    right_child,
+                    ..
+                } => {
+                    stack.extend([left_child.as_ref(), right_child.as_ref()]);
+                    return Some((*feature_index, false));
+                }
+                TreeNode::CategoricalSplit {
+                    feature_index,
+                    left_child,
+                    right_child,
+                    ..
+                } => {
+                    stack.extend([left_child.as_ref(), right_child.as_ref()]);
+                    return Some((*feature_index, true));
+                }
+                TreeNode::Leaf { .. } | TreeNode::LinearLeaf { .. } => {}
+            }
+        }
+        None
+    })
+}
+
+// an XML Schema double that parses back to exactly `value` as an f32
+fn number(value: f32) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value == f32::INFINITY {
+        "INF".to_string()
+    } else if value == f32::NEG_INFINITY {
+        "-INF".to_string()
+    } else {
+        format!("{value:?}")
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::booster::Booster;
+
+    fn leaf(value: f32) -> Box<TreeNode> {
+        Box::new(TreeNode::Leaf { value })
+    }
+
+    #[test]
+    fn test_pmml_of_small_regression() {
+        let tree = Tree::new(Box::new(TreeNode::Split {
+            feature_index: 1,
+            threshold: 2.5,
+            default_left: true,
+            left_child: Box::new(TreeNode::CategoricalSplit {
+                feature_index: 0,
+                categories: vec![1, 3],
+                left_child: leaf(1.0),
+                right_child: leaf(-2.0),
+            }),
+            right_child: leaf(4.0),
+        }))
+        .with_shrinkage(0.5);
+        let mut model = Booster::new().to_model();
+        model.trees = vec![tree];
+        model.base_scores = vec![0.25];
+        model.feature_names = Some(vec!["kind".to_string(), "size <cm>".to_string()]);
+        model.objective = Some(ObjectiveType::SquaredError);
+        let pmml = model.to_pmml_string().unwrap();
+        let expected = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<PMML xmlns="http://www.dmg.org/PMML-4_4" version="4.4">
+  <Header><Application name="metalgbm" version="{}"/></Header>
+  <DataDictionary numberOfFields="3">
+    <DataField name="kind" optype="categorical" dataType="integer"/>
+    <DataField name="size &lt;cm&gt;" optype="continuous" dataType="float"/>
+    <DataField name="target" optype="continuous" dataType="double"/>
+  </DataDictionary>
+  <MiningModel functionName="regression">
+    <MiningSchema>
+      <MiningField name="target" usageType="target"/>
+      <MiningField name="kind"/>
+      <MiningField name="size &lt;cm&gt;"/>
+    </MiningSchema>
+    <Segmentation multipleModelMethod="modelChain">
+      <Segment id="1">
+        <True/>
+        <MiningModel functionName="regression">
+          <MiningSchema>
+            <MiningField name="kind"/>
+            <MiningField name="size &lt;cm&gt;"/>
+          </MiningSchema>
+          <Output>
+            <OutputField name="raw_0" optype="continuous" dataType="double" feature="predictedValue" isFinalResult="false"/>
+          </Output>
+          <Segmentation multipleModelMethod="sum">
+            <Segment id="1">
+              <True/>
+              <TreeModel functionName="regression" missingValueStrategy="defaultChild" noTrueChildStrategy="returnLastPrediction" splitCharacteristic="binarySplit">
+                <MiningSchema>
+                  <MiningField name="kind"/>
+                  <MiningField name="size &lt;cm&gt;"/>
+                </MiningSchema>
+                <Node id="0" defaultChild="1"><True/>
+                  <Node id="1" defaultChild="4"><SimplePredicate field="size &lt;cm&gt;" operator="lessThan" value="2.5"/>
+                    <Node id="3" score="0.5"><SimpleSetPredicate field="kind" booleanOperator="isIn"><Array type="int" n="2">1 3</Array></SimpleSetPredicate></Node>
+                    <Node id="4" score="-1.0"><True/></Node>
+                  </Node>
+                  <Node id="2" score="2.0"><True/></Node>
+                </Node>
+              </TreeModel>
+            </Segment>
+          </Segmentation>
+        </MiningModel>
+      </Segment>
+      <Segment id="2">
+        <True/>
+        <RegressionModel functionName="regression" normalizationMethod="none">
+          <MiningSchema>
+            <MiningField name="target" usageType="target"/>
+            <MiningField name="raw_0"/>
+          </MiningSchema>
+          <RegressionTable intercept="0.25">
+            <NumericPredictor name="raw_0" coefficient="1"/>
+          </RegressionTable>
+        </RegressionModel>
+      </Segment>
+    </Segmentation>
+  </MiningModel>
+</PMML>
+"#,
+            env!("CARGO_PKG_VERSION")
+        );
+        assert_eq!(pmml, expected);
+    }
+
+    #[test]
+    fn test_pmml_of_classifiers() {
+        let mut model = Booster::new().to_model();
+        model.objective = Some(ObjectiveType::BinaryLogistic);
+        let pmml = model.to_pmml_string().unwrap();
+        assert!(pmml.contains(r#"<MiningModel functionName="classification">"#));
+        assert!(pmml.contains(r#"normalizationMethod="logit""#));
+        assert!(pmml.contains(r#"<RegressionTable intercept="0.0" targetCategory="1">"#));
+        assert!(pmml.contains(r#"<RegressionTable intercept="0" targetCategory="0"/>"#));
+        // without trees the segment sums a single leaf
+        assert!(pmml.contains(r#"<Node id="0" score="0.0"><True/></Node>"#));
+
+        model.objective = Some(ObjectiveType::Multiclass { num_class: 3 });
+        model.num_outputs = 3;
+        model.base_scores = vec![0.0, 1.0, 2.0];
+        let pmml = model.to_pmml_string().unwrap();
+        assert!(pmml.contains(r#"normalizationMethod="softmax""#));
+        for class in 0..3 {
+            assert!(pmml.contains(&format!(
+                r#"<RegressionTable intercept="{}.0" targetCategory="{class}">"#,
+                class
+            )));
+            assert!(pmml.contains(&format!(r#"<OutputField name="probability({class})""#)));
+        }
+    }
+}
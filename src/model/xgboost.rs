@@ -0,0 +1,568 @@
+// XGBoost's JSON model format, the file `Booster.save_model("model.json")` writes, read into
+// a `Model` so XGBoost models can be scored here.
+//
+// Numeric splits mean the same in both: `value < split_condition` goes left and missing
+// values take the default direction. Categorical splits in XGBoost send their categories
+// right, so children are swapped to fit ours, which send them left. Two differences stay:
+// XGBoost truncates non-integer values before looking up their category where ours send
+// them right, and missing values of categorical splits always go to the side of the other
+// categories here. The base score is turned into the margin it stands for, like XGBoost
+// does when predicting. Only tree boosters are read, dart with its tree weights as
+// shrinkages.
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::error::{Error, Result};
+use crate::objective::ObjectiveType;
+use crate::params::Params;
+use crate::tree::{Tree, TreeNode};
+
+use super::Model;
+
+#[derive(Deserialize)]
+struct XgbModel {
+    learner: Learner,
+}
+
+#[derive(Deserialize)]
+struct Learner {
+    #[serde(default)]
+    feature_names: Vec<String>,
+    #[serde(default)]
+    feature_types: Vec<String>,
+    gradient_booster: GradientBooster,
+    learner_model_param: LearnerModelParam,
+    objective: Value,
+}
+
+// `gbtree` has the trees in `model`, `dart` wraps a `gbtree` and weighs its trees
+#[derive(Deserialize)]
+struct GradientBooster {
+    name: String,
+    model: Option<TreeModel>,
+    gbtree: Option<Box<GradientBooster>>,
+    #[serde(default)]
+    weight_drop: Vec<f32>,
+}
+
+#[derive(Deserialize)]
+struct TreeModel {
+    #[serde(default)]
+    trees: Vec<XgbTree>,
+    // the output each tree adds to
+    #[serde(default)]
+    tree_info: Vec<usize>,
+}
+
+// XGBoost writes its parameters as strings
+#[derive(Deserialize)]
+struct LearnerModelParam {
+    base_score: String,
+    num_class: String,
+    num_target: Option<String>,
+    num_feature: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct XgbTree {
+    left_children: Vec<i64>,
+    right_children: Vec<i64>,
+    split_indices: Vec<usize>,
+    // the threshold of a split, the value of a leaf
+    split_conditions: Vec<f32>,
+    default_left: Vec<Flag>,
+    #[serde(default)]
+    split_type: Vec<u8>,
+    #[serde(default)]
+    categories: Vec<u32>,
+    #[serde(default)]
+    categories_nodes: Vec<usize>,
+    #[serde(default)]
+    categories_segments: Vec<usize>,
+    #[serde(default)]
+    categories_sizes: Vec<usize>,
+    // the hessian sum of every node, the leaf covers
+    #[serde(default)]
+    sum_hessian: Vec<f32>,
+    // the gain of every split
+    #[serde(default)]
+    loss_changes: Vec<f32>,
+    tree_param: TreeParam,
+}
+
+#[derive(Deserialize)]
+struct TreeParam {
+    size_leaf_vector: Option<String>,
+}
+
+// booleans are 0 or 1 in some versions
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Flag {
+    Bool(bool),
+    Int(u8),
+}
+
+impl Flag {
+    fn is_set(&self) -> bool {
+        match self {
+            Flag::Bool(flag) => *flag,
+            Flag::Int(flag) => *flag != 0,
+        }
+    }
+}
+
+pub(super) fn from_json(json: &str) -> Result<Model> {
+    let model: XgbModel = serde_json::from_str(json)
+        .map_err(|err| Error::format(format!("not an XGBoost JSON model: {err}")))?;
+    let learner = model.learner;
+    let param = &learner.learner_model_param;
+
+    let num_class: usize = parse_param("num_class", &param.num_class)?;
+    let num_outputs = num_class.max(1);
+    if let Some(num_target) = &param.num_target
+        && parse_param::<usize>("num_target", num_target)? > 1
+    {
+        return Err(Error::format(
+            "multi-target XGBoost models aren't supported",
+        ));
+    }
+    let objective = parse_objective(&learner.objective, num_class)?;
+
+    let (tree_model, weights) = match learner.gradient_booster.name.as_str() {
+        "gbtree" => (learner.gradient_booster.model, Vec::new()),
+        "dart" => (
+            learner
+                .gradient_booster
+                .gbtree
+                .and_then(|gbtree| gbtree.model),
+            learner.gradient_booster.weight_drop,
+        ),
+        name => {
+            return Err(Error::format(format!(
+                "unsupported XGBoost booster: {name}"
+            )));
+        }
+    };
+    let tree_model = tree_model.ok_or_else(|| Error::format("XGBoost model without trees"))?;
+    if tree_model.tree_info.len() != tree_model.trees.len()
+        || (!weights.is_empty() && weights.len() != tree_model.trees.len())
+    {
+        return Err(Error::format(
+            "XGBoost model has trees without an output or weight",
+        ));
+    }
+
+    // Trees are grouped by output here, the first of every round adding to output 0, and
+    // XGBoost's random forests can have several trees per output and round.
+    let mut outputs: Vec<Vec<Tree>> = (0..num_outputs).map(|_| Vec::new()).collect();
+    for (i, (tree, &output)) in tree_model
+        .trees
+        .iter()
+        .zip(&tree_model.tree_info)
+        .enumerate()
+    {
+        let mut tree = convert_tree(tree)
+            .map_err(|err| Error::format(format!("tree {i} of the XGBoost model: {err}")))?;
+        if let Some(&weight) = weights.get(i) {
+            tree = tree.with_shrinkage(weight);
+        }
+        outputs
+            .get_mut(output)
+            .ok_or_else(|| Error::format(format!("tree {i} adds to output {output}")))?
+            .push(tree);
+    }
+    let num_iterations = outputs[0].len();
+    if outputs.iter().any(|trees| trees.len() != num_iterations) {
+        return Err(Error::format(
+            "XGBoost model has different numbers of trees per output",
+        ));
+    }
+    let mut outputs: Vec<_> = outputs.into_iter().map(Vec::into_iter).collect();
+    let trees = (0..num_iterations * num_outputs)
+        .map(|i| outputs[i % num_outputs].next().unwrap())
+        .collect();
+
+    let base_scores = base_scores(&param.base_score, num_outputs, objective)?;
+    let params = Params {
+        objective,
+        num_iterations,
+        categorical_features: (0..learner.feature_types.len())
+            .filter(|&i| learner.feature_types[i] == "c")
+            .collect(),
+        ..Params::default()
+    };
+    Model {
+        params,
+        objective: Some(objective),
+        trees,
+        num_outputs,
+        base_scores,
+        best_iteration: None,
+        average_output: false,
+        feature_names: (!learner.feature_names.is_empty()).then_some(learner.feature_names),
+        num_features: param
+            .num_feature
+            .as_deref()
+            .map(|num_feature| parse_param("num_feature", num_feature))
+            .transpose()?,
+        crate_version: None,
+        metadata: Default::default(),
+    }
+    .validate()
+}
+
+fn parse_param<T: std::str::FromStr>(name: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| Error::format(format!("XGBoost parameter {name} isn't a number: {value}")))
+}
+
+// The margin `base_score` stands for, one per output. XGBoost writes it as a number or, for
+// models with several outputs, as a list.
+fn base_scores(base_score: &str, num_outputs: usize, objective: ObjectiveType) -> Result<Vec<f32>> {
+    let values = base_score
+        .trim_matches(['[', ']'])
+        .split(',')
+        .map(|value| parse_param::<f32>("base_score", value.trim()))
+        .collect::<Result<Vec<_>>>()?;
+    let values = match values.len() {
+        1 => vec![values[0]; num_outputs],
+        len if len == num_outputs => values,
+        len => {
+            return Err(Error::format(format!(
+                "XGBoost model has {len} base scores for {num_outputs} outputs"
+            )));
+        }
+    };
+    Ok(values
+        .into_iter()
+        .map(|value| match objective {
+            ObjectiveType::BinaryLogistic => {
+                let value = f64::from(value);
+                (value / (1.0 - value)).ln() as f32
+            }
+            ObjectiveType::Poisson { .. }
+            | ObjectiveType::Gamma
+            | ObjectiveType::Tweedie { .. } => value.ln(),
+            _ => value,
+        })
+        .collect())
+}
+
+// The objective of XGBoost's `objective` object, e.g.
+// `{"name": "reg:tweedie", "tweedie_regression_param": {"tweedie_variance_power": "1.5"}}`.
+fn parse_objective(objective: &Value, num_class: usize) -> Result<ObjectiveType> {
+    let name = objective["name"]
+        .as_str()
+        .ok_or_else(|| Error::format("XGBoost objective without a name"))?;
+    let parameter = |section: &str, key: &str| -> Result<f32> {
+        let value = objective[section][key]
+            .as_str()
+            .ok_or_else(|| Error::format(format!("XGBoost objective {name} without {key}")))?;
+        parse_param(key, value.trim_matches(['[', ']']))
+    };
+    Ok(match name {
+        "reg:squarederror" | "reg:linear" => ObjectiveType::SquaredError,
+        "binary:logistic" => ObjectiveType::BinaryLogistic,
+        "multi:softprob" | "multi:softmax" => ObjectiveType::Multiclass { num_class },
+        "rank:ndcg" | "rank:pairwise" => ObjectiveType::LambdaRank,
+        "reg:quantileerror" => ObjectiveType::Quantile {
+            alpha: parameter("quantile_loss_param", "quantile_alpha")?,
+        },
+        "reg:pseudohubererror" => ObjectiveType::Huber {
+            delta: parameter("pseudo_huber_param", "huber_slope")?,
+        },
+        "count:poisson" => ObjectiveType::Poisson {
+            max_delta_step: parameter("poisson_regression_param", "max_delta_step")?,
+        },
+        "reg:gamma" => ObjectiveType::Gamma,
+        "reg:tweedie" => ObjectiveType::Tweedie {
+            variance_power: parameter("tweedie_regression_param", "tweedie_variance_power")?,
+        },
+        _ => {
+            return Err(Error::format(format!(
+                "unsupported XGBoost objective: {name}"
+            )));
+        }
+    })
+}
+
+fn convert_tree(tree: &XgbTree) -> Result<Tree> {
+    if tree
+        .tree_param
+        .size_leaf_vector
+        .as_deref()
+        .is_some_and(|size| size != "0" && size != "1")
+    {
+        return Err(Error::format("vector leaves aren't supported"));
+    }
+    let num_nodes = tree.left_children.len();
+    if num_nodes == 0
+        || [
+            tree.right_children.len(),
+            tree.split_indices.len(),
+            tree.split_conditions.len(),
+            tree.default_left.len(),
+        ]
+        .iter()
+        .any(|&len| len != num_nodes)
+    {
+        return Err(Error::format("node arrays of different lengths"));
+    }
+
+    // Nodes pruned by XGBoost stay in the arrays, so only those reachable from the root are
+    // converted. A preorder puts every node before its children, which lets the nodes be
+    // assembled from the last to the first.
+    let mut preorder = Vec::new();
+    let mut visited = vec![false; num_nodes];
+    let mut stack = vec![0];
+    while let Some(node) = stack.pop() {
+        if std::mem::replace(&mut visited[node], true) {
+            return Err(Error::format(format!("node {node} is reached twice")));
+        }
+        preorder.push(node);
+        if tree.left_children[node] != -1 {
+            for child in [tree.right_children[node], tree.left_children[node]] {
+                if child < 0 || child as usize >= num_nodes {
+                    return Err(Error::format(format!("node {node} has child {child}")));
+                }
+                stack.push(child as usize);
+            }
+        }
+    }
+
+    let mut nodes: Vec<Option<Box<TreeNode>>> = vec![None; num_nodes];
+    for &node in preorder.iter().rev() {
+        let converted = if tree.left_children[node] == -1 {
+            TreeNode::Leaf {
+                value: tree.split_conditions[node],
+            }
+        } else {
+            let left_child = nodes[tree.left_children[node] as usize].take().unwrap();
+            let right_child = nodes[tree.right_children[node] as usize].take().unwrap();
+            let feature_index = tree.split_indices[node];
+            if tree
+                .split_type
+                .get(node)
+                .is_some_and(|&split_type| split_type == 1)
+            {
+                TreeNode::CategoricalSplit {
+                    feature_index,
+                    categories: categories(tree, node)?,
+                    left_child: right_child,
+                    right_child: left_child,
+                }
+            } else {
+                TreeNode::Split {
+                    feature_index,
+                    threshold: tree.split_conditions[node],
+                    default_left: tree.default_left[node].is_set(),
+                    left_child,
+                    right_child,
+                }
+            }
+        };
+        nodes[node] = Some(Box::new(converted));
+    }
+    let mut converted = Tree::new(nodes[0].take().unwrap());
+
+    // our preorder and leaves from left to right, categorical splits with their children
+    // swapped
+    let (mut splits, mut leaves) = (Vec::new(), Vec::new());
+    let mut stack = vec![0];
+    while let Some(node) = stack.pop() {
+        let (left, right) = (tree.left_children[node], tree.right_children[node]);
+        if left == -1 {
+            leaves.push(node);
+            continue;
+        }
+        splits.push(node);
+        if tree.split_type.get(node) == Some(&1) {
+            stack.extend([left as usize, right as usize]);
+        } else {
+            stack.extend([right as usize, left as usize]);
+        }
+    }
+    if tree.sum_hessian.len() == num_nodes {
+        converted =
+            converted.with_leaf_covers(leaves.iter().map(|&leaf| tree.sum_hessian[leaf]).collect());
+    }
+    if tree.loss_changes.len() == num_nodes {
+        converted = converted.with_split_gains(
+            splits
+                .iter()
+                .map(|&split| tree.loss_changes[split])
+                .collect(),
+        );
+    }
+    Ok(converted)
+}
+
+// the ascending categories a categorical split sends right
+fn categories(tree: &XgbTree, node: usize) -> Result<Vec<u32>> {
+    let position = tree
+        .categories_nodes
+        .iter()
+        .position(|&categories_node| categories_node == node)
+        .ok_or_else(|| Error::format(format!("categorical split {node} without categories")))?;
+    let start = *tree
+        .categories_segments
+        .get(position)
+        .unwrap_or(&usize::MAX);
+    let size = *tree.categories_sizes.get(position).unwrap_or(&0);
+    let mut categories = tree
+        .categories
+        .get(start..start.saturating_add(size))
+        .ok_or_else(|| Error::format(format!("categorical split {node} past the categories")))?
+        .to_vec();
+    categories.sort_unstable();
+    categories.dedup();
+    Ok(categories)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::booster::Booster;
+
+    // A binary XGBoost model: tree 0 splits `age` with missing values going left, then
+    // `color` sending categories 1 and 3 right; node 5 was pruned. Tree 1 is a single leaf.
+    const XGBOOST_MODEL: &str = r#"{
+        "learner": {
+            "attributes": {},
+            "feature_names": ["age", "color"],
+            "feature_types": ["float", "c"],
+            "gradient_booster": {
+                "model": {
+                    "gbtree_model_param": {"num_parallel_tree": "1", "num_trees": "2"},
+                    "iteration_indptr": [0, 1, 2],
+                    "tree_info": [0, 0],
+                    "trees": [
+                        {
+                            "base_weights": [0, 0, 0, 0, 0, 0],
+                            "categories": [3, 1],
+                            "categories_nodes": [2],
+                            "categories_segments": [0],
+                            "categories_sizes": [2],
+                            "default_left": [1, 0, 0, 0, 0, 0],
+                            "id": 0,
+                            "left_children": [1, -1, 3, -1, -1, -1],
+                            "loss_changes": [1.5, 0, 0.5, 0, 0, 0],
+                            "parents": [2147483647, 0, 0, 2, 2, 1],
+                            "right_children": [2, -1, 4, -1, -1, -1],
+                            "split_conditions": [30.5, -0.5, 0, 0.25, 0.75, 9],
+                            "split_indices": [0, 0, 1, 0, 0, 0],
+                            "split_type": [0, 0, 1, 0, 0, 0],
+                            "sum_hessian": [6, 1, 5, 2, 3, 0],
+                            "tree_param": {"num_deleted": "1", "num_feature": "2", "num_nodes": "6", "size_leaf_vector": "1"}
+                        },
+                        {
+                            "base_weights": [0.125],
+                            "categories": [],
+                            "categories_nodes": [],
+                            "categories_segments": [],
+                            "categories_sizes": [],
+                            "default_left": [false],
+                            "id": 1,
+                            "left_children": [-1],
+                            "loss_changes": [0],
+                            "parents": [2147483647],
+                            "right_children": [-1],
+                            "split_conditions": [0.125],
+                            "split_indices": [0],
+                            "split_type": [0],
+                            "sum_hessian": [6],
+                            "tree_param": {"num_deleted": "0", "num_feature": "2", "num_nodes": "1", "size_leaf_vector": "1"}
+                        }
+                    ]
+                },
+                "name": "gbtree"
+            },
+            "learner_model_param": {"base_score": "8E-1", "boost_from_average": "1", "num_class": "0", "num_feature": "2", "num_target": "1"},
+            "objective": {"name": "binary:logistic", "reg_loss_param": {"scale_pos_weight": "1"}}
+        },
+        "version": [2, 0, 3]
+    }"#;
+
+    #[test]
+    fn test_reads_xgboost_model() {
+        let model = Model::from_xgboost_json(XGBOOST_MODEL).unwrap();
+        assert_eq!(model.objective(), Some(ObjectiveType::BinaryLogistic));
+        assert_eq!(model.params().categorical_features, vec![1]);
+        assert_eq!(model.params().num_iterations, 2);
+        // the categorical split's children are swapped, so its leaves' covers are too
+        assert_eq!(model.trees()[0].leaf_covers(), Some(&[1.0, 3.0, 2.0][..]));
+        assert_eq!(model.trees()[0].split_gains(), Some(&[1.5, 0.5][..]));
+        let booster = Booster::from_model(model);
+        assert_eq!(
+            booster.feature_names(),
+            Some(&["age".to_string(), "color".to_string()][..])
+        );
+        // a base score of 0.8 is a margin of ln(4)
+        let margin = 4.0f32.ln();
+        for (x, leaf) in [
+            (vec![30.0, 1.0], -0.5),
+            (vec![f32::NAN, 1.0], -0.5),
+            (vec![30.5, 1.0], 0.75),
+            (vec![31.0, 3.0], 0.75),
+            (vec![31.0, 2.0], 0.25),
+            (vec![31.0, f32::NAN], 0.25),
+        ] {
+            let expected = leaf + 0.125 + margin;
+            assert!(
+                (booster.predict_raw(&x)[0] - expected).abs() < 1e-6,
+                "{x:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_groups_trees_by_output() {
+        // two rounds of a three class dart model with its trees listed output by output
+        let leaf = |value: f32| {
+            format!(
+                r#"{{"default_left": [0], "left_children": [-1], "right_children": [-1],
+                    "split_conditions": [{value}], "split_indices": [0], "tree_param": {{}}}}"#
+            )
+        };
+        let trees: Vec<String> = [1.0, 2.0, 10.0, 20.0, 100.0, 200.0]
+            .into_iter()
+            .map(leaf)
+            .collect();
+        let json = format!(
+            r#"{{"learner": {{
+                "gradient_booster": {{
+                    "name": "dart",
+                    "gbtree": {{"name": "gbtree", "model": {{"trees": [{}], "tree_info": [0, 0, 1, 1, 2, 2]}}}},
+                    "weight_drop": [1, 0.5, 1, 0.5, 1, 0.5]
+                }},
+                "learner_model_param": {{"base_score": "5E-1", "num_class": "3"}},
+                "objective": {{"name": "multi:softprob"}}
+            }}}}"#,
+            trees.join(", ")
+        );
+        let model = Model::from_xgboost_json(&json).unwrap();
+        assert_eq!(model.trees().len(), 6);
+        assert_eq!(model.feature_names(), None);
+        let booster = Booster::from_model(model);
+        assert_eq!(booster.predict_raw(&[0.0]), vec![2.5, 20.5, 200.5]);
+    }
+
+    #[test]
+    fn test_rejects_what_isnt_supported() {
+        let linear = XGBOOST_MODEL.replace(r#""name": "gbtree""#, r#""name": "gblinear""#);
+        let objective = XGBOOST_MODEL.replace("binary:logistic", "survival:cox");
+        let cycle = XGBOOST_MODEL.replace(
+            r#""left_children": [1, -1, 3, -1, -1, -1]"#,
+            r#""left_children": [1, -1, 0, -1, -1, -1]"#,
+        );
+        for json in [&linear, &objective, &cycle, "{}"] {
+            assert!(matches!(
+                Model::from_xgboost_json(json),
+                Err(Error::Format { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use crate::error::{Error, Result};
+use crate::tree::{Tree, TreeNode};
+
+// Builds a tree by hand, e.g. for tests or rule-based overrides:
+//
+//     let tree = NodeBuilder::split(0, 2.5)
+//         .left(leaf(1.0))
+//         .right(NodeBuilder::categorical_split(1, vec![3, 9]).left(leaf(-1.0)).right(leaf(2.0)))
+//         .build()?;
+//
+// `build` checks what `TreeNode` can't: that every split has both children, that
+// thresholds and leaf values are finite and, with `build_for_features`, that the features
+// exist. Categories may be given in any order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeBuilder {
+    kind: Kind,
+    left: Option<Box<NodeBuilder>>,
+    right: Option<Box<NodeBuilder>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Leaf {
+        value: f32,
+    },
+    Split {
+        feature_index: usize,
+        threshold: f32,
+        default_left: bool,
+    },
+    CategoricalSplit {
+        feature_index: usize,
+        categories: Vec<u32>,
+    },
+}
+
+// shorthand for `NodeBuilder::leaf`
+pub fn leaf(value: f32) -> NodeBuilder {
+    NodeBuilder::leaf(value)
+}
+
+impl NodeBuilder {
+    pub fn leaf(value: f32) -> Self {
+        Self::new(Kind::Leaf { value })
+    }
+
+    // sends values below `threshold` left, and missing values right unless `default_left`
+    pub fn split(feature_index: usize, threshold: f32) -> Self {
+        Self::new(Kind::Split {
+            feature_index,
+            threshold,
+            default_left: false,
+        })
+    }
+
+    // sends values that are one of `categories` left, everything else right
+    pub fn categorical_split(feature_index: usize, categories: Vec<u32>) -> Self {
+        Self::new(Kind::CategoricalSplit {
+            feature_index,
+            categories,
+        })
+    }
+
+    // where a threshold split sends missing values; other nodes have no say in it
+    pub fn default_left(mut self, default_left: bool) -> Self {
+        if let Kind::Split {
+            default_left: node_default_left,
+            ..
+        } = &mut self.kind
+        {
+            *node_default_left = default_left;
+        }
+        self
+    }
+
+    pub fn left(mut self, child: NodeBuilder) -> Self {
+        self.left = Some(Box::new(child));
+        self
+    }
+
+    pub fn right(mut self, child: NodeBuilder) -> Self {
+        self.right = Some(Box::new(child));
+        self
+    }
+
+    pub fn build(self) -> Result<Tree> {
+        Ok(Tree::new(Box::new(self.into_node(None)?)))
+    }
+
+    // `build`, also checking that every split is on one of `num_features` features
+    pub fn build_for_features(self, num_features: usize) -> Result<Tree> {
+        Ok(Tree::new(Box::new(self.into_node(Some(num_features))?)))
+    }
+
+    // the checked node, see `build`
+    pub fn into_node(self, num_features: Option<usize>) -> Result<TreeNode> {
+        let feature_index = match &self.kind {
+            Kind::Leaf { value } => {
+                if !value.is_finite() {
+                    return Err(Error::invalid_parameter(
+                        "value",
+                        format!("leaf values must be finite, not {value}"),
+                    ));
+                }
+                if self.left.is_some() || self.right.is_some() {
+                    return Err(Error::invalid_parameter(
+                        "leaf",
+                        "a leaf can't have children",
+                    ));
+                }
+                return Ok(TreeNode::Leaf { value: *value });
+            }
+            Kind::Split {
+                feature_index,
+                threshold,
+                ..
+            } => {
+                if !threshold.is_finite() {
+                    return Err(Error::invalid_parameter(
+                        "threshold",
+                        format!("split thresholds must be finite, not {threshold}"),
+                    ));
+                }
+                *feature_index
+            }
+            Kind::CategoricalSplit { feature_index, .. } => *feature_index,
+        };
+        if let Some(num_features) = num_features
+            && feature_index >= num_features
+        {
+            return Err(Error::invalid_parameter(
+                "feature_index",
+                format!("split on feature {feature_index} of {num_features} features"),
+            ));
+        }
+        let (Some(left), Some(right)) = (self.left, self.right) else {
+            return Err(Error::invalid_parameter(
+                "split",
+                format!("the split on feature {feature_index} needs a left and a right child"),
+            ));
+        };
+        let left_child = Box::new(left.into_node(num_features)?);
+        let right_child = Box::new(right.into_node(num_features)?);
+        Ok(match self.kind {
+            Kind::Split {
+                feature_index,
+                threshold,
+                default_left,
+            } => TreeNode::Split {
+                feature_index,
+                threshold,
+                default_left,
+                left_child,
+                right_child,
+            },
+            Kind::CategoricalSplit {
+                feature_index,
+                mut categories,
+            } => {
+                categories.sort_unstable();
+                categories.dedup();
+                TreeNode::CategoricalSplit {
+                    feature_index,
+                    categories,
+                    left_child,
+                    right_child,
+                }
+            }
+            Kind::Leaf { .. } => unreachable!(),
+        })
+    }
+
+    fn new(kind: Kind) -> Self {
+        NodeBuilder {
+            kind,
+            left: None,
+            right: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builds_the_tree_it_describes() {
+        let tree = NodeBuilder::split(0, 2.5)
+            .default_left(true)
+            .left(leaf(1.0))
+            .right(
+                NodeBuilder::categorical_split(1, vec![9, 3, 9])
+                    .left(leaf(-1.0))
+                    .right(leaf(2.0)),
+            )
+            .build_for_features(2)
+            .unwrap();
+
+        let expected = TreeNode::Split {
+            feature_index: 0,
+            threshold: 2.5,
+            default_left: true,
+            left_child: Box::new(TreeNode::Leaf { value: 1.0 }),
+            right_child: Box::new(TreeNode::CategoricalSplit {
+                feature_index: 1,
+                categories: vec![3, 9],
+                left_child: Box::new(TreeNode::Leaf { value: -1.0 }),
+                right_child: Box::new(TreeNode::Leaf { value: 2.0 }),
+            }),
+        };
+        assert_eq!(tree.root(), &expected);
+        assert_eq!(tree.predict(&[f32::NAN, 3.0]), 1.0);
+        assert_eq!(tree.predict(&[3.0, 3.0]), -1.0);
+    }
+
+    #[test]
+    fn test_rejects_malformed_trees() {
+        let stump = |feature_index, threshold| {
+            NodeBuilder::split(feature_index, threshold)
+                .left(leaf(0.0))
+                .right(leaf(1.0))
+        };
+        assert!(stump(0, 1.0).build().is_ok());
+        assert!(stump(0, f32::NAN).build().is_err());
+        assert!(stump(2, 1.0).build_for_features(2).is_err());
+        assert!(stump(2, 1.0).build().is_ok());
+        assert!(NodeBuilder::split(0, 1.0).left(leaf(0.0)).build().is_err());
+        assert!(stump(0, 1.0).left(leaf(f32::INFINITY)).build().is_err());
+        assert!(leaf(0.0).right(leaf(1.0)).build().is_err());
+    }
+}
@@ -0,0 +1,902 @@
+use serde::{Deserialize, Serialize};
+
+use crate::dataset::Dataset;
+use crate::ranking;
+
+// Objectives are `Send + Sync` so a trained `Booster` can be shared across threads.
+pub trait Objective: Send + Sync {
+    // first derivative of the loss with respect to the raw prediction
+    fn gradient(&self, pred: f32, label: f32) -> f32;
+
+    // second derivative of the loss with respect to the raw prediction
+    fn hessian(&self, pred: f32, label: f32) -> f32;
+
+    // maps a raw score to the output space of the objective (e.g. a probability)
+    fn transform(&self, raw: f32) -> f32 {
+        raw
+    }
+
+    // called once before training with the data the objective will see
+    fn init(&mut self, _dataset: &Dataset) {}
+
+    // number of raw scores (and trees per boosting round) the objective works with
+    fn num_outputs(&self) -> usize {
+        1
+    }
+
+    // Constant raw score per output that minimizes the loss over the labels, which boosting
+    // starts from instead of zero.
+    fn base_scores(&self, _labels: &[f32], _weights: Option<&[f32]>) -> Vec<f32> {
+        vec![0.0; self.num_outputs()]
+    }
+
+    // `raw` holds all `num_outputs` scores of one row
+    fn transform_scores(&self, raw: &[f32]) -> Vec<f32> {
+        raw.iter().map(|&score| self.transform(score)).collect()
+    }
+
+    // Objectives with a non-smooth loss can't rely on the Newton step -G/H for leaf values.
+    // Returning Some replaces the value of a leaf holding `rows` (before shrinkage).
+    // `weights` are the sample weights of the training data, if any.
+    fn renew_leaf_value(
+        &self,
+        _rows: &[usize],
+        _preds: &[f32],
+        _labels: &[f32],
+        _weights: Option<&[f32]>,
+    ) -> Option<f32> {
+        None
+    }
+
+    // With more than one output, `preds`, `gradients` and `hessians` are laid out output by
+    // output, i.e. the value for row i and output k is at index k * labels.len() + i.
+    fn compute_gradients(
+        &self,
+        preds: &[f32],
+        labels: &[f32],
+        gradients: &mut [f32],
+        hessians: &mut [f32],
+    ) {
+        for i in 0..preds.len() {
+            gradients[i] = self.gradient(preds[i], labels[i]);
+            hessians[i] = self.hessian(preds[i], labels[i]);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ObjectiveType {
+    #[default]
+    SquaredError,
+    BinaryLogistic,
+    // labels are class indices 0..num_class
+    Multiclass {
+        num_class: usize,
+    },
+    // needs query groups on the dataset; labels are graded relevances (0, 1, 2, ...)
+    LambdaRank,
+    // pinball loss for the alpha-quantile, alpha in (0, 1)
+    Quantile {
+        alpha: f32,
+    },
+    // quadratic within delta of the label, linear beyond it
+    Huber {
+        delta: f32,
+    },
+    // c^2 * (|x| / c - ln(1 + |x| / c)) for residual x
+    Fair {
+        c: f32,
+    },
+    // The following use a log link: raw scores are log(mean) and transform applies exp.
+    // Non-negative counts; max_delta_step inflates the hessian to keep early leaf values
+    // from exploding (LightGBM uses 0.7).
+    Poisson {
+        max_delta_step: f32,
+    },
+    // positive continuous targets
+    Gamma,
+    // non-negative targets with a point mass at zero, variance_power in (1, 2)
+    Tweedie {
+        variance_power: f32,
+    },
+}
+
+impl ObjectiveType {
+    pub fn create(&self) -> Box<dyn Objective> {
+        match self {
+            ObjectiveType::SquaredError => Box::new(SquaredError),
+            ObjectiveType::BinaryLogistic => Box::new(BinaryLogistic),
+            ObjectiveType::Multiclass { num_class } => Box::new(Softmax::new(*num_class)),
+            ObjectiveType::LambdaRank => Box::new(LambdaRank::new()),
+            ObjectiveType::Quantile { alpha } => Box::new(Quantile::new(*alpha)),
+            ObjectiveType::Huber { delta } => Box::new(Huber::new(*delta)),
+            ObjectiveType::Fair { c } => Box::new(Fair::new(*c)),
+            ObjectiveType::Poisson { max_delta_step } => Box::new(Poisson::new(*max_delta_step)),
+            ObjectiveType::Gamma => Box::new(Gamma),
+            ObjectiveType::Tweedie { variance_power } => Box::new(Tweedie::new(*variance_power)),
+        }
+    }
+
+    // number of classes of a classification objective, None for the others
+    pub fn num_classes(&self) -> Option<usize> {
+        match self {
+            ObjectiveType::BinaryLogistic => Some(2),
+            ObjectiveType::Multiclass { num_class } => Some(*num_class),
+            _ => None,
+        }
+    }
+
+    // class of a label under a classification objective: labels > 0.5 are positives for
+    // binary logistic, multiclass labels are class indices
+    pub fn class_of(&self, label: f32) -> usize {
+        match self {
+            ObjectiveType::BinaryLogistic => usize::from(label > 0.5),
+            _ => label as usize,
+        }
+    }
+}
+
+// loss = 0.5 * (pred - label)^2
+pub struct SquaredError;
+
+impl Objective for SquaredError {
+    fn gradient(&self, pred: f32, label: f32) -> f32 {
+        pred - label
+    }
+
+    fn hessian(&self, _pred: f32, _label: f32) -> f32 {
+        1.0
+    }
+
+    fn base_scores(&self, labels: &[f32], weights: Option<&[f32]>) -> Vec<f32> {
+        vec![weighted_mean(labels, weights)]
+    }
+}
+
+// Squared error for residuals up to delta and absolute error beyond, so outliers contribute
+// a bounded gradient. Like LightGBM, the hessian is kept at 1 in the linear region.
+pub struct Huber {
+    delta: f32,
+}
+
+impl Huber {
+    pub fn new(delta: f32) -> Self {
+        Self { delta }
+    }
+}
+
+impl Objective for Huber {
+    fn gradient(&self, pred: f32, label: f32) -> f32 {
+        let residual = pred - label;
+        if residual.abs() <= self.delta {
+            residual
+        } else {
+            self.delta * residual.signum()
+        }
+    }
+
+    fn hessian(&self, _pred: f32, _label: f32) -> f32 {
+        1.0
+    }
+
+    // the median, which the outliers Huber is meant for can't drag away
+    fn base_scores(&self, labels: &[f32], weights: Option<&[f32]>) -> Vec<f32> {
+        vec![label_percentile(labels, weights, 0.5)]
+    }
+}
+
+// Smooth alternative to Huber: the gradient approaches c for large residuals and the hessian
+// decays with them.
+pub struct Fair {
+    c: f32,
+}
+
+impl Fair {
+    pub fn new(c: f32) -> Self {
+        Self { c }
+    }
+}
+
+impl Objective for Fair {
+    fn gradient(&self, pred: f32, label: f32) -> f32 {
+        let residual = pred - label;
+        self.c * residual / (residual.abs() + self.c)
+    }
+
+    fn hessian(&self, pred: f32, label: f32) -> f32 {
+        let denominator = (pred - label).abs() + self.c;
+        self.c * self.c / (denominator * denominator)
+    }
+
+    fn base_scores(&self, labels: &[f32], weights: Option<&[f32]>) -> Vec<f32> {
+        vec![label_percentile(labels, weights, 0.5)]
+    }
+}
+
+pub struct Poisson {
+    max_delta_step: f32,
+}
+
+impl Poisson {
+    pub fn new(max_delta_step: f32) -> Self {
+        Self { max_delta_step }
+    }
+}
+
+impl Objective for Poisson {
+    fn gradient(&self, pred: f32, label: f32) -> f32 {
+        pred.exp() - label
+    }
+
+    // The exact hessian exp(pred) is tiny for rows with small predicted counts, which makes
+    // -G/H explode on the first iterations. Scaling it by exp(max_delta_step) bounds the step.
+    fn hessian(&self, pred: f32, _label: f32) -> f32 {
+        (pred + self.max_delta_step).exp()
+    }
+
+    fn transform(&self, raw: f32) -> f32 {
+        raw.exp()
+    }
+
+    fn base_scores(&self, labels: &[f32], weights: Option<&[f32]>) -> Vec<f32> {
+        vec![log_mean(labels, weights)]
+    }
+}
+
+// negative log-likelihood of a gamma distribution with log link (up to constants)
+pub struct Gamma;
+
+impl Objective for Gamma {
+    fn gradient(&self, pred: f32, label: f32) -> f32 {
+        1.0 - label * (-pred).exp()
+    }
+
+    fn hessian(&self, pred: f32, label: f32) -> f32 {
+        label * (-pred).exp()
+    }
+
+    fn transform(&self, raw: f32) -> f32 {
+        raw.exp()
+    }
+
+    fn base_scores(&self, labels: &[f32], weights: Option<&[f32]>) -> Vec<f32> {
+        vec![log_mean(labels, weights)]
+    }
+}
+
+// Tweedie deviance with log link for variance power rho:
+// loss = -label * exp((1 - rho) * pred) / (1 - rho) + exp((2 - rho) * pred) / (2 - rho)
+pub struct Tweedie {
+    variance_power: f32,
+}
+
+impl Tweedie {
+    pub fn new(variance_power: f32) -> Self {
+        Self { variance_power }
+    }
+}
+
+impl Objective for Tweedie {
+    fn gradient(&self, pred: f32, label: f32) -> f32 {
+        let rho = self.variance_power;
+        -label * ((1.0 - rho) * pred).exp() + ((2.0 - rho) * pred).exp()
+    }
+
+    fn hessian(&self, pred: f32, label: f32) -> f32 {
+        let rho = self.variance_power;
+        -label * (1.0 - rho) * ((1.0 - rho) * pred).exp() + (2.0 - rho) * ((2.0 - rho) * pred).exp()
+    }
+
+    fn transform(&self, raw: f32) -> f32 {
+        raw.exp()
+    }
+
+    fn base_scores(&self, labels: &[f32], weights: Option<&[f32]>) -> Vec<f32> {
+        vec![log_mean(labels, weights)]
+    }
+}
+
+// Pinball loss: alpha * (label - pred) above the prediction, (1 - alpha) * (pred - label)
+// below it. Its hessian is zero almost everywhere, so trees are grown on a unit hessian and
+// leaf values are then set to the alpha-percentile of the residuals in each leaf.
+pub struct Quantile {
+    alpha: f32,
+}
+
+impl Quantile {
+    pub fn new(alpha: f32) -> Self {
+        Self { alpha }
+    }
+}
+
+impl Objective for Quantile {
+    fn gradient(&self, pred: f32, label: f32) -> f32 {
+        if label >= pred {
+            -self.alpha
+        } else {
+            1.0 - self.alpha
+        }
+    }
+
+    fn hessian(&self, _pred: f32, _label: f32) -> f32 {
+        1.0
+    }
+
+    fn renew_leaf_value(
+        &self,
+        rows: &[usize],
+        preds: &[f32],
+        labels: &[f32],
+        weights: Option<&[f32]>,
+    ) -> Option<f32> {
+        let residuals: Vec<f32> = rows.iter().map(|&i| labels[i] - preds[i]).collect();
+        match weights {
+            Some(weights) => {
+                let row_weights: Vec<f32> = rows.iter().map(|&i| weights[i]).collect();
+                weighted_percentile(&residuals, &row_weights, self.alpha)
+            }
+            None => percentile(&residuals, self.alpha),
+        }
+    }
+
+    fn base_scores(&self, labels: &[f32], weights: Option<&[f32]>) -> Vec<f32> {
+        vec![label_percentile(labels, weights, self.alpha)]
+    }
+}
+
+// Linearly interpolated percentile, alpha in [0, 1]. None for empty input.
+pub fn percentile(values: &[f32], alpha: f32) -> Option<f32> {
+    if values.is_empty() {
+        return None;
+    }
+
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+
+    let position = alpha.clamp(0.0, 1.0) * (sorted.len() - 1) as f32;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    let fraction = position - lower as f32;
+    Some(sorted[lower] + fraction * (sorted[upper] - sorted[lower]))
+}
+
+// The smallest value whose cumulative weight reaches alpha of the total weight. None if
+// there is no weight at all.
+pub fn weighted_percentile(values: &[f32], weights: &[f32], alpha: f32) -> Option<f32> {
+    let total: f32 = weights.iter().sum();
+    if total <= 0.0 {
+        return None;
+    }
+
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+
+    let target = alpha.clamp(0.0, 1.0) * total;
+    let mut cumulative = 0.0;
+    for &i in &order {
+        cumulative += weights[i];
+        if weights[i] > 0.0 && cumulative >= target {
+            return Some(values[i]);
+        }
+    }
+    order
+        .iter()
+        .rev()
+        .find(|&&i| weights[i] > 0.0)
+        .map(|&i| values[i])
+}
+
+fn weighted_mean(labels: &[f32], weights: Option<&[f32]>) -> f32 {
+    let (sum, total) = match weights {
+        Some(weights) => labels
+            .iter()
+            .zip(weights)
+            .fold((0.0, 0.0), |(sum, total), (y, w)| (sum + w * y, total + w)),
+        None => (labels.iter().sum(), labels.len() as f32),
+    };
+    if total > 0.0 { sum / total } else { 0.0 }
+}
+
+// the score of a log link objective whose mean is the mean label
+fn log_mean(labels: &[f32], weights: Option<&[f32]>) -> f32 {
+    weighted_mean(labels, weights).max(1e-6).ln()
+}
+
+fn label_percentile(labels: &[f32], weights: Option<&[f32]>, alpha: f32) -> f32 {
+    match weights {
+        Some(weights) => weighted_percentile(labels, weights, alpha),
+        None => percentile(labels, alpha),
+    }
+    .unwrap_or(0.0)
+}
+
+// log loss on labels in {0, 1}, with raw predictions in log-odds space
+pub struct BinaryLogistic;
+
+impl Objective for BinaryLogistic {
+    fn gradient(&self, pred: f32, label: f32) -> f32 {
+        sigmoid(pred) - label
+    }
+
+    fn hessian(&self, pred: f32, _label: f32) -> f32 {
+        let p = sigmoid(pred);
+        // keep leaves finite once predictions saturate
+        (p * (1.0 - p)).max(1e-16)
+    }
+
+    fn transform(&self, raw: f32) -> f32 {
+        sigmoid(raw)
+    }
+
+    // log-odds of the share of positives
+    fn base_scores(&self, labels: &[f32], weights: Option<&[f32]>) -> Vec<f32> {
+        let p = weighted_mean(labels, weights).clamp(1e-6, 1.0 - 1e-6);
+        vec![(p / (1.0 - p)).ln()]
+    }
+}
+
+// Cross-entropy over softmax probabilities. The per-element derivatives are expressed in
+// terms of the class probability and the one-hot target, since the softmax needs all scores
+// of a row; `compute_gradients` takes care of that.
+pub struct Softmax {
+    num_class: usize,
+}
+
+impl Softmax {
+    pub fn new(num_class: usize) -> Self {
+        Self { num_class }
+    }
+}
+
+impl Objective for Softmax {
+    fn gradient(&self, prob: f32, target: f32) -> f32 {
+        prob - target
+    }
+
+    fn hessian(&self, prob: f32, _target: f32) -> f32 {
+        (2.0 * prob * (1.0 - prob)).max(1e-16)
+    }
+
+    fn num_outputs(&self) -> usize {
+        self.num_class
+    }
+
+    fn transform_scores(&self, raw: &[f32]) -> Vec<f32> {
+        softmax(raw)
+    }
+
+    fn compute_gradients(
+        &self,
+        preds: &[f32],
+        labels: &[f32],
+        gradients: &mut [f32],
+        hessians: &mut [f32],
+    ) {
+        let n_rows = labels.len();
+        let mut scores = vec![0.0; self.num_class];
+
+        for (i, &label) in labels.iter().enumerate() {
+            for (k, score) in scores.iter_mut().enumerate() {
+                *score = preds[k * n_rows + i];
+            }
+            let probs = softmax(&scores);
+
+            for (k, &prob) in probs.iter().enumerate() {
+                let target = if label as usize == k { 1.0 } else { 0.0 };
+                gradients[k * n_rows + i] = self.gradient(prob, target);
+                hessians[k * n_rows + i] = self.hessian(prob, target);
+            }
+        }
+    }
+}
+
+// LambdaRank/LambdaMART: pairwise logistic loss on the scores of documents within a query,
+// with every pair weighted by how much swapping the two documents changes the query's NDCG.
+pub struct LambdaRank {
+    sigma: f32,
+    query_boundaries: Vec<usize>,
+    inverse_max_dcgs: Vec<f32>,
+}
+
+impl Default for LambdaRank {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LambdaRank {
+    pub fn new() -> Self {
+        Self {
+            sigma: 1.0,
+            query_boundaries: vec![],
+            inverse_max_dcgs: vec![],
+        }
+    }
+
+    fn query_gradients(
+        &self,
+        scores: &[f32],
+        labels: &[f32],
+        inverse_max_dcg: f32,
+        gradients: &mut [f32],
+        hessians: &mut [f32],
+    ) {
+        gradients.fill(0.0);
+        hessians.fill(0.0);
+        if inverse_max_dcg <= 0.0 {
+            return;
+        }
+
+        let order = ranking::rank_by_score(scores);
+        let mut ranks = vec![0; order.len()];
+        for (rank, &i) in order.iter().enumerate() {
+            ranks[i] = rank;
+        }
+
+        for high in 0..scores.len() {
+            for low in 0..scores.len() {
+                if labels[high] <= labels[low] {
+                    continue;
+                }
+
+                let delta_ndcg = (ranking::label_gain(labels[high])
+                    - ranking::label_gain(labels[low]))
+                    * (ranking::discount(ranks[high]) - ranking::discount(ranks[low])).abs()
+                    * inverse_max_dcg;
+                let p = 1.0 / (1.0 + (self.sigma * (scores[high] - scores[low])).exp());
+
+                let lambda = -self.sigma * p * delta_ndcg;
+                let hessian = self.sigma * self.sigma * p * (1.0 - p) * delta_ndcg;
+                gradients[high] += lambda;
+                gradients[low] -= lambda;
+                hessians[high] += hessian;
+                hessians[low] += hessian;
+            }
+        }
+    }
+}
+
+impl Objective for LambdaRank {
+    // LambdaRank has no per-row loss; gradients only exist for whole queries, see
+    // `compute_gradients`.
+    fn gradient(&self, _pred: f32, _label: f32) -> f32 {
+        0.0
+    }
+
+    fn hessian(&self, _pred: f32, _label: f32) -> f32 {
+        0.0
+    }
+
+    fn init(&mut self, dataset: &Dataset) {
+        let boundaries = dataset
+            .query_boundaries()
+            .expect("LambdaRank requires query groups on the dataset");
+
+        self.query_boundaries = boundaries.to_vec();
+        self.inverse_max_dcgs = boundaries
+            .windows(2)
+            .map(|query| {
+                let max_dcg =
+                    ranking::max_dcg_at(&dataset.labels()[query[0]..query[1]], usize::MAX);
+                if max_dcg > 0.0 { 1.0 / max_dcg } else { 0.0 }
+            })
+            .collect();
+    }
+
+    fn compute_gradients(
+        &self,
+        preds: &[f32],
+        labels: &[f32],
+        gradients: &mut [f32],
+        hessians: &mut [f32],
+    ) {
+        for (query, &inverse_max_dcg) in
+            self.query_boundaries.windows(2).zip(&self.inverse_max_dcgs)
+        {
+            let rows = query[0]..query[1];
+            self.query_gradients(
+                &preds[rows.clone()],
+                &labels[rows.clone()],
+                inverse_max_dcg,
+                &mut gradients[rows.clone()],
+                &mut hessians[rows],
+            );
+        }
+    }
+}
+
+// Wraps a closure computing `(gradients, hessians)` for all rows at once from the current
+// raw predictions and the labels, analogous to `fobj` in LightGBM's Python API. Raw scores
+// are returned untransformed.
+pub struct CustomObjective<F> {
+    function: F,
+}
+
+impl<F> CustomObjective<F>
+where
+    F: Fn(&[f32], &[f32]) -> (Vec<f32>, Vec<f32>) + Send + Sync,
+{
+    pub fn new(function: F) -> Self {
+        Self { function }
+    }
+}
+
+impl<F> Objective for CustomObjective<F>
+where
+    F: Fn(&[f32], &[f32]) -> (Vec<f32>, Vec<f32>) + Send + Sync,
+{
+    fn gradient(&self, pred: f32, label: f32) -> f32 {
+        (self.function)(&[pred], &[label]).0[0]
+    }
+
+    fn hessian(&self, pred: f32, label: f32) -> f32 {
+        (self.function)(&[pred], &[label]).1[0]
+    }
+
+    fn compute_gradients(
+        &self,
+        preds: &[f32],
+        labels: &[f32],
+        gradients: &mut [f32],
+        hessians: &mut [f32],
+    ) {
+        let (custom_gradients, custom_hessians) = (self.function)(preds, labels);
+        assert_eq!(
+            custom_gradients.len(),
+            preds.len(),
+            "custom objective returned the wrong number of gradients"
+        );
+        assert_eq!(
+            custom_hessians.len(),
+            preds.len(),
+            "custom objective returned the wrong number of hessians"
+        );
+
+        gradients.copy_from_slice(&custom_gradients);
+        hessians.copy_from_slice(&custom_hessians);
+    }
+}
+
+pub fn softmax(scores: &[f32]) -> Vec<f32> {
+    let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let exps: Vec<f32> = scores.iter().map(|&s| (s - max).exp()).collect();
+    let sum: f32 = exps.iter().sum();
+    exps.iter().map(|&e| e / sum).collect()
+}
+
+pub fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_squared_error_derivatives() {
+        let objective = SquaredError;
+
+        assert_abs_diff_eq!(objective.gradient(3.0, 1.0), 2.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(objective.gradient(-1.0, 1.0), -2.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(objective.hessian(3.0, 1.0), 1.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(objective.transform(3.0), 3.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_compute_gradients_fills_buffers() {
+        let objective = ObjectiveType::SquaredError.create();
+        let preds = vec![0.0, 1.0, 2.0];
+        let labels = vec![1.0, 1.0, 1.0];
+        let mut gradients = vec![0.0; 3];
+        let mut hessians = vec![0.0; 3];
+
+        objective.compute_gradients(&preds, &labels, &mut gradients, &mut hessians);
+
+        assert_eq!(gradients, [-1.0, 0.0, 1.0]);
+        assert_eq!(hessians, [1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_binary_logistic_derivatives() {
+        let objective = BinaryLogistic;
+
+        assert_abs_diff_eq!(objective.gradient(0.0, 1.0), -0.5, epsilon = 1e-6);
+        assert_abs_diff_eq!(objective.gradient(0.0, 0.0), 0.5, epsilon = 1e-6);
+        assert_abs_diff_eq!(objective.hessian(0.0, 1.0), 0.25, epsilon = 1e-6);
+        assert_abs_diff_eq!(objective.transform(0.0), 0.5, epsilon = 1e-6);
+
+        let p = sigmoid(2.0);
+        assert_abs_diff_eq!(objective.gradient(2.0, 1.0), p - 1.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(objective.hessian(2.0, 0.0), p * (1.0 - p), epsilon = 1e-6);
+        assert!(objective.hessian(100.0, 1.0) > 0.0);
+    }
+
+    #[test]
+    fn test_softmax_gradients() {
+        let objective = ObjectiveType::Multiclass { num_class: 3 }.create();
+        assert_eq!(objective.num_outputs(), 3);
+
+        // two rows, scores laid out class by class
+        let preds = vec![0.0, 1.0, 0.0, 1.0, 0.0, 1.0];
+        let labels = vec![0.0, 2.0];
+        let mut gradients = vec![0.0; 6];
+        let mut hessians = vec![0.0; 6];
+        objective.compute_gradients(&preds, &labels, &mut gradients, &mut hessians);
+
+        let third = 1.0 / 3.0;
+        assert_abs_diff_eq!(gradients[0], third - 1.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(gradients[2], third, epsilon = 1e-6);
+        assert_abs_diff_eq!(gradients[4], third, epsilon = 1e-6);
+        assert_abs_diff_eq!(gradients[5], third - 1.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(hessians[0], 2.0 * third * (1.0 - third), epsilon = 1e-6);
+
+        let probs = objective.transform_scores(&[1.0, 2.0, 3.0]);
+        assert_abs_diff_eq!(probs.iter().sum::<f32>(), 1.0, epsilon = 1e-6);
+        assert!(probs[2] > probs[1] && probs[1] > probs[0]);
+    }
+
+    #[test]
+    fn test_lambdarank_pushes_relevant_documents_up() {
+        let dataset =
+            Dataset::new(vec![vec![0.0]; 5], vec![0.0, 2.0, 1.0, 1.0, 0.0]).with_groups(&[3, 2]);
+        let mut objective = LambdaRank::new();
+        objective.init(&dataset);
+
+        let preds = vec![0.0; 5];
+        let mut gradients = vec![0.0; 5];
+        let mut hessians = vec![0.0; 5];
+        objective.compute_gradients(&preds, dataset.labels(), &mut gradients, &mut hessians);
+
+        // negative gradients increase a document's score
+        assert!(gradients[1] < gradients[2] && gradients[2] < 0.0);
+        assert!(gradients[0] > 0.0);
+        assert!(gradients[3] < 0.0 && gradients[4] > 0.0);
+        assert_abs_diff_eq!(gradients.iter().sum::<f32>(), 0.0, epsilon = 1e-6);
+        assert!(hessians.iter().all(|&h| h > 0.0));
+    }
+
+    #[test]
+    fn test_quantile_gradients_and_leaf_renewal() {
+        let objective = Quantile::new(0.9);
+
+        assert_abs_diff_eq!(objective.gradient(0.0, 1.0), -0.9, epsilon = 1e-6);
+        assert_abs_diff_eq!(objective.gradient(2.0, 1.0), 0.1, epsilon = 1e-6);
+
+        let labels: Vec<f32> = (0..11).map(|i| i as f32).collect();
+        let preds = vec![0.0; 11];
+        let rows: Vec<usize> = (0..11).collect();
+        let value = objective
+            .renew_leaf_value(&rows, &preds, &labels, None)
+            .unwrap();
+        assert_abs_diff_eq!(value, 9.0, epsilon = 1e-6);
+        assert!(
+            objective
+                .renew_leaf_value(&[], &preds, &labels, None)
+                .is_none()
+        );
+
+        // all the weight on the smallest label
+        let mut weights = vec![0.0; 11];
+        weights[0] = 1.0;
+        let value = objective
+            .renew_leaf_value(&rows, &preds, &labels, Some(&weights))
+            .unwrap();
+        assert_eq!(value, 0.0);
+    }
+
+    #[test]
+    fn test_percentile_interpolates() {
+        assert_eq!(percentile(&[3.0, 1.0, 2.0], 0.5), Some(2.0));
+        assert_eq!(percentile(&[1.0, 2.0], 0.25), Some(1.25));
+        assert_eq!(percentile(&[5.0], 0.9), Some(5.0));
+    }
+
+    #[test]
+    fn test_weighted_percentile() {
+        assert_eq!(
+            weighted_percentile(&[3.0, 1.0, 2.0], &[1.0, 1.0, 1.0], 0.5),
+            Some(2.0)
+        );
+        assert_eq!(
+            weighted_percentile(&[3.0, 1.0, 2.0], &[4.0, 1.0, 1.0], 0.5),
+            Some(3.0)
+        );
+        assert_eq!(
+            weighted_percentile(&[3.0, 1.0, 2.0], &[0.0, 1.0, 1.0], 1.0),
+            Some(2.0)
+        );
+        assert_eq!(weighted_percentile(&[1.0], &[0.0], 0.5), None);
+    }
+
+    #[test]
+    fn test_huber_bounds_gradient() {
+        let objective = Huber::new(1.0);
+
+        assert_abs_diff_eq!(objective.gradient(0.5, 0.0), 0.5, epsilon = 1e-6);
+        assert_abs_diff_eq!(objective.gradient(10.0, 0.0), 1.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(objective.gradient(-10.0, 0.0), -1.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(objective.hessian(10.0, 0.0), 1.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_fair_derivatives() {
+        let objective = Fair::new(1.0);
+
+        assert_abs_diff_eq!(objective.gradient(1.0, 0.0), 0.5, epsilon = 1e-6);
+        assert_abs_diff_eq!(objective.gradient(-3.0, 0.0), -0.75, epsilon = 1e-6);
+        assert_abs_diff_eq!(objective.hessian(1.0, 0.0), 0.25, epsilon = 1e-6);
+        assert!(objective.gradient(1000.0, 0.0) < 1.0);
+    }
+
+    #[test]
+    fn test_log_link_objectives() {
+        let poisson = Poisson::new(0.7);
+        assert_abs_diff_eq!(poisson.gradient(0.0, 3.0), -2.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(poisson.hessian(0.0, 3.0), 0.7f32.exp(), epsilon = 1e-6);
+        assert_abs_diff_eq!(poisson.transform(2.0f32.ln()), 2.0, epsilon = 1e-6);
+
+        assert_abs_diff_eq!(Gamma.gradient(0.0, 2.0), -1.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(Gamma.hessian(0.0, 2.0), 2.0, epsilon = 1e-6);
+
+        // at pred = 0 the Tweedie gradient is 1 - label for every variance power
+        let tweedie = Tweedie::new(1.5);
+        assert_abs_diff_eq!(tweedie.gradient(0.0, 3.0), -2.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(tweedie.hessian(0.0, 3.0), 0.5 * 3.0 + 0.5, epsilon = 1e-6);
+        // the gradient vanishes at pred = ln(label)
+        assert_abs_diff_eq!(tweedie.gradient(3.0f32.ln(), 3.0), 0.0, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn test_base_scores() {
+        let labels = vec![0.0, 1.0, 1.0, 2.0];
+
+        assert_eq!(SquaredError.base_scores(&labels, None), vec![1.0]);
+        assert_eq!(
+            SquaredError.base_scores(&labels, Some(&[1.0, 0.0, 0.0, 1.0])),
+            vec![1.0]
+        );
+        assert_eq!(
+            SquaredError.base_scores(&labels, Some(&[3.0, 1.0, 0.0, 0.0])),
+            vec![0.25]
+        );
+        assert_abs_diff_eq!(
+            Poisson::new(0.7).base_scores(&labels, None)[0],
+            0.0,
+            epsilon = 1e-6
+        );
+        assert_eq!(
+            Huber::new(1.0).base_scores(&[0.0, 1.0, 100.0], None),
+            vec![1.0]
+        );
+        assert_eq!(Quantile::new(0.0).base_scores(&labels, None), vec![0.0]);
+
+        // a quarter positives: log(0.25 / 0.75)
+        let base = BinaryLogistic.base_scores(&[1.0, 0.0, 0.0, 0.0], None)[0];
+        assert_abs_diff_eq!(base, -(3.0f32.ln()), epsilon = 1e-6);
+        assert_abs_diff_eq!(BinaryLogistic.transform(base), 0.25, epsilon = 1e-6);
+
+        assert_eq!(Softmax::new(3).base_scores(&labels, None), vec![0.0; 3]);
+    }
+
+    #[test]
+    fn test_custom_objective_uses_closure() {
+        let objective = CustomObjective::new(|preds: &[f32], labels: &[f32]| {
+            let gradients = preds
+                .iter()
+                .zip(labels)
+                .map(|(p, y)| 2.0 * (p - y))
+                .collect();
+            (gradients, vec![2.0; preds.len()])
+        });
+
+        assert_abs_diff_eq!(objective.gradient(3.0, 1.0), 4.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(objective.hessian(3.0, 1.0), 2.0, epsilon = 1e-6);
+
+        let mut gradients = vec![0.0; 2];
+        let mut hessians = vec![0.0; 2];
+        objective.compute_gradients(&[1.0, 2.0], &[0.0, 0.0], &mut gradients, &mut hessians);
+        assert_eq!(gradients, [2.0, 4.0]);
+        assert_eq!(hessians, [2.0, 2.0]);
+    }
+}
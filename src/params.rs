@@ -0,0 +1,1057 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::dataset::{BinningStrategy, DatasetParams, HistogramParallelism};
+use crate::error::{Error, Result};
+use crate::gpu::{Backend, Device};
+use crate::histogram::HistogramPrecision;
+use crate::metrics::MetricType;
+use crate::objective::ObjectiveType;
+use crate::sampling::SampleStrategy;
+use crate::schedule::LearningRateSchedule;
+use crate::tree_builder::GrowthStrategy;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum BoostingType {
+    // gradient boosted trees: each tree fits the residuals of the ones before it
+    #[default]
+    Gbdt,
+    // Bagged trees all fit to the original targets and averaged at prediction time. Needs
+    // row or feature subsampling so the trees differ; learning rate is ignored.
+    RandomForest,
+}
+
+// Parameters missing from a saved model take their defaults, so models saved before a
+// parameter existed still load.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Params {
+    pub boosting_type: BoostingType,
+    pub objective: ObjectiveType,
+    // Classification only: every row's weight is multiplied by the weight of its class,
+    // given per class index (negatives first for binary logistic). `is_unbalance` instead
+    // weights each class by the size of the largest class over its own size.
+    pub class_weights: Vec<f32>,
+    pub is_unbalance: bool,
+    // Start from the objective's best constant score (e.g. the label mean) instead of zero.
+    // Ignored for datasets with init scores.
+    pub boost_from_average: bool,
+    pub num_iterations: usize,
+    pub learning_rate: f32,
+    // scales `learning_rate` per iteration; callbacks can further override the result
+    pub learning_rate_schedule: LearningRateSchedule,
+    pub growth_strategy: GrowthStrategy,
+    pub num_leaves: usize,
+    // no limit when None
+    pub max_depth: Option<usize>,
+    pub max_bins: usize,
+    // per-feature binning, see `DatasetParams`
+    pub dataset_params: DatasetParams,
+    // Bundle features that are rarely away from their most common bin at the same time, like
+    // one-hot columns, so their histograms come from one shared column. Up to
+    // `max_conflict_rate` of the rows may have several features of a bundle off that bin.
+    pub enable_bundle: bool,
+    pub max_conflict_rate: f32,
+    // How many nodes' histograms are kept around while a tree grows, so a child's can be
+    // derived from its parent's instead of accumulated from its rows; the least recently
+    // used are dropped beyond this. No limit when None.
+    pub histogram_pool_size: Option<usize>,
+    // f32 by default; see `HistogramPrecision::Double` for datasets with very many rows
+    pub histogram_precision: HistogramPrecision,
+    // Grow trees from gradients and hessians quantized to `num_grad_quant_bins` levels, see
+    // `QuantizedGradients`. Leaf values still come from the exact gradients.
+    pub use_quantized_grad: bool,
+    pub num_grad_quant_bins: usize,
+    pub lambda_l1: f32,
+    pub lambda_l2: f32,
+    // a node is only split when the best split's gain exceeds this
+    pub min_gain_to_split: f32,
+    // Every leaf keeps at least this many training rows and this much hessian. LightGBM
+    // defaults to 20 rows, which is too strict for the small datasets this crate is often
+    // tried on, so the row limit is off by default.
+    pub min_data_in_leaf: usize,
+    pub min_sum_hessian_in_leaf: f32,
+    pub sample_strategy: SampleStrategy,
+    // Every `bagging_freq` iterations a new `bagging_fraction` of the rows is drawn, and
+    // the trees until the next draw only see those rows. Off when bagging_freq is 0.
+    pub bagging_fraction: f32,
+    pub bagging_freq: usize,
+    // fraction of the features each tree may split on, and of those each node may
+    pub feature_fraction: f32,
+    pub feature_fraction_bynode: f32,
+    // Per feature: 1 for increasing, -1 for decreasing and 0 for no constraint. Features
+    // past the end of the list are unconstrained.
+    pub monotone_constraints: Vec<i8>,
+    // Features holding category codes, non-negative integers. Their splits send a set of
+    // categories left, chosen among at most `max_cat_threshold` categories ordered by
+    // gradient over hessian sum plus `cat_smooth`, with at least `min_data_per_group` rows
+    // between the candidate split points.
+    pub categorical_features: Vec<usize>,
+    pub max_cat_threshold: usize,
+    pub cat_smooth: f32,
+    pub min_data_per_group: usize,
+    // Leaves fit a ridge regression in the features split on above them instead of a
+    // constant; `linear_lambda` is the penalty on the coefficients.
+    pub linear_tree: bool,
+    pub linear_lambda: f32,
+    // try a single random threshold per feature instead of every bin boundary
+    pub extra_trees: bool,
+    // seeds all random sampling so training is reproducible
+    pub seed: u64,
+    // threads histograms are built and splits searched on, every core when None; the
+    // trees don't depend on it
+    pub num_threads: Option<usize>,
+    // whether threads split a node's histograms by columns or by rows
+    pub histogram_parallelism: HistogramParallelism,
+    // where node histograms are built; without a usable GPU they are built on the CPU
+    pub device: Device,
+    // the GPU API of `Device::Gpu`
+    pub gpu_backend: Backend,
+    // With `Device::Gpu`, nodes with fewer rows are built on the CPU, which is faster than
+    // waiting for a GPU dispatch. Oblivious trees build a level's small nodes on the CPU
+    // while the GPU builds the large ones.
+    pub gpu_min_rows: usize,
+    // evaluated on the training and validation data after every iteration
+    pub metrics: Vec<MetricType>,
+    // Stop once the first metric on the first validation set hasn't improved for this many
+    // iterations. Needs at least one metric and one validation set.
+    pub early_stopping_rounds: Option<usize>,
+    // When set, the model and training state are written here every `checkpoint_freq`
+    // iterations so an interrupted run can pick up with `Booster::resume_from_checkpoint`.
+    pub checkpoint_path: Option<PathBuf>,
+    pub checkpoint_freq: usize,
+    // Wall-clock budget for a training call. Once it runs out, training stops after the
+    // current iteration, keeping the trees trained so far.
+    pub max_train_seconds: Option<f64>,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            boosting_type: BoostingType::Gbdt,
+            objective: ObjectiveType::SquaredError,
+            class_weights: vec![],
+            is_unbalance: false,
+            boost_from_average: true,
+            num_iterations: 100,
+            learning_rate: 0.1,
+            learning_rate_schedule: LearningRateSchedule::Constant,
+            growth_strategy: GrowthStrategy::LeafWise,
+            num_leaves: 31,
+            max_depth: None,
+            max_bins: 255,
+            dataset_params: DatasetParams::default(),
+            enable_bundle: true,
+            max_conflict_rate: 0.0,
+            histogram_pool_size: None,
+            histogram_precision: HistogramPrecision::Single,
+            use_quantized_grad: false,
+            num_grad_quant_bins: 4,
+            lambda_l1: 0.0,
+            lambda_l2: 0.0,
+            min_gain_to_split: 0.0,
+            min_data_in_leaf: 1,
+            min_sum_hessian_in_leaf: 1e-3,
+            sample_strategy: SampleStrategy::Bagging,
+            bagging_fraction: 1.0,
+            bagging_freq: 0,
+            feature_fraction: 1.0,
+            feature_fraction_bynode: 1.0,
+            monotone_constraints: vec![],
+            categorical_features: vec![],
+            max_cat_threshold: 32,
+            cat_smooth: 10.0,
+            min_data_per_group: 100,
+            linear_tree: false,
+            linear_lambda: 0.0,
+            extra_trees: false,
+            seed: 0,
+            num_threads: None,
+            histogram_parallelism: HistogramParallelism::Auto,
+            device: Device::Cpu,
+            gpu_backend: Backend::Auto,
+            gpu_min_rows: 4096,
+            metrics: vec![],
+            early_stopping_rounds: None,
+            checkpoint_path: None,
+            checkpoint_freq: 10,
+            max_train_seconds: None,
+        }
+    }
+}
+
+impl Params {
+    // Starts from the defaults, e.g.
+    // `Params::builder().num_leaves(31).learning_rate(0.05).build()?`
+    pub fn builder() -> ParamsBuilder {
+        ParamsBuilder {
+            params: Params::default(),
+        }
+    }
+
+    // Checks value ranges and combinations of parameters. Training calls this too, so
+    // parameters assembled by hand are held to the same rules as built ones.
+    pub fn validate(&self) -> Result<()> {
+        validate_objective(&self.objective)?;
+        validate_class_weights(&self.objective, &self.class_weights, self.is_unbalance)?;
+
+        if self.num_iterations == 0 {
+            return Err(Error::invalid_parameter(
+                "num_iterations",
+                "must be at least 1",
+            ));
+        }
+        if !(self.learning_rate.is_finite() && self.learning_rate > 0.0) {
+            return Err(Error::invalid_parameter(
+                "learning_rate",
+                format!("must be positive, got {}", self.learning_rate),
+            ));
+        }
+        validate_schedule(&self.learning_rate_schedule, self.learning_rate)?;
+
+        if self.num_leaves < 2 {
+            return Err(Error::invalid_parameter(
+                "num_leaves",
+                format!("must be at least 2, got {}", self.num_leaves),
+            ));
+        }
+        if self.max_depth == Some(0) {
+            return Err(Error::invalid_parameter(
+                "max_depth",
+                "must be at least 1, use None for unlimited depth",
+            ));
+        }
+        // bin indices are stored in at most 16 bits, and the highest index is left for NaN
+        if !(2..1 << 16).contains(&self.max_bins) {
+            return Err(Error::invalid_parameter(
+                "max_bins",
+                format!("must be between 2 and 65535, got {}", self.max_bins),
+            ));
+        }
+        let dataset_params = &self.dataset_params;
+        if let Some((feature, max_bins)) = dataset_params
+            .max_bins_by_feature
+            .iter()
+            .enumerate()
+            .find(|(_, max_bins)| !(2..1 << 16).contains(*max_bins))
+        {
+            return Err(Error::invalid_parameter(
+                "max_bins_by_feature",
+                format!("must be between 2 and 65535, got {max_bins} for feature {feature}"),
+            ));
+        }
+        for (feature, edges) in &dataset_params.forced_bins {
+            validate_edges("forced_bins", *feature, edges)?;
+        }
+        if let BinningStrategy::Custom(edges) = &dataset_params.binning_strategy {
+            for (feature, edges) in edges.iter().enumerate() {
+                validate_edges("binning_strategy", feature, edges)?;
+            }
+        }
+        if !(0.0..1.0).contains(&self.max_conflict_rate) {
+            return Err(Error::invalid_parameter(
+                "max_conflict_rate",
+                format!("must be in [0, 1), got {}", self.max_conflict_rate),
+            ));
+        }
+        // levels are stored in an i8
+        if !(2..=127).contains(&self.num_grad_quant_bins) {
+            return Err(Error::invalid_parameter(
+                "num_grad_quant_bins",
+                format!(
+                    "must be between 2 and 127, got {}",
+                    self.num_grad_quant_bins
+                ),
+            ));
+        }
+        if self.num_threads == Some(0) {
+            return Err(Error::invalid_parameter(
+                "num_threads",
+                "must be at least 1, use None for every core",
+            ));
+        }
+        if self.histogram_pool_size == Some(0) {
+            return Err(Error::invalid_parameter(
+                "histogram_pool_size",
+                "must be at least 1, use None for no limit",
+            ));
+        }
+        if self.min_data_in_leaf == 0 {
+            return Err(Error::invalid_parameter(
+                "min_data_in_leaf",
+                "must be at least 1",
+            ));
+        }
+        for (name, value) in [
+            ("lambda_l1", self.lambda_l1),
+            ("lambda_l2", self.lambda_l2),
+            ("min_gain_to_split", self.min_gain_to_split),
+            ("min_sum_hessian_in_leaf", self.min_sum_hessian_in_leaf),
+            ("linear_lambda", self.linear_lambda),
+        ] {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(Error::invalid_parameter(
+                    name,
+                    format!("must be non-negative, got {value}"),
+                ));
+            }
+        }
+
+        for (name, value) in [
+            ("bagging_fraction", self.bagging_fraction),
+            ("feature_fraction", self.feature_fraction),
+            ("feature_fraction_bynode", self.feature_fraction_bynode),
+        ] {
+            if !(value > 0.0 && value <= 1.0) {
+                return Err(Error::invalid_parameter(
+                    name,
+                    format!("must be in (0, 1], got {value}"),
+                ));
+            }
+        }
+
+        if let SampleStrategy::Goss {
+            top_rate,
+            other_rate,
+        } = self.sample_strategy
+        {
+            if !(top_rate > 0.0 && other_rate > 0.0 && top_rate + other_rate <= 1.0) {
+                return Err(Error::invalid_parameter(
+                    "sample_strategy",
+                    "GOSS needs positive top_rate and other_rate adding up to at most 1",
+                ));
+            }
+            if self.bagging_freq > 0 && self.bagging_fraction < 1.0 {
+                return Err(Error::invalid_parameter(
+                    "sample_strategy",
+                    "GOSS can't be combined with bagging",
+                ));
+            }
+        }
+
+        if self.boosting_type == BoostingType::RandomForest {
+            let bagging = self.bagging_freq > 0 && self.bagging_fraction < 1.0;
+            if !bagging && self.feature_fraction >= 1.0 && self.feature_fraction_bynode >= 1.0 {
+                return Err(Error::invalid_parameter(
+                    "boosting_type",
+                    "random forests need bagging or feature subsampling",
+                ));
+            }
+            if self.sample_strategy != SampleStrategy::Bagging {
+                return Err(Error::invalid_parameter(
+                    "boosting_type",
+                    "random forests need the bagging sample strategy",
+                ));
+            }
+        }
+
+        if let Some(constraint) = self
+            .monotone_constraints
+            .iter()
+            .find(|constraint| !(-1..=1).contains(*constraint))
+        {
+            return Err(Error::invalid_parameter(
+                "monotone_constraints",
+                format!("must be -1, 0 or 1, got {constraint}"),
+            ));
+        }
+
+        if let Some(&feature) = self.categorical_features.iter().find(|&&feature| {
+            self.monotone_constraints
+                .get(feature)
+                .is_some_and(|&c| c != 0)
+        }) {
+            return Err(Error::invalid_parameter(
+                "monotone_constraints",
+                format!("categorical feature {feature} can't be monotone"),
+            ));
+        }
+        if self.max_cat_threshold == 0 {
+            return Err(Error::invalid_parameter(
+                "max_cat_threshold",
+                "must be at least 1",
+            ));
+        }
+        if !(self.cat_smooth >= 0.0 && self.cat_smooth.is_finite()) {
+            return Err(Error::invalid_parameter(
+                "cat_smooth",
+                format!("must be finite and non-negative, got {}", self.cat_smooth),
+            ));
+        }
+        if self.min_data_per_group == 0 {
+            return Err(Error::invalid_parameter(
+                "min_data_per_group",
+                "must be at least 1",
+            ));
+        }
+
+        if self.linear_tree && self.monotone_constraints.iter().any(|&c| c != 0) {
+            return Err(Error::invalid_parameter(
+                "linear_tree",
+                "linear leaves can't be combined with monotone constraints",
+            ));
+        }
+        if self.growth_strategy == GrowthStrategy::Oblivious
+            && self.monotone_constraints.iter().any(|&c| c != 0)
+        {
+            return Err(Error::invalid_parameter(
+                "growth_strategy",
+                "oblivious trees can't be combined with monotone constraints",
+            ));
+        }
+        if self.growth_strategy == GrowthStrategy::Oblivious
+            && !self.categorical_features.is_empty()
+        {
+            return Err(Error::invalid_parameter(
+                "growth_strategy",
+                "oblivious trees can't split on categorical features",
+            ));
+        }
+
+        if self.checkpoint_path.is_some() && self.checkpoint_freq == 0 {
+            return Err(Error::invalid_parameter(
+                "checkpoint_freq",
+                "must be at least 1",
+            ));
+        }
+
+        if let Some(seconds) = self.max_train_seconds
+            && (seconds.is_nan() || seconds <= 0.0)
+        {
+            return Err(Error::invalid_parameter(
+                "max_train_seconds",
+                format!("must be positive, got {seconds}"),
+            ));
+        }
+
+        for metric in &self.metrics {
+            if let MetricType::Ndcg { k: 0 } | MetricType::Map { k: 0 } = metric {
+                return Err(Error::invalid_parameter(
+                    "metrics",
+                    format!("{metric:?} needs k of at least 1"),
+                ));
+            }
+        }
+        match self.early_stopping_rounds {
+            Some(0) => Err(Error::invalid_parameter(
+                "early_stopping_rounds",
+                "must be at least 1",
+            )),
+            Some(_) if self.metrics.is_empty() => Err(Error::invalid_parameter(
+                "early_stopping_rounds",
+                "needs at least one entry in `metrics` to monitor",
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn validate_class_weights(
+    objective: &ObjectiveType,
+    class_weights: &[f32],
+    is_unbalance: bool,
+) -> Result<()> {
+    if class_weights.is_empty() && !is_unbalance {
+        return Ok(());
+    }
+    let name = if is_unbalance {
+        "is_unbalance"
+    } else {
+        "class_weights"
+    };
+    let Some(num_classes) = objective.num_classes() else {
+        return Err(Error::invalid_parameter(
+            name,
+            "only applies to classification objectives",
+        ));
+    };
+    if is_unbalance && !class_weights.is_empty() {
+        return Err(Error::invalid_parameter(
+            "is_unbalance",
+            "can't be combined with class_weights",
+        ));
+    }
+    if !class_weights.is_empty() && class_weights.len() != num_classes {
+        return Err(Error::invalid_parameter(
+            "class_weights",
+            format!(
+                "needs one weight per class ({num_classes}), got {}",
+                class_weights.len()
+            ),
+        ));
+    }
+    if let Some(weight) = class_weights
+        .iter()
+        .find(|w| !(w.is_finite() && **w >= 0.0))
+    {
+        return Err(Error::invalid_parameter(
+            "class_weights",
+            format!("must be non-negative, got {weight}"),
+        ));
+    }
+    Ok(())
+}
+
+fn validate_edges(name: &'static str, feature: usize, edges: &[f32]) -> Result<()> {
+    if !(2..=1 << 16).contains(&edges.len())
+        || !edges.iter().all(|edge| edge.is_finite())
+        || !edges.windows(2).all(|pair| pair[0] < pair[1])
+    {
+        return Err(Error::invalid_parameter(
+            name,
+            format!("feature {feature} needs 2 to 65536 finite, strictly ascending edges"),
+        ));
+    }
+    Ok(())
+}
+
+fn validate_objective(objective: &ObjectiveType) -> Result<()> {
+    let in_range = match *objective {
+        ObjectiveType::Multiclass { num_class } => num_class >= 2,
+        ObjectiveType::Quantile { alpha } => alpha > 0.0 && alpha < 1.0,
+        ObjectiveType::Huber { delta } => delta.is_finite() && delta > 0.0,
+        ObjectiveType::Fair { c } => c.is_finite() && c > 0.0,
+        ObjectiveType::Poisson { max_delta_step } => {
+            max_delta_step.is_finite() && max_delta_step > 0.0
+        }
+        ObjectiveType::Tweedie { variance_power } => variance_power > 1.0 && variance_power < 2.0,
+        _ => true,
+    };
+
+    if in_range {
+        Ok(())
+    } else {
+        let expected = match objective {
+            ObjectiveType::Multiclass { .. } => "num_class of at least 2",
+            ObjectiveType::Quantile { .. } => "alpha in (0, 1)",
+            ObjectiveType::Tweedie { .. } => "variance_power in (1, 2)",
+            _ => "a positive parameter",
+        };
+        Err(Error::invalid_parameter(
+            "objective",
+            format!("{objective:?} needs {expected}"),
+        ))
+    }
+}
+
+fn validate_schedule(schedule: &LearningRateSchedule, learning_rate: f32) -> Result<()> {
+    let rate = match *schedule {
+        LearningRateSchedule::Constant => return Ok(()),
+        LearningRateSchedule::LinearDecay { final_rate } => final_rate,
+        LearningRateSchedule::Cosine { min_rate } => min_rate,
+    };
+
+    if rate.is_finite() && rate >= 0.0 && rate <= learning_rate {
+        Ok(())
+    } else {
+        Err(Error::invalid_parameter(
+            "learning_rate_schedule",
+            format!("{schedule:?} must end between 0 and learning_rate ({learning_rate})"),
+        ))
+    }
+}
+
+pub struct ParamsBuilder {
+    params: Params,
+}
+
+impl ParamsBuilder {
+    pub fn boosting_type(mut self, boosting_type: BoostingType) -> Self {
+        self.params.boosting_type = boosting_type;
+        self
+    }
+
+    pub fn objective(mut self, objective: ObjectiveType) -> Self {
+        self.params.objective = objective;
+        self
+    }
+
+    pub fn class_weights(mut self, class_weights: Vec<f32>) -> Self {
+        self.params.class_weights = class_weights;
+        self
+    }
+
+    pub fn is_unbalance(mut self, is_unbalance: bool) -> Self {
+        self.params.is_unbalance = is_unbalance;
+        self
+    }
+
+    pub fn boost_from_average(mut self, boost_from_average: bool) -> Self {
+        self.params.boost_from_average = boost_from_average;
+        self
+    }
+
+    pub fn num_iterations(mut self, num_iterations: usize) -> Self {
+        self.params.num_iterations = num_iterations;
+        self
+    }
+
+    pub fn learning_rate(mut self, learning_rate: f32) -> Self {
+        self.params.learning_rate = learning_rate;
+        self
+    }
+
+    pub fn learning_rate_schedule(mut self, schedule: LearningRateSchedule) -> Self {
+        self.params.learning_rate_schedule = schedule;
+        self
+    }
+
+    pub fn growth_strategy(mut self, growth_strategy: GrowthStrategy) -> Self {
+        self.params.growth_strategy = growth_strategy;
+        self
+    }
+
+    pub fn num_leaves(mut self, num_leaves: usize) -> Self {
+        self.params.num_leaves = num_leaves;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.params.max_depth = Some(max_depth);
+        self
+    }
+
+    pub fn max_bins(mut self, max_bins: usize) -> Self {
+        self.params.max_bins = max_bins;
+        self
+    }
+
+    pub fn binning_strategy(mut self, binning_strategy: BinningStrategy) -> Self {
+        self.params.dataset_params.binning_strategy = binning_strategy;
+        self
+    }
+
+    pub fn max_bins_by_feature(mut self, max_bins_by_feature: Vec<usize>) -> Self {
+        self.params.dataset_params.max_bins_by_feature = max_bins_by_feature;
+        self
+    }
+
+    // bins `feature` at exactly `edges`
+    pub fn forced_bins(mut self, feature: usize, edges: Vec<f32>) -> Self {
+        self.params
+            .dataset_params
+            .forced_bins
+            .insert(feature, edges);
+        self
+    }
+
+    pub fn enable_bundle(mut self, enable_bundle: bool) -> Self {
+        self.params.enable_bundle = enable_bundle;
+        self
+    }
+
+    pub fn max_conflict_rate(mut self, max_conflict_rate: f32) -> Self {
+        self.params.max_conflict_rate = max_conflict_rate;
+        self
+    }
+
+    pub fn histogram_pool_size(mut self, histogram_pool_size: usize) -> Self {
+        self.params.histogram_pool_size = Some(histogram_pool_size);
+        self
+    }
+
+    pub fn histogram_precision(mut self, histogram_precision: HistogramPrecision) -> Self {
+        self.params.histogram_precision = histogram_precision;
+        self
+    }
+
+    pub fn use_quantized_grad(mut self, use_quantized_grad: bool) -> Self {
+        self.params.use_quantized_grad = use_quantized_grad;
+        self
+    }
+
+    pub fn num_grad_quant_bins(mut self, num_grad_quant_bins: usize) -> Self {
+        self.params.num_grad_quant_bins = num_grad_quant_bins;
+        self
+    }
+
+    pub fn lambda_l1(mut self, lambda_l1: f32) -> Self {
+        self.params.lambda_l1 = lambda_l1;
+        self
+    }
+
+    pub fn lambda_l2(mut self, lambda_l2: f32) -> Self {
+        self.params.lambda_l2 = lambda_l2;
+        self
+    }
+
+    pub fn min_gain_to_split(mut self, min_gain_to_split: f32) -> Self {
+        self.params.min_gain_to_split = min_gain_to_split;
+        self
+    }
+
+    pub fn min_data_in_leaf(mut self, min_data_in_leaf: usize) -> Self {
+        self.params.min_data_in_leaf = min_data_in_leaf;
+        self
+    }
+
+    pub fn min_sum_hessian_in_leaf(mut self, min_sum_hessian_in_leaf: f32) -> Self {
+        self.params.min_sum_hessian_in_leaf = min_sum_hessian_in_leaf;
+        self
+    }
+
+    pub fn sample_strategy(mut self, sample_strategy: SampleStrategy) -> Self {
+        self.params.sample_strategy = sample_strategy;
+        self
+    }
+
+    pub fn bagging_fraction(mut self, bagging_fraction: f32) -> Self {
+        self.params.bagging_fraction = bagging_fraction;
+        self
+    }
+
+    pub fn bagging_freq(mut self, bagging_freq: usize) -> Self {
+        self.params.bagging_freq = bagging_freq;
+        self
+    }
+
+    pub fn feature_fraction(mut self, feature_fraction: f32) -> Self {
+        self.params.feature_fraction = feature_fraction;
+        self
+    }
+
+    pub fn feature_fraction_bynode(mut self, feature_fraction_bynode: f32) -> Self {
+        self.params.feature_fraction_bynode = feature_fraction_bynode;
+        self
+    }
+
+    pub fn monotone_constraints(mut self, monotone_constraints: Vec<i8>) -> Self {
+        self.params.monotone_constraints = monotone_constraints;
+        self
+    }
+
+    pub fn categorical_features(mut self, categorical_features: Vec<usize>) -> Self {
+        self.params.categorical_features = categorical_features;
+        self
+    }
+
+    pub fn max_cat_threshold(mut self, max_cat_threshold: usize) -> Self {
+        self.params.max_cat_threshold = max_cat_threshold;
+        self
+    }
+
+    pub fn cat_smooth(mut self, cat_smooth: f32) -> Self {
+        self.params.cat_smooth = cat_smooth;
+        self
+    }
+
+    pub fn min_data_per_group(mut self, min_data_per_group: usize) -> Self {
+        self.params.min_data_per_group = min_data_per_group;
+        self
+    }
+
+    pub fn linear_tree(mut self, linear_tree: bool) -> Self {
+        self.params.linear_tree = linear_tree;
+        self
+    }
+
+    pub fn linear_lambda(mut self, linear_lambda: f32) -> Self {
+        self.params.linear_lambda = linear_lambda;
+        self
+    }
+
+    pub fn extra_trees(mut self, extra_trees: bool) -> Self {
+        self.params.extra_trees = extra_trees;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.params.seed = seed;
+        self
+    }
+
+    pub fn num_threads(mut self, num_threads: usize) -> Self {
+        self.params.num_threads = Some(num_threads);
+        self
+    }
+
+    pub fn histogram_parallelism(mut self, histogram_parallelism: HistogramParallelism) -> Self {
+        self.params.histogram_parallelism = histogram_parallelism;
+        self
+    }
+
+    pub fn device(mut self, device: Device) -> Self {
+        self.params.device = device;
+        self
+    }
+
+    pub fn gpu_backend(mut self, gpu_backend: Backend) -> Self {
+        self.params.gpu_backend = gpu_backend;
+        self
+    }
+
+    pub fn gpu_min_rows(mut self, gpu_min_rows: usize) -> Self {
+        self.params.gpu_min_rows = gpu_min_rows;
+        self
+    }
+
+    pub fn metric(mut self, metric: MetricType) -> Self {
+        self.params.metrics.push(metric);
+        self
+    }
+
+    pub fn early_stopping_rounds(mut self, rounds: usize) -> Self {
+        self.params.early_stopping_rounds = Some(rounds);
+        self
+    }
+
+    // write a checkpoint to `path` every `freq` iterations
+    pub fn checkpoint(mut self, path: impl Into<PathBuf>, freq: usize) -> Self {
+        self.params.checkpoint_path = Some(path.into());
+        self.params.checkpoint_freq = freq;
+        self
+    }
+
+    pub fn max_train_seconds(mut self, seconds: f64) -> Self {
+        self.params.max_train_seconds = Some(seconds);
+        self
+    }
+
+    pub fn build(self) -> Result<Params> {
+        self.params.validate()?;
+        Ok(self.params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_sets_fields() {
+        let params = Params::builder()
+            .num_leaves(15)
+            .learning_rate(0.05)
+            .metric(MetricType::Rmse)
+            .metric(MetricType::Mae)
+            .early_stopping_rounds(10)
+            .build()
+            .unwrap();
+
+        assert_eq!(params.num_leaves, 15);
+        assert_eq!(params.learning_rate, 0.05);
+        assert_eq!(params.metrics, vec![MetricType::Rmse, MetricType::Mae]);
+        assert_eq!(params.early_stopping_rounds, Some(10));
+        assert_eq!(params.num_iterations, Params::default().num_iterations);
+    }
+
+    #[test]
+    fn test_builder_rejects_out_of_range_values() {
+        let err = Params::builder().learning_rate(-0.1).build().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid parameter `learning_rate`: must be positive, got -0.1"
+        );
+
+        let err = Params::builder().lambda_l1(-1.0).build().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid parameter `lambda_l1`: must be non-negative, got -1"
+        );
+
+        let err = Params::builder().num_threads(0).build().unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidParameter {
+                name: "num_threads",
+                ..
+            }
+        ));
+
+        let err = Params::builder()
+            .num_grad_quant_bins(128)
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid parameter `num_grad_quant_bins`: must be between 2 and 127, got 128"
+        );
+
+        let err = Params::builder()
+            .histogram_pool_size(0)
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidParameter {
+                name: "histogram_pool_size",
+                ..
+            }
+        ));
+
+        let err = Params::builder().num_leaves(1).build().unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidParameter {
+                name: "num_leaves",
+                ..
+            }
+        ));
+
+        let err = Params::builder()
+            .objective(ObjectiveType::Quantile { alpha: 1.5 })
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("alpha in (0, 1)"));
+
+        let err = Params::builder()
+            .categorical_features(vec![1])
+            .monotone_constraints(vec![0, -1])
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid parameter `monotone_constraints`: categorical feature 1 can't be monotone"
+        );
+
+        let err = Params::builder()
+            .forced_bins(3, vec![1.0, 1.0])
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid parameter `forced_bins`: feature 3 needs 2 to 65536 finite, strictly ascending edges"
+        );
+        let err = Params::builder()
+            .binning_strategy(BinningStrategy::Custom(vec![
+                vec![0.0, 1.0],
+                vec![f32::NAN, 1.0],
+            ]))
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidParameter {
+                name: "binning_strategy",
+                ..
+            }
+        ));
+        let err = Params::builder()
+            .max_bins_by_feature(vec![255, 1])
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidParameter {
+                name: "max_bins_by_feature",
+                ..
+            }
+        ));
+
+        let err = Params::builder()
+            .max_conflict_rate(1.0)
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid parameter `max_conflict_rate`: must be in [0, 1), got 1"
+        );
+
+        let err = Params::builder()
+            .max_train_seconds(0.0)
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid parameter `max_train_seconds`: must be positive, got 0"
+        );
+
+        let err = Params::builder()
+            .checkpoint("model.ckpt", 0)
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidParameter {
+                name: "checkpoint_freq",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_builder_rejects_inconsistent_parameters() {
+        let err = Params::builder()
+            .early_stopping_rounds(5)
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidParameter {
+                name: "early_stopping_rounds",
+                ..
+            }
+        ));
+
+        let err = Params::builder()
+            .sample_strategy(SampleStrategy::Goss {
+                top_rate: 0.2,
+                other_rate: 0.1,
+            })
+            .bagging_fraction(0.5)
+            .bagging_freq(1)
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid parameter `sample_strategy`: GOSS can't be combined with bagging"
+        );
+
+        let err = Params::builder().is_unbalance(true).build().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid parameter `is_unbalance`: only applies to classification objectives"
+        );
+        let err = Params::builder()
+            .objective(ObjectiveType::Multiclass { num_class: 3 })
+            .class_weights(vec![1.0, 2.0])
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidParameter {
+                name: "class_weights",
+                ..
+            }
+        ));
+
+        let err = Params::builder()
+            .growth_strategy(GrowthStrategy::Oblivious)
+            .monotone_constraints(vec![1])
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidParameter {
+                name: "growth_strategy",
+                ..
+            }
+        ));
+
+        let err = Params::builder()
+            .boosting_type(BoostingType::RandomForest)
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidParameter {
+                name: "boosting_type",
+                ..
+            }
+        ));
+
+        let err = Params::builder()
+            .learning_rate(0.1)
+            .learning_rate_schedule(LearningRateSchedule::Cosine { min_rate: 0.2 })
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidParameter {
+                name: "learning_rate_schedule",
+                ..
+            }
+        ));
+    }
+}
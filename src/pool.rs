@@ -0,0 +1,125 @@
+use std::collections::VecDeque;
+
+use crate::histogram::Histogram;
+
+// Recycles the per-feature histograms of tree nodes, so growing a tree doesn't allocate
+// `bins × features` buffers for every node it evaluates. Histograms handed back with
+// `insert` stay cached under their node until they are `take`n, e.g. to derive a child's
+// histograms from its parent's; once more than `capacity` nodes are cached, the least
+// recently used node's buffers go back to the free list.
+pub struct HistogramPool {
+    // empty histograms of every feature that new buffers are cloned from
+    templates: Vec<Histogram>,
+    // no limit when None
+    capacity: Option<usize>,
+    // least recently used first
+    cached: VecDeque<(usize, Vec<Histogram>)>,
+    free: Vec<Vec<Histogram>>,
+}
+
+impl HistogramPool {
+    pub fn new(templates: &[Histogram], capacity: Option<usize>) -> Self {
+        Self {
+            templates: templates.to_vec(),
+            capacity,
+            cached: VecDeque::new(),
+            free: vec![],
+        }
+    }
+
+    // Empty histograms of every feature, recycled when a buffer is free.
+    pub fn acquire(&mut self) -> Vec<Histogram> {
+        match self.free.pop() {
+            Some(mut histograms) => {
+                histograms.iter_mut().for_each(Histogram::reset);
+                histograms
+            }
+            None => self.templates.clone(),
+        }
+    }
+
+    // Caches the histograms of `node` as the most recently used.
+    pub fn insert(&mut self, node: usize, histograms: Vec<Histogram>) {
+        if let Some(stale) = self.take(node) {
+            self.recycle(stale);
+        }
+        self.cached.push_back((node, histograms));
+        while self
+            .capacity
+            .is_some_and(|capacity| self.cached.len() > capacity)
+        {
+            let (_, evicted) = self.cached.pop_front().expect("over capacity");
+            self.free.push(evicted);
+        }
+    }
+
+    // The cached histograms of `node`, which then become the most recently used.
+    pub fn get(&mut self, node: usize) -> Option<&[Histogram]> {
+        let position = self.cached.iter().position(|(cached, _)| *cached == node)?;
+        let entry = self.cached.remove(position)?;
+        self.cached.push_back(entry);
+        self.cached
+            .back()
+            .map(|(_, histograms)| histograms.as_slice())
+    }
+
+    // Removes the cached histograms of `node` from the pool, None if they were evicted.
+    pub fn take(&mut self, node: usize) -> Option<Vec<Histogram>> {
+        let position = self.cached.iter().position(|(cached, _)| *cached == node)?;
+        self.cached
+            .remove(position)
+            .map(|(_, histograms)| histograms)
+    }
+
+    // hands back histograms that won't be needed again
+    pub fn recycle(&mut self, histograms: Vec<Histogram>) {
+        self.free.push(histograms);
+    }
+
+    // frees every cached node, e.g. before the next tree reuses the node numbers
+    pub fn clear(&mut self) {
+        self.free
+            .extend(self.cached.drain(..).map(|(_, histograms)| histograms));
+    }
+
+    pub fn num_cached(&self) -> usize {
+        self.cached.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_recycles_evicted_buffers() {
+        let templates = vec![Histogram::from_feature(&[0.0, 1.0, 2.0], 2)];
+        let mut pool = HistogramPool::new(&templates, Some(2));
+
+        for node in 0..3 {
+            let mut histograms = pool.acquire();
+            histograms[0].accumulate(&[1.0], &[node as f32 + 1.0], &[1.0], None);
+            pool.insert(node, histograms);
+        }
+        // node 0 was the least recently used
+        assert!(pool.get(0).is_none());
+        assert_eq!(pool.num_cached(), 2);
+        assert_eq!(pool.get(1).unwrap()[0].gradients(), &[0.0, 2.0]);
+
+        // node 0's buffer comes back empty
+        let histograms = pool.acquire();
+        assert_eq!(histograms[0].gradients(), &[0.0, 0.0]);
+        assert_eq!(histograms[0].counts(), &[0, 0]);
+
+        // node 1 was used last, so node 2 goes
+        pool.insert(3, histograms);
+        assert!(pool.get(2).is_none());
+        assert!(pool.get(1).is_some());
+
+        let taken = pool.take(1).unwrap();
+        assert!(pool.take(1).is_none());
+        pool.recycle(taken);
+        pool.clear();
+        assert_eq!(pool.num_cached(), 0);
+    }
+}
@@ -0,0 +1,233 @@
+use crate::tree::{Tree, TreeNode};
+
+// `tree` with subtrees collapsed into single leaves, bottom-up, where
+// - a split with two leaves below it gained less than `min_gain` in training, which only
+//   applies to trees that recorded their gains, or
+// - the leaves of a subtree all lie within `epsilon` of the collapsed leaf after the
+//   shrinkage, so no prediction changes by `epsilon` or more.
+// A collapsed leaf takes the mean of the leaves it replaces, weighted by their covers when
+// the tree has them, and their summed cover. Subtrees with linear leaves are kept.
+pub fn prune_tree(tree: &Tree, min_gain: f32, epsilon: f32) -> Tree {
+    let mut pruner = Pruner {
+        min_gain,
+        epsilon,
+        shrinkage: tree.shrinkage(),
+        leaf_covers: tree.leaf_covers().map(|covers| covers.iter()),
+        split_gains: tree.split_gains().map(|gains| gains.iter()),
+    };
+    let pruned = pruner.prune(tree.root());
+
+    let mut result = Tree::new(Box::new(pruned.node)).with_shrinkage(tree.shrinkage());
+    if tree.lookup_table().is_some() {
+        result = result.with_lookup_table();
+    }
+    if tree.leaf_covers().is_some() {
+        let covers = pruned.leaves.iter().map(|&(_, cover)| cover).collect();
+        result = result.with_leaf_covers(covers);
+    }
+    if tree.split_gains().is_some() {
+        result = result.with_split_gains(pruned.gains);
+    }
+    result
+}
+
+struct Pruner<'a> {
+    min_gain: f32,
+    epsilon: f32,
+    shrinkage: f32,
+    // leaves are visited left to right and splits in preorder, like their records
+    leaf_covers: Option<std::slice::Iter<'a, f32>>,
+    split_gains: Option<std::slice::Iter<'a, f32>>,
+}
+
+struct Pruned {
+    node: TreeNode,
+    // the value and cover of every leaf left, left to right
+    leaves: Vec<(f32, f32)>,
+    // whether the node is a leaf, or a split that could be collapsed into one since no
+    // linear leaf is below it
+    collapsible: bool,
+    // the gains of the splits left, in preorder
+    gains: Vec<f32>,
+}
+
+impl Pruner<'_> {
+    fn prune(&mut self, node: &TreeNode) -> Pruned {
+        let (left_child, right_child) = match node {
+            TreeNode::Leaf { value } => {
+                let cover = self.next_cover();
+                return Pruned {
+                    node: node.clone(),
+                    leaves: vec![(*value, cover)],
+                    collapsible: true,
+                    gains: vec![],
+                };
+            }
+            TreeNode::LinearLeaf { value, .. } => {
+                let cover = self.next_cover();
+                return Pruned {
+                    node: node.clone(),
+                    leaves: vec![(*value, cover)],
+                    collapsible: false,
+                    gains: vec![],
+                };
+            }
+            TreeNode::Split {
+                left_child,
+                right_child,
+                ..
+            }
+            | TreeNode::CategoricalSplit {
+                left_child,
+                right_child,
+                ..
+            } => (left_child, right_child),
+        };
+
+        let gain = self.split_gains.as_mut().and_then(Iterator::next).copied();
+        let left = self.prune(left_child);
+        let right = self.prune(right_child);
+        let mut leaves = left.leaves;
+        leaves.extend(right.leaves);
+
+        if left.collapsible && right.collapsible {
+            let (weighted_sum, total_cover) =
+                leaves
+                    .iter()
+                    .fold((0.0f64, 0.0f64), |(sum, total), &(value, cover)| {
+                        (
+                            sum + f64::from(value) * f64::from(cover),
+                            total + f64::from(cover),
+                        )
+                    });
+            let mean = if total_cover > 0.0 {
+                (weighted_sum / total_cover) as f32
+            } else {
+                leaves.iter().map(|&(value, _)| value).sum::<f32>() / leaves.len() as f32
+            };
+            let spread = leaves
+                .iter()
+                .map(|&(value, _)| (value - mean).abs())
+                .fold(0.0, f32::max);
+            let low_gain = leaves.len() == 2 && gain.is_some_and(|gain| gain < self.min_gain);
+            if low_gain || spread * self.shrinkage.abs() < self.epsilon {
+                return Pruned {
+                    node: TreeNode::Leaf { value: mean },
+                    leaves: vec![(mean, total_cover as f32)],
+                    collapsible: true,
+                    gains: vec![],
+                };
+            }
+        }
+
+        let mut gains: Vec<f32> = gain.into_iter().collect();
+        gains.extend(left.gains);
+        gains.extend(right.gains);
+        let (left_child, right_child) = (Box::new(left.node), Box::new(right.node));
+        let node = match node {
+            TreeNode::Split {
+                feature_index,
+                threshold,
+                default_left,
+                ..
+            } => TreeNode::Split {
+                feature_index: *feature_index,
+                threshold: *threshold,
+                default_left: *default_left,
+                left_child,
+                right_child,
+            },
+            TreeNode::CategoricalSplit {
+                feature_index,
+                categories,
+                ..
+            } => TreeNode::CategoricalSplit {
+                feature_index: *feature_index,
+                categories: categories.clone(),
+                left_child,
+                right_child,
+            },
+            TreeNode::Leaf { .. } | TreeNode::LinearLeaf { .. } => unreachable!(),
+        };
+        Pruned {
+            node,
+            leaves,
+            collapsible: false,
+            gains,
+        }
+    }
+
+    // the cover of the next leaf, 1 when the tree records none so leaves count equally
+    fn next_cover(&mut self) -> f32 {
+        self.leaf_covers
+            .as_mut()
+            .and_then(Iterator::next)
+            .copied()
+            .unwrap_or(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // splits on f0 < 5 into a pair of close leaves and a pair of distant ones
+    fn tree() -> Tree {
+        let stump = |threshold, left, right| {
+            Box::new(TreeNode::Split {
+                feature_index: 1,
+                threshold,
+                default_left: false,
+                left_child: Box::new(TreeNode::Leaf { value: left }),
+                right_child: Box::new(TreeNode::Leaf { value: right }),
+            })
+        };
+        Tree::new(Box::new(TreeNode::Split {
+            feature_index: 0,
+            threshold: 5.0,
+            default_left: false,
+            left_child: stump(1.0, 1.0, 1.1),
+            right_child: stump(2.0, -4.0, 4.0),
+        }))
+        .with_shrinkage(0.5)
+        .with_leaf_covers(vec![3.0, 1.0, 2.0, 2.0])
+        .with_split_gains(vec![50.0, 0.1, 9.0])
+    }
+
+    #[test]
+    fn test_collapses_subtrees_that_barely_change_predictions() {
+        let tree = tree();
+        let pruned = prune_tree(&tree, 0.0, 0.1);
+        assert_eq!(pruned.num_leaves(), 3);
+        assert_eq!(pruned.leaf_covers(), Some(&[4.0, 2.0, 2.0][..]));
+        assert_eq!(pruned.split_gains(), Some(&[50.0, 9.0][..]));
+        for row in [[0.0, 0.0], [0.0, 3.0], [7.0, 0.0], [7.0, 3.0]] {
+            assert!((pruned.predict(&row) - tree.predict(&row)).abs() < 0.1);
+        }
+        // the cover-weighted mean of 1.0 and 1.1, with the shrinkage
+        assert!((pruned.predict(&[0.0, 0.0]) - 0.5125).abs() < 1e-6);
+
+        // nothing is close enough to collapse
+        assert_eq!(prune_tree(&tree, 0.0, 0.01).root(), tree.root());
+        // everything is
+        let stump = prune_tree(&tree, 0.0, 10.0);
+        assert_eq!(stump.num_leaves(), 1);
+        assert_eq!(stump.split_gains(), Some(&[][..]));
+    }
+
+    #[test]
+    fn test_collapses_low_gain_splits_bottom_up() {
+        let tree = tree();
+        let pruned = prune_tree(&tree, 1.0, 0.0);
+        assert_eq!(pruned.num_leaves(), 3);
+        assert_eq!(pruned.split_gains(), Some(&[50.0, 9.0][..]));
+
+        // once both stumps collapse the root has two leaves, and its gain is high enough
+        assert_eq!(prune_tree(&tree, 10.0, 0.0).num_leaves(), 2);
+        assert_eq!(prune_tree(&tree, 100.0, 0.0).num_leaves(), 1);
+
+        // without gains only the spread of the leaves counts
+        let ungained = Tree::new(Box::new(tree.root().clone()));
+        assert_eq!(prune_tree(&ungained, 100.0, 0.0).num_leaves(), 4);
+    }
+}
@@ -0,0 +1,134 @@
+use crate::random::Rng;
+
+// Gradients and hessians rounded to a few integer levels, as LightGBM's
+// `use_quantized_grad` does, so histograms read one byte per row instead of four and sum
+// integers exactly. Rounding is stochastic, which keeps every sum unbiased. A gradient of
+// level q stands for `q * gradient_scale`, a hessian likewise.
+#[derive(Debug, Clone)]
+pub struct QuantizedGradients {
+    gradients: Vec<i8>,
+    hessians: Vec<i8>,
+    gradient_scale: f32,
+    hessian_scale: f32,
+}
+
+impl QuantizedGradients {
+    // Gradients get `num_bins / 2` levels on either side of zero and hessians `num_bins`
+    // levels, after scaling both by `weights`. `num_bins` can be at most 127 so levels fit
+    // in an i8.
+    pub fn new(
+        gradients: &[f32],
+        hessians: &[f32],
+        weights: Option<&[f32]>,
+        num_bins: usize,
+        rng: &mut Rng,
+    ) -> Self {
+        debug_assert!((2..=127).contains(&num_bins));
+        let weight = |row: usize| weights.map_or(1.0, |weights| weights[row]);
+        let weighted = |values: &[f32]| -> Vec<f32> {
+            (0..values.len())
+                .map(|row| weight(row) * values[row])
+                .collect()
+        };
+        let (gradients, hessians) = (weighted(gradients), weighted(hessians));
+
+        let scale = |values: &[f32], levels: usize| {
+            let max = values
+                .iter()
+                .fold(0.0f32, |max, value| max.max(value.abs()));
+            if max > 0.0 { max / levels as f32 } else { 1.0 }
+        };
+        let gradient_scale = scale(&gradients, num_bins / 2);
+        let hessian_scale = scale(&hessians, num_bins);
+        let mut round = |value: f32, scale: f32| {
+            let level = value / scale;
+            let floor = level.floor();
+            // up with the probability of the fractional part
+            let up = rng.next_f32() < level - floor;
+            (floor + up as u8 as f32) as i8
+        };
+        Self {
+            gradients: gradients
+                .iter()
+                .map(|&gradient| round(gradient, gradient_scale))
+                .collect(),
+            hessians: hessians
+                .iter()
+                .map(|&hessian| round(hessian, hessian_scale))
+                .collect(),
+            gradient_scale,
+            hessian_scale,
+        }
+    }
+
+    pub fn gradients(&self) -> &[i8] {
+        &self.gradients
+    }
+
+    pub fn hessians(&self) -> &[i8] {
+        &self.hessians
+    }
+
+    // the integer gradient and hessian sums of `rows`
+    pub fn sums(&self, rows: &[usize]) -> (i64, i64) {
+        rows.iter().fold((0, 0), |(gradient, hessian), &row| {
+            (
+                gradient + self.gradients[row] as i64,
+                hessian + self.hessians[row] as i64,
+            )
+        })
+    }
+
+    // integer sums back in the scale of the original gradients and hessians
+    pub fn dequantize(&self, (gradient, hessian): (i64, i64)) -> (f32, f32) {
+        (
+            gradient as f32 * self.gradient_scale,
+            hessian as f32 * self.hessian_scale,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_quantized_sums_stay_close_and_unbiased() {
+        let n = 10_000;
+        let gradients: Vec<f32> = (0..n).map(|i| ((i % 201) as f32 - 100.0) / 37.0).collect();
+        let hessians = vec![0.25; n];
+        let quantized = QuantizedGradients::new(&gradients, &hessians, None, 4, &mut Rng::new(3));
+
+        assert!(
+            quantized
+                .gradients()
+                .iter()
+                .all(|level| (-2..=2).contains(level))
+        );
+        // a constant hessian is the top level exactly
+        assert!(quantized.hessians().iter().all(|&level| level == 4));
+
+        let rows: Vec<usize> = (0..n).collect();
+        let (sum_gradients, sum_hessians) = quantized.dequantize(quantized.sums(&rows));
+        let expected: f32 = gradients.iter().sum();
+        let max = 100.0 / 37.0;
+        // the rounding errors of n rows cancel out to about sqrt(n) levels
+        assert!((sum_gradients - expected).abs() < 4.0 * (n as f32).sqrt() * max / 2.0);
+        assert_abs_diff_eq!(sum_hessians, 0.25 * n as f32, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_weights_scale_before_quantizing() {
+        let weights = [1.0, 0.0, 2.0];
+        let quantized = QuantizedGradients::new(
+            &[2.0, 5.0, 1.0],
+            &[1.0; 3],
+            Some(&weights),
+            2,
+            &mut Rng::new(0),
+        );
+        assert_eq!(quantized.gradients(), &[1, 0, 1]);
+        assert_eq!(quantized.hessians(), &[1, 0, 2]);
+    }
+}
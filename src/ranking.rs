@@ -0,0 +1,169 @@
+use std::ops::Range;
+
+// DCG helpers shared by the LambdaRank objective and ranking evaluation. Relevance labels
+// use the exponential gain 2^label - 1 and positions are discounted by 1 / log2(rank + 2),
+// with ranks starting at 0.
+
+pub fn label_gain(label: f32) -> f32 {
+    label.exp2() - 1.0
+}
+
+pub fn discount(rank: usize) -> f32 {
+    1.0 / (rank as f32 + 2.0).log2()
+}
+
+// Row indices of one query sorted by descending score; ties keep their original order.
+pub fn rank_by_score(scores: &[f32]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..scores.len()).collect();
+    order.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+    order
+}
+
+// DCG of the best possible ordering, truncated at k
+pub fn max_dcg_at(labels: &[f32], k: usize) -> f32 {
+    let mut sorted = labels.to_vec();
+    sorted.sort_by(|a, b| b.total_cmp(a));
+    sorted
+        .iter()
+        .take(k)
+        .enumerate()
+        .map(|(rank, &label)| label_gain(label) * discount(rank))
+        .sum()
+}
+
+// NDCG@k of a single query. A query without any relevant document counts as perfectly
+// ranked, following LightGBM.
+pub fn ndcg_at(scores: &[f32], labels: &[f32], k: usize) -> f32 {
+    let max_dcg = max_dcg_at(labels, k);
+    if max_dcg <= 0.0 {
+        return 1.0;
+    }
+
+    let dcg: f32 = rank_by_score(scores)
+        .iter()
+        .take(k)
+        .enumerate()
+        .map(|(rank, &i)| label_gain(labels[i]) * discount(rank))
+        .sum();
+    dcg / max_dcg
+}
+
+// NDCG@k averaged over all queries described by `query_boundaries`
+pub fn mean_ndcg_at(scores: &[f32], labels: &[f32], query_boundaries: &[usize], k: usize) -> f32 {
+    mean_over_queries(query_boundaries, |rows| {
+        ndcg_at(&scores[rows.clone()], &labels[rows], k)
+    })
+}
+
+// Average precision within the top k of a single query, treating labels > 0 as relevant and
+// normalizing by min(k, number of relevant documents). A query without relevant documents
+// counts as 1, following LightGBM.
+pub fn average_precision_at(scores: &[f32], labels: &[f32], k: usize) -> f32 {
+    let n_relevant = labels.iter().filter(|&&label| label > 0.0).count();
+    if n_relevant == 0 {
+        return 1.0;
+    }
+
+    let mut hits = 0;
+    let mut precision_sum = 0.0;
+    for (rank, &i) in rank_by_score(scores).iter().take(k).enumerate() {
+        if labels[i] > 0.0 {
+            hits += 1;
+            precision_sum += hits as f32 / (rank + 1) as f32;
+        }
+    }
+    precision_sum / n_relevant.min(k) as f32
+}
+
+// MAP@k averaged over all queries described by `query_boundaries`
+pub fn mean_average_precision_at(
+    scores: &[f32],
+    labels: &[f32],
+    query_boundaries: &[usize],
+    k: usize,
+) -> f32 {
+    mean_over_queries(query_boundaries, |rows| {
+        average_precision_at(&scores[rows.clone()], &labels[rows], k)
+    })
+}
+
+fn mean_over_queries(query_boundaries: &[usize], per_query: impl Fn(Range<usize>) -> f32) -> f32 {
+    let n_queries = query_boundaries.len().saturating_sub(1);
+    if n_queries == 0 {
+        return 1.0;
+    }
+
+    let total: f32 = query_boundaries
+        .windows(2)
+        .map(|query| per_query(query[0]..query[1]))
+        .sum();
+    total / n_queries as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_ndcg_perfect_and_reversed_ranking() {
+        let labels = vec![3.0, 2.0, 0.0];
+
+        assert_abs_diff_eq!(ndcg_at(&[3.0, 2.0, 1.0], &labels, 3), 1.0, epsilon = 1e-6);
+
+        // reversed: dcg = 0 + 3 / log2(3) + 7 / 2, ideal = 7 + 3 / log2(3)
+        let expected = (3.0 / 3f32.log2() + 3.5) / (7.0 + 3.0 / 3f32.log2());
+        assert_abs_diff_eq!(
+            ndcg_at(&[1.0, 2.0, 3.0], &labels, 3),
+            expected,
+            epsilon = 1e-6
+        );
+    }
+
+    #[test]
+    fn test_ndcg_truncation_and_empty_queries() {
+        // only the top position counts at k = 1
+        assert_abs_diff_eq!(ndcg_at(&[2.0, 1.0], &[1.0, 0.0], 1), 1.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(ndcg_at(&[1.0, 2.0], &[1.0, 0.0], 1), 0.0, epsilon = 1e-6);
+
+        assert_abs_diff_eq!(ndcg_at(&[1.0, 2.0], &[0.0, 0.0], 2), 1.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_mean_ndcg_over_queries() {
+        let scores = vec![2.0, 1.0, 1.0, 2.0];
+        let labels = vec![1.0, 0.0, 1.0, 0.0];
+
+        let ndcg = mean_ndcg_at(&scores, &labels, &[0, 2, 4], 1);
+
+        assert_abs_diff_eq!(ndcg, 0.5, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_average_precision() {
+        // relevant documents at ranks 1 and 3: (1 / 1 + 2 / 3) / 2
+        let scores = vec![4.0, 3.0, 2.0, 1.0];
+        let labels = vec![1.0, 0.0, 1.0, 0.0];
+        assert_abs_diff_eq!(
+            average_precision_at(&scores, &labels, 4),
+            5.0 / 6.0,
+            epsilon = 1e-6
+        );
+
+        // at k = 1 only the first hit counts, normalized by min(1, 2)
+        assert_abs_diff_eq!(
+            average_precision_at(&scores, &labels, 1),
+            1.0,
+            epsilon = 1e-6
+        );
+        assert_abs_diff_eq!(
+            average_precision_at(&scores, &[0.0; 4], 4),
+            1.0,
+            epsilon = 1e-6
+        );
+
+        let map =
+            mean_average_precision_at(&[1.0, 2.0, 2.0, 1.0], &[1.0, 0.0, 1.0, 0.0], &[0, 2, 4], 2);
+        assert_abs_diff_eq!(map, (0.5 + 1.0) / 2.0, epsilon = 1e-6);
+    }
+}
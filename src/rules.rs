@@ -0,0 +1,302 @@
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use crate::sparse::FeatureRow;
+use crate::tree::{Tree, TreeNode, in_categories};
+
+// One root-to-leaf path of a tree as a conjunction of conditions: a row that meets all of
+// them reaches the leaf and gets `value` added to its score.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Rule {
+    pub conditions: Vec<Condition>,
+    pub value: f32,
+}
+
+// What one split on the path asks of a row. `missing` says whether rows missing the
+// feature meet a threshold condition; they never meet `In` and always meet `NotIn`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Condition {
+    Below {
+        feature_index: usize,
+        threshold: f32,
+        missing: bool,
+    },
+    AtLeast {
+        feature_index: usize,
+        threshold: f32,
+        missing: bool,
+    },
+    In {
+        feature_index: usize,
+        categories: Vec<u32>,
+    },
+    NotIn {
+        feature_index: usize,
+        categories: Vec<u32>,
+    },
+}
+
+// The rules of every tree of a model, see `Model::rule_set`. A row's raw score for an
+// output is its base score plus the values of the rules it meets among that output's
+// trees.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RuleSet {
+    pub feature_names: Option<Vec<String>>,
+    pub base_scores: Vec<f32>,
+    pub trees: Vec<TreeRules>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TreeRules {
+    // the output the tree adds to
+    pub output: usize,
+    pub rules: Vec<Rule>,
+}
+
+// The rules of `tree`, one per leaf left to right, whose values are the leaf values with
+// the tree's shrinkage applied times `scale`. Linear leaves have no single value, so trees
+// with them have no rules.
+pub fn tree_rules(tree: &Tree, scale: f32) -> Result<Vec<Rule>> {
+    let mut rules = Vec::new();
+    let mut stack = vec![(tree.root(), Vec::new())];
+    while let Some((node, conditions)) = stack.pop() {
+        let (left, right, left_child, right_child) = match node {
+            TreeNode::Leaf { value } => {
+                rules.push(Rule {
+                    conditions,
+                    value: tree.shrinkage() * value * scale,
+                });
+                continue;
+            }
+            TreeNode::LinearLeaf { .. } => {
+                return Err(Error::invalid_data(
+                    "trees with linear leaves can't be written as rules",
+                ));
+            }
+            TreeNode::Split {
+                feature_index,
+                threshold,
+                default_left,
+                left_child,
+                right_child,
+            } => (
+                Condition::Below {
+                    feature_index: *feature_index,
+                    threshold: *threshold,
+                    missing: *default_left,
+                },
+                Condition::AtLeast {
+                    feature_index: *feature_index,
+                    threshold: *threshold,
+                    missing: !default_left,
+                },
+                left_child,
+                right_child,
+            ),
+            TreeNode::CategoricalSplit {
+                feature_index,
+                categories,
+                left_child,
+                right_child,
+            } => (
+                Condition::In {
+                    feature_index: *feature_index,
+                    categories: categories.clone(),
+                },
+                Condition::NotIn {
+                    feature_index: *feature_index,
+                    categories: categories.clone(),
+                },
+                left_child,
+                right_child,
+            ),
+        };
+        let mut right_conditions = conditions.clone();
+        right_conditions.push(right);
+        let mut left_conditions = conditions;
+        left_conditions.push(left);
+        stack.push((right_child, right_conditions));
+        stack.push((left_child, left_conditions));
+    }
+    Ok(rules)
+}
+
+impl Condition {
+    pub fn feature_index(&self) -> usize {
+        match self {
+            Condition::Below { feature_index, .. }
+            | Condition::AtLeast { feature_index, .. }
+            | Condition::In { feature_index, .. }
+            | Condition::NotIn { feature_index, .. } => *feature_index,
+        }
+    }
+
+    pub fn matches<R: FeatureRow + ?Sized>(&self, row: &R) -> bool {
+        let value = row.value(self.feature_index());
+        match self {
+            Condition::Below {
+                threshold, missing, ..
+            } => value < *threshold || (value.is_nan() && *missing),
+            Condition::AtLeast {
+                threshold, missing, ..
+            } => value >= *threshold || (value.is_nan() && *missing),
+            Condition::In { categories, .. } => in_categories(categories, value),
+            Condition::NotIn { categories, .. } => !in_categories(categories, value),
+        }
+    }
+
+    // e.g. `f3 < 0.5`, `(f3 >= 0.5 OR f3 is missing)` or `color in {3, 9}`, features called
+    // by `feature_names` when given
+    pub fn to_text(&self, feature_names: Option<&[String]>) -> String {
+        let name = match feature_names.and_then(|names| names.get(self.feature_index())) {
+            Some(name) => name.clone(),
+            None => format!("f{}", self.feature_index()),
+        };
+        let (op, threshold, missing) = match self {
+            Condition::Below {
+                threshold, missing, ..
+            } => ("<", threshold, missing),
+            Condition::AtLeast {
+                threshold, missing, ..
+            } => (">=", threshold, missing),
+            Condition::In { categories, .. } => {
+                return format!("{name} in {}", category_list(categories));
+            }
+            Condition::NotIn { categories, .. } => {
+                return format!("{name} not in {}", category_list(categories));
+            }
+        };
+        if *missing {
+            format!("({name} {op} {threshold} OR {name} is missing)")
+        } else {
+            format!("{name} {op} {threshold}")
+        }
+    }
+}
+
+fn category_list(categories: &[u32]) -> String {
+    let categories: Vec<String> = categories.iter().map(u32::to_string).collect();
+    format!("{{{}}}", categories.join(", "))
+}
+
+impl Rule {
+    pub fn matches<R: FeatureRow + ?Sized>(&self, row: &R) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.matches(row))
+    }
+
+    // e.g. `f3 < 0.5 AND f7 >= 12 => 0.83`; a rule without conditions is `TRUE => 0.83`
+    pub fn to_text(&self, feature_names: Option<&[String]>) -> String {
+        let conditions: Vec<String> = self
+            .conditions
+            .iter()
+            .map(|condition| condition.to_text(feature_names))
+            .collect();
+        if conditions.is_empty() {
+            format!("TRUE => {}", self.value)
+        } else {
+            format!("{} => {}", conditions.join(" AND "), self.value)
+        }
+    }
+}
+
+impl RuleSet {
+    // every tree's rules under a `tree {i} (output {o}):` header, after the base scores
+    pub fn to_text(&self) -> String {
+        let feature_names = self.feature_names.as_deref();
+        let mut text = String::new();
+        for (output, base_score) in self.base_scores.iter().enumerate() {
+            writeln!(text, "base score (output {output}): {base_score}").unwrap();
+        }
+        for (i, tree) in self.trees.iter().enumerate() {
+            writeln!(text, "tree {i} (output {}):", tree.output).unwrap();
+            for rule in &tree.rules {
+                writeln!(text, "    {}", rule.to_text(feature_names)).unwrap();
+            }
+        }
+        text
+    }
+
+    // the rule set as JSON, conditions naming their feature by index and operator by `op`
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|err| Error::format(format!("writing the rules as JSON: {err}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree() -> Tree {
+        Tree::new(Box::new(TreeNode::Split {
+            feature_index: 3,
+            threshold: 0.5,
+            default_left: false,
+            left_child: Box::new(TreeNode::Leaf { value: 1.0 }),
+            right_child: Box::new(TreeNode::CategoricalSplit {
+                feature_index: 7,
+                categories: vec![2, 12],
+                left_child: Box::new(TreeNode::Leaf { value: -2.0 }),
+                right_child: Box::new(TreeNode::Leaf { value: 4.0 }),
+            }),
+        }))
+        .with_shrinkage(0.5)
+    }
+
+    #[test]
+    fn test_one_rule_per_leaf() {
+        let rules = tree_rules(&tree(), 1.0).unwrap();
+        let text: Vec<String> = rules.iter().map(|rule| rule.to_text(None)).collect();
+        assert_eq!(
+            text,
+            [
+                "f3 < 0.5 => 0.5",
+                "(f3 >= 0.5 OR f3 is missing) AND f7 in {2, 12} => -1",
+                "(f3 >= 0.5 OR f3 is missing) AND f7 not in {2, 12} => 2",
+            ]
+        );
+
+        // every row meets exactly the rule of the leaf it reaches
+        let mut row = [0.0; 8];
+        for (x3, x7) in [(0.0, 2.0), (1.0, 12.0), (1.0, 5.0), (f32::NAN, f32::NAN)] {
+            row[3] = x3;
+            row[7] = x7;
+            let met: Vec<&Rule> = rules.iter().filter(|rule| rule.matches(&row[..])).collect();
+            assert_eq!(met.len(), 1);
+            assert_eq!(met[0].value, tree().predict(&row));
+        }
+
+        let leaf = Tree::new(Box::new(TreeNode::Leaf { value: 3.0 }));
+        assert_eq!(
+            tree_rules(&leaf, 0.5).unwrap()[0].to_text(None),
+            "TRUE => 1.5"
+        );
+    }
+
+    #[test]
+    fn test_json_tags_the_conditions() {
+        let rule_set = RuleSet {
+            feature_names: None,
+            base_scores: vec![0.25],
+            trees: vec![TreeRules {
+                output: 0,
+                rules: tree_rules(&tree(), 1.0).unwrap(),
+            }],
+        };
+        let json: serde_json::Value = serde_json::from_str(&rule_set.to_json().unwrap()).unwrap();
+        let condition = &json["trees"][0]["rules"][1]["conditions"][0];
+        assert_eq!(condition["op"], "at_least");
+        assert_eq!(condition["feature_index"], 3);
+        assert_eq!(condition["missing"], true);
+        assert!(
+            rule_set
+                .to_text()
+                .starts_with("base score (output 0): 0.25\ntree 0")
+        );
+    }
+}
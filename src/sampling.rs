@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+
+use crate::random::Rng;
+
+// How the rows each iteration's trees are grown on are chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum SampleStrategy {
+    // all rows, or a random `bagging_fraction` of them when bagging is enabled
+    #[default]
+    Bagging,
+    // Gradient-based one-side sampling: keep the `top_rate` of rows with the largest
+    // gradients, sample `other_rate` of all rows from the remainder and up-weight those by
+    // (1 - top_rate) / other_rate so the gradient sums stay unbiased.
+    Goss {
+        top_rate: f32,
+        other_rate: f32,
+    },
+}
+
+// A random `fraction` of 0..n_rows, at least one row. With `weights`, only rows of positive
+// weight are drawn from, and the fraction is of those rows.
+pub fn bagging(rng: &mut Rng, n_rows: usize, weights: Option<&[f32]>, fraction: f32) -> Vec<usize> {
+    let candidates = weighted_rows(n_rows, weights);
+    let bag_size = (candidates.len() as f32 * fraction).round() as usize;
+    rng.sample(candidates.len(), bag_size.max(1))
+        .into_iter()
+        .map(|i| candidates[i])
+        .collect()
+}
+
+// The rows of 0..n_rows that carry weight; zero weight rows would only count towards
+// min_data_in_leaf without contributing to the fit.
+pub fn weighted_rows(n_rows: usize, weights: Option<&[f32]>) -> Vec<usize> {
+    match weights {
+        Some(weights) => (0..n_rows).filter(|&row| weights[row] > 0.0).collect(),
+        None => (0..n_rows).collect(),
+    }
+}
+
+// Selects the GOSS rows and scales the gradients and hessians of the sampled small-gradient
+// rows in place. `gradients` and `hessians` hold `num_outputs` blocks of n_rows values; a
+// row's magnitude is its weight times the sum of |gradient| over the outputs.
+pub fn goss(
+    rng: &mut Rng,
+    gradients: &mut [f32],
+    hessians: &mut [f32],
+    weights: Option<&[f32]>,
+    n_rows: usize,
+    top_rate: f32,
+    other_rate: f32,
+) -> Vec<usize> {
+    let num_outputs = gradients.len() / n_rows.max(1);
+    let magnitude = |row: usize| -> f32 {
+        let weight = weights.map_or(1.0, |weights| weights[row]);
+        weight
+            * (0..num_outputs)
+                .map(|k| gradients[k * n_rows + row].abs())
+                .sum::<f32>()
+    };
+
+    let mut order: Vec<usize> = (0..n_rows).collect();
+    order.sort_by(|&a, &b| magnitude(b).total_cmp(&magnitude(a)));
+
+    let n_top = ((n_rows as f32 * top_rate).round() as usize).min(n_rows);
+    let n_other = ((n_rows as f32 * other_rate).round() as usize).min(n_rows - n_top);
+    let (top, rest) = order.split_at(n_top);
+
+    let weight = (1.0 - top_rate) / other_rate;
+    let mut rows = top.to_vec();
+    for i in rng.sample(rest.len(), n_other) {
+        let row = rest[i];
+        for k in 0..num_outputs {
+            gradients[k * n_rows + row] *= weight;
+            hessians[k * n_rows + row] *= weight;
+        }
+        rows.push(row);
+    }
+
+    rows.sort_unstable();
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_goss_keeps_large_gradients_and_reweights_the_rest() {
+        let mut gradients: Vec<f32> = vec![5.0, -0.1, 0.2, -6.0, 0.1, 0.3, -0.2, 0.1, 4.0, 0.2];
+        let mut hessians = vec![1.0; 10];
+        let original = gradients.clone();
+
+        let rows = goss(
+            &mut Rng::new(0),
+            &mut gradients,
+            &mut hessians,
+            None,
+            10,
+            0.3,
+            0.2,
+        );
+
+        assert_eq!(rows.len(), 5);
+        for top in [0, 3, 8] {
+            assert!(rows.contains(&top));
+            assert_eq!(gradients[top], original[top]);
+            assert_eq!(hessians[top], 1.0);
+        }
+
+        // (1 - 0.3) / 0.2
+        let sampled: Vec<usize> = rows
+            .iter()
+            .copied()
+            .filter(|row| ![0, 3, 8].contains(row))
+            .collect();
+        assert_eq!(sampled.len(), 2);
+        for row in sampled {
+            assert_abs_diff_eq!(gradients[row], original[row] * 3.5, epsilon = 1e-6);
+            assert_abs_diff_eq!(hessians[row], 3.5, epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_bagging_draws_fraction_of_rows() {
+        let rows = bagging(&mut Rng::new(1), 20, None, 0.25);
+
+        assert_eq!(rows.len(), 5);
+        assert!(rows.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(bagging(&mut Rng::new(1), 3, None, 0.01).len(), 1);
+
+        let weights: Vec<f32> = (0..20).map(|i| (i % 2) as f32).collect();
+        let rows = bagging(&mut Rng::new(1), 20, Some(&weights), 0.4);
+        assert_eq!(rows.len(), 4);
+        assert!(rows.iter().all(|row| row % 2 == 1));
+    }
+}
@@ -0,0 +1,66 @@
+use std::f32::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+// How the learning rate changes over the boosting iterations. `Params::learning_rate` is
+// the rate of the first iteration; the decaying schedules reach their final rate on the
+// last one.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum LearningRateSchedule {
+    #[default]
+    Constant,
+    LinearDecay {
+        final_rate: f32,
+    },
+    Cosine {
+        min_rate: f32,
+    },
+}
+
+impl LearningRateSchedule {
+    pub fn learning_rate(&self, base_rate: f32, iteration: usize, num_iterations: usize) -> f32 {
+        // fraction of training done, 0 on the first iteration and 1 on the last
+        let progress = if num_iterations > 1 {
+            iteration as f32 / (num_iterations - 1) as f32
+        } else {
+            0.0
+        };
+
+        match *self {
+            LearningRateSchedule::Constant => base_rate,
+            LearningRateSchedule::LinearDecay { final_rate } => {
+                base_rate + (final_rate - base_rate) * progress
+            }
+            LearningRateSchedule::Cosine { min_rate } => {
+                min_rate + 0.5 * (base_rate - min_rate) * (1.0 + (PI * progress).cos())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_linear_decay() {
+        let schedule = LearningRateSchedule::LinearDecay { final_rate: 0.0 };
+
+        assert_abs_diff_eq!(schedule.learning_rate(0.4, 0, 5), 0.4, epsilon = 1e-6);
+        assert_abs_diff_eq!(schedule.learning_rate(0.4, 1, 5), 0.3, epsilon = 1e-6);
+        assert_abs_diff_eq!(schedule.learning_rate(0.4, 4, 5), 0.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_cosine() {
+        let schedule = LearningRateSchedule::Cosine { min_rate: 0.1 };
+
+        assert_abs_diff_eq!(schedule.learning_rate(0.5, 0, 3), 0.5, epsilon = 1e-6);
+        assert_abs_diff_eq!(schedule.learning_rate(0.5, 1, 3), 0.3, epsilon = 1e-6);
+        assert_abs_diff_eq!(schedule.learning_rate(0.5, 2, 3), 0.1, epsilon = 1e-6);
+
+        // a single iteration uses the base rate
+        assert_abs_diff_eq!(schedule.learning_rate(0.5, 0, 1), 0.5, epsilon = 1e-6);
+    }
+}
@@ -0,0 +1,656 @@
+// TreeSHAP (Lundberg, Erion and Lee, "Consistent Individualized Feature Attribution for
+// Tree Ensembles"): the exact SHAP values of a tree's prediction in time polynomial in its
+// depth, where the definition sums over every subset of the features. A feature that
+// isn't known is integrated out by following both sides of its splits, weighed by the
+// share of the training data that went each way, like LightGBM's `pred_contrib` and
+// XGBoost's `pred_contribs`.
+//
+// The algorithm walks the tree once, keeping for the path from the root the features split
+// on along it and, for every number of them, the weight of the subsets of that size in the
+// Shapley sum. A feature split on twice along a path is kept once, its fractions
+// multiplied.
+//
+// `add_approximate_contributions` is Saabas' much cheaper attribution instead, XGBoost's
+// `approx_contribs`: following only the row's own path, every split credits its feature
+// with how much it moved the expected prediction. It needs one pass down the path, but
+// splits near the root get credit for the interactions below them, and the result
+// depends on the order the tree splits in.
+//
+// `add_interactions` splits the SHAP values further into the SHAP interaction values of
+// every pair of features, from the same walk run twice for every feature the tree splits
+// on: once with that feature always known and once with it never known. Half of the
+// difference the feature makes to another's SHAP value is their interaction.
+
+use crate::error::{Error, Result};
+use crate::sparse::FeatureRow;
+use crate::tree::{Tree, TreeNode};
+
+// a node of the tree with how much training data reached it
+struct Node<'a> {
+    node: &'a TreeNode,
+    cover: f64,
+    // the feature split on and the left and right children, None for leaves
+    split: Option<(usize, usize, usize)>,
+}
+
+// A feature taken as known on every path (true) or unknown on all of them, and the share
+// of the weight still coming down a path once its splits on the feature are counted.
+#[derive(Clone, Copy)]
+struct Condition {
+    feature: Option<(usize, bool)>,
+    fraction: f64,
+}
+
+const UNCONDITIONED: Condition = Condition {
+    feature: None,
+    fraction: 1.0,
+};
+
+#[derive(Clone, Copy)]
+struct PathElement {
+    // None for the element the root starts the path with
+    feature_index: Option<usize>,
+    // the share of the rows reaching the split that continue on the path
+    zero_fraction: f64,
+    // 1 if the row being explained continues on the path, 0 if not
+    one_fraction: f64,
+    // weight of the subsets with as many features as the element's position
+    weight: f64,
+}
+
+// Adds the SHAP values of `tree`'s prediction for `row`, times `scale`, to
+// `contributions`: one per feature, followed by the tree's expected value.
+pub fn add_contributions<R: FeatureRow + ?Sized>(
+    tree: &Tree,
+    row: &R,
+    scale: f64,
+    contributions: &mut [f64],
+) -> Result<()> {
+    let scale = scale * f64::from(tree.shrinkage());
+    let bias = contributions.len() - 1;
+    if let TreeNode::Leaf { value } = tree.root() {
+        contributions[bias] += scale * f64::from(*value);
+        return Ok(());
+    }
+
+    let nodes = flatten_with_covers(tree)?;
+    contributions[bias] += scale * expected_values(&nodes)[0];
+
+    visit(
+        &nodes,
+        0,
+        row,
+        Vec::new(),
+        (1.0, 1.0, None),
+        UNCONDITIONED,
+        &mut |feature_index, value| {
+            contributions[feature_index] += scale * value;
+        },
+    );
+    Ok(())
+}
+
+// Adds the SHAP interaction values of `tree`'s prediction for `row`, times `scale`, to
+// the square `interactions`, row by row: one row and column per feature, then one for the
+// tree's expected value in the last diagonal entry. Each feature's row adds up to its
+// SHAP value, the interactions off the diagonal and its main effect on it.
+pub fn add_interactions<R: FeatureRow + ?Sized>(
+    tree: &Tree,
+    row: &R,
+    scale: f64,
+    interactions: &mut [f64],
+) -> Result<()> {
+    let scale = scale * f64::from(tree.shrinkage());
+    let size = interactions.len().isqrt();
+    let bias = size - 1;
+    if let TreeNode::Leaf { value } = tree.root() {
+        interactions[bias * size + bias] += scale * f64::from(*value);
+        return Ok(());
+    }
+    let nodes = flatten_with_covers(tree)?;
+    interactions[bias * size + bias] += scale * expected_values(&nodes)[0];
+
+    let contributions_given = |condition: Condition| {
+        let mut contributions = vec![0.0; size];
+        visit(
+            &nodes,
+            0,
+            row,
+            Vec::new(),
+            (1.0, 1.0, None),
+            condition,
+            &mut |feature_index, value| contributions[feature_index] += value,
+        );
+        contributions
+    };
+    let contributions = contributions_given(UNCONDITIONED);
+    let mut features: Vec<usize> = nodes
+        .iter()
+        .filter_map(|node| node.split.map(|(feature_index, ..)| feature_index))
+        .collect();
+    features.sort_unstable();
+    features.dedup();
+    for feature in features {
+        let [known, unknown] = [true, false].map(|known| {
+            contributions_given(Condition {
+                feature: Some((feature, known)),
+                fraction: 1.0,
+            })
+        });
+        let feature_row = &mut interactions[feature * size..(feature + 1) * size];
+        feature_row[feature] += scale * contributions[feature];
+        for other in (0..bias).filter(|&other| other != feature) {
+            let interaction = scale * (known[other] - unknown[other]) / 2.0;
+            feature_row[other] += interaction;
+            feature_row[feature] -= interaction;
+        }
+    }
+    Ok(())
+}
+
+// Like `add_contributions`, but Saabas' approximation: each split on the path of `row`
+// adds the change in the expected prediction below it to its feature.
+pub fn add_approximate_contributions<R: FeatureRow + ?Sized>(
+    tree: &Tree,
+    row: &R,
+    scale: f64,
+    contributions: &mut [f64],
+) -> Result<()> {
+    let scale = scale * f64::from(tree.shrinkage());
+    let bias = contributions.len() - 1;
+    if let TreeNode::Leaf { value } = tree.root() {
+        contributions[bias] += scale * f64::from(*value);
+        return Ok(());
+    }
+    let nodes = flatten_with_covers(tree)?;
+    let expected = expected_values(&nodes);
+    contributions[bias] += scale * expected[0];
+    let mut index = 0;
+    while let Some((feature_index, left, right)) = nodes[index].split {
+        let (goes_left, ..) = Tree::branch(nodes[index].node, row).unwrap();
+        let child = if goes_left { left } else { right };
+        contributions[feature_index] += scale * (expected[child] - expected[index]);
+        index = child;
+    }
+    Ok(())
+}
+
+// the nodes of a tree that isn't a single leaf, from its leaf covers
+fn flatten_with_covers(tree: &Tree) -> Result<Vec<Node<'_>>> {
+    let leaf_covers = tree.leaf_covers().ok_or_else(|| {
+        Error::invalid_data("feature contributions need leaf covers, which the tree doesn't have")
+    })?;
+    let mut nodes = Vec::new();
+    flatten(tree.root(), &mut leaf_covers.iter(), &mut nodes)?;
+    if nodes[0].cover <= 0.0 {
+        return Err(Error::invalid_data(
+            "feature contributions need leaf covers, the tree's are all zero",
+        ));
+    }
+    Ok(nodes)
+}
+
+// The prediction expected below every node, the leaves weighed by their covers. Nodes no
+// training data reached weigh their children equally.
+fn expected_values(nodes: &[Node]) -> Vec<f64> {
+    let mut expected = vec![0.0; nodes.len()];
+    // children come after their parents
+    for (index, node) in nodes.iter().enumerate().rev() {
+        expected[index] = match node.split {
+            None => leaf_value(node.node),
+            Some((_, left, right)) if node.cover > 0.0 => {
+                (nodes[left].cover * expected[left] + nodes[right].cover * expected[right])
+                    / node.cover
+            }
+            Some((_, left, right)) => (expected[left] + expected[right]) / 2.0,
+        };
+    }
+    expected
+}
+
+// Appends `node` and the nodes below it in preorder, taking the leaves' covers from
+// `leaf_covers`; returns the index of `node`.
+fn flatten<'a>(
+    node: &'a TreeNode,
+    leaf_covers: &mut std::slice::Iter<f32>,
+    nodes: &mut Vec<Node<'a>>,
+) -> Result<usize> {
+    let index = nodes.len();
+    nodes.push(Node {
+        node,
+        cover: 0.0,
+        split: None,
+    });
+    match node {
+        TreeNode::Leaf { .. } => {
+            let cover = leaf_covers
+                .next()
+                .ok_or_else(|| Error::invalid_data("tree has fewer leaf covers than leaves"))?;
+            nodes[index].cover = f64::from(*cover);
+        }
+        TreeNode::LinearLeaf { .. } => {
+            return Err(Error::invalid_data(
+                "feature contributions of linear leaves aren't supported",
+            ));
+        }
+        TreeNode::Split {
+            feature_index,
+            left_child,
+            right_child,
+            ..
+        }
+        | TreeNode::CategoricalSplit {
+            feature_index,
+            left_child,
+            right_child,
+            ..
+        } => {
+            let left = flatten(left_child, leaf_covers, nodes)?;
+            let right = flatten(right_child, leaf_covers, nodes)?;
+            nodes[index].cover = nodes[left].cover + nodes[right].cover;
+            nodes[index].split = Some((*feature_index, left, right));
+        }
+    }
+    Ok(index)
+}
+
+fn leaf_value(node: &TreeNode) -> f64 {
+    match node {
+        TreeNode::Leaf { value } => f64::from(*value),
+        _ => unreachable!(),
+    }
+}
+
+// Extends `path` by the split leading to `nodes[index]`, given as its zero fraction, one
+// fraction and feature, and passes every feature's contribution below it to `add`. The
+// feature of `condition` is left off the path, its splits weigh the paths instead.
+fn visit<R: FeatureRow + ?Sized>(
+    nodes: &[Node],
+    index: usize,
+    row: &R,
+    mut path: Vec<PathElement>,
+    (zero_fraction, one_fraction, feature_index): (f64, f64, Option<usize>),
+    condition: Condition,
+    add: &mut dyn FnMut(usize, f64),
+) {
+    if condition.fraction == 0.0 {
+        return;
+    }
+    if condition
+        .feature
+        .is_none_or(|(condition_feature, _)| feature_index != Some(condition_feature))
+    {
+        extend(&mut path, zero_fraction, one_fraction, feature_index);
+    }
+    let node = &nodes[index];
+    let Some((split_feature, left, right)) = node.split else {
+        let value = leaf_value(node.node) * condition.fraction;
+        for (i, element) in path.iter().enumerate().skip(1) {
+            let weight = unwound_weight_sum(&path, i);
+            add(
+                element.feature_index.unwrap(),
+                weight * (element.one_fraction - element.zero_fraction) * value,
+            );
+        }
+        return;
+    };
+
+    let (goes_left, ..) = Tree::branch(node.node, row).unwrap();
+    let (hot, cold) = if goes_left {
+        (left, right)
+    } else {
+        (right, left)
+    };
+    // a feature split on before is taken off the path and comes back with both fractions
+    let (mut incoming_zero, mut incoming_one) = (1.0, 1.0);
+    if let Some(i) = path
+        .iter()
+        .position(|element| element.feature_index == Some(split_feature))
+    {
+        incoming_zero = path[i].zero_fraction;
+        incoming_one = path[i].one_fraction;
+        unwind(&mut path, i);
+    }
+    let fraction = |child: usize| nodes[child].cover / node.cover;
+    let (mut hot_condition, mut cold_condition) = (condition, condition);
+    match condition.feature {
+        // a known feature sends all the weight the row's way, an unknown one shares it
+        Some((condition_feature, true)) if condition_feature == split_feature => {
+            cold_condition.fraction = 0.0;
+        }
+        Some((condition_feature, false)) if condition_feature == split_feature => {
+            hot_condition.fraction *= fraction(hot);
+            cold_condition.fraction *= fraction(cold);
+        }
+        _ => {}
+    }
+    for (child, one_fraction, condition) in [
+        (hot, incoming_one, hot_condition),
+        (cold, 0.0, cold_condition),
+    ] {
+        let zero_fraction = fraction(child) * incoming_zero;
+        // a side neither the row nor any training data reaches adds nothing
+        if zero_fraction == 0.0 && one_fraction == 0.0 {
+            continue;
+        }
+        visit(
+            nodes,
+            child,
+            row,
+            path.clone(),
+            (zero_fraction, one_fraction, Some(split_feature)),
+            condition,
+            add,
+        );
+    }
+}
+
+fn extend(
+    path: &mut Vec<PathElement>,
+    zero_fraction: f64,
+    one_fraction: f64,
+    feature_index: Option<usize>,
+) {
+    let depth = path.len();
+    path.push(PathElement {
+        feature_index,
+        zero_fraction,
+        one_fraction,
+        weight: if depth == 0 { 1.0 } else { 0.0 },
+    });
+    for i in (0..depth).rev() {
+        path[i + 1].weight += one_fraction * path[i].weight * (i + 1) as f64 / (depth + 1) as f64;
+        path[i].weight = zero_fraction * path[i].weight * (depth - i) as f64 / (depth + 1) as f64;
+    }
+}
+
+// undoes the `extend` that added `path[index]`
+fn unwind(path: &mut Vec<PathElement>, index: usize) {
+    let depth = path.len() - 1;
+    let PathElement {
+        zero_fraction,
+        one_fraction,
+        ..
+    } = path[index];
+    let mut next_one_portion = path[depth].weight;
+    for i in (0..depth).rev() {
+        if one_fraction != 0.0 {
+            let weight = path[i].weight;
+            path[i].weight =
+                next_one_portion * (depth + 1) as f64 / ((i + 1) as f64 * one_fraction);
+            next_one_portion =
+                weight - path[i].weight * zero_fraction * (depth - i) as f64 / (depth + 1) as f64;
+        } else {
+            path[i].weight =
+                path[i].weight * (depth + 1) as f64 / (zero_fraction * (depth - i) as f64);
+        }
+    }
+    for i in index..depth {
+        path[i].feature_index = path[i + 1].feature_index;
+        path[i].zero_fraction = path[i + 1].zero_fraction;
+        path[i].one_fraction = path[i + 1].one_fraction;
+    }
+    path.pop();
+}
+
+// the total weight of the path's subsets if `path[index]` were unwound
+fn unwound_weight_sum(path: &[PathElement], index: usize) -> f64 {
+    let depth = path.len() - 1;
+    let PathElement {
+        zero_fraction,
+        one_fraction,
+        ..
+    } = path[index];
+    let mut next_one_portion = path[depth].weight;
+    let mut total = 0.0;
+    for i in (0..depth).rev() {
+        if one_fraction != 0.0 {
+            let weight = next_one_portion * (depth + 1) as f64 / ((i + 1) as f64 * one_fraction);
+            total += weight;
+            next_one_portion =
+                path[i].weight - weight * zero_fraction * (depth - i) as f64 / (depth + 1) as f64;
+        } else {
+            total += path[i].weight * (depth + 1) as f64 / (zero_fraction * (depth - i) as f64);
+        }
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    fn leaf(value: f32) -> Box<TreeNode> {
+        Box::new(TreeNode::Leaf { value })
+    }
+
+    fn split(
+        feature_index: usize,
+        threshold: f32,
+        left_child: Box<TreeNode>,
+        right_child: Box<TreeNode>,
+    ) -> Box<TreeNode> {
+        Box::new(TreeNode::Split {
+            feature_index,
+            threshold,
+            default_left: false,
+            left_child,
+            right_child,
+        })
+    }
+
+    // The expected prediction when only the features in `known` are: unknown features
+    // follow both sides of a split weighed by their covers.
+    fn conditional_expectation(
+        node: &TreeNode,
+        covers: &mut std::slice::Iter<f32>,
+        row: &[f32],
+        known: &[usize],
+    ) -> (f64, f64) {
+        match node {
+            TreeNode::Leaf { value } => {
+                let cover = f64::from(*covers.next().unwrap());
+                (f64::from(*value), cover)
+            }
+            TreeNode::Split {
+                feature_index,
+                threshold,
+                left_child,
+                right_child,
+                ..
+            } => {
+                let (left, left_cover) = conditional_expectation(left_child, covers, row, known);
+                let (right, right_cover) = conditional_expectation(right_child, covers, row, known);
+                let cover = left_cover + right_cover;
+                let value = if !known.contains(feature_index) {
+                    (left * left_cover + right * right_cover) / cover
+                } else if row[*feature_index] < *threshold {
+                    left
+                } else {
+                    right
+                };
+                (value, cover)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // the Shapley values by their definition, a sum over every subset of the other features
+    fn brute_force(tree: &Tree, row: &[f32], num_features: usize) -> Vec<f64> {
+        let value = |known: &[usize]| expected_given(tree, row, known);
+        let mut contributions = vec![0.0; num_features + 1];
+        for (feature, contribution) in contributions.iter_mut().enumerate().take(num_features) {
+            for mut known in subsets_without(num_features, &[feature]) {
+                let weight = factorial(known.len()) * factorial(num_features - known.len() - 1)
+                    / factorial(num_features);
+                let without = value(&known);
+                known.push(feature);
+                *contribution += weight * (value(&known) - without);
+            }
+        }
+        contributions[num_features] = value(&[]);
+        contributions
+    }
+
+    // the Shapley interaction index of every pair, with the rest of the SHAP values on the
+    // diagonal
+    fn brute_force_interactions(tree: &Tree, row: &[f32], num_features: usize) -> Vec<f64> {
+        let value =
+            |known: &[usize], added: &[usize]| expected_given(tree, row, &[known, added].concat());
+        let contributions = brute_force(tree, row, num_features);
+        let size = num_features + 1;
+        let mut interactions = vec![0.0; size * size];
+        for i in 0..num_features {
+            interactions[i * size + i] = contributions[i];
+            for j in (0..num_features).filter(|&j| j != i) {
+                let mut interaction = 0.0;
+                for known in subsets_without(num_features, &[i, j]) {
+                    let weight = factorial(known.len()) * factorial(num_features - known.len() - 2)
+                        / (2.0 * factorial(num_features - 1));
+                    interaction += weight
+                        * (value(&known, &[i, j]) - value(&known, &[i]) - value(&known, &[j])
+                            + value(&known, &[]));
+                }
+                interactions[i * size + j] = interaction;
+                interactions[i * size + i] -= interaction;
+            }
+        }
+        interactions[size * size - 1] = contributions[num_features];
+        interactions
+    }
+
+    fn expected_given(tree: &Tree, row: &[f32], known: &[usize]) -> f64 {
+        let covers = &mut tree.leaf_covers().unwrap().iter();
+        conditional_expectation(tree.root(), covers, row, known).0
+    }
+
+    // every subset of the features but `excluded`
+    fn subsets_without(num_features: usize, excluded: &[usize]) -> Vec<Vec<usize>> {
+        let others: Vec<usize> = (0..num_features)
+            .filter(|feature| !excluded.contains(feature))
+            .collect();
+        (0..1 << others.len())
+            .map(|subset| {
+                others
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| subset & (1 << i) != 0)
+                    .map(|(_, &other)| other)
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn factorial(n: usize) -> f64 {
+        (1..=n).product::<usize>() as f64
+    }
+
+    // feature 0 is split on twice along a path, feature 3 never
+    fn example_tree() -> Tree {
+        Tree::new(split(
+            0,
+            5.0,
+            split(1, 2.0, leaf(1.0), split(0, 2.0, leaf(-3.0), leaf(4.0))),
+            split(2, 0.5, leaf(2.0), split(1, 7.0, leaf(0.5), leaf(6.0))),
+        ))
+        .with_leaf_covers(vec![10.0, 3.0, 7.0, 20.0, 5.0, 15.0])
+    }
+
+    const EXAMPLE_ROWS: [[f32; 4]; 5] = [
+        [1.0, 1.0, 0.0, 9.0],
+        [1.0, 3.0, 1.0, 9.0],
+        [4.0, 3.0, 1.0, 9.0],
+        [6.0, 8.0, 0.0, 9.0],
+        [6.0, 8.0, 1.0, 9.0],
+    ];
+
+    #[test]
+    fn test_matches_the_shapley_values_by_definition() {
+        let tree = example_tree();
+        for row in EXAMPLE_ROWS {
+            let mut contributions = vec![0.0; 5];
+            add_contributions(&tree, &row[..], 1.0, &mut contributions).unwrap();
+            let expected = brute_force(&tree, &row, 4);
+            for (contribution, expected) in contributions.iter().zip(&expected) {
+                assert_abs_diff_eq!(contribution, expected, epsilon = 1e-9);
+            }
+            let total: f64 = contributions.iter().sum();
+            assert_abs_diff_eq!(total, f64::from(tree.predict(&row)), epsilon = 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_interactions_match_their_definition() {
+        let tree = example_tree();
+        for row in EXAMPLE_ROWS {
+            let mut interactions = vec![0.0; 25];
+            add_interactions(&tree, &row[..], 1.0, &mut interactions).unwrap();
+            let expected = brute_force_interactions(&tree, &row, 4);
+            for (interaction, expected) in interactions.iter().zip(&expected) {
+                assert_abs_diff_eq!(interaction, expected, epsilon = 1e-9);
+            }
+
+            // symmetric, and the rows add up to the SHAP values
+            let mut contributions = vec![0.0; 5];
+            add_contributions(&tree, &row[..], 1.0, &mut contributions).unwrap();
+            for i in 0..5 {
+                let row_sum: f64 = interactions[i * 5..(i + 1) * 5].iter().sum();
+                assert_abs_diff_eq!(row_sum, contributions[i], epsilon = 1e-9);
+                for j in 0..5 {
+                    assert_abs_diff_eq!(
+                        interactions[i * 5 + j],
+                        interactions[j * 5 + i],
+                        epsilon = 1e-9
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_approximation_credits_each_split_on_the_path() {
+        // expected values: 5.8 at the root, 2.5 on its left
+        let tree = Tree::new(split(
+            0,
+            5.0,
+            split(1, 2.0, leaf(1.0), leaf(3.0)),
+            leaf(8.0),
+        ))
+        .with_leaf_covers(vec![10.0, 30.0, 60.0]);
+        let mut contributions = vec![0.0; 3];
+        add_approximate_contributions(&tree, &[0.0, 0.0][..], 1.0, &mut contributions).unwrap();
+        for (contribution, expected) in contributions.iter().zip([-3.3, -1.5, 5.8]) {
+            assert_abs_diff_eq!(*contribution, expected, epsilon = 1e-9);
+        }
+
+        // on a single split the approximation is exact
+        let stump = Tree::new(split(1, 2.0, leaf(1.0), leaf(3.0))).with_leaf_covers(vec![1.0, 3.0]);
+        let (mut exact, mut approximate) = (vec![0.0; 3], vec![0.0; 3]);
+        add_contributions(&stump, &[0.0, 0.0][..], 1.0, &mut exact).unwrap();
+        add_approximate_contributions(&stump, &[0.0, 0.0][..], 1.0, &mut approximate).unwrap();
+        assert_eq!(exact, approximate);
+    }
+
+    #[test]
+    fn test_scales_by_shrinkage() {
+        let tree = Tree::new(split(0, 1.0, leaf(2.0), leaf(6.0)))
+            .with_shrinkage(0.5)
+            .with_leaf_covers(vec![1.0, 3.0]);
+        let mut contributions = vec![0.0; 2];
+        add_contributions(&tree, &[0.0][..], 2.0, &mut contributions).unwrap();
+        // expected value 5, the row's leaf 2, both times 0.5 * 2
+        assert_eq!(contributions, vec![-3.0, 5.0]);
+    }
+
+    #[test]
+    fn test_needs_leaf_covers() {
+        let mut contributions = vec![0.0; 2];
+        assert!(
+            add_contributions(&Tree::new(leaf(1.0)), &[0.0][..], 1.0, &mut contributions).is_ok()
+        );
+        assert_eq!(contributions, vec![0.0, 1.0]);
+        let tree = Tree::new(split(0, 1.0, leaf(2.0), leaf(6.0)));
+        assert!(add_contributions(&tree, &[0.0][..], 1.0, &mut contributions).is_err());
+    }
+}
@@ -0,0 +1,299 @@
+// Weighted quantile sketch in the style of XGBoost's WQSummary, used to pick bin edges
+// without sorting a whole feature. Values are buffered and turned into exact summaries,
+// which are merged level by level like a binary counter and pruned back to a fixed size,
+// so memory stays O(entries * log(n / entries)) and the rank error about
+// log(n / entries) / entries of the total weight.
+
+// One value of a summary. The weight of the values below it lies between `rmin` and
+// `rmax - weight`, and the weight of the values up to it, itself included, between
+// `rmin + weight` and `rmax`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Entry {
+    value: f32,
+    rmin: f64,
+    rmax: f64,
+    weight: f64,
+}
+
+impl Entry {
+    fn rmin_next(&self) -> f64 {
+        self.rmin + self.weight
+    }
+
+    fn rmax_prev(&self) -> f64 {
+        self.rmax - self.weight
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QuantileSketch {
+    // size summaries are pruned to, also the size of the buffer
+    entries: usize,
+    buffer: Vec<(f32, f64)>,
+    // levels[k] merges 2^k buffers' worth of values, or is empty
+    levels: Vec<Vec<Entry>>,
+    // whether a summary lost entries to pruning, after which ranks are approximate
+    pruned: bool,
+}
+
+impl QuantileSketch {
+    pub fn new(entries: usize) -> Self {
+        let entries = entries.max(2);
+        Self {
+            entries,
+            buffer: Vec::with_capacity(entries),
+            levels: vec![],
+            pruned: false,
+        }
+    }
+
+    // NaN values are skipped
+    pub fn push(&mut self, value: f32, weight: f32) {
+        if value.is_nan() {
+            return;
+        }
+        self.buffer.push((value, weight as f64));
+        if self.buffer.len() == self.entries {
+            self.flush();
+        }
+    }
+
+    // everything pushed so far: ascending distinct values with bounds on their ranks
+    fn merged(&self) -> Vec<Entry> {
+        let mut summary = exact_summary(self.buffer.clone());
+        for level in &self.levels {
+            summary = combine(&summary, level);
+        }
+        summary
+    }
+
+    fn summary(&self) -> Vec<Entry> {
+        prune(&self.merged(), self.entries)
+    }
+
+    // The distinct values seen, ascending, when there were at most `entries` of them.
+    pub fn distinct_values(&self) -> Option<Vec<f32>> {
+        if self.pruned {
+            return None;
+        }
+        let merged = self.merged();
+        (merged.len() <= self.entries).then(|| merged.iter().map(|entry| entry.value).collect())
+    }
+
+    // The smallest value for which `fraction` of the total weight is at or below it, for
+    // each of the ascending `fractions`, approximately once the summary was pruned. None
+    // without any values.
+    pub fn quantiles(&self, fractions: &[f64]) -> Option<Vec<f32>> {
+        let summary = self.summary();
+        let total = summary.last()?.rmax;
+        let mut entry = 0;
+        Some(
+            fractions
+                .iter()
+                .map(|fraction| {
+                    let rank = fraction * total;
+                    while entry + 1 < summary.len() && summary[entry].rmax < rank {
+                        entry += 1;
+                    }
+                    summary[entry].value
+                })
+                .collect(),
+        )
+    }
+
+    pub fn min(&self) -> Option<f32> {
+        self.summary().first().map(|entry| entry.value)
+    }
+
+    pub fn max(&self) -> Option<f32> {
+        self.summary().last().map(|entry| entry.value)
+    }
+
+    fn flush(&mut self) {
+        let mut summary = exact_summary(std::mem::take(&mut self.buffer));
+        for level in &mut self.levels {
+            if level.is_empty() {
+                *level = summary;
+                return;
+            }
+            let combined = combine(level, &summary);
+            summary = prune(&combined, self.entries);
+            self.pruned |= summary.len() < combined.len();
+            level.clear();
+        }
+        self.levels.push(summary);
+    }
+}
+
+fn exact_summary(mut values: Vec<(f32, f64)>) -> Vec<Entry> {
+    values.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut summary: Vec<Entry> = vec![];
+    let mut below = 0.0;
+    for (value, weight) in values {
+        match summary.last_mut() {
+            Some(last) if last.value == value => {
+                last.weight += weight;
+                last.rmax += weight;
+            }
+            _ => summary.push(Entry {
+                value,
+                rmin: below,
+                rmax: below + weight,
+                weight,
+            }),
+        }
+        below += weight;
+    }
+    summary
+}
+
+// The summary of the union of what `a` and `b` summarize.
+fn combine(a: &[Entry], b: &[Entry]) -> Vec<Entry> {
+    if a.is_empty() {
+        return b.to_vec();
+    }
+    if b.is_empty() {
+        return a.to_vec();
+    }
+    let mut out = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    // the rank bounds an entry of one side gets from the other side's entries around it
+    let before = |side: &[Entry], next: usize| {
+        if next == 0 {
+            0.0
+        } else {
+            side[next - 1].rmin_next()
+        }
+    };
+    let after = |side: &[Entry], next: usize| {
+        if next == side.len() {
+            side[side.len() - 1].rmax
+        } else {
+            side[next].rmax_prev()
+        }
+    };
+    while i < a.len() || j < b.len() {
+        let entry = if j == b.len() || (i < a.len() && a[i].value < b[j].value) {
+            let entry = Entry {
+                rmin: a[i].rmin + before(b, j),
+                rmax: a[i].rmax + after(b, j),
+                ..a[i]
+            };
+            i += 1;
+            entry
+        } else if i == a.len() || b[j].value < a[i].value {
+            let entry = Entry {
+                rmin: b[j].rmin + before(a, i),
+                rmax: b[j].rmax + after(a, i),
+                ..b[j]
+            };
+            j += 1;
+            entry
+        } else {
+            let entry = Entry {
+                value: a[i].value,
+                rmin: a[i].rmin + b[j].rmin,
+                rmax: a[i].rmax + b[j].rmax,
+                weight: a[i].weight + b[j].weight,
+            };
+            i += 1;
+            j += 1;
+            entry
+        };
+        out.push(entry);
+    }
+    out
+}
+
+// Keeps at most `size` entries, always the first and last ones, spread evenly over the
+// ranks in between.
+fn prune(summary: &[Entry], size: usize) -> Vec<Entry> {
+    if summary.len() <= size {
+        return summary.to_vec();
+    }
+    let n = size - 1;
+    let begin = summary[0].rmax;
+    let range = summary[summary.len() - 1].rmin - begin;
+    let mut out = vec![summary[0]];
+    let mut last = 0;
+    let mut i = 1;
+    for k in 1..n {
+        let target = 2.0 * (k as f64 * range / n as f64 + begin);
+        while i < summary.len() - 1 && target >= summary[i + 1].rmax + summary[i + 1].rmin {
+            i += 1;
+        }
+        if i == summary.len() - 1 {
+            break;
+        }
+        let pick = if target < summary[i].rmin_next() + summary[i + 1].rmax_prev() {
+            i
+        } else {
+            i + 1
+        };
+        if pick != last {
+            out.push(summary[pick]);
+            last = pick;
+        }
+    }
+    if last != summary.len() - 1 {
+        out.push(summary[summary.len() - 1]);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_small_sketch_is_exact() {
+        let mut sketch = QuantileSketch::new(16);
+        for value in [3.0, 1.0, 2.0, 1.0, f32::NAN, 4.0] {
+            sketch.push(value, 1.0);
+        }
+
+        assert_eq!(sketch.distinct_values(), Some(vec![1.0, 2.0, 3.0, 4.0]));
+        // 1 holds 2 of the 5 values
+        assert_eq!(
+            sketch.quantiles(&[0.2, 0.4, 0.5, 1.0]),
+            Some(vec![1.0, 1.0, 2.0, 4.0])
+        );
+        assert_eq!((sketch.min(), sketch.max()), (Some(1.0), Some(4.0)));
+        assert_eq!(QuantileSketch::new(16).quantiles(&[0.5]), None);
+    }
+
+    #[test]
+    fn test_large_sketch_stays_close_to_true_quantiles() {
+        let n = 100_000;
+        let mut sketch = QuantileSketch::new(256);
+        // a scrambled order so the buffers aren't sorted already
+        for i in 0..n {
+            sketch.push(((i * 7919) % n) as f32, 1.0);
+        }
+
+        assert_eq!(sketch.distinct_values(), None);
+        assert_eq!(
+            (sketch.min(), sketch.max()),
+            (Some(0.0), Some((n - 1) as f32))
+        );
+        let fractions: Vec<f64> = (1..10).map(|i| i as f64 / 10.0).collect();
+        for (fraction, value) in fractions.iter().zip(sketch.quantiles(&fractions).unwrap()) {
+            let error = (value as f64 / n as f64 - fraction).abs();
+            assert!(error < 0.02, "{fraction}: {value}");
+        }
+    }
+
+    #[test]
+    fn test_weights_shift_quantiles() {
+        let mut sketch = QuantileSketch::new(32);
+        for i in 0..100 {
+            // the upper half weighs three times as much
+            let weight = if i < 50 { 1.0 } else { 3.0 };
+            sketch.push(i as f32, weight);
+        }
+
+        // the median by weight is a third of the way into the upper half
+        let median = sketch.quantiles(&[0.5]).unwrap()[0];
+        assert!((60.0..=73.0).contains(&median), "{median}");
+    }
+}
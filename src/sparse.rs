@@ -0,0 +1,192 @@
+// Sparse features in compressed sparse row (CSR) form. Entries left out of a row are 0, so
+// one-hot or bag-of-words data only stores its non-zero values.
+
+use crate::error::{Error, Result};
+
+pub use metalgbm_predict::FeatureRow;
+
+// The non-zero entries of one row, with `indices` ascending.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SparseRow<'a> {
+    indices: &'a [usize],
+    values: &'a [f32],
+}
+
+impl<'a> SparseRow<'a> {
+    pub fn new(indices: &'a [usize], values: &'a [f32]) -> Self {
+        assert_eq!(
+            indices.len(),
+            values.len(),
+            "there must be one value per index"
+        );
+        debug_assert!(indices.is_sorted(), "indices must be ascending");
+        Self { indices, values }
+    }
+
+    pub fn indices(&self) -> &'a [usize] {
+        self.indices
+    }
+
+    pub fn values(&self) -> &'a [f32] {
+        self.values
+    }
+}
+
+impl FeatureRow for SparseRow<'_> {
+    fn value(&self, feature: usize) -> f32 {
+        match self.indices.binary_search(&feature) {
+            Ok(entry) => self.values[entry],
+            Err(_) => 0.0,
+        }
+    }
+}
+
+// Row i holds the entries indptr[i]..indptr[i + 1] of `indices` and `values`, like
+// scipy.sparse.csr_matrix.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsrMatrix {
+    indptr: Vec<usize>,
+    indices: Vec<usize>,
+    values: Vec<f32>,
+    num_features: usize,
+}
+
+impl CsrMatrix {
+    pub fn new(
+        indptr: Vec<usize>,
+        indices: Vec<usize>,
+        values: Vec<f32>,
+        num_features: usize,
+    ) -> Result<Self> {
+        if indptr.first() != Some(&0) || indptr.last() != Some(&indices.len()) {
+            return Err(Error::invalid_data(
+                "indptr must start at 0 and end at the number of entries",
+            ));
+        }
+        if indices.len() != values.len() {
+            return Err(Error::invalid_data("there must be one value per index"));
+        }
+        for (row, bounds) in indptr.windows(2).enumerate() {
+            let row_indices = indices
+                .get(bounds[0]..bounds[1])
+                .ok_or_else(|| Error::invalid_data(format!("indptr decreases at row {row}")))?;
+            if !row_indices.windows(2).all(|pair| pair[0] < pair[1]) {
+                return Err(Error::invalid_data(format!(
+                    "indices of row {row} aren't strictly ascending"
+                )));
+            }
+            if row_indices
+                .last()
+                .is_some_and(|&index| index >= num_features)
+            {
+                return Err(Error::invalid_data(format!(
+                    "row {row} has an index beyond the {num_features} features"
+                )));
+            }
+        }
+
+        Ok(Self {
+            indptr,
+            indices,
+            values,
+            num_features,
+        })
+    }
+
+    // keeps the non-zero values of row-major `rows`
+    pub fn from_dense(rows: &[Vec<f32>]) -> Self {
+        let mut indptr = vec![0];
+        let mut indices = vec![];
+        let mut values = vec![];
+        for row in rows {
+            for (index, &value) in row.iter().enumerate() {
+                if value != 0.0 {
+                    indices.push(index);
+                    values.push(value);
+                }
+            }
+            indptr.push(indices.len());
+        }
+        Self {
+            indptr,
+            indices,
+            values,
+            num_features: rows.first().map_or(0, |row| row.len()),
+        }
+    }
+
+    pub fn row(&self, row: usize) -> SparseRow<'_> {
+        let entries = self.indptr[row]..self.indptr[row + 1];
+        SparseRow {
+            indices: &self.indices[entries.clone()],
+            values: &self.values[entries],
+        }
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.indptr.len() - 1
+    }
+
+    pub fn num_features(&self) -> usize {
+        self.num_features
+    }
+
+    // number of stored entries
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+
+    // The entries column by column: for every feature the rows it is stored for, ascending,
+    // and its values there.
+    pub fn to_columns(&self) -> Vec<(Vec<usize>, Vec<f32>)> {
+        let mut columns = vec![(vec![], vec![]); self.num_features];
+        for row in 0..self.num_rows() {
+            let SparseRow { indices, values } = self.row(row);
+            for (&index, &value) in indices.iter().zip(values) {
+                columns[index].0.push(row);
+                columns[index].1.push(value);
+            }
+        }
+        columns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csr_matrix_rows_and_columns() {
+        let dense = vec![
+            vec![0.0, 2.0, 0.0],
+            vec![0.0, 0.0, 0.0],
+            vec![1.0, 0.0, 3.0],
+        ];
+        let matrix = CsrMatrix::from_dense(&dense);
+        assert_eq!(
+            matrix,
+            CsrMatrix::new(vec![0, 1, 1, 3], vec![1, 0, 2], vec![2.0, 1.0, 3.0], 3).unwrap()
+        );
+        assert_eq!(matrix.num_rows(), 3);
+        assert_eq!(matrix.nnz(), 3);
+
+        for (i, row) in dense.iter().enumerate() {
+            for (feature, &value) in row.iter().enumerate() {
+                assert_eq!(matrix.row(i).value(feature), value);
+            }
+        }
+        assert_eq!(matrix.to_columns()[2], (vec![2], vec![3.0]));
+    }
+
+    #[test]
+    fn test_csr_matrix_validates_structure() {
+        assert!(CsrMatrix::new(vec![0, 2], vec![1, 1], vec![1.0, 1.0], 3).is_err());
+        assert!(CsrMatrix::new(vec![0, 1], vec![3], vec![1.0], 3).is_err());
+        assert!(CsrMatrix::new(vec![0, 2, 1], vec![0], vec![1.0], 3).is_err());
+        let err = CsrMatrix::new(vec![1], vec![], vec![], 3).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid data: indptr must start at 0 and end at the number of entries"
+        );
+    }
+}
@@ -0,0 +1,859 @@
+use rayon::prelude::*;
+
+use crate::histogram::Histogram;
+use crate::random::Rng;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitInfo {
+    pub feature_index: usize,
+    pub threshold: f32,
+    // whether NaN values go left
+    pub default_left: bool,
+    // For categorical features: the categories sent left, ascending, with `threshold` unused.
+    pub categories: Option<Vec<u32>>,
+    pub gain: f32,
+    pub left_sum_gradients: f32,
+    pub left_sum_hessians: f32,
+    pub left_count: usize,
+    pub right_sum_gradients: f32,
+    pub right_sum_hessians: f32,
+    pub right_count: usize,
+    // child leaf values, already clamped to the bounds the split was searched under
+    pub left_value: f32,
+    pub right_value: f32,
+}
+
+// One split applied to every leaf of a level of an oblivious tree, with the gain summed
+// over those leaves.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelSplit {
+    pub feature_index: usize,
+    pub threshold: f32,
+    pub gain: f32,
+}
+
+// Range a leaf value has to stay in so that monotone constraints on the splits above it
+// hold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ValueBounds {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl ValueBounds {
+    pub const UNBOUNDED: ValueBounds = ValueBounds {
+        min: f32::NEG_INFINITY,
+        max: f32::INFINITY,
+    };
+
+    pub fn clamp(&self, value: f32) -> f32 {
+        value.clamp(self.min, self.max)
+    }
+}
+
+pub struct SplitFinder {
+    lambda_l1: f32,
+    lambda_l2: f32,
+    min_gain_to_split: f32,
+    min_data_in_leaf: usize,
+    min_sum_hessian_in_leaf: f32,
+    // per feature: 1 increasing, -1 decreasing, 0 (or missing) unconstrained
+    monotone_constraints: Vec<i8>,
+    // per feature, missing entries are numerical
+    categorical: Vec<bool>,
+    max_cat_threshold: usize,
+    cat_smooth: f32,
+    min_data_per_group: usize,
+}
+
+impl SplitFinder {
+    pub fn new(lambda_l2: f32) -> Self {
+        Self {
+            lambda_l1: 0.0,
+            lambda_l2,
+            min_gain_to_split: 0.0,
+            min_data_in_leaf: 1,
+            min_sum_hessian_in_leaf: 0.0,
+            monotone_constraints: vec![],
+            categorical: vec![],
+            max_cat_threshold: 32,
+            cat_smooth: 10.0,
+            min_data_per_group: 1,
+        }
+    }
+
+    // soft-thresholds the gradient sums, which pulls small leaf values to exactly zero
+    pub fn with_lambda_l1(mut self, lambda_l1: f32) -> Self {
+        self.lambda_l1 = lambda_l1;
+        self
+    }
+
+    // splits must gain more than this to be kept
+    pub fn with_min_gain_to_split(mut self, min_gain_to_split: f32) -> Self {
+        self.min_gain_to_split = min_gain_to_split;
+        self
+    }
+
+    // both children of a split need at least this many samples
+    pub fn with_min_data_in_leaf(mut self, min_data_in_leaf: usize) -> Self {
+        self.min_data_in_leaf = min_data_in_leaf;
+        self
+    }
+
+    // both children of a split need a hessian sum of at least this
+    pub fn with_min_sum_hessian_in_leaf(mut self, min_sum_hessian_in_leaf: f32) -> Self {
+        self.min_sum_hessian_in_leaf = min_sum_hessian_in_leaf;
+        self
+    }
+
+    // Splits on a feature with an increasing constraint must not give the left child a
+    // larger value than the right one, and the other way around for decreasing ones.
+    pub fn with_monotone_constraints(mut self, monotone_constraints: Vec<i8>) -> Self {
+        self.monotone_constraints = monotone_constraints;
+        self
+    }
+
+    // Splits on these features send a set of categories left instead of the values below a
+    // threshold. Their histograms need one bin per category, see
+    // `Histogram::from_categories`.
+    pub fn with_categorical_features(mut self, features: &[usize]) -> Self {
+        self.categorical = vec![false; features.iter().max().map_or(0, |&max| max + 1)];
+        for &feature in features {
+            self.categorical[feature] = true;
+        }
+        self
+    }
+
+    // Categorical splits send at most `max_cat_threshold` categories to one side. Categories
+    // are ordered by gradient over hessian sum plus `cat_smooth`, which keeps rare ones from
+    // dominating the order, and split points must be `min_data_per_group` rows apart.
+    pub fn with_categorical_options(
+        mut self,
+        max_cat_threshold: usize,
+        cat_smooth: f32,
+        min_data_per_group: usize,
+    ) -> Self {
+        self.max_cat_threshold = max_cat_threshold;
+        self.cat_smooth = cat_smooth;
+        self.min_data_per_group = min_data_per_group;
+        self
+    }
+
+    // `histograms[j]` holds the accumulated statistics of feature j for the rows of one node.
+    pub fn find_best_split(&self, histograms: &[Histogram]) -> Option<SplitInfo> {
+        let features: Vec<usize> = (0..histograms.len()).collect();
+        self.find_best_split_among(histograms, &features, ValueBounds::UNBOUNDED)
+    }
+
+    // Like `find_best_split`, but only considers the features listed in `features` and
+    // keeps the child values within `bounds`.
+    pub fn find_best_split_among(
+        &self,
+        histograms: &[Histogram],
+        features: &[usize],
+        bounds: ValueBounds,
+    ) -> Option<SplitInfo> {
+        // Features are scanned in parallel; reducing in feature order keeps the earliest of
+        // equally good splits, as a sequential scan would.
+        features
+            .par_iter()
+            .filter_map(|&feature_index| {
+                let hist = &histograms[feature_index];
+                if self.is_categorical(feature_index) {
+                    self.scan_categorical(feature_index, hist, bounds, None)
+                } else {
+                    self.scan(feature_index, hist, bounds, None)
+                }
+            })
+            .reduce_with(|best, split| if split.gain > best.gain { split } else { best })
+    }
+
+    // Extremely randomized trees: every feature in `features` is only tried at one random
+    // bin boundary, and the best of those splits wins. Categorical features are tried at one
+    // random number of categories.
+    pub fn find_random_split_among(
+        &self,
+        histograms: &[Histogram],
+        features: &[usize],
+        bounds: ValueBounds,
+        rng: &mut Rng,
+    ) -> Option<SplitInfo> {
+        let mut best: Option<SplitInfo> = None;
+
+        for &feature_index in features {
+            let hist = &histograms[feature_index];
+            let n_boundaries = hist.gradients().len().saturating_sub(1);
+            if n_boundaries == 0 {
+                continue;
+            }
+            let split = if self.is_categorical(feature_index) {
+                self.scan_categorical(feature_index, hist, bounds, Some(rng))
+            } else {
+                let bin = rng.below(n_boundaries);
+                self.scan(feature_index, hist, bounds, Some(bin))
+            };
+            if let Some(split) = split
+                && best.as_ref().is_none_or(|b| split.gain > b.gain)
+            {
+                best = Some(split);
+            }
+        }
+
+        best
+    }
+
+    // Best split shared by all leaves of one level, for oblivious trees, which only split on
+    // numerical features. `level[l]` holds the histograms of leaf l, indexed by feature like
+    // in `find_best_split`. A leaf where the split would break min_data_in_leaf or
+    // min_sum_hessian_in_leaf adds no gain, but still gets split along with the others. With
+    // `rng`, every feature is only tried at one random bin boundary, as in
+    // `find_random_split_among`.
+    pub fn find_best_level_split(
+        &self,
+        level: &[Vec<Histogram>],
+        features: &[usize],
+        mut rng: Option<&mut Rng>,
+    ) -> Option<LevelSplit> {
+        let mut best: Option<LevelSplit> = None;
+        let first = level.first()?;
+
+        // the gains are summed in parallel, the random boundaries drawn in feature order
+        let level_gains: Vec<Vec<f32>> = features
+            .par_iter()
+            .map(|&feature_index| {
+                let mut gains = vec![0.0; first[feature_index].gradients().len().saturating_sub(1)];
+                for histograms in level {
+                    for (total, gain) in gains
+                        .iter_mut()
+                        .zip(self.boundary_gains(&histograms[feature_index]))
+                    {
+                        *total += gain;
+                    }
+                }
+                gains
+            })
+            .collect();
+        for (&feature_index, gains) in features.iter().zip(level_gains) {
+            if gains.is_empty() {
+                continue;
+            }
+
+            let bins = match rng.as_deref_mut() {
+                Some(rng) => {
+                    let bin = rng.below(gains.len());
+                    bin..bin + 1
+                }
+                None => 0..gains.len(),
+            };
+            for bin in bins {
+                let gain = gains[bin];
+                if gain > self.min_gain_to_split && best.as_ref().is_none_or(|b| gain > b.gain) {
+                    best = Some(LevelSplit {
+                        feature_index,
+                        threshold: first[feature_index].bins()[bin + 1],
+                        gain,
+                    });
+                }
+            }
+        }
+
+        best
+    }
+
+    pub fn best_split_for_feature(
+        &self,
+        feature_index: usize,
+        hist: &Histogram,
+    ) -> Option<SplitInfo> {
+        self.scan(feature_index, hist, ValueBounds::UNBOUNDED, None)
+    }
+
+    // Bounds for the children of `split` made under `bounds`. A monotone split puts the
+    // boundary between its children at the midpoint of their values, so later splits below
+    // either child can't cross over to the other side.
+    pub fn child_bounds(
+        &self,
+        split: &SplitInfo,
+        bounds: ValueBounds,
+    ) -> (ValueBounds, ValueBounds) {
+        let mid = (split.left_value + split.right_value) / 2.0;
+        match self.monotone_constraint(split.feature_index) {
+            0 => (bounds, bounds),
+            constraint if constraint > 0 => (
+                ValueBounds {
+                    max: bounds.max.min(mid),
+                    ..bounds
+                },
+                ValueBounds {
+                    min: bounds.min.max(mid),
+                    ..bounds
+                },
+            ),
+            _ => (
+                ValueBounds {
+                    min: bounds.min.max(mid),
+                    ..bounds
+                },
+                ValueBounds {
+                    max: bounds.max.min(mid),
+                    ..bounds
+                },
+            ),
+        }
+    }
+
+    fn is_categorical(&self, feature_index: usize) -> bool {
+        self.categorical
+            .get(feature_index)
+            .copied()
+            .unwrap_or(false)
+    }
+
+    fn monotone_constraint(&self, feature_index: usize) -> i8 {
+        self.monotone_constraints
+            .get(feature_index)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    // Evaluates splitting after every bin, or only after `only_bin` when given. When the node
+    // has NaN values, both sides are tried for them.
+    fn scan(
+        &self,
+        feature_index: usize,
+        hist: &Histogram,
+        bounds: ValueBounds,
+        only_bin: Option<usize>,
+    ) -> Option<SplitInfo> {
+        let monotone = self.monotone_constraint(feature_index);
+
+        let gradients = hist.gradients();
+        let hessians = hist.hessians();
+        let counts = hist.counts();
+
+        let (sum_gradients, sum_hessians, count) = totals(hist);
+        let parent_score = self.score(sum_gradients, sum_hessians);
+
+        let (missing_gradients, missing_hessians, missing_count) = hist.missing();
+        let default_directions: &[bool] = if missing_count > 0 {
+            &[false, true]
+        } else {
+            &[false]
+        };
+
+        // Splitting after bin i sends bins 0..=i left, i.e. values below bins[i + 1].
+        let mut best: Option<SplitInfo> = None;
+        let mut bins_gradients = 0.0;
+        let mut bins_hessians = 0.0;
+        let mut bins_count = 0;
+        for (i, &default_left) in (0..gradients.len().saturating_sub(1)).flat_map(|i| {
+            default_directions
+                .iter()
+                .map(move |direction| (i, direction))
+        }) {
+            if !default_left {
+                bins_gradients += gradients[i];
+                bins_hessians += hessians[i];
+                bins_count += counts[i];
+            }
+            if only_bin.is_some_and(|bin| bin != i) {
+                continue;
+            }
+            let (left_gradients, left_hessians, left_count) = if default_left {
+                (
+                    bins_gradients + missing_gradients,
+                    bins_hessians + missing_hessians,
+                    bins_count + missing_count,
+                )
+            } else {
+                (bins_gradients, bins_hessians, bins_count)
+            };
+            let right_gradients = sum_gradients - left_gradients;
+            let right_hessians = sum_hessians - left_hessians;
+            let right_count = count - left_count;
+            if left_count < self.min_data_in_leaf || right_count < self.min_data_in_leaf {
+                continue;
+            }
+            if left_hessians <= 0.0
+                || right_hessians <= 0.0
+                || left_hessians < self.min_sum_hessian_in_leaf
+                || right_hessians < self.min_sum_hessian_in_leaf
+            {
+                continue;
+            }
+
+            let (left_value, left_score) = self.bounded_leaf(left_gradients, left_hessians, bounds);
+            let (right_value, right_score) =
+                self.bounded_leaf(right_gradients, right_hessians, bounds);
+            if (monotone > 0 && left_value > right_value)
+                || (monotone < 0 && left_value < right_value)
+            {
+                continue;
+            }
+
+            let gain = left_score + right_score - parent_score;
+            if gain > self.min_gain_to_split && best.as_ref().is_none_or(|b| gain > b.gain) {
+                best = Some(SplitInfo {
+                    feature_index,
+                    threshold: hist.bins()[i + 1],
+                    default_left,
+                    categories: None,
+                    gain,
+                    left_sum_gradients: left_gradients,
+                    left_sum_hessians: left_hessians,
+                    left_count,
+                    right_sum_gradients: right_gradients,
+                    right_sum_hessians: right_hessians,
+                    right_count,
+                    left_value,
+                    right_value,
+                });
+            }
+        }
+
+        best
+    }
+
+    // The LightGBM search for categorical features: the categories of the node are sorted by
+    // smoothed gradient over hessian, and the best split sends a prefix of that order left,
+    // scanning from either end so the side with at most `max_cat_threshold` categories can
+    // be either one. With `rng`, only one random prefix length is tried per end. NaN values
+    // go right, with the categories not listed.
+    fn scan_categorical(
+        &self,
+        feature_index: usize,
+        hist: &Histogram,
+        bounds: ValueBounds,
+        mut rng: Option<&mut Rng>,
+    ) -> Option<SplitInfo> {
+        let gradients = hist.gradients();
+        let hessians = hist.hessians();
+        let counts = hist.counts();
+
+        let (sum_gradients, sum_hessians, count) = totals(hist);
+        let parent_score = self.score(sum_gradients, sum_hessians);
+
+        let mut order: Vec<usize> = (0..counts.len()).filter(|&bin| counts[bin] > 0).collect();
+        if order.len() < 2 {
+            return None;
+        }
+        let ratio = |bin: usize| gradients[bin] / (hessians[bin] + self.cat_smooth);
+        order.sort_by(|&a, &b| ratio(a).total_cmp(&ratio(b)));
+        let max_categories = self.max_cat_threshold.min(order.len() - 1);
+
+        let mut best: Option<(Vec<usize>, SplitInfo)> = None;
+        for reversed in [false, true] {
+            if reversed {
+                order.reverse();
+            }
+            let only = rng.as_deref_mut().map(|rng| rng.below(max_categories) + 1);
+            let mut sides = (0.0, 0.0, 0);
+            let mut group_count = 0;
+            for (prefix, &bin) in order.iter().take(max_categories).enumerate() {
+                sides.0 += gradients[bin];
+                sides.1 += hessians[bin];
+                sides.2 += counts[bin];
+                group_count += counts[bin];
+                let (left_gradients, left_hessians, left_count) = sides;
+                if group_count < self.min_data_per_group || only.is_some_and(|n| n != prefix + 1) {
+                    continue;
+                }
+                let right_gradients = sum_gradients - left_gradients;
+                let right_hessians = sum_hessians - left_hessians;
+                let right_count = count - left_count;
+                if left_count < self.min_data_in_leaf || right_count < self.min_data_in_leaf {
+                    continue;
+                }
+                if left_hessians <= 0.0
+                    || right_hessians <= 0.0
+                    || left_hessians < self.min_sum_hessian_in_leaf
+                    || right_hessians < self.min_sum_hessian_in_leaf
+                {
+                    continue;
+                }
+                group_count = 0;
+
+                let (left_value, left_score) =
+                    self.bounded_leaf(left_gradients, left_hessians, bounds);
+                let (right_value, right_score) =
+                    self.bounded_leaf(right_gradients, right_hessians, bounds);
+                let gain = left_score + right_score - parent_score;
+                if gain > self.min_gain_to_split && best.as_ref().is_none_or(|(_, b)| gain > b.gain)
+                {
+                    let split = SplitInfo {
+                        feature_index,
+                        threshold: 0.0,
+                        default_left: false,
+                        categories: None,
+                        gain,
+                        left_sum_gradients: left_gradients,
+                        left_sum_hessians: left_hessians,
+                        left_count,
+                        right_sum_gradients: right_gradients,
+                        right_sum_hessians: right_hessians,
+                        right_count,
+                        left_value,
+                        right_value,
+                    };
+                    best = Some((order[..=prefix].to_vec(), split));
+                }
+            }
+        }
+
+        best.map(|(left_bins, mut split)| {
+            let mut categories: Vec<u32> = left_bins
+                .into_iter()
+                .map(|bin| hist.bins()[bin] as u32)
+                .collect();
+            categories.sort_unstable();
+            split.categories = Some(categories);
+            split
+        })
+    }
+
+    // Gain of splitting after each bin boundary of `hist`, zero where the split isn't allowed.
+    // NaN values go right, like everywhere in oblivious trees.
+    fn boundary_gains(&self, hist: &Histogram) -> Vec<f32> {
+        let gradients = hist.gradients();
+        let hessians = hist.hessians();
+        let counts = hist.counts();
+
+        let (sum_gradients, sum_hessians, count) = totals(hist);
+        let parent_score = self.score(sum_gradients, sum_hessians);
+
+        let mut gains = Vec::with_capacity(gradients.len().saturating_sub(1));
+        let mut left_gradients = 0.0;
+        let mut left_hessians = 0.0;
+        let mut left_count = 0;
+        for i in 0..gradients.len().saturating_sub(1) {
+            left_gradients += gradients[i];
+            left_hessians += hessians[i];
+            left_count += counts[i];
+            let right_hessians = sum_hessians - left_hessians;
+            let right_count = count - left_count;
+            let allowed = left_count >= self.min_data_in_leaf
+                && right_count >= self.min_data_in_leaf
+                && left_hessians > 0.0
+                && right_hessians > 0.0
+                && left_hessians >= self.min_sum_hessian_in_leaf
+                && right_hessians >= self.min_sum_hessian_in_leaf;
+            gains.push(if allowed {
+                self.score(left_gradients, left_hessians)
+                    + self.score(sum_gradients - left_gradients, right_hessians)
+                    - parent_score
+            } else {
+                0.0
+            });
+        }
+        gains
+    }
+
+    pub fn leaf_value(&self, sum_gradients: f32, sum_hessians: f32) -> f32 {
+        let denominator = sum_hessians + self.lambda_l2;
+        if denominator <= 0.0 {
+            0.0
+        } else {
+            -self.threshold_l1(sum_gradients) / denominator
+        }
+    }
+
+    // The leaf value clamped to `bounds` and its score. A clamped value scores
+    // -(2 T(G) w + (H + lambda_l2) w^2), which is what `score` reduces to at the optimum.
+    fn bounded_leaf(
+        &self,
+        sum_gradients: f32,
+        sum_hessians: f32,
+        bounds: ValueBounds,
+    ) -> (f32, f32) {
+        let value = self.leaf_value(sum_gradients, sum_hessians);
+        let clamped = bounds.clamp(value);
+        if clamped == value {
+            (value, self.score(sum_gradients, sum_hessians))
+        } else {
+            let score = -(2.0 * self.threshold_l1(sum_gradients) * clamped
+                + (sum_hessians + self.lambda_l2) * clamped * clamped);
+            (clamped, score)
+        }
+    }
+
+    // T(G)^2 / (H + lambda_l2): the (negated, doubled) loss reduction of an optimal leaf,
+    // where T shrinks G towards zero by lambda_l1
+    fn score(&self, sum_gradients: f32, sum_hessians: f32) -> f32 {
+        let gradients = self.threshold_l1(sum_gradients);
+        gradients * gradients / (sum_hessians + self.lambda_l2)
+    }
+
+    fn threshold_l1(&self, sum_gradients: f32) -> f32 {
+        sum_gradients.signum() * (sum_gradients.abs() - self.lambda_l1).max(0.0)
+    }
+}
+
+// gradient sum, hessian sum and count over all bins, the missing one included
+fn totals(hist: &Histogram) -> (f32, f32, usize) {
+    let (missing_gradients, missing_hessians, missing_count) = hist.missing();
+    (
+        hist.gradients().iter().sum::<f32>() + missing_gradients,
+        hist.hessians().iter().sum::<f32>() + missing_hessians,
+        hist.counts().iter().sum::<usize>() + missing_count,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    fn histogram(feature_values: &[f32], gradients: &[f32], max_bins: usize) -> Histogram {
+        let mut hist = Histogram::from_feature(feature_values, max_bins);
+        hist.accumulate(feature_values, gradients, &vec![1.0; gradients.len()], None);
+        hist
+    }
+
+    #[test]
+    fn test_find_best_split_separates_gradient_signs() {
+        let feature_values = vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+        let gradients = vec![-1.0, -1.0, -1.0, 1.0, 1.0, 1.0];
+        let hist = histogram(&feature_values, &gradients, 5);
+
+        let split = SplitFinder::new(0.0).find_best_split(&[hist]).unwrap();
+
+        assert_eq!(split.feature_index, 0);
+        assert_eq!(split.threshold, 3.0);
+        // 9 / 3 + 9 / 3 - 0 / 6
+        assert_abs_diff_eq!(split.gain, 6.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(split.left_sum_gradients, -3.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(split.right_sum_hessians, 3.0, epsilon = 1e-6);
+        assert_eq!((split.left_count, split.right_count), (3, 3));
+    }
+
+    #[test]
+    fn test_find_best_split_picks_most_informative_feature() {
+        let noise = histogram(
+            &[0.0, 1.0, 2.0, 0.0, 1.0, 2.0],
+            &[-1.0, 1.0, -1.0, 1.0, -1.0, 1.0],
+            2,
+        );
+        let signal = histogram(
+            &[0.0, 0.0, 0.0, 1.0, 2.0, 2.0],
+            &[-1.0, -1.0, -1.0, 1.0, 1.0, 1.0],
+            2,
+        );
+
+        let split = SplitFinder::new(0.0)
+            .find_best_split(&[noise, signal])
+            .unwrap();
+
+        assert_eq!(split.feature_index, 1);
+        assert_eq!(split.threshold, 1.0);
+    }
+
+    #[test]
+    fn test_lambda_shrinks_gain_and_leaf_value() {
+        let feature_values = vec![0.0, 1.0, 2.0, 3.0];
+        let gradients = vec![-2.0, -2.0, 2.0, 2.0];
+        let hist = histogram(&feature_values, &gradients, 3);
+
+        let plain = SplitFinder::new(0.0)
+            .best_split_for_feature(0, &hist)
+            .unwrap();
+        let regularized = SplitFinder::new(2.0)
+            .best_split_for_feature(0, &hist)
+            .unwrap();
+
+        assert!(regularized.gain < plain.gain);
+        assert_abs_diff_eq!(
+            SplitFinder::new(0.0).leaf_value(-4.0, 2.0),
+            2.0,
+            epsilon = 1e-6
+        );
+        assert_abs_diff_eq!(
+            SplitFinder::new(2.0).leaf_value(-4.0, 2.0),
+            1.0,
+            epsilon = 1e-6
+        );
+    }
+
+    #[test]
+    fn test_lambda_l1_thresholds_gradients() {
+        let finder = SplitFinder::new(1.0).with_lambda_l1(1.0);
+
+        // (4 - 1) / (2 + 1)
+        assert_abs_diff_eq!(finder.leaf_value(-4.0, 2.0), 1.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(finder.leaf_value(4.0, 2.0), -1.0, epsilon = 1e-6);
+        assert_eq!(finder.leaf_value(0.5, 2.0), 0.0);
+
+        // each side's |G| = 2 shrinks to 1: 1 / 3 + 1 / 3 - 0
+        let hist = histogram(&[0.0, 1.0, 2.0, 3.0], &[-1.0, -1.0, 1.0, 1.0], 3);
+        let split = finder.best_split_for_feature(0, &hist).unwrap();
+        assert_abs_diff_eq!(split.gain, 2.0 / 3.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_min_gain_to_split() {
+        // best split gains 9 / 3 + 9 / 3 = 6
+        let hist = histogram(
+            &[0.0, 1.0, 2.0, 3.0, 4.0, 5.0],
+            &[-1.0, -1.0, -1.0, 1.0, 1.0, 1.0],
+            5,
+        );
+
+        let finder = SplitFinder::new(0.0).with_min_gain_to_split(5.0);
+        assert!(finder.best_split_for_feature(0, &hist).is_some());
+
+        let finder = SplitFinder::new(0.0).with_min_gain_to_split(6.5);
+        assert!(finder.best_split_for_feature(0, &hist).is_none());
+    }
+
+    #[test]
+    fn test_min_data_and_hessian_in_leaf() {
+        // the best split isolates the single outlier in bin 0
+        let feature_values = vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+        let gradients = vec![-10.0, 1.0, 1.0, 1.0, 1.0, 1.0];
+        let hist = histogram(&feature_values, &gradients, 5);
+
+        let split = SplitFinder::new(0.0)
+            .best_split_for_feature(0, &hist)
+            .unwrap();
+        assert_eq!(split.threshold, 1.0);
+
+        let split = SplitFinder::new(0.0)
+            .with_min_data_in_leaf(2)
+            .best_split_for_feature(0, &hist)
+            .unwrap();
+        assert_eq!(split.threshold, 2.0);
+        assert!(split.left_count >= 2 && split.right_count >= 2);
+
+        // every row has hessian 1, so this acts like min_data_in_leaf = 3
+        let split = SplitFinder::new(0.0)
+            .with_min_sum_hessian_in_leaf(2.5)
+            .best_split_for_feature(0, &hist)
+            .unwrap();
+        assert_eq!(split.threshold, 3.0);
+
+        assert!(
+            SplitFinder::new(0.0)
+                .with_min_data_in_leaf(4)
+                .best_split_for_feature(0, &hist)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_random_split_uses_one_boundary_per_feature() {
+        let feature_values = vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+        let gradients = vec![-1.0, -1.0, -1.0, 1.0, 1.0, 1.0];
+        let hist = histogram(&feature_values, &gradients, 5);
+        let finder = SplitFinder::new(0.0);
+        let best = finder.best_split_for_feature(0, &hist).unwrap();
+
+        let mut rng = Rng::new(0);
+        let mut thresholds = vec![];
+        for _ in 0..50 {
+            let split = finder
+                .find_random_split_among(
+                    std::slice::from_ref(&hist),
+                    &[0],
+                    ValueBounds::UNBOUNDED,
+                    &mut rng,
+                )
+                .unwrap();
+            assert!(split.gain <= best.gain);
+            thresholds.push(split.threshold);
+        }
+        thresholds.sort_by(f32::total_cmp);
+        thresholds.dedup();
+
+        // all four boundaries at or above 1.0 get drawn
+        assert_eq!(thresholds, vec![1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn test_monotone_constraint_rejects_violating_splits() {
+        // the only gainful split puts the larger value on the left
+        let hist = histogram(&[0.0, 1.0, 2.0, 3.0], &[-1.0, -1.0, 1.0, 1.0], 3);
+
+        let increasing = SplitFinder::new(0.0).with_monotone_constraints(vec![1]);
+        assert!(
+            increasing
+                .find_best_split(std::slice::from_ref(&hist))
+                .is_none()
+        );
+
+        let decreasing = SplitFinder::new(0.0).with_monotone_constraints(vec![-1]);
+        let split = decreasing
+            .find_best_split(std::slice::from_ref(&hist))
+            .unwrap();
+        assert!(split.left_value > split.right_value);
+
+        // below a decreasing split at midpoint 0 the left child may not drop under it
+        let (left, right) = decreasing.child_bounds(&split, ValueBounds::UNBOUNDED);
+        assert_eq!(left.min, 0.0);
+        assert_eq!(right.max, 0.0);
+    }
+
+    #[test]
+    fn test_bounds_clamp_child_values_and_gain() {
+        let hist = histogram(&[0.0, 1.0, 2.0, 3.0], &[-1.0, -1.0, 1.0, 1.0], 3);
+        let finder = SplitFinder::new(0.0);
+        let bounds = ValueBounds {
+            min: -0.5,
+            max: 0.5,
+        };
+
+        let split = finder
+            .find_best_split_among(std::slice::from_ref(&hist), &[0], bounds)
+            .unwrap();
+
+        assert_eq!(split.left_value, 0.5);
+        assert_eq!(split.right_value, -0.5);
+        // each side: -(2 * -2 * 0.5 + 2 * 0.25) = 1.5 instead of the unclamped 2
+        assert_abs_diff_eq!(split.gain, 3.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_level_split_sums_gain_over_leaves() {
+        // feature 0 separates the gradients in both leaves, feature 1 only in the first one
+        let leaf = |x0: &[f32], x1: &[f32], gradients: &[f32]| {
+            vec![histogram(x0, gradients, 4), histogram(x1, gradients, 4)]
+        };
+        let level = vec![
+            leaf(
+                &[0.0, 1.0, 2.0, 3.0],
+                &[0.0, 1.0, 2.0, 3.0],
+                &[-1.0, -1.0, 1.0, 1.0],
+            ),
+            leaf(
+                &[0.0, 1.0, 2.0, 3.0],
+                &[0.0, 2.0, 1.0, 3.0],
+                &[-2.0, -2.0, 2.0, 2.0],
+            ),
+        ];
+        let finder = SplitFinder::new(0.0);
+
+        let split = finder.find_best_level_split(&level, &[0, 1], None).unwrap();
+        assert_eq!(split.feature_index, 0);
+        assert_eq!(split.threshold, 2.0);
+        // 4 from the first leaf and 16 from the second
+        assert_abs_diff_eq!(split.gain, 20.0, epsilon = 1e-5);
+
+        let only_second = finder.find_best_level_split(&level, &[1], None).unwrap();
+        assert!(only_second.gain < split.gain);
+
+        let level_of_one = vec![level[0].clone()];
+        let never = finder.with_min_data_in_leaf(3);
+        assert!(
+            never
+                .find_best_level_split(&level_of_one, &[0, 1], None)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_no_split_without_gain() {
+        let hist = histogram(&[0.0, 1.0, 2.0, 3.0], &[1.0, 1.0, 1.0, 1.0], 3);
+        assert!(SplitFinder::new(0.0).find_best_split(&[hist]).is_none());
+
+        let single = histogram(&[5.0, 5.0], &[1.0, -1.0], 3);
+        assert!(SplitFinder::new(0.0).find_best_split(&[single]).is_none());
+    }
+}
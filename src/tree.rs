@@ -1,47 +1,680 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::dot;
+use crate::dump;
+use crate::sparse::FeatureRow;
+
+pub use metalgbm_predict::in_categories;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum TreeNode {
     Split {
         feature_index: usize,
         threshold: f32,
+        // where NaN values go; models saved before it existed sent them right
+        #[serde(default)]
+        default_left: bool,
+        left_child: Box<TreeNode>,
+        right_child: Box<TreeNode>,
+    },
+    // Sends rows whose value is one of `categories`, ascending, left and all others right,
+    // including categories never seen in training.
+    CategoricalSplit {
+        feature_index: usize,
+        categories: Vec<u32>,
         left_child: Box<TreeNode>,
         right_child: Box<TreeNode>,
     },
     Leaf {
         value: f32,
     },
+    // Linear model in the features split on along the path to the leaf. `value` is the
+    // constant leaf, used instead when one of those features is NaN.
+    LinearLeaf {
+        value: f32,
+        intercept: f32,
+        feature_indices: Vec<usize>,
+        coefficients: Vec<f32>,
+    },
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Tree {
     root: Box<TreeNode>,
+    // learning rate of the iteration that built the tree, applied on top of the leaf values
+    shrinkage: f32,
+    // flat form of an oblivious tree, used instead of walking `root` when present
+    lookup_table: Option<LookupTable>,
+    // `root` as arrays, walked instead of it unless there is a lookup table; models saved
+    // before it existed walk `root`
+    #[serde(default)]
+    flat: Option<FlatTree>,
+    // how much of the training data reached each leaf, leaves numbered as by `leaf_index`:
+    // the rows for trees grown here or read from LightGBM, the hessian sum for XGBoost's.
+    // Feature contributions weigh the leaves by them; models saved before they were
+    // recorded have none.
+    #[serde(default)]
+    leaf_covers: Option<Vec<f32>>,
+    // the gain of every split, splits in preorder, left child first, for feature importance;
+    // models saved before it was recorded have none
+    #[serde(default)]
+    split_gains: Option<Vec<f32>>,
+}
+
+// An oblivious tree, where every node at depth d splits on `feature_indices[d]` at
+// `thresholds[d]` and sends NaN values right. Going right at depth d sets bit (depth - 1 - d) of the leaf index, so
+// prediction is one comparison per level followed by a lookup in `leaf_values`, which are
+// ordered left to right.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LookupTable {
+    feature_indices: Vec<usize>,
+    thresholds: Vec<f32>,
+    leaf_values: Vec<f32>,
+}
+
+impl LookupTable {
+    // None unless `root` is a complete tree with the same split throughout each level and
+    // constant leaves.
+    pub fn from_root(root: &TreeNode) -> Option<Self> {
+        let mut feature_indices = vec![];
+        let mut thresholds = vec![];
+        let mut level = vec![root];
+        loop {
+            match level[0] {
+                TreeNode::Split {
+                    feature_index,
+                    threshold,
+                    ..
+                } => {
+                    let mut next_level = Vec::with_capacity(level.len() * 2);
+                    for node in &level {
+                        match node {
+                            TreeNode::Split {
+                                feature_index: f,
+                                threshold: t,
+                                default_left: false,
+                                left_child,
+                                right_child,
+                            } if f == feature_index && t == threshold => {
+                                next_level.push(left_child.as_ref());
+                                next_level.push(right_child.as_ref());
+                            }
+                            _ => return None,
+                        }
+                    }
+                    feature_indices.push(*feature_index);
+                    thresholds.push(*threshold);
+                    level = next_level;
+                }
+                _ => {
+                    let leaf_values = level
+                        .iter()
+                        .map(|node| match node {
+                            TreeNode::Leaf { value } => Some(*value),
+                            _ => None,
+                        })
+                        .collect::<Option<Vec<f32>>>()?;
+                    return Some(Self {
+                        feature_indices,
+                        thresholds,
+                        leaf_values,
+                    });
+                }
+            }
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.feature_indices.len()
+    }
+
+    pub fn leaf_values(&self) -> &[f32] {
+        &self.leaf_values
+    }
+
+    pub fn predict(&self, features: &[f32]) -> f32 {
+        self.predict_row(features)
+    }
+
+    pub fn predict_row<R: FeatureRow + ?Sized>(&self, row: &R) -> f32 {
+        let mut index = 0;
+        for (&feature_index, &threshold) in self.feature_indices.iter().zip(&self.thresholds) {
+            // NaN goes right, like in `Tree::walk`
+            let right = if row.value(feature_index) < threshold {
+                0
+            } else {
+                1
+            };
+            index = (index << 1) | right;
+        }
+        self.leaf_values[index]
+    }
+}
+
+// Preorder iterator over the nodes of a tree, see `Tree::nodes`.
+pub struct Nodes<'a> {
+    stack: Vec<&'a TreeNode>,
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = &'a TreeNode;
+
+    fn next(&mut self) -> Option<&'a TreeNode> {
+        let node = self.stack.pop()?;
+        if let TreeNode::Split {
+            left_child,
+            right_child,
+            ..
+        }
+        | TreeNode::CategoricalSplit {
+            left_child,
+            right_child,
+            ..
+        } = node
+        {
+            self.stack
+                .extend([right_child.as_ref(), left_child.as_ref()]);
+        }
+        Some(node)
+    }
+}
+
+// The spread of a set of leaf values, see `Tree::leaf_stats`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LeafStats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+}
+
+impl LeafStats {
+    // the stats of `values`, which mustn't be empty
+    pub(crate) fn of(values: &[f32]) -> Self {
+        let (min, max, sum) = values.iter().fold(
+            (f32::INFINITY, f32::NEG_INFINITY, 0.0f64),
+            |(min, max, sum), &value| (min.min(value), max.max(value), sum + value as f64),
+        );
+        LeafStats {
+            min,
+            max,
+            mean: (sum / values.len() as f64) as f32,
+        }
+    }
+}
+
+// One split on the way from the root to the leaf a row reaches, see `Tree::decision_path`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decision {
+    pub feature_index: usize,
+    pub condition: SplitCondition,
+    // the row's value of the feature, NaN when it is missing
+    pub value: f32,
+    // whether the row went to the left child
+    pub went_left: bool,
+}
+
+// what sends a row left at a split
+#[derive(Debug, Clone, PartialEq)]
+pub enum SplitCondition {
+    // values below the threshold, and missing values when `default_left`
+    Threshold { threshold: f32, default_left: bool },
+    // values that are one of the ascending categories
+    Categories(Vec<u32>),
+}
+
+// The nodes of a tree as parallel arrays in preorder, so prediction is a loop over indices
+// instead of chasing boxes, which keeps the nodes close together in memory and deep trees
+// off the stack. Node i is a leaf when `left[i]` is 0, since no node points back to the
+// root. Trees with linear leaves have no flat form.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlatTree {
+    pub(crate) feature_index: Vec<u32>,
+    pub(crate) threshold: Vec<f32>,
+    pub(crate) default_left: Vec<bool>,
+    // categorical splits send `categories[category_start[i]..category_start[i + 1]]` left
+    pub(crate) categorical: Vec<bool>,
+    pub(crate) left: Vec<u32>,
+    pub(crate) right: Vec<u32>,
+    pub(crate) leaf_value: Vec<f32>,
+    // one more entry than there are nodes
+    pub(crate) category_start: Vec<u32>,
+    pub(crate) categories: Vec<u32>,
+}
+
+impl FlatTree {
+    // None when the tree has linear leaves.
+    pub fn new(root: &TreeNode) -> Option<Self> {
+        let mut flat = Self {
+            feature_index: vec![],
+            threshold: vec![],
+            default_left: vec![],
+            categorical: vec![],
+            left: vec![],
+            right: vec![],
+            leaf_value: vec![],
+            category_start: vec![0],
+            categories: vec![],
+        };
+        // nodes still to add, with the parent pointing to them and whether as its left child
+        let mut stack: Vec<(&TreeNode, Option<(usize, bool)>)> = vec![(root, None)];
+        while let Some((node, parent)) = stack.pop() {
+            let index = flat.left.len();
+            match parent {
+                Some((parent, true)) => flat.left[parent] = index as u32,
+                Some((parent, false)) => flat.right[parent] = index as u32,
+                None => {}
+            }
+            let (left_child, right_child) = match node {
+                TreeNode::Leaf { value } => {
+                    flat.push(0, 0.0, false, false, *value);
+                    continue;
+                }
+                TreeNode::LinearLeaf { .. } => return None,
+                TreeNode::Split {
+                    feature_index,
+                    threshold,
+                    default_left,
+                    left_child,
+                    right_child,
+                } => {
+                    flat.push(*feature_index as u32, *threshold, *default_left, false, 0.0);
+                    (left_child, right_child)
+                }
+                TreeNode::CategoricalSplit {
+                    feature_index,
+                    categories,
+                    left_child,
+                    right_child,
+                } => {
+                    flat.categories.extend_from_slice(categories);
+                    flat.push(*feature_index as u32, 0.0, false, true, 0.0);
+                    (left_child, right_child)
+                }
+            };
+            // the left subtree comes first
+            stack.push((right_child, Some((index, false))));
+            stack.push((left_child, Some((index, true))));
+        }
+        Some(flat)
+    }
+
+    fn push(
+        &mut self,
+        feature_index: u32,
+        threshold: f32,
+        default_left: bool,
+        categorical: bool,
+        leaf_value: f32,
+    ) {
+        self.feature_index.push(feature_index);
+        self.threshold.push(threshold);
+        self.default_left.push(default_left);
+        self.categorical.push(categorical);
+        self.left.push(0);
+        self.right.push(0);
+        self.leaf_value.push(leaf_value);
+        self.category_start.push(self.categories.len() as u32);
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.left.len()
+    }
+
+    // the leaf value `row` ends up at, before shrinkage
+    pub fn predict_row<R: FeatureRow + ?Sized>(&self, row: &R) -> f32 {
+        self.leaf_value[self.leaf_node(row)]
+    }
+
+    // For every node, in the order of the nodes, the number of leaves before it. The nodes
+    // are in preorder, so that numbers the leaves from left to right.
+    pub(crate) fn leaf_ranks(&self) -> Vec<u32> {
+        let mut num_leaves = 0;
+        self.left
+            .iter()
+            .map(|&left| {
+                let rank = num_leaves;
+                num_leaves += u32::from(left == 0);
+                rank
+            })
+            .collect()
+    }
+
+    // the index of the leaf node `row` ends up at
+    pub(crate) fn leaf_node<R: FeatureRow + ?Sized>(&self, row: &R) -> usize {
+        let mut index = 0;
+        while self.left[index] != 0 {
+            let value = row.value(self.feature_index[index] as usize);
+            let left = if self.categorical[index] {
+                let categories =
+                    self.category_start[index] as usize..self.category_start[index + 1] as usize;
+                in_categories(&self.categories[categories], value)
+            } else if value.is_nan() {
+                self.default_left[index]
+            } else {
+                value < self.threshold[index]
+            };
+            index = if left {
+                self.left[index]
+            } else {
+                self.right[index]
+            } as usize;
+        }
+        index
+    }
 }
 
 impl Tree {
     pub fn new(root: Box<TreeNode>) -> Self {
-        Self { root }
+        Self {
+            flat: FlatTree::new(&root),
+            root,
+            shrinkage: 1.0,
+            lookup_table: None,
+            leaf_covers: None,
+            split_gains: None,
+        }
+    }
+
+    // Precomputes the lookup table prediction uses for oblivious trees; trees of any other
+    // shape are left as they are.
+    pub fn with_lookup_table(mut self) -> Self {
+        self.lookup_table = LookupTable::from_root(&self.root);
+        self
+    }
+
+    pub fn with_shrinkage(mut self, shrinkage: f32) -> Self {
+        self.shrinkage = shrinkage;
+        self
+    }
+
+    // Records how much training data reached each leaf, see `leaf_covers`. Panics unless
+    // there is one cover per leaf.
+    pub fn with_leaf_covers(mut self, leaf_covers: Vec<f32>) -> Self {
+        assert_eq!(leaf_covers.len(), self.num_leaves(), "one cover per leaf");
+        self.leaf_covers = Some(leaf_covers);
+        self
+    }
+
+    // Records the gain of every split, see `split_gains`. Panics unless there is one gain
+    // per split.
+    pub fn with_split_gains(mut self, split_gains: Vec<f32>) -> Self {
+        assert_eq!(
+            split_gains.len(),
+            self.num_leaves() - 1,
+            "one gain per split"
+        );
+        self.split_gains = Some(split_gains);
+        self
+    }
+
+    pub fn root(&self) -> &TreeNode {
+        &self.root
+    }
+
+    pub fn shrinkage(&self) -> f32 {
+        self.shrinkage
+    }
+
+    pub fn lookup_table(&self) -> Option<&LookupTable> {
+        self.lookup_table.as_ref()
+    }
+
+    pub fn flat(&self) -> Option<&FlatTree> {
+        self.flat.as_ref()
+    }
+
+    pub fn leaf_covers(&self) -> Option<&[f32]> {
+        self.leaf_covers.as_deref()
+    }
+
+    pub fn split_gains(&self) -> Option<&[f32]> {
+        self.split_gains.as_deref()
+    }
+
+    // the feature of every split, splits in preorder like `split_gains`
+    pub fn split_features(&self) -> Vec<usize> {
+        self.nodes()
+            .filter_map(|node| match node {
+                TreeNode::Split { feature_index, .. }
+                | TreeNode::CategoricalSplit { feature_index, .. } => Some(*feature_index),
+                TreeNode::Leaf { .. } | TreeNode::LinearLeaf { .. } => None,
+            })
+            .collect()
+    }
+
+    // All the nodes of the tree in preorder, starting at the root
+    pub fn nodes(&self) -> Nodes<'_> {
+        Nodes {
+            stack: vec![self.root.as_ref()],
+        }
+    }
+
+    // The value of every leaf, left to right, with the shrinkage applied. Linear leaves
+    // count with the value they predict for rows missing one of their features.
+    pub fn leaf_values(&self) -> Vec<f32> {
+        self.nodes()
+            .filter_map(|node| match node {
+                TreeNode::Leaf { value } | TreeNode::LinearLeaf { value, .. } => {
+                    Some(self.shrinkage * value)
+                }
+                TreeNode::Split { .. } | TreeNode::CategoricalSplit { .. } => None,
+            })
+            .collect()
+    }
+
+    pub fn leaf_stats(&self) -> LeafStats {
+        LeafStats::of(&self.leaf_values())
+    }
+
+    // The number of splits on the longest path from the root to a leaf. Without `max_depth`
+    // leaf-wise growth can make this as large as the number of leaves.
+    pub fn depth(&self) -> usize {
+        let mut depth = 0;
+        let mut stack = vec![(self.root.as_ref(), 0)];
+        while let Some((node, node_depth)) = stack.pop() {
+            match node {
+                TreeNode::Split {
+                    left_child,
+                    right_child,
+                    ..
+                }
+                | TreeNode::CategoricalSplit {
+                    left_child,
+                    right_child,
+                    ..
+                } => {
+                    stack.push((left_child, node_depth + 1));
+                    stack.push((right_child, node_depth + 1));
+                }
+                TreeNode::Leaf { .. } | TreeNode::LinearLeaf { .. } => {
+                    depth = depth.max(node_depth)
+                }
+            }
+        }
+        depth
     }
 
     pub fn predict(&self, features: &[f32]) -> f32 {
-        Self::predict_recursive(&self.root, features)
+        self.predict_row(features)
+    }
+
+    // `predict` on any row representation, e.g. a `SparseRow`
+    pub fn predict_row<R: FeatureRow + ?Sized>(&self, row: &R) -> f32 {
+        let value = match (&self.lookup_table, &self.flat) {
+            (Some(table), _) => table.predict_row(row),
+            (None, Some(flat)) => flat.predict_row(row),
+            (None, None) => Self::walk(&self.root, row),
+        };
+        self.shrinkage * value
+    }
+
+    // Graphviz DOT source drawing the tree, its features called by `feature_names` when
+    // given; see `dot::tree_to_dot`.
+    pub fn to_dot(&self, feature_names: Option<&[String]>) -> String {
+        dot::tree_to_dot(self, feature_names)
+    }
+
+    // The text listing of the tree, its features called by `feature_names` when given; see
+    // `dump::tree_to_text`. `Display` writes it with the features unnamed.
+    pub fn to_text(&self, feature_names: Option<&[String]>) -> String {
+        dump::tree_to_text(self, feature_names)
+    }
+
+    // The splits `row` passes from the root to its leaf, in order, each with how the row
+    // was sent on there; `leaf_index` numbers the leaf it ends at. Empty for a single leaf.
+    pub fn decision_path<R: FeatureRow + ?Sized>(&self, row: &R) -> Vec<Decision> {
+        let mut path = Vec::new();
+        let mut node = self.root.as_ref();
+        while let Some((went_left, left_child, right_child)) = Self::branch(node, row) {
+            let (feature_index, condition) = match node {
+                TreeNode::Split {
+                    feature_index,
+                    threshold,
+                    default_left,
+                    ..
+                } => (
+                    *feature_index,
+                    SplitCondition::Threshold {
+                        threshold: *threshold,
+                        default_left: *default_left,
+                    },
+                ),
+                TreeNode::CategoricalSplit {
+                    feature_index,
+                    categories,
+                    ..
+                } => (
+                    *feature_index,
+                    SplitCondition::Categories(categories.clone()),
+                ),
+                TreeNode::Leaf { .. } | TreeNode::LinearLeaf { .. } => unreachable!(),
+            };
+            path.push(Decision {
+                feature_index,
+                condition,
+                value: row.value(feature_index),
+                went_left,
+            });
+            node = if went_left { left_child } else { right_child };
+        }
+        path
+    }
+
+    // The leaf `row` ends up at, leaves numbered from 0 left to right; the same for every
+    // row reaching the same leaf, e.g. to one-hot encode rows by their leaves.
+    pub fn leaf_index<R: FeatureRow + ?Sized>(&self, row: &R) -> usize {
+        if let Some(flat) = &self.flat {
+            return flat.leaf_ranks()[flat.leaf_node(row)] as usize;
+        }
+        // going right passes all the leaves on the left
+        let mut node = self.root.as_ref();
+        let mut index = 0;
+        while let Some((left, left_child, right_child)) = Self::branch(node, row) {
+            if left {
+                node = left_child;
+            } else {
+                index += num_leaves(left_child);
+                node = right_child;
+            }
+        }
+        index
     }
 
-    fn predict_recursive(node: &TreeNode, features: &[f32]) -> f32 {
+    pub fn num_leaves(&self) -> usize {
+        num_leaves(&self.root)
+    }
+
+    // `FlatTree::predict_row` on the boxed nodes, for trees without a flat form
+    fn walk<R: FeatureRow + ?Sized>(mut node: &TreeNode, features: &R) -> f32 {
+        while let Some((left, left_child, right_child)) = Self::branch(node, features) {
+            node = if left { left_child } else { right_child };
+        }
         match node {
             TreeNode::Leaf { value } => *value,
+            TreeNode::LinearLeaf {
+                value,
+                intercept,
+                feature_indices,
+                coefficients,
+            } => {
+                let mut prediction = *intercept;
+                for (&feature_index, coefficient) in feature_indices.iter().zip(coefficients) {
+                    let feature_value = features.value(feature_index);
+                    if feature_value.is_nan() {
+                        return *value;
+                    }
+                    prediction += coefficient * feature_value;
+                }
+                prediction
+            }
+            TreeNode::Split { .. } | TreeNode::CategoricalSplit { .. } => unreachable!(),
+        }
+    }
+
+    // Whether `features` go left at the split `node`, with its left and right children;
+    // None for leaves.
+    pub(crate) fn branch<'a, R: FeatureRow + ?Sized>(
+        node: &'a TreeNode,
+        features: &R,
+    ) -> Option<(bool, &'a TreeNode, &'a TreeNode)> {
+        match node {
+            TreeNode::Leaf { .. } | TreeNode::LinearLeaf { .. } => None,
             TreeNode::Split {
                 feature_index,
                 threshold,
+                default_left,
                 left_child,
                 right_child,
             } => {
-                let feature_value = features[*feature_index];
-
-                if feature_value < *threshold {
-                    Self::predict_recursive(left_child, features)
+                let feature_value = features.value(*feature_index);
+                let left = if feature_value.is_nan() {
+                    *default_left
                 } else {
-                    Self::predict_recursive(right_child, features)
-                }
+                    feature_value < *threshold
+                };
+                Some((left, left_child, right_child))
+            }
+            TreeNode::CategoricalSplit {
+                feature_index,
+                categories,
+                left_child,
+                right_child,
+            } => Some((
+                in_categories(categories, features.value(*feature_index)),
+                left_child,
+                right_child,
+            )),
+        }
+    }
+}
+
+fn num_leaves(root: &TreeNode) -> usize {
+    let mut count = 0;
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        match node {
+            TreeNode::Split {
+                left_child,
+                right_child,
+                ..
             }
+            | TreeNode::CategoricalSplit {
+                left_child,
+                right_child,
+                ..
+            } => stack.extend([left_child.as_ref(), right_child.as_ref()]),
+            TreeNode::Leaf { .. } | TreeNode::LinearLeaf { .. } => count += 1,
         }
     }
+    count
+}
+
+impl fmt::Display for Tree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_text(None))
+    }
 }
 
 #[cfg(test)]
@@ -56,6 +689,7 @@ mod tests {
         let root = TreeNode::Split {
             feature_index: 0,
             threshold: 5.0,
+            default_left: false,
             left_child: Box::new(left_leaf),
             right_child: Box::new(right_leaf),
         };
@@ -63,5 +697,324 @@ mod tests {
         let tree = Tree::new(Box::new(root));
 
         assert_eq!(tree.predict(&[3.0]), 10.0);
+        assert_eq!(tree.predict(&[f32::NAN]), 20.0);
+    }
+
+    #[test]
+    fn test_missing_values_follow_default_direction() {
+        let root = TreeNode::Split {
+            feature_index: 0,
+            threshold: 5.0,
+            default_left: true,
+            left_child: Box::new(TreeNode::Leaf { value: 10.0 }),
+            right_child: Box::new(TreeNode::Leaf { value: 20.0 }),
+        };
+        let tree = Tree::new(Box::new(root)).with_lookup_table();
+
+        assert!(tree.lookup_table().is_none());
+        assert_eq!(tree.predict(&[f32::NAN]), 10.0);
+        assert_eq!(tree.predict(&[6.0]), 20.0);
+    }
+
+    #[test]
+    fn test_linear_leaf_prediction() {
+        let root = TreeNode::Split {
+            feature_index: 0,
+            threshold: 5.0,
+            default_left: false,
+            left_child: Box::new(TreeNode::LinearLeaf {
+                value: 1.0,
+                intercept: 2.0,
+                feature_indices: vec![0, 1],
+                coefficients: vec![0.5, -1.0],
+            }),
+            right_child: Box::new(TreeNode::Leaf { value: 20.0 }),
+        };
+        let tree = Tree::new(Box::new(root));
+
+        // 2 + 0.5 * 4 - 1 * 3
+        assert_eq!(tree.predict(&[4.0, 3.0]), 1.0);
+        assert_eq!(tree.predict(&[2.0, 0.0]), 3.0);
+        // falls back to the constant value on missing path features
+        assert_eq!(tree.predict(&[2.0, f32::NAN]), 1.0);
+        assert_eq!(tree.predict(&[6.0, 3.0]), 20.0);
+    }
+
+    #[test]
+    fn test_categorical_split_prediction() {
+        let root = TreeNode::CategoricalSplit {
+            feature_index: 0,
+            categories: vec![1, 4, 7],
+            left_child: Box::new(TreeNode::Leaf { value: 10.0 }),
+            right_child: Box::new(TreeNode::Leaf { value: 20.0 }),
+        };
+        let tree = Tree::new(Box::new(root));
+
+        assert_eq!(tree.predict(&[4.0]), 10.0);
+        assert_eq!(tree.predict(&[7.0]), 10.0);
+        assert_eq!(tree.predict(&[2.0]), 20.0);
+        // unseen and invalid categories go right
+        assert_eq!(tree.predict(&[100.0]), 20.0);
+        assert_eq!(tree.predict(&[4.5]), 20.0);
+        assert_eq!(tree.predict(&[-1.0]), 20.0);
+        assert_eq!(tree.predict(&[f32::NAN]), 20.0);
+    }
+
+    #[test]
+    fn test_prediction_applies_shrinkage() {
+        let tree = Tree::new(Box::new(TreeNode::Leaf { value: 10.0 })).with_shrinkage(0.25);
+
+        assert_eq!(tree.predict(&[0.0]), 2.5);
+        assert_eq!(tree.shrinkage(), 0.25);
+    }
+
+    fn split(feature_index: usize, threshold: f32, left: TreeNode, right: TreeNode) -> TreeNode {
+        TreeNode::Split {
+            feature_index,
+            threshold,
+            default_left: false,
+            left_child: Box::new(left),
+            right_child: Box::new(right),
+        }
+    }
+
+    fn leaf(value: f32) -> TreeNode {
+        TreeNode::Leaf { value }
+    }
+
+    #[test]
+    fn test_lookup_table_matches_tree_walk() {
+        let root = split(
+            0,
+            5.0,
+            split(1, 0.0, leaf(1.0), leaf(2.0)),
+            split(1, 0.0, leaf(3.0), leaf(4.0)),
+        );
+        let walked = Tree::new(Box::new(root));
+        let table = LookupTable::from_root(walked.root()).unwrap();
+        assert_eq!(table.depth(), 2);
+        assert_eq!(table.leaf_values(), &[1.0, 2.0, 3.0, 4.0]);
+
+        let tree = Tree::new(Box::new(split(
+            0,
+            5.0,
+            split(1, 0.0, leaf(1.0), leaf(2.0)),
+            split(1, 0.0, leaf(3.0), leaf(4.0)),
+        )))
+        .with_lookup_table();
+        assert!(tree.lookup_table().is_some());
+        for features in [
+            [4.0, -1.0],
+            [4.0, 1.0],
+            [6.0, -1.0],
+            [6.0, 0.0],
+            [f32::NAN, -1.0],
+        ] {
+            assert_eq!(tree.predict(&features), walked.predict(&features));
+        }
+    }
+
+    #[test]
+    fn test_leaf_indices_count_from_the_left() {
+        let linear_leaf = TreeNode::LinearLeaf {
+            value: 2.0,
+            intercept: 2.0,
+            feature_indices: vec![1],
+            coefficients: vec![0.5],
+        };
+        let flat = Tree::new(Box::new(split(
+            0,
+            5.0,
+            split(1, 0.0, leaf(1.0), leaf(2.0)),
+            leaf(3.0),
+        )));
+        let linear = Tree::new(Box::new(split(
+            0,
+            5.0,
+            split(1, 0.0, leaf(1.0), linear_leaf),
+            leaf(3.0),
+        )));
+        assert!(flat.flat().is_some() && linear.flat().is_none());
+        for tree in [flat, linear] {
+            assert_eq!(tree.num_leaves(), 3);
+            for (features, index) in [
+                ([4.0, -1.0], 0),
+                ([4.0, 1.0], 1),
+                ([6.0, 0.0], 2),
+                ([f32::NAN, 0.0], 2),
+            ] {
+                assert_eq!(tree.leaf_index(&features[..]), index, "{features:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_nodes_and_leaf_stats() {
+        let tree = Tree::new(Box::new(TreeNode::Split {
+            feature_index: 0,
+            threshold: 1.0,
+            default_left: false,
+            left_child: Box::new(TreeNode::Leaf { value: -2.0 }),
+            right_child: Box::new(TreeNode::Split {
+                feature_index: 1,
+                threshold: 3.0,
+                default_left: true,
+                left_child: Box::new(TreeNode::Leaf { value: 1.0 }),
+                right_child: Box::new(TreeNode::Leaf { value: 7.0 }),
+            }),
+        }))
+        .with_shrinkage(0.5);
+
+        let kinds: Vec<bool> = tree
+            .nodes()
+            .map(|node| matches!(node, TreeNode::Leaf { .. }))
+            .collect();
+        assert_eq!(kinds, [false, true, false, true, true]);
+        assert_eq!(tree.split_features(), [0, 1]);
+        assert_eq!(tree.leaf_values(), [-1.0, 0.5, 3.5]);
+        assert_eq!(
+            tree.leaf_stats(),
+            LeafStats {
+                min: -1.0,
+                max: 3.5,
+                mean: 1.0
+            }
+        );
+    }
+
+    #[test]
+    fn test_decision_path_follows_the_row() {
+        let tree = Tree::new(Box::new(split(
+            0,
+            5.0,
+            TreeNode::CategoricalSplit {
+                feature_index: 1,
+                categories: vec![2, 7],
+                left_child: Box::new(leaf(1.0)),
+                right_child: Box::new(leaf(2.0)),
+            },
+            leaf(3.0),
+        )));
+        let path = tree.decision_path(&[4.0, 3.0][..]);
+        assert_eq!(
+            path,
+            vec![
+                Decision {
+                    feature_index: 0,
+                    condition: SplitCondition::Threshold {
+                        threshold: 5.0,
+                        default_left: false
+                    },
+                    value: 4.0,
+                    went_left: true,
+                },
+                Decision {
+                    feature_index: 1,
+                    condition: SplitCondition::Categories(vec![2, 7]),
+                    value: 3.0,
+                    went_left: false,
+                },
+            ]
+        );
+        let path = tree.decision_path(&[f32::NAN, 3.0][..]);
+        assert_eq!(path.len(), 1);
+        assert!(path[0].value.is_nan() && !path[0].went_left);
+        assert!(
+            Tree::new(Box::new(leaf(1.0)))
+                .decision_path(&[0.0][..])
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_lookup_table_requires_symmetric_tree() {
+        let different_splits = split(
+            0,
+            5.0,
+            split(1, 0.0, leaf(1.0), leaf(2.0)),
+            split(1, 1.0, leaf(3.0), leaf(4.0)),
+        );
+        assert!(LookupTable::from_root(&different_splits).is_none());
+
+        let unbalanced = split(0, 5.0, split(1, 0.0, leaf(1.0), leaf(2.0)), leaf(3.0));
+        assert!(LookupTable::from_root(&unbalanced).is_none());
+
+        let stump = Tree::new(Box::new(leaf(7.0))).with_lookup_table();
+        assert_eq!(stump.lookup_table().unwrap().depth(), 0);
+        assert_eq!(stump.predict(&[]), 7.0);
+    }
+
+    #[test]
+    fn test_flat_tree_matches_tree_walk() {
+        let categorical = TreeNode::CategoricalSplit {
+            feature_index: 1,
+            categories: vec![1, 4],
+            left_child: Box::new(leaf(5.0)),
+            right_child: Box::new(leaf(6.0)),
+        };
+        let missing_left = TreeNode::Split {
+            feature_index: 0,
+            threshold: 8.0,
+            default_left: true,
+            left_child: Box::new(leaf(3.0)),
+            right_child: Box::new(categorical),
+        };
+        let root = split(0, 5.0, split(1, 2.0, leaf(1.0), leaf(2.0)), missing_left);
+        let tree = Tree::new(Box::new(root));
+        let flat = tree.flat().unwrap();
+        assert_eq!(flat.num_nodes(), 9);
+        assert_eq!(flat.category_start.len(), 10);
+
+        for features in [
+            [4.0, 1.0],
+            [4.0, 3.0],
+            [f32::NAN, 1.0],
+            [6.0, 0.0],
+            [9.0, 4.0],
+            [9.0, 2.0],
+            [9.0, f32::NAN],
+        ] {
+            assert_eq!(
+                flat.predict_row(&features[..]),
+                Tree::walk(tree.root(), &features[..])
+            );
+        }
+    }
+
+    #[test]
+    fn test_linear_leaves_have_no_flat_tree() {
+        let root = split(
+            0,
+            5.0,
+            leaf(1.0),
+            TreeNode::LinearLeaf {
+                value: 1.0,
+                intercept: 2.0,
+                feature_indices: vec![0],
+                coefficients: vec![0.5],
+            },
+        );
+        let tree = Tree::new(Box::new(root));
+        assert!(tree.flat().is_none());
+        assert_eq!(tree.predict(&[3.0]), 1.0);
+        assert_eq!(tree.predict(&[6.0]), 5.0);
+    }
+
+    #[test]
+    fn test_deep_tree_depth_and_prediction() {
+        // every split sends values below its threshold to a leaf and the rest further down
+        let mut root = leaf(-1.0);
+        for i in (0..1000).rev() {
+            root = split(0, i as f32 + 0.5, leaf(i as f32), root);
+        }
+        let tree = Tree::new(Box::new(root));
+        assert_eq!(tree.depth(), 1000);
+        assert_eq!(tree.predict(&[700.0]), 700.0);
+        assert_eq!(tree.predict(&[5000.0]), -1.0);
+        assert_eq!(Tree::walk(tree.root(), &[700.0][..]), 700.0);
+
+        assert_eq!(Tree::new(Box::new(leaf(1.0))).depth(), 0);
+        let lopsided = split(0, 0.0, leaf(1.0), split(1, 0.0, leaf(2.0), leaf(3.0)));
+        assert_eq!(Tree::new(Box::new(lopsided)).depth(), 2);
     }
 }
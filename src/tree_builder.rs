@@ -0,0 +1,1273 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use rayon::ThreadPool;
+use serde::{Deserialize, Serialize};
+
+use crate::dataset::BinnedDataset;
+use crate::gpu::{Device, DeviceTimings, GpuContext};
+use crate::histogram::Histogram;
+use crate::linear;
+use crate::params::Params;
+use crate::pool::HistogramPool;
+use crate::quantize::QuantizedGradients;
+use crate::random::Rng;
+use crate::split::{LevelSplit, SplitFinder, SplitInfo, ValueBounds};
+use crate::tree::{self, Tree, TreeNode};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum GrowthStrategy {
+    // LightGBM style: always expand the leaf with the highest gain
+    #[default]
+    LeafWise,
+    // XGBoost style: expand every splittable leaf of one level before moving to the next
+    DepthWise,
+    // CatBoost style: every node of a level uses the same split, so the tree is complete and
+    // can be predicted with a lookup table (see `LookupTable`)
+    Oblivious,
+}
+
+// Grows one tree per call from the gradients/hessians of the current boosting round.
+// `data` holds the raw and binned feature values along with the empty per-feature
+// histograms whose bin edges every node reuses.
+pub struct TreeBuilder<'a> {
+    data: &'a BinnedDataset,
+    split_finder: SplitFinder,
+    growth_strategy: GrowthStrategy,
+    num_leaves: usize,
+    max_depth: Option<usize>,
+    feature_fraction: f32,
+    feature_fraction_bynode: f32,
+    extra_trees: bool,
+    // fit a linear model in the path features of each leaf, with this ridge penalty
+    linear_tree: bool,
+    linear_lambda: f32,
+    rng: Rng,
+    // features the current tree may split on, drawn anew for every tree
+    tree_features: Vec<usize>,
+    // per-row sample weights the gradients and hessians are scaled by
+    weights: Option<&'a [f32]>,
+    // histograms of the leaves that may still be split, keyed by node
+    pool: HistogramPool,
+    // levels gradients are quantized to for histograms, when they are
+    num_grad_quant_bins: Option<usize>,
+    // the current tree's quantized gradients and hessians
+    quantized: Option<QuantizedGradients>,
+    // where histograms are built and splits searched, rayon's global pool when None
+    thread_pool: Option<Arc<ThreadPool>>,
+    // builds the histograms of unquantized gradients when set
+    gpu: Option<GpuContext<'a>>,
+    // nodes with fewer rows are built on the CPU even with `gpu`
+    gpu_min_rows: usize,
+    // histogram time of every tree built so far
+    timings: DeviceTimings,
+}
+
+// Nodes are grown in a flat arena first, since leaf-wise growth expands them out of order,
+// and only converted to boxed `TreeNode`s once the tree is complete.
+enum ArenaNode {
+    Leaf {
+        value: f32,
+        // range monotone constraints allow for the value, also applied to renewed values
+        bounds: ValueBounds,
+    },
+    Split {
+        feature_index: usize,
+        threshold: f32,
+        default_left: bool,
+        // categorical splits send these categories left instead, see `SplitInfo::categories`
+        categories: Option<Vec<u32>>,
+        left: usize,
+        right: usize,
+        gain: f32,
+    },
+}
+
+// a leaf of the tree being grown together with what is needed to split it further
+struct OpenLeaf {
+    node: usize,
+    // number of splits between the root and this node
+    depth: usize,
+    bounds: ValueBounds,
+    rows: Vec<usize>,
+}
+
+struct Candidate {
+    leaf: OpenLeaf,
+    split: SplitInfo,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    // highest gain first; ties go to the node created first so growth is deterministic
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.split
+            .gain
+            .total_cmp(&other.split.gain)
+            .then_with(|| other.leaf.node.cmp(&self.leaf.node))
+    }
+}
+
+impl<'a> TreeBuilder<'a> {
+    pub fn new(data: &'a BinnedDataset, params: &Params) -> Self {
+        Self {
+            data,
+            split_finder: SplitFinder::new(params.lambda_l2)
+                .with_lambda_l1(params.lambda_l1)
+                .with_min_gain_to_split(params.min_gain_to_split)
+                .with_min_data_in_leaf(params.min_data_in_leaf)
+                .with_min_sum_hessian_in_leaf(params.min_sum_hessian_in_leaf)
+                .with_monotone_constraints(params.monotone_constraints.clone())
+                .with_categorical_features(&params.categorical_features)
+                .with_categorical_options(
+                    params.max_cat_threshold,
+                    params.cat_smooth,
+                    params.min_data_per_group,
+                ),
+            growth_strategy: params.growth_strategy,
+            num_leaves: params.num_leaves,
+            max_depth: params.max_depth,
+            feature_fraction: params.feature_fraction,
+            feature_fraction_bynode: params.feature_fraction_bynode,
+            extra_trees: params.extra_trees,
+            linear_tree: params.linear_tree,
+            linear_lambda: params.linear_lambda,
+            rng: Rng::new(params.seed),
+            tree_features: vec![],
+            weights: None,
+            pool: HistogramPool::new(data.templates(), params.histogram_pool_size),
+            num_grad_quant_bins: params
+                .use_quantized_grad
+                .then_some(params.num_grad_quant_bins),
+            quantized: None,
+            thread_pool: None,
+            gpu: None,
+            gpu_min_rows: params.gpu_min_rows,
+            timings: DeviceTimings::default(),
+        }
+    }
+
+    // replaces the generator that feature subsampling draws from
+    pub fn with_rng(mut self, rng: Rng) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    pub fn with_thread_pool(mut self, thread_pool: Arc<ThreadPool>) -> Self {
+        self.thread_pool = Some(thread_pool);
+        self
+    }
+
+    // builds histograms on the GPU `gpu` holds the binned features of `data` on
+    pub fn with_gpu(mut self, gpu: GpuContext<'a>) -> Self {
+        self.gpu = Some(gpu);
+        self
+    }
+
+    // scales every row's gradient and hessian by `weights[row]`
+    pub fn with_weights(mut self, weights: Option<&'a [f32]>) -> Self {
+        self.weights = weights;
+        self
+    }
+
+    // the generator's current state, e.g. to pick up after a checkpoint
+    pub fn rng(&self) -> &Rng {
+        &self.rng
+    }
+
+    pub fn timings(&self) -> &DeviceTimings {
+        &self.timings
+    }
+
+    pub fn build(&mut self, gradients: &[f32], hessians: &[f32], rows: &[usize]) -> Tree {
+        self.build_with_renewal(gradients, hessians, rows, &|_| None)
+    }
+
+    // Like `build`, but every leaf value for which `renew` returns Some is replaced. `renew`
+    // receives the rows that ended up in the leaf; objectives whose optimal leaf value is
+    // not the Newton step (e.g. quantile regression) use this.
+    pub fn build_with_renewal(
+        &mut self,
+        gradients: &[f32],
+        hessians: &[f32],
+        rows: &[usize],
+        renew: &dyn Fn(&[usize]) -> Option<f32>,
+    ) -> Tree {
+        let mut nodes = self.grow(gradients, hessians, rows);
+
+        let mut leaf_rows: Vec<Vec<usize>> = vec![vec![]; nodes.len()];
+        for &row in rows {
+            leaf_rows[self.find_leaf(&nodes, row)].push(row);
+        }
+        let mut renewed = vec![false; nodes.len()];
+        for ((node, rows), renewed) in nodes.iter_mut().zip(&leaf_rows).zip(&mut renewed) {
+            if let ArenaNode::Leaf { value, bounds } = node
+                && let Some(value_for_rows) = renew(rows)
+            {
+                *value = bounds.clamp(value_for_rows);
+                *renewed = true;
+            }
+        }
+
+        // renewed leaves keep their value, the objective asked for a constant there
+        let mut linear_models: Vec<Option<LinearModel>> = (0..nodes.len()).map(|_| None).collect();
+        if self.linear_tree {
+            for (index, features) in path_features(&nodes).into_iter().enumerate() {
+                if renewed[index] || features.is_empty() {
+                    continue;
+                }
+                linear_models[index] = linear::fit_leaf(
+                    &|feature, row| self.data.value(feature, row),
+                    &leaf_rows[index],
+                    &features,
+                    gradients,
+                    hessians,
+                    self.weights,
+                    self.linear_lambda,
+                )
+                .map(|(intercept, coefficients)| LinearModel {
+                    intercept,
+                    feature_indices: features,
+                    coefficients,
+                });
+            }
+        }
+
+        let (mut leaf_covers, mut split_gains) = (Vec::new(), Vec::new());
+        push_node_stats(&nodes, &leaf_rows, 0, &mut leaf_covers, &mut split_gains);
+        let tree = Tree::new(Box::new(to_tree_node(&nodes, &mut linear_models, 0)))
+            .with_leaf_covers(leaf_covers)
+            .with_split_gains(split_gains);
+        if self.growth_strategy == GrowthStrategy::Oblivious {
+            tree.with_lookup_table()
+        } else {
+            tree
+        }
+    }
+
+    fn find_leaf(&self, nodes: &[ArenaNode], row: usize) -> usize {
+        let mut index = 0;
+        while let ArenaNode::Split {
+            feature_index,
+            threshold,
+            default_left,
+            ref categories,
+            left,
+            right,
+            ..
+        } = nodes[index]
+        {
+            let split = (threshold, default_left, categories.as_deref());
+            index = if self.goes_left(feature_index, split, row) {
+                left
+            } else {
+                right
+            };
+        }
+        index
+    }
+
+    // `split` is the threshold, default direction and categories of a split, routing rows
+    // like `Tree::predict`
+    fn goes_left(
+        &self,
+        feature_index: usize,
+        (threshold, default_left, categories): (f32, bool, Option<&[u32]>),
+        row: usize,
+    ) -> bool {
+        let value = self.data.value(feature_index, row);
+        match categories {
+            Some(categories) => tree::in_categories(categories, value),
+            None if value.is_nan() => default_left,
+            None => value < threshold,
+        }
+    }
+
+    fn grow(&mut self, gradients: &[f32], hessians: &[f32], rows: &[usize]) -> Vec<ArenaNode> {
+        self.tree_features = self.sample_features(self.data.num_features(), self.feature_fraction);
+        self.pool.clear();
+        self.quantized = self.num_grad_quant_bins.map(|num_bins| {
+            QuantizedGradients::new(gradients, hessians, self.weights, num_bins, &mut self.rng)
+        });
+        if self.quantized.is_none()
+            && let Some(gpu) = &mut self.gpu
+        {
+            gpu.upload_gradients(gradients, hessians, self.weights);
+        }
+
+        let (sum_gradients, sum_hessians) = self.sums(rows, gradients, hessians);
+
+        let mut nodes = vec![ArenaNode::Leaf {
+            value: self.split_finder.leaf_value(sum_gradients, sum_hessians),
+            bounds: ValueBounds::UNBOUNDED,
+        }];
+        let root = OpenLeaf {
+            node: 0,
+            depth: 0,
+            bounds: ValueBounds::UNBOUNDED,
+            rows: rows.to_vec(),
+        };
+
+        match self.growth_strategy {
+            GrowthStrategy::LeafWise => {
+                let root = self.evaluate(root, gradients, hessians);
+                self.grow_leaf_wise(&mut nodes, root, gradients, hessians)
+            }
+            GrowthStrategy::DepthWise => {
+                let root = self.evaluate(root, gradients, hessians);
+                self.grow_depth_wise(&mut nodes, root, gradients, hessians)
+            }
+            // levels are searched as a whole rather than per leaf
+            GrowthStrategy::Oblivious => self.grow_oblivious(&mut nodes, root, gradients, hessians),
+        }
+
+        nodes
+    }
+
+    // Always split the leaf with the highest gain until `num_leaves` is reached or no leaf
+    // has a split with positive gain.
+    fn grow_leaf_wise(
+        &mut self,
+        nodes: &mut Vec<ArenaNode>,
+        root: Option<Candidate>,
+        gradients: &[f32],
+        hessians: &[f32],
+    ) {
+        let mut queue: BinaryHeap<Candidate> = root.into_iter().collect();
+
+        let mut num_leaves = 1;
+        while num_leaves < self.num_leaves {
+            let Some(candidate) = queue.pop() else {
+                break;
+            };
+            let parent = candidate.leaf.node;
+            let children = self.apply_split(nodes, candidate, gradients, hessians);
+            num_leaves += 1;
+
+            queue.extend(
+                self.evaluate_children(parent, children, gradients, hessians)
+                    .into_iter()
+                    .flatten(),
+            );
+        }
+    }
+
+    // Split every leaf of the current level that has a positive-gain split, then move on to
+    // the next level, until `max_depth` or `num_leaves` is reached.
+    fn grow_depth_wise(
+        &mut self,
+        nodes: &mut Vec<ArenaNode>,
+        root: Option<Candidate>,
+        gradients: &[f32],
+        hessians: &[f32],
+    ) {
+        let mut level: Vec<Candidate> = root.into_iter().collect();
+
+        let mut num_leaves = 1;
+        while !level.is_empty() {
+            // when num_leaves cuts a level short, the best splits of that level win
+            level.sort_by(|a, b| b.cmp(a));
+
+            let mut next_level = vec![];
+            for candidate in level {
+                if num_leaves >= self.num_leaves {
+                    return;
+                }
+                let parent = candidate.leaf.node;
+                let children = self.apply_split(nodes, candidate, gradients, hessians);
+                num_leaves += 1;
+
+                next_level.extend(
+                    self.evaluate_children(parent, children, gradients, hessians)
+                        .into_iter()
+                        .flatten(),
+                );
+            }
+
+            level = next_level;
+        }
+    }
+
+    // Split all leaves of the current level on the split with the highest total gain over
+    // the level, until `max_depth` is reached, another level would exceed `num_leaves`, or
+    // no split has positive gain. Every level doubles the number of leaves, empty ones
+    // included, so the tree stays complete.
+    fn grow_oblivious(
+        &mut self,
+        nodes: &mut Vec<ArenaNode>,
+        root: OpenLeaf,
+        gradients: &[f32],
+        hessians: &[f32],
+    ) {
+        let mut level = vec![root];
+
+        let mut depth = 0;
+        while level.len() * 2 <= self.num_leaves
+            && self.max_depth.is_none_or(|max_depth| depth < max_depth)
+        {
+            let features = self.node_features();
+            let histograms = self.build_level_histograms(&level, &features, gradients, hessians);
+            let rng = self.extra_trees.then_some(&mut self.rng);
+            let split_finder = &self.split_finder;
+            let split = in_thread_pool(&self.thread_pool, || {
+                split_finder.find_best_level_split(&histograms, &features, rng)
+            });
+            histograms
+                .into_iter()
+                .for_each(|histograms| self.pool.recycle(histograms));
+            let Some(split) = split else {
+                break;
+            };
+
+            // the level's gain is split evenly between its nodes
+            let node_gain = split.gain / level.len() as f32;
+            level = level
+                .into_iter()
+                .flat_map(|leaf| {
+                    self.apply_level_split(nodes, leaf, &split, node_gain, gradients, hessians)
+                })
+                .collect();
+            depth += 1;
+        }
+    }
+
+    fn apply_level_split(
+        &self,
+        nodes: &mut Vec<ArenaNode>,
+        leaf: OpenLeaf,
+        split: &LevelSplit,
+        gain: f32,
+        gradients: &[f32],
+        hessians: &[f32],
+    ) -> [OpenLeaf; 2] {
+        let (left_rows, right_rows): (Vec<usize>, Vec<usize>) = leaf
+            .rows
+            .iter()
+            .partition(|&&i| self.data.value(split.feature_index, i) < split.threshold);
+
+        let children = [left_rows, right_rows].map(|rows| {
+            let (sum_gradients, sum_hessians) = self.sums(&rows, gradients, hessians);
+            nodes.push(ArenaNode::Leaf {
+                value: self.split_finder.leaf_value(sum_gradients, sum_hessians),
+                bounds: ValueBounds::UNBOUNDED,
+            });
+            OpenLeaf {
+                node: nodes.len() - 1,
+                depth: leaf.depth + 1,
+                bounds: ValueBounds::UNBOUNDED,
+                rows,
+            }
+        });
+        nodes[leaf.node] = ArenaNode::Split {
+            feature_index: split.feature_index,
+            threshold: split.threshold,
+            default_left: false,
+            categories: None,
+            left: children[0].node,
+            right: children[1].node,
+            gain,
+        };
+        children
+    }
+
+    // Turns the candidate's leaf into a split node and returns the two new leaves.
+    fn apply_split(
+        &self,
+        nodes: &mut Vec<ArenaNode>,
+        candidate: Candidate,
+        gradients: &[f32],
+        hessians: &[f32],
+    ) -> [OpenLeaf; 2] {
+        let Candidate { leaf, split } = candidate;
+
+        let (left_rows, right_rows): (Vec<usize>, Vec<usize>) = leaf.rows.iter().partition(|&&i| {
+            self.goes_left(
+                split.feature_index,
+                (
+                    split.threshold,
+                    split.default_left,
+                    split.categories.as_deref(),
+                ),
+                i,
+            )
+        });
+        let (left_bounds, right_bounds) = self.split_finder.child_bounds(&split, leaf.bounds);
+        // the split's values come from the quantized sums then, which only had to rank splits
+        let value = |rows: &[usize], split_value: f32, bounds: ValueBounds| {
+            if self.quantized.is_some() {
+                let (sum_gradients, sum_hessians) = self.sums(rows, gradients, hessians);
+                bounds.clamp(self.split_finder.leaf_value(sum_gradients, sum_hessians))
+            } else {
+                split_value
+            }
+        };
+
+        let left = nodes.len();
+        nodes.push(ArenaNode::Leaf {
+            value: value(&left_rows, split.left_value, left_bounds),
+            bounds: left_bounds,
+        });
+        let right = nodes.len();
+        nodes.push(ArenaNode::Leaf {
+            value: value(&right_rows, split.right_value, right_bounds),
+            bounds: right_bounds,
+        });
+        nodes[leaf.node] = ArenaNode::Split {
+            feature_index: split.feature_index,
+            threshold: split.threshold,
+            default_left: split.default_left,
+            categories: split.categories,
+            left,
+            right,
+            gain: split.gain,
+        };
+
+        [
+            OpenLeaf {
+                node: left,
+                depth: leaf.depth + 1,
+                bounds: left_bounds,
+                rows: left_rows,
+            },
+            OpenLeaf {
+                node: right,
+                depth: leaf.depth + 1,
+                bounds: right_bounds,
+                rows: right_rows,
+            },
+        ]
+    }
+
+    fn splittable(&self, leaf: &OpenLeaf) -> bool {
+        leaf.rows.len() >= 2
+            && self
+                .max_depth
+                .is_none_or(|max_depth| leaf.depth < max_depth)
+    }
+
+    // Finds the best split of a leaf, unless the leaf is already at `max_depth`.
+    fn evaluate(
+        &mut self,
+        leaf: OpenLeaf,
+        gradients: &[f32],
+        hessians: &[f32],
+    ) -> Option<Candidate> {
+        if !self.splittable(&leaf) {
+            return None;
+        }
+
+        let features = self.node_features();
+        let histograms = self.build_histograms(&leaf.rows, &features, gradients, hessians);
+        self.find_split(leaf, &features, histograms)
+    }
+
+    // Like `evaluate` for both children of the split `parent`. Only the child with fewer
+    // rows is accumulated from its rows; the other one gets what remains of the parent's
+    // histograms, as long as the pool still has them and they cover the same features.
+    fn evaluate_children(
+        &mut self,
+        parent: usize,
+        [left, right]: [OpenLeaf; 2],
+        gradients: &[f32],
+        hessians: &[f32],
+    ) -> [Option<Candidate>; 2] {
+        let parent_histograms = self.pool.take(parent);
+        // nodes sampling their own features may need ones the parent left empty
+        let Some(mut remaining) = parent_histograms.filter(|_| self.feature_fraction_bynode >= 1.0)
+        else {
+            return [
+                self.evaluate(left, gradients, hessians),
+                self.evaluate(right, gradients, hessians),
+            ];
+        };
+        if !self.splittable(&left) && !self.splittable(&right) {
+            self.pool.recycle(remaining);
+            return [None, None];
+        }
+
+        let features = self.node_features();
+        let smaller = if left.rows.len() <= right.rows.len() {
+            &left.rows
+        } else {
+            &right.rows
+        };
+        let histograms = self.build_histograms(smaller, &features, gradients, hessians);
+        for &feature in &features {
+            remaining[feature].subtract(&histograms[feature]);
+        }
+        let (left_histograms, right_histograms) = if left.rows.len() <= right.rows.len() {
+            (histograms, remaining)
+        } else {
+            (remaining, histograms)
+        };
+        [
+            self.find_split(left, &features, left_histograms),
+            self.find_split(right, &features, right_histograms),
+        ]
+    }
+
+    // The best split of `leaf` from its filled `histograms`, which stay in the pool for its
+    // children when there is one.
+    fn find_split(
+        &mut self,
+        leaf: OpenLeaf,
+        features: &[usize],
+        histograms: Vec<Histogram>,
+    ) -> Option<Candidate> {
+        if !self.splittable(&leaf) {
+            self.pool.recycle(histograms);
+            return None;
+        }
+        let split = if self.extra_trees {
+            self.split_finder.find_random_split_among(
+                &histograms,
+                features,
+                leaf.bounds,
+                &mut self.rng,
+            )
+        } else {
+            in_thread_pool(&self.thread_pool, || {
+                self.split_finder
+                    .find_best_split_among(&histograms, features, leaf.bounds)
+            })
+        };
+        match split {
+            Some(split) => {
+                self.pool.insert(leaf.node, histograms);
+                Some(Candidate { leaf, split })
+            }
+            None => {
+                self.pool.recycle(histograms);
+                None
+            }
+        }
+    }
+
+    // The tree's features, subsampled by `feature_fraction_bynode`.
+    fn node_features(&mut self) -> Vec<usize> {
+        if self.feature_fraction_bynode < 1.0 {
+            let sampled =
+                self.sample_features(self.tree_features.len(), self.feature_fraction_bynode);
+            sampled.iter().map(|&i| self.tree_features[i]).collect()
+        } else {
+            self.tree_features.clone()
+        }
+    }
+
+    // At least one feature, `fraction` of `n` otherwise.
+    fn sample_features(&mut self, n: usize, fraction: f32) -> Vec<usize> {
+        if fraction >= 1.0 {
+            return (0..n).collect();
+        }
+        let k = (n as f32 * fraction).round() as usize;
+        self.rng.sample(n, k.max(1))
+    }
+
+    // Histograms are indexed by feature, but only the ones in `features` are filled; the
+    // others stay empty.
+    fn build_histograms(
+        &mut self,
+        rows: &[usize],
+        features: &[usize],
+        gradients: &[f32],
+        hessians: &[f32],
+    ) -> Vec<Histogram> {
+        let mut histograms = self.pool.acquire();
+        let device = self.histogram_device(rows);
+        let start = Instant::now();
+        in_thread_pool(&self.thread_pool, || {
+            self.fill_histograms(&mut histograms, device, rows, features, gradients, hessians)
+        });
+        self.timings.add(device, start.elapsed(), 1);
+        histograms
+    }
+
+    // Like `build_histograms` for every leaf of a level. The GPU's leaves are sent to it from
+    // a thread of their own, so the CPU builds the small leaves while it waits for them.
+    fn build_level_histograms(
+        &mut self,
+        leaves: &[OpenLeaf],
+        features: &[usize],
+        gradients: &[f32],
+        hessians: &[f32],
+    ) -> Vec<Vec<Histogram>> {
+        let mut histograms: Vec<Vec<Histogram>> =
+            leaves.iter().map(|_| self.pool.acquire()).collect();
+        let (on_gpu, on_cpu): (Vec<_>, Vec<_>) = histograms
+            .iter_mut()
+            .zip(leaves)
+            .partition(|(_, leaf)| self.histogram_device(&leaf.rows) == Device::Gpu);
+        let (num_gpu, num_cpu) = (on_gpu.len(), on_cpu.len());
+
+        let this = &*self;
+        let build = |device: Device, leaves: Vec<(&mut Vec<Histogram>, &OpenLeaf)>| {
+            let start = Instant::now();
+            in_thread_pool(&this.thread_pool, || {
+                for (histograms, leaf) in leaves {
+                    this.fill_histograms(
+                        histograms, device, &leaf.rows, features, gradients, hessians,
+                    );
+                }
+            });
+            start.elapsed()
+        };
+        let (gpu_time, cpu_time) = std::thread::scope(|scope| {
+            let gpu = (!on_gpu.is_empty()).then(|| scope.spawn(|| build(Device::Gpu, on_gpu)));
+            let cpu_time = build(Device::Cpu, on_cpu);
+            let gpu_time = gpu.map(|gpu| gpu.join().expect("building histograms panicked"));
+            (gpu_time, cpu_time)
+        });
+        if let Some(gpu_time) = gpu_time {
+            self.timings.add(Device::Gpu, gpu_time, num_gpu);
+        }
+        if num_cpu > 0 {
+            self.timings.add(Device::Cpu, cpu_time, num_cpu);
+        }
+        histograms
+    }
+
+    // where the histograms of the node holding `rows` are built
+    fn histogram_device(&self, rows: &[usize]) -> Device {
+        if self.quantized.is_none() && self.gpu.is_some() && rows.len() >= self.gpu_min_rows {
+            Device::Gpu
+        } else {
+            Device::Cpu
+        }
+    }
+
+    fn fill_histograms(
+        &self,
+        histograms: &mut [Histogram],
+        device: Device,
+        rows: &[usize],
+        features: &[usize],
+        gradients: &[f32],
+        hessians: &[f32],
+    ) {
+        match (&self.quantized, &self.gpu, device) {
+            (Some(quantized), _, _) => self
+                .data
+                .fill_quantized_histograms(histograms, features, rows, quantized),
+            (None, Some(gpu), Device::Gpu) => {
+                let node_sums = self.sums(rows, gradients, hessians);
+                gpu.fill_histograms(
+                    self.data,
+                    histograms,
+                    features,
+                    rows,
+                    gradients,
+                    hessians,
+                    self.weights,
+                    node_sums,
+                )
+            }
+            (None, _, _) => {
+                let node_sums = self.sums(rows, gradients, hessians);
+                self.data.fill_histograms(
+                    histograms,
+                    features,
+                    rows,
+                    gradients,
+                    hessians,
+                    self.weights,
+                    node_sums,
+                )
+            }
+        }
+    }
+
+    // weighted sums of the gradients and hessians of `rows`
+    fn sums(&self, rows: &[usize], gradients: &[f32], hessians: &[f32]) -> (f32, f32) {
+        let weight = |i: usize| self.weights.map_or(1.0, |weights| weights[i]);
+        let sum_gradients = rows.iter().map(|&i| weight(i) * gradients[i]).sum();
+        let sum_hessians = rows.iter().map(|&i| weight(i) * hessians[i]).sum();
+        (sum_gradients, sum_hessians)
+    }
+}
+
+fn in_thread_pool<R: Send>(
+    thread_pool: &Option<Arc<ThreadPool>>,
+    op: impl FnOnce() -> R + Send,
+) -> R {
+    match thread_pool {
+        Some(thread_pool) => thread_pool.install(op),
+        None => op(),
+    }
+}
+
+struct LinearModel {
+    intercept: f32,
+    feature_indices: Vec<usize>,
+    coefficients: Vec<f32>,
+}
+
+// The distinct features split on between the root and every node, in increasing order.
+// Categorical splits don't count, a coefficient on category codes means nothing.
+fn path_features(nodes: &[ArenaNode]) -> Vec<Vec<usize>> {
+    let mut paths: Vec<Vec<usize>> = vec![vec![]; nodes.len()];
+    let mut stack = vec![0];
+    while let Some(index) = stack.pop() {
+        if let ArenaNode::Split {
+            feature_index,
+            ref categories,
+            left,
+            right,
+            ..
+        } = nodes[index]
+        {
+            let mut features = paths[index].clone();
+            if categories.is_none()
+                && let Err(position) = features.binary_search(&feature_index)
+            {
+                features.insert(position, feature_index);
+            }
+            paths[left] = features.clone();
+            paths[right] = features;
+            stack.extend([left, right]);
+        }
+    }
+    paths
+}
+
+fn to_tree_node(
+    nodes: &[ArenaNode],
+    linear_models: &mut [Option<LinearModel>],
+    index: usize,
+) -> TreeNode {
+    match nodes[index] {
+        ArenaNode::Leaf { value, .. } => match linear_models[index].take() {
+            Some(model) => TreeNode::LinearLeaf {
+                value,
+                intercept: model.intercept,
+                feature_indices: model.feature_indices,
+                coefficients: model.coefficients,
+            },
+            None => TreeNode::Leaf { value },
+        },
+        ArenaNode::Split {
+            feature_index,
+            threshold,
+            default_left,
+            ref categories,
+            left,
+            right,
+            ..
+        } => {
+            let left_child = Box::new(to_tree_node(nodes, linear_models, left));
+            let right_child = Box::new(to_tree_node(nodes, linear_models, right));
+            match categories {
+                Some(categories) => TreeNode::CategoricalSplit {
+                    feature_index,
+                    categories: categories.clone(),
+                    left_child,
+                    right_child,
+                },
+                None => TreeNode::Split {
+                    feature_index,
+                    threshold,
+                    default_left,
+                    left_child,
+                    right_child,
+                },
+            }
+        }
+    }
+}
+
+// the number of rows in each leaf below `index`, leaves from left to right, and the gain
+// of each split there, splits in preorder
+fn push_node_stats(
+    nodes: &[ArenaNode],
+    leaf_rows: &[Vec<usize>],
+    index: usize,
+    covers: &mut Vec<f32>,
+    gains: &mut Vec<f32>,
+) {
+    match nodes[index] {
+        ArenaNode::Leaf { .. } => covers.push(leaf_rows[index].len() as f32),
+        ArenaNode::Split {
+            left, right, gain, ..
+        } => {
+            gains.push(gain);
+            push_node_stats(nodes, leaf_rows, left, covers, gains);
+            push_node_stats(nodes, leaf_rows, right, covers, gains);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    fn binned(columns: &[Vec<f32>]) -> BinnedDataset {
+        BinnedDataset::from_columns(columns.to_vec(), 255)
+    }
+
+    fn leaf_wise(num_leaves: usize) -> Params {
+        Params {
+            num_leaves,
+            ..Params::default()
+        }
+    }
+
+    fn leaf_wise_with_depth(num_leaves: usize, max_depth: usize) -> Params {
+        Params {
+            num_leaves,
+            max_depth: Some(max_depth),
+            ..Params::default()
+        }
+    }
+
+    fn depth_wise(max_depth: Option<usize>, num_leaves: usize) -> Params {
+        Params {
+            growth_strategy: GrowthStrategy::DepthWise,
+            max_depth,
+            num_leaves,
+            ..Params::default()
+        }
+    }
+
+    fn depth(node: &TreeNode) -> usize {
+        match node {
+            TreeNode::Leaf { .. } | TreeNode::LinearLeaf { .. } => 0,
+            TreeNode::Split {
+                left_child,
+                right_child,
+                ..
+            }
+            | TreeNode::CategoricalSplit {
+                left_child,
+                right_child,
+                ..
+            } => 1 + depth(left_child).max(depth(right_child)),
+        }
+    }
+
+    fn count_leaves(node: &TreeNode) -> usize {
+        match node {
+            TreeNode::Leaf { .. } | TreeNode::LinearLeaf { .. } => 1,
+            TreeNode::Split {
+                left_child,
+                right_child,
+                ..
+            }
+            | TreeNode::CategoricalSplit {
+                left_child,
+                right_child,
+                ..
+            } => count_leaves(left_child) + count_leaves(right_child),
+        }
+    }
+
+    #[test]
+    fn test_categorical_split_groups_categories() {
+        // categories 1, 4 and 7 pull one way and the rest the other, so no threshold on the
+        // codes separates them
+        let codes: Vec<f32> = (0..60).map(|i| (i % 10) as f32).collect();
+        let gradients: Vec<f32> = codes
+            .iter()
+            .map(|&code| {
+                if [1.0, 4.0, 7.0].contains(&code) {
+                    -1.0
+                } else {
+                    1.0
+                }
+            })
+            .collect();
+        let hessians = vec![1.0; 60];
+        let rows: Vec<usize> = (0..60).collect();
+        let data = binned(&[codes]).with_categorical_features(&[0]).unwrap();
+        let params = Params {
+            num_leaves: 2,
+            categorical_features: vec![0],
+            cat_smooth: 1.0,
+            min_data_per_group: 1,
+            ..Params::default()
+        };
+
+        let tree = TreeBuilder::new(&data, &params).build(&gradients, &hessians, &rows);
+        let TreeNode::CategoricalSplit { categories, .. } = tree.root() else {
+            panic!("expected a categorical split, got {:?}", tree.root());
+        };
+        assert!(categories == &[1, 4, 7] || categories == &[0, 2, 3, 5, 6, 8, 9]);
+        for code in 0..10 {
+            let negative = [1, 4, 7].contains(&code);
+            assert_eq!(tree.predict(&[code as f32]) > 0.0, negative);
+        }
+        // an unseen category goes right, with the categories that weren't listed
+        let right = (0..10).find(|code| !categories.contains(code)).unwrap();
+        assert_eq!(tree.predict(&[12.0]), tree.predict(&[right as f32]));
+    }
+
+    #[test]
+    fn test_build_respects_num_leaves() {
+        let columns = vec![(0..16).map(|i| i as f32).collect::<Vec<f32>>()];
+        let data = binned(&columns);
+        let gradients: Vec<f32> = (0..16).map(|i| ((i * 7) % 5) as f32 - 2.0).collect();
+        let hessians = vec![1.0; 16];
+        let rows: Vec<usize> = (0..16).collect();
+
+        for num_leaves in [1, 2, 3, 5] {
+            let mut builder = TreeBuilder::new(&data, &leaf_wise(num_leaves));
+            let tree = builder.build(&gradients, &hessians, &rows);
+            assert_eq!(count_leaves(tree.root()), num_leaves);
+            // every row is counted in the leaf it reaches
+            let mut counts = vec![0.0; num_leaves];
+            for row in &columns[0] {
+                counts[tree.leaf_index(&[*row][..])] += 1.0;
+            }
+            assert_eq!(tree.leaf_covers(), Some(&counts[..]));
+        }
+    }
+
+    #[test]
+    fn test_histogram_pool_size_does_not_change_the_tree() {
+        // with room for one node, most children are accumulated from their rows instead of
+        // derived from their parent's histograms
+        let columns = vec![
+            (0..64).map(|i| i as f32).collect::<Vec<f32>>(),
+            (0..64).map(|i| ((i * 5) % 7) as f32).collect(),
+        ];
+        let data = binned(&columns);
+        let gradients: Vec<f32> = (0..64).map(|i| ((i * 7) % 11) as f32 - 5.0).collect();
+        let hessians = vec![1.0; 64];
+        let rows: Vec<usize> = (0..64).collect();
+
+        let unbounded = TreeBuilder::new(&data, &leaf_wise(8)).build(&gradients, &hessians, &rows);
+        let params = Params {
+            histogram_pool_size: Some(1),
+            ..leaf_wise(8)
+        };
+        let bounded = TreeBuilder::new(&data, &params).build(&gradients, &hessians, &rows);
+        for (&x, &y) in columns[0].iter().zip(&columns[1]) {
+            let row = [x, y];
+            assert_abs_diff_eq!(
+                unbounded.predict(&row),
+                bounded.predict(&row),
+                epsilon = 1e-5
+            );
+        }
+    }
+
+    #[test]
+    fn test_build_expands_highest_gain_leaf_first() {
+        // The root splits rows 0..4 from 4..8. Splitting the right child {2, 2 | 8, 8}
+        // gains more than splitting the left child {-6, -6 | -4, -4}, so with three
+        // leaves only the right child is expanded.
+        let columns = vec![(0..8).map(|i| i as f32).collect::<Vec<f32>>()];
+        let data = binned(&columns);
+        let gradients = vec![-6.0, -6.0, -4.0, -4.0, 2.0, 2.0, 8.0, 8.0];
+        let hessians = vec![1.0; 8];
+        let rows: Vec<usize> = (0..8).collect();
+
+        let mut builder = TreeBuilder::new(&data, &leaf_wise(3));
+        let tree = builder.build(&gradients, &hessians, &rows);
+
+        assert_abs_diff_eq!(tree.predict(&[0.0]), 5.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(tree.predict(&[3.0]), 5.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(tree.predict(&[4.0]), -2.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(tree.predict(&[7.0]), -8.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_leaf_wise_respects_max_depth() {
+        // Gradients that keep getting more extreme towards the right end make unlimited
+        // leaf-wise growth chase them into a deep chain.
+        let columns = vec![(0..32).map(|i| i as f32).collect::<Vec<f32>>()];
+        let data = binned(&columns);
+        let gradients: Vec<f32> = (0..32).map(|i| (i as f32).exp2() / 1e6).collect();
+        let hessians = vec![1.0; 32];
+        let rows: Vec<usize> = (0..32).collect();
+
+        let unlimited = TreeBuilder::new(&data, &leaf_wise(8)).build(&gradients, &hessians, &rows);
+        assert!(depth(unlimited.root()) > 3);
+
+        let tree = TreeBuilder::new(&data, &leaf_wise_with_depth(8, 3))
+            .build(&gradients, &hessians, &rows);
+        assert_eq!(depth(tree.root()), 3);
+        assert!(count_leaves(tree.root()) > count_leaves(unlimited.root()) / 2);
+    }
+
+    #[test]
+    fn test_feature_fraction_restricts_split_features() {
+        // feature 0 separates the gradients perfectly, feature 1 is noise
+        let columns = vec![
+            (0..8).map(|i| i as f32).collect::<Vec<f32>>(),
+            (0..8).map(|i| ((i * 3) % 8) as f32).collect::<Vec<f32>>(),
+        ];
+        let data = binned(&columns);
+        let gradients = vec![-1.0, -1.0, -1.0, -1.0, 1.0, 1.0, 1.0, 1.0];
+        let hessians = vec![1.0; 8];
+        let rows: Vec<usize> = (0..8).collect();
+
+        let split_features = |params: &Params| {
+            let mut builder = TreeBuilder::new(&data, params);
+            (0..20)
+                .map(
+                    |_| match builder.build(&gradients, &hessians, &rows).root() {
+                        TreeNode::Split { feature_index, .. } => *feature_index,
+                        _ => panic!("expected a split"),
+                    },
+                )
+                .collect::<Vec<usize>>()
+        };
+
+        let all = split_features(&Params {
+            num_leaves: 2,
+            ..Params::default()
+        });
+        assert!(all.iter().all(|&feature| feature == 0));
+
+        // with one feature per tree the noise feature has to be used sometimes
+        for params in [
+            Params {
+                num_leaves: 2,
+                feature_fraction: 0.5,
+                ..Params::default()
+            },
+            Params {
+                num_leaves: 2,
+                feature_fraction_bynode: 0.5,
+                ..Params::default()
+            },
+        ] {
+            let sampled = split_features(&params);
+            assert!(sampled.contains(&0) && sampled.contains(&1));
+        }
+    }
+
+    #[test]
+    fn test_build_stops_without_gain() {
+        let columns = vec![vec![0.0, 1.0, 2.0, 3.0]];
+        let data = binned(&columns);
+        let gradients = vec![1.0; 4];
+        let hessians = vec![1.0; 4];
+        let rows: Vec<usize> = (0..4).collect();
+
+        let mut builder = TreeBuilder::new(&data, &Params::default());
+        let tree = builder.build(&gradients, &hessians, &rows);
+
+        assert_eq!(count_leaves(tree.root()), 1);
+        assert_abs_diff_eq!(tree.predict(&[0.0]), -1.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_depth_wise_expands_whole_level() {
+        // Same data as the leaf-wise test above: with three leaves leaf-wise growth only
+        // expands the right child, while depth-wise growth with four leaves splits both
+        // children of the root before going deeper.
+        let columns = vec![(0..8).map(|i| i as f32).collect::<Vec<f32>>()];
+        let data = binned(&columns);
+        let gradients = vec![-6.0, -6.0, -4.0, -4.0, 2.0, 2.0, 8.0, 8.0];
+        let hessians = vec![1.0; 8];
+        let rows: Vec<usize> = (0..8).collect();
+
+        let mut builder = TreeBuilder::new(&data, &depth_wise(Some(2), 31));
+        let tree = builder.build(&gradients, &hessians, &rows);
+
+        assert_eq!(count_leaves(tree.root()), 4);
+        assert_eq!(depth(tree.root()), 2);
+        assert_abs_diff_eq!(tree.predict(&[0.0]), 6.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(tree.predict(&[3.0]), 4.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(tree.predict(&[4.0]), -2.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(tree.predict(&[7.0]), -8.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_depth_wise_respects_max_depth_and_num_leaves() {
+        let columns = vec![(0..16).map(|i| i as f32).collect::<Vec<f32>>()];
+        let data = binned(&columns);
+        let gradients: Vec<f32> = (0..16).map(|i| ((i * 7) % 5) as f32 - 2.0).collect();
+        let hessians = vec![1.0; 16];
+        let rows: Vec<usize> = (0..16).collect();
+
+        let stump =
+            TreeBuilder::new(&data, &depth_wise(Some(1), 31)).build(&gradients, &hessians, &rows);
+        assert_eq!(depth(stump.root()), 1);
+        assert_eq!(count_leaves(stump.root()), 2);
+
+        let capped =
+            TreeBuilder::new(&data, &depth_wise(None, 3)).build(&gradients, &hessians, &rows);
+        assert_eq!(count_leaves(capped.root()), 3);
+        assert_eq!(depth(capped.root()), 2);
+    }
+
+    #[test]
+    fn test_oblivious_shares_splits_within_levels() {
+        // additive target: 2 for x0 >= 2 plus 1 for x1 >= 2
+        let columns = vec![
+            (0..16).map(|i| (i % 4) as f32).collect::<Vec<f32>>(),
+            (0..16).map(|i| (i / 4) as f32).collect::<Vec<f32>>(),
+        ];
+        let data = binned(&columns);
+        let gradients: Vec<f32> = (0..16)
+            .map(|i| {
+                let x0 = if columns[0][i] >= 2.0 { 2.0 } else { 0.0 };
+                let x1 = if columns[1][i] >= 2.0 { 1.0 } else { 0.0 };
+                -(x0 + x1)
+            })
+            .collect();
+        let hessians = vec![1.0; 16];
+        let rows: Vec<usize> = (0..16).collect();
+        let params = Params {
+            growth_strategy: GrowthStrategy::Oblivious,
+            num_leaves: 7,
+            ..Params::default()
+        };
+
+        let tree = TreeBuilder::new(&data, &params).build(&gradients, &hessians, &rows);
+
+        // a third level would need 8 leaves
+        let table = tree.lookup_table().unwrap();
+        assert_eq!(table.depth(), 2);
+        assert_eq!(count_leaves(tree.root()), 4);
+        assert_eq!(table.leaf_values(), &[0.0, 1.0, 2.0, 3.0]);
+        assert_eq!(tree.predict(&[3.0, 0.0]), 2.0);
+        assert_eq!(tree.predict(&[1.0, 3.0]), 1.0);
+
+        let stump = TreeBuilder::new(
+            &data,
+            &Params {
+                max_depth: Some(1),
+                ..params
+            },
+        )
+        .build(&gradients, &hessians, &rows);
+        assert_eq!(stump.lookup_table().unwrap().depth(), 1);
+    }
+
+    #[test]
+    fn test_build_with_renewal_replaces_leaf_values() {
+        let columns = vec![(0..8).map(|i| i as f32).collect::<Vec<f32>>()];
+        let data = binned(&columns);
+        let gradients = vec![-6.0, -6.0, -4.0, -4.0, 2.0, 2.0, 8.0, 8.0];
+        let hessians = vec![1.0; 8];
+        let rows: Vec<usize> = (0..8).collect();
+
+        // renew every leaf with the smallest row index it holds
+        let mut builder = TreeBuilder::new(&data, &leaf_wise(3));
+        let tree = builder.build_with_renewal(&gradients, &hessians, &rows, &|leaf_rows| {
+            leaf_rows.iter().min().map(|&i| i as f32)
+        });
+
+        assert_eq!(tree.predict(&[3.0]), 0.0);
+        assert_eq!(tree.predict(&[5.0]), 4.0);
+        assert_eq!(tree.predict(&[7.0]), 6.0);
+    }
+}
@@ -0,0 +1,160 @@
+// JavaScript bindings for scoring lite models in the browser, Node or Cloudflare Workers,
+// built with wasm-bindgen for `wasm32-unknown-unknown`. Models are trained and saved
+// natively with metalgbm (`Model::save_lite`), and the bytes of the file are handed to
+// `new Model(bytes)`. Rows are `Float32Array`s with NaN for missing values.
+
+use metalgbm_predict::lite::LiteModel;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(js_name = Model)]
+pub struct WasmModel {
+    model: LiteModel,
+}
+
+#[wasm_bindgen(js_class = Model)]
+impl WasmModel {
+    #[wasm_bindgen(constructor)]
+    pub fn new(bytes: &[u8]) -> Result<WasmModel, JsError> {
+        let model = LiteModel::from_bytes(bytes).map_err(|err| JsError::new(&err.to_string()))?;
+        Ok(WasmModel { model })
+    }
+
+    // the predictions of one row, e.g. probabilities, one per output
+    pub fn predict(&self, row: &[f32]) -> Result<Vec<f32>, JsError> {
+        self.scores(row, false)
+            .map_err(|reason| JsError::new(&reason))
+    }
+
+    #[wasm_bindgen(js_name = predictRaw)]
+    pub fn predict_raw(&self, row: &[f32]) -> Result<Vec<f32>, JsError> {
+        self.scores(row, true)
+            .map_err(|reason| JsError::new(&reason))
+    }
+
+    // The predictions of the rows of a row-major matrix with `numFeatures` columns, the
+    // outputs of each row after another.
+    #[wasm_bindgen(js_name = predictBatch)]
+    pub fn predict_batch(&self, rows: &[f32]) -> Result<Vec<f32>, JsError> {
+        self.batch_scores(rows, false)
+            .map_err(|reason| JsError::new(&reason))
+    }
+
+    #[wasm_bindgen(js_name = predictRawBatch)]
+    pub fn predict_raw_batch(&self, rows: &[f32]) -> Result<Vec<f32>, JsError> {
+        self.batch_scores(rows, true)
+            .map_err(|reason| JsError::new(&reason))
+    }
+
+    #[wasm_bindgen(getter, js_name = numFeatures)]
+    pub fn num_features(&self) -> usize {
+        self.model.num_features()
+    }
+
+    #[wasm_bindgen(getter, js_name = numOutputs)]
+    pub fn num_outputs(&self) -> usize {
+        self.model.num_outputs()
+    }
+
+    #[wasm_bindgen(getter, js_name = numTrees)]
+    pub fn num_trees(&self) -> usize {
+        self.model.num_trees()
+    }
+
+    #[wasm_bindgen(getter, js_name = rawErrorBound)]
+    pub fn raw_error_bound(&self) -> f32 {
+        self.model.raw_error_bound()
+    }
+}
+
+// The checks and scoring behind the bindings, which report errors as strings since
+// `JsError`s can only be made on wasm targets. A row shorter than the model's features
+// would panic, which aborts the whole wasm instance, so it is an error instead.
+impl WasmModel {
+    fn scores(&self, row: &[f32], raw: bool) -> Result<Vec<f32>, String> {
+        let num_features = self.model.num_features();
+        if row.len() < num_features {
+            return Err(format!(
+                "the row has {} features, the model {num_features}",
+                row.len()
+            ));
+        }
+        Ok(if raw {
+            self.model.predict_raw(row)
+        } else {
+            self.model.predict(row)
+        })
+    }
+
+    fn batch_scores(&self, rows: &[f32], raw: bool) -> Result<Vec<f32>, String> {
+        let num_features = self.model.num_features();
+        if num_features == 0 {
+            return Err("a model without features can't split a batch into rows".to_string());
+        }
+        if !rows.len().is_multiple_of(num_features) {
+            return Err(format!(
+                "{} values aren't whole rows of {num_features} features",
+                rows.len()
+            ));
+        }
+        let mut scores = Vec::with_capacity(rows.len() / num_features * self.num_outputs());
+        for row in rows.chunks(num_features) {
+            scores.extend(self.scores(row, raw)?);
+        }
+        Ok(scores)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metalgbm::booster::Booster;
+    use metalgbm::dataset::Dataset;
+    use metalgbm::model::LitePrecision;
+    use metalgbm::objective::ObjectiveType;
+    use metalgbm::params::Params;
+
+    fn model() -> (WasmModel, Booster) {
+        let features: Vec<Vec<f32>> = (0..100)
+            .map(|i| vec![(i % 10) as f32, (i % 3) as f32])
+            .collect();
+        let labels = features
+            .iter()
+            .map(|x| if x[0] > 4.0 { 1.0 } else { 0.0 })
+            .collect();
+        let params = Params {
+            objective: ObjectiveType::BinaryLogistic,
+            num_iterations: 10,
+            ..Params::default()
+        };
+        let mut booster = Booster::new();
+        booster
+            .train(&Dataset::new(features, labels), &params)
+            .unwrap();
+        let bytes = booster
+            .to_model()
+            .to_lite_bytes(LitePrecision::F16)
+            .unwrap();
+        let model = LiteModel::from_bytes(&bytes).unwrap();
+        (WasmModel { model }, booster)
+    }
+
+    #[test]
+    fn test_scores_rows_and_batches() {
+        let (model, booster) = model();
+        assert_eq!(model.num_features(), 2);
+        assert_eq!(model.num_trees(), 10);
+        let probability = model.scores(&[7.0, 1.0], false).unwrap()[0];
+        assert!((probability - booster.predict(&[7.0, 1.0])).abs() < 1e-3);
+
+        let batch = model.batch_scores(&[7.0, 1.0, 2.0, 0.0], true).unwrap();
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch[1], model.scores(&[2.0, 0.0], true).unwrap()[0]);
+    }
+
+    #[test]
+    fn test_rejects_rows_of_the_wrong_size() {
+        let (model, _) = model();
+        assert!(model.scores(&[7.0], false).is_err());
+        assert!(model.batch_scores(&[7.0, 1.0, 2.0], false).is_err());
+    }
+}